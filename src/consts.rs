@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 use bitflags::bitflags;
-use crate::java_code::Op;
+use num_derive::FromPrimitive;
 
 bitflags! {
     pub struct FieldAccessFlags: u16 {
@@ -55,6 +55,20 @@ bitflags! {
     }
 }
 
+// reference_kind of a CONSTANT_MethodHandle
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
 pub const NOP: u8 = 0;
 pub const ACONST_NULL: u8 = 1;
 pub const ICONST_M1: u8 = 2;
@@ -258,6 +272,38 @@ pub const MULTIANEWARRAY: u8 = 197;
 pub const IFNULL: u8 = 198;
 pub const IFNONNULL: u8 = 199;
 
+// newarray types
+pub const T_BOOLEAN: u8 = 4;
+pub const T_CHAR: u8 = 5;
+pub const T_FLOAT: u8 = 6;
+pub const T_DOUBLE: u8 = 7;
+pub const T_BYTE: u8 = 8;
+pub const T_SHORT: u8 = 9;
+pub const T_INT: u8 = 10;
+pub const T_LONG: u8 = 11;
+
+pub fn print_array_type(array_type: &u8) -> &'static str {
+    match *array_type {
+        T_BOOLEAN => "boolean",
+        T_CHAR => "char",
+        T_FLOAT => "float",
+        T_DOUBLE => "double",
+        T_BYTE => "byte",
+        T_SHORT => "short",
+        T_INT => "int",
+        T_LONG => "long",
+        _ => "WHAT",
+    }
+}
+
+
+// The opcode for a mnemonic from print_op, ignoring case.
+pub fn parse_op(name: &str) -> Option<u8> {
+    (0..=u8::MAX).find(|op| {
+        let mnemonic = print_op(op);
+        mnemonic != "WHAT" && mnemonic.eq_ignore_ascii_case(name)
+    })
+}
 
 pub fn print_op(op: &u8) -> &'static str {
    match *op {
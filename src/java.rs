@@ -1,17 +1,18 @@
 #![allow(dead_code)]
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
 
-use nom::bytes::complete::{tag, take};
-use nom::combinator::{map, map_opt, map_res};
-use nom::error::{ErrorKind, make_error};
-use nom::multi::{length_count, length_data, length_value};
-use nom::number::complete::{be_i16, be_u16, be_u32, be_u64, be_u8};
+use nom::bytes::complete::tag;
+use nom::combinator::{all_consuming, map, map_opt};
+use nom::error::{ErrorKind, FromExternalError, make_error};
+use nom::multi::{count, length_count, length_data};
+use nom::number::complete::{be_u16, be_u32, be_u64, be_u8};
 use nom::sequence::{pair, tuple};
 
-use crate::consts::{self, ClassAccessFlags, FieldAccessFlags, MethodAccessFlags};
-use crate::java::AttributeInfo::CodeAttribute;
-use crate::java_code::Code;
+use num_traits::FromPrimitive;
+
+use crate::consts::{ClassAccessFlags, FieldAccessFlags, MethodAccessFlags, ReferenceKind};
+use crate::java_code::{Code, JumpError};
 
 pub struct ClassInfo {
     pub minor_version: u16,
@@ -25,27 +26,46 @@ pub struct ClassInfo {
     pub interfaces: Vec<u16>,
     pub fields: Vec<FieldInfo>,
     pub methods: Vec<MethodInfo>,
-    pub attributes: Vec<AttributeInfo>,
+    pub attributes: Vec<Attribute>,
+}
+
+pub type IResult<'a, O> = nom::IResult<&'a [u8], O, ParseError<'a>>;
+
+// nom's own errors, plus the jumps of some code which do not land on an op.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError<'a> {
+    Nom { input: &'a [u8], kind: ErrorKind },
+    Jump(JumpError),
 }
 
-pub type IResult<'a, O> = nom::IResult<&'a [u8], O>;
+impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> Self {
+        ParseError::Nom { input, kind }
+    }
+
+    fn append(_: &'a [u8], _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a, E> FromExternalError<&'a [u8], E> for ParseError<'a> {
+    fn from_external_error(input: &'a [u8], kind: ErrorKind, _: E) -> Self {
+        ParseError::Nom { input, kind }
+    }
+}
 
 impl ClassInfo {
-    pub fn parse(input: &[u8]) -> IResult<Self> {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
         let (input, _) = tag(b"\xca\xfe\xba\xbe")(input)?;
         let (input, minor_version) = be_u16(input)?;
         let (input, major_version) = be_u16(input)?;
 
-        let (input, constant_pool) = map(
-            length_count(map(be_u16, |num| num - 1), ConstantInfo::parse),
-            |v| ConstantPool(v),
-        )(input)?;
+        let (input, constant_pool) = ConstantPool::parse(input)?;
 
         let (input, access_flags) = map_opt(be_u16, ClassAccessFlags::from_bits)(input)?;
         let (input, this_class) = be_u16(input)?;
         let (input, super_class) = be_u16(input)?;
         let (input, interfaces) = length_count(be_u16, be_u16)(input)?;
-        println!("{:?}", interfaces);
 
         let (input, fields) =
             length_count(be_u16, |input| FieldInfo::parse(input, &constant_pool))(input)?;
@@ -53,7 +73,7 @@ impl ClassInfo {
         let (input, methods) =
             length_count(be_u16, |input| MethodInfo::parse(input, &constant_pool))(input)?;
         let (input, attributes) =
-            length_count(be_u16, |input| AttributeInfo::parse(input, &constant_pool))(input)?;
+            length_count(be_u16, |input| Attribute::parse(input, &constant_pool))(input)?;
 
         Ok((
             input,
@@ -71,6 +91,55 @@ impl ClassInfo {
             },
         ))
     }
+
+    pub fn write(&self) -> Result<Vec<u8>, WriteError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"\xca\xfe\xba\xbe");
+        put_u16(&mut out, self.minor_version);
+        put_u16(&mut out, self.major_version);
+
+        self.constant_pool.write(&mut out);
+
+        put_u16(&mut out, self.access_flags.bits());
+        put_u16(&mut out, self.this_class);
+        put_u16(&mut out, self.super_class);
+        put_u16(&mut out, self.interfaces.len() as u16);
+        for interface in &self.interfaces {
+            put_u16(&mut out, *interface);
+        }
+
+        put_u16(&mut out, self.fields.len() as u16);
+        for field in &self.fields {
+            field.write(&mut out, &self.constant_pool)?;
+        }
+        put_u16(&mut out, self.methods.len() as u16);
+        for method in &self.methods {
+            method.write(&mut out, &self.constant_pool)?;
+        }
+        Attribute::write_all(&self.attributes, &mut out, &self.constant_pool)?;
+        Ok(out)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteError {
+    // The attribute is not parsed yet, so there is nothing to write.
+    UnsupportedAttribute { name: &'static str },
+    UnsupportedInstruction { op: u8 },
+    // The jump target is not an op or does not fit in the jump's offset.
+    JumpOutOfRange { op_pos: u32 },
+}
+
+pub fn put_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+pub fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
 }
 
 //field_info {
@@ -81,10 +150,10 @@ impl ClassInfo {
 //     attribute_info attributes[attributes_count];
 // }
 pub struct FieldInfo {
-    access_flags: FieldAccessFlags,
-    name_index: u16,
-    descriptor_index: u16,
-    attribute_info: Vec<AttributeInfo>,
+    pub access_flags: FieldAccessFlags,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attribute_info: Vec<Attribute>,
 }
 
 impl FieldInfo {
@@ -93,7 +162,7 @@ impl FieldInfo {
         let (input, name_index) = be_u16(input)?;
         let (input, descriptor_index) = be_u16(input)?;
         let (input, attribute_info) =
-            length_count(be_u16, |input| AttributeInfo::parse(input, &constant_pool))(input)?;
+            length_count(be_u16, |input| Attribute::parse(input, constant_pool))(input)?;
 
         Ok((
             input,
@@ -105,6 +174,13 @@ impl FieldInfo {
             },
         ))
     }
+
+    pub fn write(&self, out: &mut Vec<u8>, constant_pool: &ConstantPool) -> Result<(), WriteError> {
+        put_u16(out, self.access_flags.bits());
+        put_u16(out, self.name_index);
+        put_u16(out, self.descriptor_index);
+        Attribute::write_all(&self.attribute_info, out, constant_pool)
+    }
 }
 
 //method_info {
@@ -119,7 +195,7 @@ pub struct MethodInfo {
     pub access_flags: MethodAccessFlags,
     pub name_index: u16,
     pub descriptor_index: u16,
-    pub attribute_info: Vec<AttributeInfo>,
+    pub attribute_info: Vec<Attribute>,
 }
 
 impl MethodInfo {
@@ -128,7 +204,7 @@ impl MethodInfo {
         let (input, name_index) = be_u16(input)?;
         let (input, descriptor_index) = be_u16(input)?;
         let (input, attribute_info) =
-            length_count(be_u16, |input| AttributeInfo::parse(input, constant_pool))(input)?;
+            length_count(be_u16, |input| Attribute::parse(input, constant_pool))(input)?;
 
         Ok((
             input,
@@ -140,8 +216,16 @@ impl MethodInfo {
             },
         ))
     }
+
+    pub fn write(&self, out: &mut Vec<u8>, constant_pool: &ConstantPool) -> Result<(), WriteError> {
+        put_u16(out, self.access_flags.bits());
+        put_u16(out, self.name_index);
+        put_u16(out, self.descriptor_index);
+        Attribute::write_all(&self.attribute_info, out, constant_pool)
+    }
 }
 
+#[derive(PartialEq)]
 pub enum ConstantInfo {
     Class {
         name_index: u16,
@@ -179,14 +263,9 @@ pub enum ConstantInfo {
     },
     UTF8 {
         text: String,
-    },
-    MethodDescriptor {
-        owner: String,
-        parameters: Vec<String>,
-    },
-    FieldDescriptor {
-        owner: String,
-        field: String,
+        // The bytes as read, kept only when writing text would not give them back, like for a
+        // surrogate without its pair.
+        data: Option<Vec<u8>>,
     },
     MethodHandle {
         reference_kind: u8,
@@ -199,10 +278,32 @@ pub enum ConstantInfo {
         bootstrap_method_attr_index: u16,
         name_and_type_index: u16,
     },
+    // The slot following a Long or Double, which the JVM counts but never uses.
+    Unusable,
 }
 
 impl ConstantInfo {
-    pub fn parse_method_descriptor(text: &String) {
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            ConstantInfo::Class { .. } => "Class",
+            ConstantInfo::Field { .. } => "Fieldref",
+            ConstantInfo::Method { .. } => "Methodref",
+            ConstantInfo::Interface { .. } => "InterfaceMethodref",
+            ConstantInfo::String { .. } => "String",
+            ConstantInfo::Integer { .. } => "Integer",
+            ConstantInfo::Float { .. } => "Float",
+            ConstantInfo::Long { .. } => "Long",
+            ConstantInfo::Double { .. } => "Double",
+            ConstantInfo::NameAndType { .. } => "NameAndType",
+            ConstantInfo::UTF8 { .. } => "Utf8",
+            ConstantInfo::MethodHandle { .. } => "MethodHandle",
+            ConstantInfo::MethodType { .. } => "MethodType",
+            ConstantInfo::InvokeDynamic { .. } => "InvokeDynamic",
+            ConstantInfo::Unusable => "Unusable",
+        }
+    }
+
+    pub fn parse_method_descriptor(text: &str) {
         let chars = text.chars();
         for c in chars {
             if c == ')' {
@@ -213,7 +314,7 @@ impl ConstantInfo {
         }
     }
 
-    pub fn parse(input: &[u8]) -> IResult<Self> {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
         let (input, variant) = be_u8(input)?;
         match variant {
             7 => map(be_u16, |name_index| ConstantInfo::Class { name_index })(input),
@@ -249,13 +350,22 @@ impl ConstantInfo {
                     descriptor_index,
                 }
             })(input),
-            1 => map_res(
-                length_data(be_u16),
-                //FIXME(leocth): Java uses MUTF-8, which Rust does *not* expect. https://en.wikipedia.org/wiki/UTF-8#Modified_UTF-8
-                |data: &[u8]| {
-                    String::from_utf8(data.into()).map(|text| ConstantInfo::UTF8 { text })
-                },
-            )(input),
+            1 => map_opt(length_data(be_u16), |data: &[u8]| {
+                decode_mutf8(data).map(|text| match text {
+                    Cow::Borrowed(text) => ConstantInfo::UTF8 {
+                        text: text.to_string(),
+                        data: None,
+                    },
+                    Cow::Owned(text) => {
+                        let mut written = Vec::with_capacity(data.len());
+                        put_mutf8(&mut written, &text);
+                        ConstantInfo::UTF8 {
+                            text,
+                            data: (written != data).then(|| data.to_vec()),
+                        }
+                    }
+                })
+            })(input),
             15 => map(pair(be_u8, be_u16), |(reference_kind, reference_index)| {
                 ConstantInfo::MethodHandle {
                     reference_kind,
@@ -272,20 +382,113 @@ impl ConstantInfo {
                     name_and_type_index,
                 },
             )(input),
-            _ => return Err(nom::Err::Error(make_error(input, ErrorKind::Alt))),
+            _ => Err(nom::Err::Error(make_error(input, ErrorKind::Alt))),
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            ConstantInfo::Class { name_index } => {
+                put_u8(out, 7);
+                put_u16(out, *name_index);
+            }
+            ConstantInfo::Field {
+                class_index,
+                name_and_type_index,
+            } => {
+                put_u8(out, 9);
+                put_u16(out, *class_index);
+                put_u16(out, *name_and_type_index);
+            }
+            ConstantInfo::Method {
+                class_index,
+                name_and_type_index,
+            } => {
+                put_u8(out, 10);
+                put_u16(out, *class_index);
+                put_u16(out, *name_and_type_index);
+            }
+            ConstantInfo::Interface {
+                class_index,
+                name_and_type_index,
+            } => {
+                put_u8(out, 11);
+                put_u16(out, *class_index);
+                put_u16(out, *name_and_type_index);
+            }
+            ConstantInfo::String { string_index } => {
+                put_u8(out, 8);
+                put_u16(out, *string_index);
+            }
+            ConstantInfo::Integer { bytes } => {
+                put_u8(out, 3);
+                put_u32(out, *bytes);
+            }
+            ConstantInfo::Float { bytes } => {
+                put_u8(out, 4);
+                put_u32(out, *bytes);
+            }
+            ConstantInfo::Long { bytes } => {
+                put_u8(out, 5);
+                out.extend_from_slice(&bytes.to_be_bytes());
+            }
+            ConstantInfo::Double { bytes } => {
+                put_u8(out, 6);
+                out.extend_from_slice(&bytes.to_be_bytes());
+            }
+            ConstantInfo::NameAndType {
+                name_index,
+                descriptor_index,
+            } => {
+                put_u8(out, 12);
+                put_u16(out, *name_index);
+                put_u16(out, *descriptor_index);
+            }
+            ConstantInfo::UTF8 { text, data } => {
+                let mut written = Vec::new();
+                let data = data.as_ref().unwrap_or_else(|| {
+                    put_mutf8(&mut written, text);
+                    &written
+                });
+                put_u8(out, 1);
+                put_u16(out, data.len() as u16);
+                out.extend_from_slice(data);
+            }
+            ConstantInfo::MethodHandle {
+                reference_kind,
+                reference_index,
+            } => {
+                put_u8(out, 15);
+                put_u8(out, *reference_kind);
+                put_u16(out, *reference_index);
+            }
+            ConstantInfo::MethodType { descriptor_index } => {
+                put_u8(out, 16);
+                put_u16(out, *descriptor_index);
+            }
+            ConstantInfo::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                put_u8(out, 18);
+                put_u16(out, *bootstrap_method_attr_index);
+                put_u16(out, *name_and_type_index);
+            }
+            // Only takes up a slot, it has no bytes of its own.
+            ConstantInfo::Unusable => {}
         }
     }
 }
 
 pub struct AttributeException {
-    start_pc: u16,
-    end_pc: u16,
-    handler_pc: u16,
-    catch_type: u16,
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    pub catch_type: u16,
 }
 
 impl AttributeException {
-    pub fn parse(input: &[u8]) -> IResult<Self> {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
         map(
             tuple((be_u16, be_u16, be_u16, be_u16)),
             |(start_pc, end_pc, handler_pc, catch_type)| AttributeException {
@@ -296,39 +499,244 @@ impl AttributeException {
             },
         )(input)
     }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        put_u16(out, self.start_pc);
+        put_u16(out, self.end_pc);
+        put_u16(out, self.handler_pc);
+        put_u16(out, self.catch_type);
+    }
 }
 
 pub struct AttributeClass {
-    inner_class_info_index: u16,
-    outer_class_info_index: u16,
-    inner_name_index: u16,
-    inner_class_access_flags: u16,
+    pub inner_class_info_index: u16,
+    pub outer_class_info_index: u16,
+    pub inner_name_index: u16,
+    pub inner_class_access_flags: u16,
+}
+
+impl AttributeClass {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        map(
+            tuple((be_u16, be_u16, be_u16, be_u16)),
+            |(inner_class_info_index, outer_class_info_index, inner_name_index, inner_class_access_flags)| {
+                AttributeClass {
+                    inner_class_info_index,
+                    outer_class_info_index,
+                    inner_name_index,
+                    inner_class_access_flags,
+                }
+            },
+        )(input)
+    }
 }
 
 pub struct AttributeLineNumber {
-    start_pc: u16,
-    line_number: u16,
+    pub start_pc: u16,
+    pub line_number: u16,
+}
+
+impl AttributeLineNumber {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        map(pair(be_u16, be_u16), |(start_pc, line_number)| {
+            AttributeLineNumber {
+                start_pc,
+                line_number,
+            }
+        })(input)
+    }
 }
 
 pub struct AttributeLocalVariable {
-    start_pc: u16,
-    length: u16,
-    name_index: u16,
-    descriptor_index: u16,
-    index: u16,
+    pub start_pc: u16,
+    pub length: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub index: u16,
+}
+
+impl AttributeLocalVariable {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        map(
+            tuple((be_u16, be_u16, be_u16, be_u16, be_u16)),
+            |(start_pc, length, name_index, descriptor_index, index)| AttributeLocalVariable {
+                start_pc,
+                length,
+                name_index,
+                descriptor_index,
+                index,
+            },
+        )(input)
+    }
 }
 
 pub struct AttributeLocalVariableType {
-    start_pc: u16,
-    length: u16,
-    name_index: u16,
-    signature_index: u16,
-    index: u16,
+    pub start_pc: u16,
+    pub length: u16,
+    pub name_index: u16,
+    pub signature_index: u16,
+    pub index: u16,
+}
+
+impl AttributeLocalVariableType {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        map(
+            tuple((be_u16, be_u16, be_u16, be_u16, be_u16)),
+            |(start_pc, length, name_index, signature_index, index)| AttributeLocalVariableType {
+                start_pc,
+                length,
+                name_index,
+                signature_index,
+                index,
+            },
+        )(input)
+    }
 }
 
 pub struct AttributeBootstrapMethod {
-    bootstrap_method_ref: u16,
-    bootstrap_arguments: Vec<u16>,
+    pub bootstrap_method_ref: u16,
+    pub bootstrap_arguments: Vec<u16>,
+}
+
+impl AttributeBootstrapMethod {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        map(
+            pair(be_u16, length_count(be_u16, be_u16)),
+            |(bootstrap_method_ref, bootstrap_arguments)| AttributeBootstrapMethod {
+                bootstrap_method_ref,
+                bootstrap_arguments,
+            },
+        )(input)
+    }
+}
+
+// verification_type_info, the tag is the variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object { pool_pos: u16 },
+    // The offset of the new instruction which created the object.
+    Uninitialized { offset: u16 },
+}
+
+impl VerificationType {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        let (input, variant) = be_u8(input)?;
+        match variant {
+            0 => Ok((input, VerificationType::Top)),
+            1 => Ok((input, VerificationType::Integer)),
+            2 => Ok((input, VerificationType::Float)),
+            3 => Ok((input, VerificationType::Double)),
+            4 => Ok((input, VerificationType::Long)),
+            5 => Ok((input, VerificationType::Null)),
+            6 => Ok((input, VerificationType::UninitializedThis)),
+            7 => map(be_u16, |pool_pos| VerificationType::Object { pool_pos })(input),
+            8 => map(be_u16, |offset| VerificationType::Uninitialized { offset })(input),
+            _ => Err(nom::Err::Error(make_error(input, ErrorKind::Alt))),
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            VerificationType::Top => put_u8(out, 0),
+            VerificationType::Integer => put_u8(out, 1),
+            VerificationType::Float => put_u8(out, 2),
+            VerificationType::Double => put_u8(out, 3),
+            VerificationType::Long => put_u8(out, 4),
+            VerificationType::Null => put_u8(out, 5),
+            VerificationType::UninitializedThis => put_u8(out, 6),
+            VerificationType::Object { pool_pos } => {
+                put_u8(out, 7);
+                put_u16(out, *pool_pos);
+            }
+            VerificationType::Uninitialized { offset } => {
+                put_u8(out, 8);
+                put_u16(out, *offset);
+            }
+        }
+    }
+}
+
+// A single stack_map_frame. The frame type decides which of the other fields are stored:
+// 0-63 same, 64-127 same_locals_1_stack_item, 247 same_locals_1_stack_item_extended,
+// 248-250 chop, 251 same_frame_extended, 252-254 append and 255 full_frame.
+pub struct StackMapFrame {
+    pub frame_type: u8,
+    pub offset_delta: u16,
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+
+impl StackMapFrame {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        let (input, frame_type) = be_u8(input)?;
+        let (input, (offset_delta, locals, stack)) = match frame_type {
+            0..=63 => (input, (frame_type as u16, Vec::new(), Vec::new())),
+            64..=127 => map(VerificationType::parse, |stack| {
+                (frame_type as u16 - 64, Vec::new(), vec![stack])
+            })(input)?,
+            247 => map(pair(be_u16, VerificationType::parse), |(offset_delta, stack)| {
+                (offset_delta, Vec::new(), vec![stack])
+            })(input)?,
+            248..=251 => map(be_u16, |offset_delta| (offset_delta, Vec::new(), Vec::new()))(input)?,
+            252..=254 => map(
+                pair(be_u16, count(VerificationType::parse, frame_type as usize - 251)),
+                |(offset_delta, locals)| (offset_delta, locals, Vec::new()),
+            )(input)?,
+            255 => tuple((
+                be_u16,
+                length_count(be_u16, VerificationType::parse),
+                length_count(be_u16, VerificationType::parse),
+            ))(input)?,
+            // 128-246 are reserved
+            _ => return Err(nom::Err::Error(make_error(input, ErrorKind::Alt))),
+        };
+
+        Ok((
+            input,
+            StackMapFrame {
+                frame_type,
+                offset_delta,
+                locals,
+                stack,
+            },
+        ))
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        put_u8(out, self.frame_type);
+        match self.frame_type {
+            0..=63 => {}
+            64..=127 => self.stack[0].write(out),
+            247 => {
+                put_u16(out, self.offset_delta);
+                self.stack[0].write(out);
+            }
+            248..=254 => {
+                put_u16(out, self.offset_delta);
+                for local in &self.locals {
+                    local.write(out);
+                }
+            }
+            _ => {
+                put_u16(out, self.offset_delta);
+                put_u16(out, self.locals.len() as u16);
+                for local in &self.locals {
+                    local.write(out);
+                }
+                put_u16(out, self.stack.len() as u16);
+                for stack in &self.stack {
+                    stack.write(out);
+                }
+            }
+        }
+    }
 }
 
 pub enum AttributeInfo {
@@ -338,8 +746,9 @@ pub enum AttributeInfo {
     CodeAttribute {
         code: Code,
     },
-    // TODO do this
-    StackMapTable,
+    StackMapTable {
+        entries: Vec<StackMapFrame>,
+    },
     Exceptions {
         exception_index_table: Vec<u16>,
     },
@@ -383,45 +792,802 @@ pub enum AttributeInfo {
     BootstrapMethods {
         bootstrap_methods: Vec<AttributeBootstrapMethod>,
     },
+    // Attributes which are not parsed, kept as is so they can be written back.
+    Unknown {
+        info: Vec<u8>,
+    },
+}
+
+// attribute_info {
+//     u16 attribute_name_index;
+//     u32 attribute_length;
+//     u8  info[attribute_length];
+// }
+// The name index is kept as parsed, a pool may hold the same name more than once.
+pub struct Attribute {
+    pub name_index: u16,
+    pub info: AttributeInfo,
+}
+
+impl Attribute {
+    pub fn parse<'a, P: Utf8Pool>(input: &'a [u8], constant_pool: &P) -> IResult<'a, Self> {
+        let (input, (name_index, name)) = map_opt(be_u16, |index| {
+            constant_pool.utf8(index).ok().map(|name| (index, name))
+        })(input)?;
+        let (input, info) = length_data(be_u32)(input)?;
+        let (_, info) = AttributeInfo::parse(info, &name, constant_pool)?;
+        Ok((input, Attribute { name_index, info }))
+    }
+
+    pub fn write_all(
+        attributes: &[Attribute],
+        out: &mut Vec<u8>,
+        constant_pool: &ConstantPool,
+    ) -> Result<(), WriteError> {
+        put_u16(out, attributes.len() as u16);
+        for attribute in attributes {
+            attribute.write(out, constant_pool)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>, constant_pool: &ConstantPool) -> Result<(), WriteError> {
+        put_u16(out, self.name_index);
+
+        // The length is only known once the attribute is written.
+        let length_pos = out.len();
+        put_u32(out, 0);
+        self.info.write(out, constant_pool)?;
+
+        let length = (out.len() - length_pos - 4) as u32;
+        out[length_pos..length_pos + 4].copy_from_slice(&length.to_be_bytes());
+        Ok(())
+    }
 }
 
 impl AttributeInfo {
-    pub fn parse<'a>(input: &'a [u8], constant_pool: &ConstantPool) -> IResult<'a, Self> {
-        println!("Attribute");
-
-        let (input, info) = map_opt(be_u16, |index| constant_pool.get(index))(input)?;
-        let (input, length) = be_u32(input)?;
-
-        match info {
-            ConstantInfo::UTF8 { text } => match text.as_str() {
-                "ConstantValue" => map(be_u16, |constant_index| AttributeInfo::ConstantValue {
-                    constant_index,
-                })(input),
-                "Code" => map((|input| Code::parse(input, &constant_pool)), |code| {
-                    AttributeInfo::CodeAttribute { code }
-                })(input),
-                _ => map(take(length), |_| AttributeInfo::AnnotationDefault)(input),
-            },
-            //discard the remaining bytes
-            _ => map(take(length), |_| AttributeInfo::AnnotationDefault)(input),
+    // Every attribute is parsed from exactly its own bytes, so a bad length can not spill
+    // over into the next attribute.
+    pub fn parse<'a, P: Utf8Pool>(info: &'a [u8], name: &str, constant_pool: &P) -> IResult<'a, Self> {
+        all_consuming(|info| match name {
+            "ConstantValue" => map(be_u16, |constant_index| AttributeInfo::ConstantValue {
+                constant_index,
+            })(info),
+            "Code" => map(|info| Code::parse(info, constant_pool), |code| {
+                AttributeInfo::CodeAttribute { code }
+            })(info),
+            "StackMapTable" => map(length_count(be_u16, StackMapFrame::parse), |entries| {
+                AttributeInfo::StackMapTable { entries }
+            })(info),
+            "Exceptions" => map(length_count(be_u16, be_u16), |exception_index_table| {
+                AttributeInfo::Exceptions {
+                    exception_index_table,
+                }
+            })(info),
+            "InnerClasses" => map(length_count(be_u16, AttributeClass::parse), |classes| {
+                AttributeInfo::InnerClasses { classes }
+            })(info),
+            "EnclosingMethod" => map(pair(be_u16, be_u16), |(class_index, method_index)| {
+                AttributeInfo::EnclosingMethod {
+                    class_index,
+                    method_index,
+                }
+            })(info),
+            "Synthetic" => Ok((info, AttributeInfo::Synthetic)),
+            "Signature" => map(be_u16, |signature_index| AttributeInfo::Signature {
+                signature_index,
+            })(info),
+            "SourceFile" => map(be_u16, |source_file_index| AttributeInfo::SourceFile {
+                source_file_index,
+            })(info),
+            "SourceDebugExtension" => Ok((
+                &info[info.len()..],
+                AttributeInfo::SourceDebugExtension {
+                    debug_extension: info.to_vec(),
+                },
+            )),
+            "LineNumberTable" => map(
+                length_count(be_u16, AttributeLineNumber::parse),
+                |line_number_table| AttributeInfo::LineNumberTable { line_number_table },
+            )(info),
+            "LocalVariableTable" => map(
+                length_count(be_u16, AttributeLocalVariable::parse),
+                |local_variable_table| AttributeInfo::LocalVariableTable {
+                    local_variable_table,
+                },
+            )(info),
+            "LocalVariableTypeTable" => map(
+                length_count(be_u16, AttributeLocalVariableType::parse),
+                |local_variable_type_table| AttributeInfo::LocalVariableTypeTable {
+                    local_variable_type_table,
+                },
+            )(info),
+            "Deprecated" => Ok((info, AttributeInfo::Deprecated)),
+            "BootstrapMethods" => map(
+                length_count(be_u16, AttributeBootstrapMethod::parse),
+                |bootstrap_methods| AttributeInfo::BootstrapMethods { bootstrap_methods },
+            )(info),
+            _ => Ok((
+                &info[info.len()..],
+                AttributeInfo::Unknown {
+                    info: info.to_vec(),
+                },
+            )),
+        })(info)
+    }
+
+    // The name this attribute is stored under, None for unknown attributes which keep their own.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            AttributeInfo::ConstantValue { .. } => Some("ConstantValue"),
+            AttributeInfo::CodeAttribute { .. } => Some("Code"),
+            AttributeInfo::StackMapTable { .. } => Some("StackMapTable"),
+            AttributeInfo::Exceptions { .. } => Some("Exceptions"),
+            AttributeInfo::InnerClasses { .. } => Some("InnerClasses"),
+            AttributeInfo::EnclosingMethod { .. } => Some("EnclosingMethod"),
+            AttributeInfo::Synthetic => Some("Synthetic"),
+            AttributeInfo::Signature { .. } => Some("Signature"),
+            AttributeInfo::SourceFile { .. } => Some("SourceFile"),
+            AttributeInfo::SourceDebugExtension { .. } => Some("SourceDebugExtension"),
+            AttributeInfo::LineNumberTable { .. } => Some("LineNumberTable"),
+            AttributeInfo::LocalVariableTable { .. } => Some("LocalVariableTable"),
+            AttributeInfo::LocalVariableTypeTable { .. } => Some("LocalVariableTypeTable"),
+            AttributeInfo::Deprecated => Some("Deprecated"),
+            AttributeInfo::RuntimeInvisibleAnnotations => Some("RuntimeInvisibleAnnotations"),
+            AttributeInfo::RuntimeVisibleParameterAnnotations => {
+                Some("RuntimeVisibleParameterAnnotations")
+            }
+            AttributeInfo::RuntimeInvisibleParameterAnnotations => {
+                Some("RuntimeInvisibleParameterAnnotations")
+            }
+            AttributeInfo::AnnotationDefault => Some("AnnotationDefault"),
+            AttributeInfo::RuntimeVisibleAnnotations => Some("RuntimeVisibleAnnotations"),
+            AttributeInfo::BootstrapMethods { .. } => Some("BootstrapMethods"),
+            AttributeInfo::Unknown { .. } => None,
+        }
+    }
+
+    // Writes the info only, the name and the length are written by Attribute.
+    pub fn write(&self, out: &mut Vec<u8>, constant_pool: &ConstantPool) -> Result<(), WriteError> {
+        match self {
+            AttributeInfo::ConstantValue { constant_index } => put_u16(out, *constant_index),
+            AttributeInfo::CodeAttribute { code } => code.write(out, constant_pool)?,
+            AttributeInfo::Exceptions {
+                exception_index_table,
+            } => {
+                put_u16(out, exception_index_table.len() as u16);
+                for exception_index in exception_index_table {
+                    put_u16(out, *exception_index);
+                }
+            }
+            AttributeInfo::InnerClasses { classes } => {
+                put_u16(out, classes.len() as u16);
+                for class in classes {
+                    put_u16(out, class.inner_class_info_index);
+                    put_u16(out, class.outer_class_info_index);
+                    put_u16(out, class.inner_name_index);
+                    put_u16(out, class.inner_class_access_flags);
+                }
+            }
+            AttributeInfo::EnclosingMethod {
+                class_index,
+                method_index,
+            } => {
+                put_u16(out, *class_index);
+                put_u16(out, *method_index);
+            }
+            AttributeInfo::Synthetic | AttributeInfo::Deprecated => {}
+            AttributeInfo::Signature { signature_index } => put_u16(out, *signature_index),
+            AttributeInfo::SourceFile { source_file_index } => put_u16(out, *source_file_index),
+            AttributeInfo::SourceDebugExtension { debug_extension } => {
+                out.extend_from_slice(debug_extension)
+            }
+            AttributeInfo::LineNumberTable { line_number_table } => {
+                put_u16(out, line_number_table.len() as u16);
+                for line in line_number_table {
+                    put_u16(out, line.start_pc);
+                    put_u16(out, line.line_number);
+                }
+            }
+            AttributeInfo::LocalVariableTable {
+                local_variable_table,
+            } => {
+                put_u16(out, local_variable_table.len() as u16);
+                for local in local_variable_table {
+                    put_u16(out, local.start_pc);
+                    put_u16(out, local.length);
+                    put_u16(out, local.name_index);
+                    put_u16(out, local.descriptor_index);
+                    put_u16(out, local.index);
+                }
+            }
+            AttributeInfo::LocalVariableTypeTable {
+                local_variable_type_table,
+            } => {
+                put_u16(out, local_variable_type_table.len() as u16);
+                for local in local_variable_type_table {
+                    put_u16(out, local.start_pc);
+                    put_u16(out, local.length);
+                    put_u16(out, local.name_index);
+                    put_u16(out, local.signature_index);
+                    put_u16(out, local.index);
+                }
+            }
+            AttributeInfo::BootstrapMethods { bootstrap_methods } => {
+                put_u16(out, bootstrap_methods.len() as u16);
+                for method in bootstrap_methods {
+                    put_u16(out, method.bootstrap_method_ref);
+                    put_u16(out, method.bootstrap_arguments.len() as u16);
+                    for argument in &method.bootstrap_arguments {
+                        put_u16(out, *argument);
+                    }
+                }
+            }
+            AttributeInfo::StackMapTable { entries } => {
+                put_u16(out, entries.len() as u16);
+                for entry in entries {
+                    entry.write(out);
+                }
+            }
+            AttributeInfo::Unknown { info, .. } => out.extend_from_slice(info),
+            AttributeInfo::RuntimeInvisibleAnnotations
+            | AttributeInfo::RuntimeVisibleParameterAnnotations
+            | AttributeInfo::RuntimeInvisibleParameterAnnotations
+            | AttributeInfo::AnnotationDefault
+            | AttributeInfo::RuntimeVisibleAnnotations => {
+                return Err(WriteError::UnsupportedAttribute {
+                    name: self.name().unwrap(),
+                })
+            }
         }
+        Ok(())
     }
 }
 
+#[derive(Default)]
 pub struct ConstantPool(Vec<ConstantInfo>);
 
+// Attribute names are all that is needed to parse attributes, which lets them be parsed
+// against either a ConstantPool or a borrowed PoolView.
+pub trait Utf8Pool {
+    fn utf8(&self, index: u16) -> Result<Cow<'_, str>, PoolError>;
+}
+
+impl Utf8Pool for ConstantPool {
+    fn utf8(&self, index: u16) -> Result<Cow<'_, str>, PoolError> {
+        ConstantPool::utf8(self, index).map(Cow::Borrowed)
+    }
+}
+
+// Class files hold text as modified UTF-8, where NUL takes two bytes and a character outside the
+// BMP is written as its two surrogates, of three bytes each. Text which has neither is the same
+// in UTF-8 and is borrowed. A surrogate without its pair becomes U+FFFD.
+pub fn decode_mutf8(data: &[u8]) -> Option<Cow<'_, str>> {
+    // a raw NUL and four byte sequences are valid UTF-8 but not modified UTF-8
+    if !data.iter().any(|byte| *byte == 0 || *byte >= 0xf0) {
+        if let Ok(text) = std::str::from_utf8(data) {
+            return Some(Cow::Borrowed(text));
+        }
+    }
+    let mut units: Vec<u16> = Vec::with_capacity(data.len());
+    let mut rest = data;
+    while let [first, tail @ ..] = rest {
+        let (unit, length) = match (*first, tail) {
+            (0x01..=0x7f, _) => (*first as u16, 1),
+            (0xc0..=0xdf, [second, ..]) if second & 0xc0 == 0x80 => {
+                (((*first as u16) & 0x1f) << 6 | (*second as u16) & 0x3f, 2)
+            }
+            (0xe0..=0xef, [second, third, ..]) if second & 0xc0 == 0x80 && third & 0xc0 == 0x80 => (
+                ((*first as u16) & 0x0f) << 12 | ((*second as u16) & 0x3f) << 6 | (*third as u16) & 0x3f,
+                3,
+            ),
+            _ => return None,
+        };
+        units.push(unit);
+        rest = &rest[length..];
+    }
+    Some(Cow::Owned(String::from_utf16_lossy(&units)))
+}
+
+pub fn put_mutf8(out: &mut Vec<u8>, text: &str) {
+    for unit in text.encode_utf16() {
+        match unit {
+            0x01..=0x7f => put_u8(out, unit as u8),
+            0x00 | 0x80..=0x7ff => {
+                put_u8(out, 0xc0 | (unit >> 6) as u8);
+                put_u8(out, 0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                put_u8(out, 0xe0 | (unit >> 12) as u8);
+                put_u8(out, 0x80 | (unit >> 6 & 0x3f) as u8);
+                put_u8(out, 0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    InvalidIndex {
+        index: u16,
+    },
+    KindMismatch {
+        index: u16,
+        expected: &'static str,
+        found: &'static str,
+    },
+    InvalidReferenceKind {
+        index: u16,
+        reference_kind: u8,
+    },
+    InvalidUtf8 {
+        index: u16,
+    },
+    // An entry which is none of the kinds a pool can hold.
+    InvalidTag {
+        index: u16,
+        tag: u8,
+    },
+}
+
+// A resolved Fieldref, Methodref or InterfaceMethodref.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemberRef<'a> {
+    pub owner: &'a str,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Literal {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodHandleRef<'a> {
+    pub kind: ReferenceKind,
+    pub member: MemberRef<'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvokeDynamicRef<'a> {
+    pub bootstrap_method_attr_index: u16,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
 impl ConstantPool {
+    pub fn parse(input: &[u8]) -> IResult<'_, Self> {
+        let (mut input, count) = be_u16(input)?;
+
+        // Long and Double take up two slots, so the count can not be used with length_count.
+        let mut entries = Vec::with_capacity(count as usize);
+        while entries.len() + 1 < count as usize {
+            let (input2, info) = ConstantInfo::parse(input)?;
+            let wide = matches!(info, ConstantInfo::Long { .. } | ConstantInfo::Double { .. });
+            entries.push(info);
+            if wide {
+                entries.push(ConstantInfo::Unusable);
+            }
+            input = input2;
+        }
+
+        Ok((input, ConstantPool(entries)))
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        put_u16(out, self.0.len() as u16 + 1);
+        for info in &self.0 {
+            info.write(out);
+        }
+    }
+
+    // The amount of slots, which is one less than the constant_pool_count in the class file.
+    pub fn len(&self) -> u16 {
+        self.0.len() as u16
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn get(&self, index: u16) -> Option<&ConstantInfo> {
-        assert!(index >= 1);
+        self.0.get((index as usize).checked_sub(1)?)
+    }
+
+    // Returns the index of an equal entry, only adding the entry if there is none.
+    pub fn add(&mut self, info: ConstantInfo) -> u16 {
+        if let Some(pos) = self.0.iter().position(|other| *other == info) {
+            return pos as u16 + 1;
+        }
+
+        let wide = matches!(info, ConstantInfo::Long { .. } | ConstantInfo::Double { .. });
+        self.0.push(info);
+        let index = self.0.len() as u16;
+        if wide {
+            self.0.push(ConstantInfo::Unusable);
+        }
+        index
+    }
+
+    pub fn add_utf8(&mut self, text: &str) -> u16 {
+        self.add(ConstantInfo::UTF8 {
+            text: text.to_string(),
+            data: None,
+        })
+    }
+
+    pub fn add_class(&mut self, name: &str) -> u16 {
+        let name_index = self.add_utf8(name);
+        self.add(ConstantInfo::Class { name_index })
+    }
 
-        self.0.get(index as usize - 1)
+    pub fn add_string(&mut self, text: &str) -> u16 {
+        let string_index = self.add_utf8(text);
+        self.add(ConstantInfo::String { string_index })
     }
 
-    pub fn get_method_descriptor(&self, index: u16) -> Result<ConstantInfo::MethodDescriptor, &'static str> {
-        let option = self.get(index).unwrap();
-        match option {
-            ConstantInfo::UTF8 { text } => Ok()
-            _ => Err("ConstantInfo not UTF-8"),
+    pub fn add_name_and_type(&mut self, name: &str, descriptor: &str) -> u16 {
+        let name_index = self.add_utf8(name);
+        let descriptor_index = self.add_utf8(descriptor);
+        self.add(ConstantInfo::NameAndType {
+            name_index,
+            descriptor_index,
+        })
+    }
+
+    pub fn add_field_ref(&mut self, member: MemberRef) -> u16 {
+        let class_index = self.add_class(member.owner);
+        let name_and_type_index = self.add_name_and_type(member.name, member.descriptor);
+        self.add(ConstantInfo::Field {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn add_method_ref(&mut self, member: MemberRef) -> u16 {
+        let class_index = self.add_class(member.owner);
+        let name_and_type_index = self.add_name_and_type(member.name, member.descriptor);
+        self.add(ConstantInfo::Method {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn add_interface_method_ref(&mut self, member: MemberRef) -> u16 {
+        let class_index = self.add_class(member.owner);
+        let name_and_type_index = self.add_name_and_type(member.name, member.descriptor);
+        self.add(ConstantInfo::Interface {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    fn entry(&self, index: u16) -> Result<&ConstantInfo, PoolError> {
+        self.get(index).ok_or(PoolError::InvalidIndex { index })
+    }
+
+    fn mismatch(index: u16, expected: &'static str, found: &ConstantInfo) -> PoolError {
+        PoolError::KindMismatch {
+            index,
+            expected,
+            found: found.kind_name(),
+        }
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<&str, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::UTF8 { text, .. } => Ok(text),
+            info => Err(Self::mismatch(index, "Utf8", info)),
+        }
+    }
+
+    pub fn class_name(&self, index: u16) -> Result<&str, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::Class { name_index } => self.utf8(*name_index),
+            info => Err(Self::mismatch(index, "Class", info)),
+        }
+    }
+
+    // Returns the name and descriptor.
+    pub fn name_and_type(&self, index: u16) -> Result<(&str, &str), PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Ok((self.utf8(*name_index)?, self.utf8(*descriptor_index)?)),
+            info => Err(Self::mismatch(index, "NameAndType", info)),
+        }
+    }
+
+    pub fn member_ref(&self, index: u16) -> Result<MemberRef<'_>, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::Field {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantInfo::Method {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantInfo::Interface {
+                class_index,
+                name_and_type_index,
+            } => {
+                let owner = self.class_name(*class_index)?;
+                let (name, descriptor) = self.name_and_type(*name_and_type_index)?;
+                Ok(MemberRef {
+                    owner,
+                    name,
+                    descriptor,
+                })
+            }
+            info => Err(Self::mismatch(index, "Fieldref, Methodref or InterfaceMethodref", info)),
+        }
+    }
+
+    pub fn string(&self, index: u16) -> Result<&str, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::String { string_index } => self.utf8(*string_index),
+            info => Err(Self::mismatch(index, "String", info)),
+        }
+    }
+
+    pub fn literal(&self, index: u16) -> Result<Literal, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::Integer { bytes } => Ok(Literal::Integer(*bytes as i32)),
+            ConstantInfo::Float { bytes } => Ok(Literal::Float(f32::from_bits(*bytes))),
+            ConstantInfo::Long { bytes } => Ok(Literal::Long(*bytes as i64)),
+            ConstantInfo::Double { bytes } => Ok(Literal::Double(f64::from_bits(*bytes))),
+            info => Err(Self::mismatch(index, "Integer, Float, Long or Double", info)),
+        }
+    }
+
+    pub fn method_handle(&self, index: u16) -> Result<MethodHandleRef<'_>, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::MethodHandle {
+                reference_kind,
+                reference_index,
+            } => {
+                let kind = ReferenceKind::from_u8(*reference_kind).ok_or(
+                    PoolError::InvalidReferenceKind {
+                        index,
+                        reference_kind: *reference_kind,
+                    },
+                )?;
+                Ok(MethodHandleRef {
+                    kind,
+                    member: self.member_ref(*reference_index)?,
+                })
+            }
+            info => Err(Self::mismatch(index, "MethodHandle", info)),
+        }
+    }
+
+    pub fn method_type(&self, index: u16) -> Result<&str, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::MethodType { descriptor_index } => self.utf8(*descriptor_index),
+            info => Err(Self::mismatch(index, "MethodType", info)),
+        }
+    }
+
+    pub fn invoke_dynamic(&self, index: u16) -> Result<InvokeDynamicRef<'_>, PoolError> {
+        match self.entry(index)? {
+            ConstantInfo::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                let (name, descriptor) = self.name_and_type(*name_and_type_index)?;
+                Ok(InvokeDynamicRef {
+                    bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                    name,
+                    descriptor,
+                })
+            }
+            info => Err(Self::mismatch(index, "InvokeDynamic", info)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    // Every class file under a directory, for the corpus checks.
+    pub fn class_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                class_files(&path, files);
+            } else if path.extension().is_some_and(|extension| extension == "class") {
+                files.push(path);
+            }
+        }
+    }
+
+    // The checked in classes, plus every class under REFLOW_CORPUS when it is set,
+    // e.g. an extracted java.base.
+    pub fn corpus() -> Vec<PathBuf> {
+        let mut files = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("run/Test.class")];
+        if let Some(dir) = std::env::var_os("REFLOW_CORPUS") {
+            class_files(Path::new(&dir), &mut files);
+        }
+        files
+    }
+
+    // java/lang/String.length()I and the constants around it, with the slot after the Long and
+    // after the Double left unusable.
+    fn pool() -> ConstantPool {
+        let mut data = vec![0, 15];
+        data.extend_from_slice(b"\x01\x00\x10java/lang/String");
+        data.extend_from_slice(b"\x07\x00\x01");
+        data.extend_from_slice(b"\x01\x00\x06length");
+        data.extend_from_slice(b"\x01\x00\x03()I");
+        data.extend_from_slice(b"\x0c\x00\x03\x00\x04");
+        data.extend_from_slice(b"\x0a\x00\x02\x00\x05");
+        data.extend_from_slice(b"\x05\xfe\xdc\xba\x98\x76\x54\x32\x10");
+        data.extend_from_slice(b"\x06\x7f\xf8\x00\x00\x00\x00\x00\x01");
+        data.extend_from_slice(b"\x0f\x05\x00\x06");
+        data.extend_from_slice(b"\x0f\x0a\x00\x06");
+        data.extend_from_slice(b"\x03\xff\xff\xff\xff");
+        data.extend_from_slice(b"\x04\x7f\xc0\x00\x01");
+        let (rest, pool) = ConstantPool::parse(&data).unwrap();
+        assert!(rest.is_empty());
+        pool
+    }
+
+    #[test]
+    fn class_name() {
+        let pool = pool();
+        assert_eq!(pool.class_name(2), Ok("java/lang/String"));
+        assert_eq!(
+            pool.class_name(1),
+            Err(PoolError::KindMismatch {
+                index: 1,
+                expected: "Class",
+                found: "Utf8",
+            })
+        );
+        assert_eq!(pool.class_name(0), Err(PoolError::InvalidIndex { index: 0 }));
+        assert_eq!(pool.class_name(15), Err(PoolError::InvalidIndex { index: 15 }));
+    }
+
+    #[test]
+    fn member_ref() {
+        let pool = pool();
+        assert_eq!(
+            pool.member_ref(6),
+            Ok(MemberRef {
+                owner: "java/lang/String",
+                name: "length",
+                descriptor: "()I",
+            })
+        );
+        assert_eq!(
+            pool.member_ref(5),
+            Err(PoolError::KindMismatch {
+                index: 5,
+                expected: "Fieldref, Methodref or InterfaceMethodref",
+                found: "NameAndType",
+            })
+        );
+    }
+
+    #[test]
+    fn literal() {
+        let pool = pool();
+        assert_eq!(pool.literal(7), Ok(Literal::Long(0xfedc_ba98_7654_3210_u64 as i64)));
+        // NaN payloads survive, so the bits are compared instead of the values
+        match pool.literal(9) {
+            Ok(Literal::Double(value)) => assert_eq!(value.to_bits(), 0x7ff8_0000_0000_0001),
+            literal => panic!("{:?}", literal),
+        }
+        match pool.literal(14) {
+            Ok(Literal::Float(value)) => assert_eq!(value.to_bits(), 0x7fc0_0001),
+            literal => panic!("{:?}", literal),
+        }
+        assert_eq!(pool.literal(13), Ok(Literal::Integer(-1)));
+        for index in [8, 10] {
+            assert_eq!(
+                pool.literal(index),
+                Err(PoolError::KindMismatch {
+                    index,
+                    expected: "Integer, Float, Long or Double",
+                    found: "Unusable",
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn method_handle() {
+        let pool = pool();
+        assert_eq!(
+            pool.method_handle(11),
+            Ok(MethodHandleRef {
+                kind: ReferenceKind::InvokeVirtual,
+                member: pool.member_ref(6).unwrap(),
+            })
+        );
+        assert_eq!(
+            pool.method_handle(12),
+            Err(PoolError::InvalidReferenceKind {
+                index: 12,
+                reference_kind: 10,
+            })
+        );
+        assert_eq!(
+            pool.method_handle(6),
+            Err(PoolError::KindMismatch {
+                index: 6,
+                expected: "MethodHandle",
+                found: "Methodref",
+            })
+        );
+    }
+
+    #[test]
+    fn mutf8() {
+        // NUL and the surrogates of U+1F600 each take their own sequence, a surrogate without
+        // its pair is replaced
+        for (data, text) in [
+            (&b"reflow"[..], "reflow"),
+            (&[b'a', 0xc0, 0x80, b'b'][..], "a\0b"),
+            (&[0xc3, 0xa9, 0xe2, 0x82, 0xac][..], "\u{e9}\u{20ac}"),
+            (&[0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80][..], "\u{1f600}"),
+            (&[0xed, 0xa0, 0x80, b'a'][..], "\u{fffd}a"),
+        ] {
+            assert_eq!(decode_mutf8(data).as_deref(), Some(text), "{:?}", data);
+            if !text.contains('\u{fffd}') {
+                let mut out = Vec::new();
+                put_mutf8(&mut out, text);
+                assert_eq!(out, data, "{:?}", text);
+            }
+        }
+        assert!(matches!(decode_mutf8("\u{e9}t\u{e9}".as_bytes()), Some(Cow::Borrowed(_))));
+
+        // a raw NUL, a four byte sequence and a cut off sequence
+        for data in [&[b'a', 0][..], &[0xf0, 0x9f, 0x98, 0x80], &[0xe2, 0x82]] {
+            assert_eq!(decode_mutf8(data), None, "{:?}", data);
+        }
+    }
+
+    // An empty class A whose pool also holds "\uD800", a surrogate without its pair, which
+    // cannot be kept in a String.
+    fn lone_surrogate() -> Vec<u8> {
+        let mut data = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x06".to_vec();
+        data.extend_from_slice(b"\x01\x00\x01A\x07\x00\x01");
+        data.extend_from_slice(b"\x01\x00\x10java/lang/Object\x07\x00\x03");
+        data.extend_from_slice(b"\x01\x00\x03\xed\xa0\x80");
+        data.extend_from_slice(b"\x00\x21\x00\x02\x00\x04\x00\x00\x00\x00\x00\x00\x00\x00");
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let (_, class_info) = ClassInfo::parse(&lone_surrogate()).unwrap();
+        assert_eq!(class_info.constant_pool.utf8(5), Ok("\u{fffd}"));
+
+        let mut differ = Vec::new();
+        let classes = corpus().into_iter().map(|path| {
+            let buffer = std::fs::read(&path).unwrap();
+            (path, buffer)
+        });
+        for (path, buffer) in std::iter::once((PathBuf::from("lone surrogate"), lone_surrogate())).chain(classes) {
+            let Ok((_, class_info)) = ClassInfo::parse(&buffer) else {
+                differ.push((path, None));
+                continue;
+            };
+            match class_info.write() {
+                Ok(out) if out == buffer => {}
+                result => differ.push((path, result.err())),
+            }
         }
+        assert!(differ.is_empty(), "{:?}", differ);
     }
 }
@@ -0,0 +1,1018 @@
+use std::collections::HashMap;
+
+use crate::consts::*;
+use crate::java::{
+    Attribute, AttributeInfo, ClassInfo, ConstantInfo, ConstantPool, FieldInfo, MemberRef, MethodInfo,
+    ParseError, put_u16, put_u32, put_u8,
+};
+use crate::java_code::Code;
+
+// Assembles a class from a Jasmin-like listing, mainly so decompiler fixtures can be written by hand.
+//
+// .version 49 0
+// .source Hello.java
+// .class public super Hello
+// .super java/lang/Object
+// .implements java/lang/Runnable
+//
+// .field private static count I = 5
+//
+// .method public static main([Ljava/lang/String;)V
+//     .limit stack 2
+//     .limit locals 1
+// loop:
+//     getstatic java/lang/System/out Ljava/io/PrintStream;
+//     ldc "Hello"
+//     invokevirtual java/io/PrintStream/println(Ljava/lang/String;)V
+//     goto loop
+// .end method
+//
+// Mnemonics are the ones from consts::print_op in any case. Jumps take a label, switches take
+// their cases on one line (`tableswitch 0 L1 L2 default L3`, `lookupswitch 1:L1 5:L2 default L3`)
+// and `.catch java/lang/Exception from L1 to L2 using L3` adds a handler, `all` catches anything.
+// Loads, stores and iinc are widened automatically. A `;` at the start of a token begins a comment.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+pub fn assemble(text: &str) -> Result<ClassInfo, AsmError> {
+    let mut assembler = Assembler {
+        constant_pool: ConstantPool::default(),
+        minor_version: 0,
+        major_version: 49,
+        access_flags: ClassAccessFlags::empty(),
+        this_class: 0,
+        super_class: 0,
+        interfaces: Vec::new(),
+        fields: Vec::new(),
+        methods: Vec::new(),
+        attributes: Vec::new(),
+        method: None,
+    };
+
+    for (line_pos, text) in text.lines().enumerate() {
+        let line = line_pos + 1;
+        let tokens = tokenize(text).map_err(|message| AsmError { line, message })?;
+        assembler.line(line, tokens)?;
+    }
+
+    let line = text.lines().count();
+    if assembler.method.is_some() {
+        return Err(AsmError {
+            line,
+            message: "missing .end method".to_string(),
+        });
+    }
+    if assembler.this_class == 0 {
+        return Err(AsmError {
+            line,
+            message: "missing .class".to_string(),
+        });
+    }
+    if assembler.super_class == 0 {
+        assembler.super_class = assembler.constant_pool.add_class("java/lang/Object");
+    }
+
+    Ok(ClassInfo {
+        minor_version: assembler.minor_version,
+        major_version: assembler.major_version,
+        constant_pool: assembler.constant_pool,
+        access_flags: assembler.access_flags,
+        this_class: assembler.this_class,
+        super_class: assembler.super_class,
+        interfaces: assembler.interfaces,
+        fields: assembler.fields,
+        methods: assembler.methods,
+        attributes: assembler.attributes,
+    })
+}
+
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+impl Token {
+    fn word(&self) -> Result<&str, String> {
+        match self {
+            Token::Word(word) => Ok(word),
+            Token::Str(text) => Err(format!("expected a word, found \"{}\"", text)),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        match chars.peek() {
+            None | Some(';') => return Ok(tokens),
+            Some('"') => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err("unterminated string".to_string()),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some('r') => text.push('\r'),
+                            Some('"') => text.push('"'),
+                            Some('\\') => text.push('\\'),
+                            Some('u') => {
+                                let hex: String = chars.by_ref().take(4).collect();
+                                let c = u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(char::from_u32)
+                                    .ok_or(format!("invalid escape \\u{}", hex))?;
+                                text.push(c);
+                            }
+                            other => return Err(format!("invalid escape \\{:?}", other)),
+                        },
+                        Some(c) => text.push(c),
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+}
+
+const CLASS_FLAGS: &[(&str, u16)] = &[
+    ("public", ClassAccessFlags::PUBLIC.bits()),
+    ("final", ClassAccessFlags::FINAL.bits()),
+    ("super", ClassAccessFlags::SUPER.bits()),
+    ("interface", ClassAccessFlags::INTERFACE.bits()),
+    ("abstract", ClassAccessFlags::ABSTRACT.bits()),
+    ("synthetic", ClassAccessFlags::SYNTHETIC.bits()),
+    ("annotation", ClassAccessFlags::ANNOTATION.bits()),
+    ("enum", ClassAccessFlags::ENUM.bits()),
+];
+
+const FIELD_FLAGS: &[(&str, u16)] = &[
+    ("public", FieldAccessFlags::PUBLIC.bits()),
+    ("private", FieldAccessFlags::PRIVATE.bits()),
+    ("protected", FieldAccessFlags::PROTECTED.bits()),
+    ("static", FieldAccessFlags::STATIC.bits()),
+    ("final", FieldAccessFlags::FINAL.bits()),
+    ("volatile", FieldAccessFlags::VOLATILE.bits()),
+    ("transient", FieldAccessFlags::TRANSIENT.bits()),
+    ("synthetic", FieldAccessFlags::SYNTHETIC.bits()),
+    ("enum", FieldAccessFlags::ENUM.bits()),
+];
+
+const METHOD_FLAGS: &[(&str, u16)] = &[
+    ("public", MethodAccessFlags::PUBLIC.bits()),
+    ("private", MethodAccessFlags::PRIVATE.bits()),
+    ("protected", MethodAccessFlags::PROTECTED.bits()),
+    ("static", MethodAccessFlags::STATIC.bits()),
+    ("final", MethodAccessFlags::FINAL.bits()),
+    ("synchronized", MethodAccessFlags::SYNCHRONIZED.bits()),
+    ("bridge", MethodAccessFlags::BRIDGE.bits()),
+    ("varargs", MethodAccessFlags::VARARGS.bits()),
+    ("native", MethodAccessFlags::NATIVE.bits()),
+    ("abstract", MethodAccessFlags::ABSTRACT.bits()),
+    ("strict", MethodAccessFlags::STRICT.bits()),
+    ("synthetic", MethodAccessFlags::SYNTHETIC.bits()),
+];
+
+// Reads the leading flags, returning them with the tokens after them.
+fn parse_flags<'a>(tokens: &'a [Token], table: &[(&str, u16)]) -> (u16, &'a [Token]) {
+    let mut flags = 0;
+    let mut pos = 0;
+    while let Some(Token::Word(word)) = tokens.get(pos) {
+        match table.iter().find(|(name, _)| name == word) {
+            Some((_, bits)) => flags |= bits,
+            None => break,
+        }
+        pos += 1;
+    }
+    (flags, &tokens[pos..])
+}
+
+fn parse_number<T: std::str::FromStr>(token: &Token) -> Result<T, String> {
+    let word = token.word()?;
+    word.parse().map_err(|_| format!("invalid number {}", word))
+}
+
+fn is_floating(word: &str) -> bool {
+    word.contains(['.', 'e', 'E']) || word.ends_with("NaN") || word.ends_with("Infinity")
+}
+
+// Splits owner/name(descriptor) or owner/name into its owner and the rest.
+fn split_owner(word: &str) -> Result<(&str, &str), String> {
+    let end = word.find('(').unwrap_or(word.len());
+    let slash = word[..end]
+        .rfind('/')
+        .ok_or(format!("expected owner/name, found {}", word))?;
+    Ok((&word[..slash], &word[slash + 1..]))
+}
+
+// Splits name(descriptor)return into the name and the method descriptor.
+fn split_descriptor(word: &str) -> Result<(&str, &str), String> {
+    let start = word
+        .find('(')
+        .ok_or(format!("expected a method descriptor, found {}", word))?;
+    Ok((&word[..start], &word[start..]))
+}
+
+// The amount of argument slots a method descriptor takes, long and double take two.
+fn argument_slots(descriptor: &str) -> Result<u8, String> {
+    let mut slots = 0;
+    let mut chars = descriptor.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            ')' => return Ok(slots),
+            'J' | 'D' => slots += 2,
+            'L' => {
+                chars.by_ref().find(|c| *c == ';');
+                slots += 1;
+            }
+            '[' => {
+                let mut c = chars.next();
+                while c == Some('[') {
+                    c = chars.next();
+                }
+                if c == Some('L') {
+                    chars.by_ref().find(|c| *c == ';');
+                }
+                slots += 1;
+            }
+            _ => slots += 1,
+        }
+    }
+    Err(format!("invalid method descriptor {}", descriptor))
+}
+
+struct PendingOp {
+    line: usize,
+    op: u8,
+    operands: Vec<Token>,
+}
+
+struct PendingCatch {
+    line: usize,
+    catch_type: u16,
+    from: String,
+    to: String,
+    using: String,
+}
+
+struct MethodBuilder {
+    access_flags: MethodAccessFlags,
+    name_index: u16,
+    descriptor_index: u16,
+    max_stack: Option<u16>,
+    max_locals: Option<u16>,
+    ops: Vec<PendingOp>,
+    // label to the op it is in front of
+    labels: HashMap<String, usize>,
+    catches: Vec<PendingCatch>,
+    exceptions: Vec<u16>,
+}
+
+struct Assembler {
+    constant_pool: ConstantPool,
+    minor_version: u16,
+    major_version: u16,
+    access_flags: ClassAccessFlags,
+    this_class: u16,
+    super_class: u16,
+    interfaces: Vec<u16>,
+    fields: Vec<FieldInfo>,
+    methods: Vec<MethodInfo>,
+    attributes: Vec<Attribute>,
+    method: Option<MethodBuilder>,
+}
+
+impl Assembler {
+    fn line(&mut self, line: usize, tokens: Vec<Token>) -> Result<(), AsmError> {
+        let error = |message: String| AsmError { line, message };
+        let mut tokens = tokens.as_slice();
+
+        // labels
+        if let Some(Token::Word(word)) = tokens.first() {
+            if let Some(label) = word.strip_suffix(':') {
+                let method = self
+                    .method
+                    .as_mut()
+                    .ok_or(error("label outside of a method".to_string()))?;
+                let op_pos = method.ops.len();
+                if method.labels.insert(label.to_string(), op_pos).is_some() {
+                    return Err(error(format!("duplicate label {}", label)));
+                }
+                tokens = &tokens[1..];
+            }
+        }
+
+        let (first, rest) = match tokens.split_first() {
+            Some((first, rest)) => (first.word().map_err(error)?, rest),
+            None => return Ok(()),
+        };
+        if first == ".end" {
+            match rest {
+                [Token::Word(word)] if word == "method" => {
+                    let method = self
+                        .method
+                        .take()
+                        .ok_or(error(".end method outside of a method".to_string()))?;
+                    self.end_method(line, method)
+                }
+                _ => Err(error("expected .end method".to_string())),
+            }
+        } else if first.starts_with('.') {
+            self.directive(line, first, rest).map_err(error)
+        } else {
+            let op = parse_op(first).ok_or(error(format!("unknown instruction {}", first)))?;
+            let method = self
+                .method
+                .as_mut()
+                .ok_or(error("instruction outside of a method".to_string()))?;
+            method.ops.push(PendingOp {
+                line,
+                op,
+                operands: rest.iter().map(|token| match token {
+                    Token::Word(word) => Token::Word(word.clone()),
+                    Token::Str(text) => Token::Str(text.clone()),
+                }).collect(),
+            });
+            Ok(())
+        }
+    }
+
+    fn directive(&mut self, line: usize, directive: &str, tokens: &[Token]) -> Result<(), String> {
+        let words = tokens
+            .iter()
+            .map(|token| token.word())
+            .collect::<Result<Vec<&str>, String>>();
+
+        match directive {
+            ".version" => match words?.as_slice() {
+                [major, minor] => {
+                    self.major_version = major.parse().map_err(|_| "invalid version")?;
+                    self.minor_version = minor.parse().map_err(|_| "invalid version")?;
+                }
+                _ => return Err("expected .version <major> <minor>".to_string()),
+            },
+            ".source" => match tokens {
+                [token] => {
+                    let name = match token {
+                        Token::Word(word) => word,
+                        Token::Str(text) => text,
+                    };
+                    let name_index = self.constant_pool.add_utf8("SourceFile");
+                    let source_file_index = self.constant_pool.add_utf8(name);
+                    self.attributes.push(Attribute {
+                        name_index,
+                        info: AttributeInfo::SourceFile { source_file_index },
+                    });
+                }
+                _ => return Err("expected .source <file>".to_string()),
+            },
+            ".class" => {
+                let (flags, rest) = parse_flags(tokens, CLASS_FLAGS);
+                match rest {
+                    [name] => {
+                        self.access_flags = ClassAccessFlags::from_bits_truncate(flags);
+                        self.this_class = self.constant_pool.add_class(name.word()?);
+                    }
+                    _ => return Err("expected .class <flags> <name>".to_string()),
+                }
+            }
+            ".super" => match words?.as_slice() {
+                [name] => self.super_class = self.constant_pool.add_class(name),
+                _ => return Err("expected .super <name>".to_string()),
+            },
+            ".implements" => match words?.as_slice() {
+                [name] => {
+                    let interface = self.constant_pool.add_class(name);
+                    self.interfaces.push(interface);
+                }
+                _ => return Err("expected .implements <name>".to_string()),
+            },
+            ".field" => self.field(tokens)?,
+            ".method" => {
+                if self.method.is_some() {
+                    return Err("missing .end method".to_string());
+                }
+                let (flags, rest) = parse_flags(tokens, METHOD_FLAGS);
+                let (name, descriptor) = match rest {
+                    [name] => split_descriptor(name.word()?)?,
+                    _ => return Err("expected .method <flags> <name><descriptor>".to_string()),
+                };
+                self.method = Some(MethodBuilder {
+                    access_flags: MethodAccessFlags::from_bits_truncate(flags),
+                    name_index: self.constant_pool.add_utf8(name),
+                    descriptor_index: self.constant_pool.add_utf8(descriptor),
+                    max_stack: None,
+                    max_locals: None,
+                    ops: Vec::new(),
+                    labels: HashMap::new(),
+                    catches: Vec::new(),
+                    exceptions: Vec::new(),
+                });
+            }
+            ".limit" => {
+                let method = self.method.as_mut().ok_or(".limit outside of a method")?;
+                match words?.as_slice() {
+                    ["stack", value] => {
+                        method.max_stack = Some(value.parse().map_err(|_| "invalid .limit")?)
+                    }
+                    ["locals", value] => {
+                        method.max_locals = Some(value.parse().map_err(|_| "invalid .limit")?)
+                    }
+                    _ => return Err("expected .limit stack|locals <value>".to_string()),
+                }
+            }
+            ".throws" => match words?.as_slice() {
+                [name] => {
+                    let exception = self.constant_pool.add_class(name);
+                    let method = self.method.as_mut().ok_or(".throws outside of a method")?;
+                    method.exceptions.push(exception);
+                }
+                _ => return Err("expected .throws <name>".to_string()),
+            },
+            ".catch" => match words?.as_slice() {
+                [catch_type, "from", from, "to", to, "using", using] => {
+                    let catch_type = match *catch_type {
+                        "all" => 0,
+                        name => self.constant_pool.add_class(name),
+                    };
+                    let method = self.method.as_mut().ok_or(".catch outside of a method")?;
+                    method.catches.push(PendingCatch {
+                        line,
+                        catch_type,
+                        from: from.to_string(),
+                        to: to.to_string(),
+                        using: using.to_string(),
+                    });
+                }
+                _ => return Err("expected .catch <type> from <label> to <label> using <label>".to_string()),
+            },
+            _ => return Err(format!("unknown directive {}", directive)),
+        }
+        Ok(())
+    }
+
+    fn field(&mut self, tokens: &[Token]) -> Result<(), String> {
+        let (flags, rest) = parse_flags(tokens, FIELD_FLAGS);
+        let (name, descriptor, value) = match rest {
+            [name, descriptor] => (name.word()?, descriptor.word()?, None),
+            [name, descriptor, Token::Word(equals), value] if equals == "=" => {
+                (name.word()?, descriptor.word()?, Some(value))
+            }
+            _ => return Err("expected .field <flags> <name> <descriptor> [= <value>]".to_string()),
+        };
+
+        let mut attribute_info = Vec::new();
+        if let Some(value) = value {
+            let info = match (descriptor, value) {
+                ("Ljava/lang/String;", Token::Str(text)) => {
+                    let string_index = self.constant_pool.add_utf8(text);
+                    ConstantInfo::String { string_index }
+                }
+                ("I" | "S" | "C" | "B" | "Z", value) => ConstantInfo::Integer {
+                    bytes: parse_number::<i32>(value)? as u32,
+                },
+                ("J", value) => ConstantInfo::Long {
+                    bytes: parse_number::<i64>(value)? as u64,
+                },
+                ("F", value) => ConstantInfo::Float {
+                    bytes: parse_number::<f32>(value)?.to_bits(),
+                },
+                ("D", value) => ConstantInfo::Double {
+                    bytes: parse_number::<f64>(value)?.to_bits(),
+                },
+                _ => return Err(format!("a {} field can not have a constant value", descriptor)),
+            };
+            let name_index = self.constant_pool.add_utf8("ConstantValue");
+            let constant_index = self.constant_pool.add(info);
+            attribute_info.push(Attribute {
+                name_index,
+                info: AttributeInfo::ConstantValue { constant_index },
+            });
+        }
+
+        self.fields.push(FieldInfo {
+            access_flags: FieldAccessFlags::from_bits_truncate(flags),
+            name_index: self.constant_pool.add_utf8(name),
+            descriptor_index: self.constant_pool.add_utf8(descriptor),
+            attribute_info,
+        });
+        Ok(())
+    }
+
+    fn end_method(&mut self, line: usize, method: MethodBuilder) -> Result<(), AsmError> {
+        let error = |line: usize| move |message: String| AsmError { line, message };
+        let mut attribute_info = Vec::new();
+
+        if !method.ops.is_empty() {
+            // Ops only change size with their position (switch padding), so the first pass with
+            // every jump offset at 0 is enough to know where every op and label ends up.
+            let mut op_bytes: Vec<u32> = Vec::with_capacity(method.ops.len() + 1);
+            let mut op_byte = 0;
+            for op in &method.ops {
+                op_bytes.push(op_byte);
+                let encoded = self
+                    .encode(op, op_byte, &method.labels, None)
+                    .map_err(error(op.line))?;
+                op_byte += encoded.len() as u32;
+            }
+            op_bytes.push(op_byte);
+
+            let mut bytes = Vec::new();
+            let max_stack = method.max_stack.ok_or("missing .limit stack".to_string());
+            let max_locals = method.max_locals.ok_or("missing .limit locals".to_string());
+            put_u16(&mut bytes, max_stack.map_err(error(line))?);
+            put_u16(&mut bytes, max_locals.map_err(error(line))?);
+            put_u32(&mut bytes, op_byte);
+            for (op_pos, op) in method.ops.iter().enumerate() {
+                let encoded = self
+                    .encode(op, op_bytes[op_pos], &method.labels, Some(&op_bytes))
+                    .map_err(error(op.line))?;
+                bytes.extend_from_slice(&encoded);
+            }
+
+            put_u16(&mut bytes, method.catches.len() as u16);
+            for catch in &method.catches {
+                let label_byte = |label: &String| {
+                    method
+                        .labels
+                        .get(label)
+                        .map(|op_pos| op_bytes[*op_pos] as u16)
+                        .ok_or(format!("unknown label {}", label))
+                        .map_err(error(catch.line))
+                };
+                put_u16(&mut bytes, label_byte(&catch.from)?);
+                put_u16(&mut bytes, label_byte(&catch.to)?);
+                put_u16(&mut bytes, label_byte(&catch.using)?);
+                put_u16(&mut bytes, catch.catch_type);
+            }
+            // no attributes
+            put_u16(&mut bytes, 0);
+
+            // The code goes through the same decoder as a class file would.
+            let (_, code) = Code::parse(&bytes, &self.constant_pool).map_err(|nom_error| {
+                // the input is left out, it is the rest of the method's bytes
+                let nom_error = nom_error.map(|nom_error| match nom_error {
+                    ParseError::Nom { kind, .. } => format!("{:?}", kind),
+                    ParseError::Jump(jump_error) => format!("{:?}", jump_error),
+                });
+                error(line)(format!("could not decode the method: {:?}", nom_error))
+            })?;
+            attribute_info.push(Attribute {
+                name_index: self.constant_pool.add_utf8("Code"),
+                info: AttributeInfo::CodeAttribute { code },
+            });
+        }
+
+        if !method.exceptions.is_empty() {
+            attribute_info.push(Attribute {
+                name_index: self.constant_pool.add_utf8("Exceptions"),
+                info: AttributeInfo::Exceptions {
+                    exception_index_table: method.exceptions,
+                },
+            });
+        }
+
+        self.methods.push(MethodInfo {
+            access_flags: method.access_flags,
+            name_index: method.name_index,
+            descriptor_index: method.descriptor_index,
+            attribute_info,
+        });
+        Ok(())
+    }
+
+    // Encodes an op at op_byte. Without op_bytes every jump offset is 0.
+    fn encode(
+        &mut self,
+        op: &PendingOp,
+        op_byte: u32,
+        labels: &HashMap<String, usize>,
+        op_bytes: Option<&[u32]>,
+    ) -> Result<Vec<u8>, String> {
+        let operands = op.operands.as_slice();
+        let expect = |count: usize| {
+            if operands.len() == count {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} takes {} operands, found {}",
+                    print_op(&op.op),
+                    count,
+                    operands.len()
+                ))
+            }
+        };
+        let jump_offset = |label: &str| -> Result<i32, String> {
+            let op_pos = labels.get(label).ok_or(format!("unknown label {}", label))?;
+            Ok(op_bytes.map_or(0, |op_bytes| op_bytes[*op_pos] as i32 - op_byte as i32))
+        };
+
+        let mut out = Vec::new();
+        put_u8(&mut out, op.op);
+        match op.op {
+            BIPUSH => {
+                expect(1)?;
+                put_u8(&mut out, parse_number::<i8>(&operands[0])? as u8);
+            }
+            SIPUSH => {
+                expect(1)?;
+                put_u16(&mut out, parse_number::<i16>(&operands[0])? as u16);
+            }
+            LDC | LDC_W | LDC2_W => {
+                expect(1)?;
+                let info = match &operands[0] {
+                    Token::Str(_) if op.op == LDC2_W => {
+                        return Err("ldc2_w only takes a long or a double".to_string())
+                    }
+                    Token::Str(text) => ConstantInfo::String {
+                        string_index: self.constant_pool.add_utf8(text),
+                    },
+                    Token::Word(word) if op.op == LDC2_W && is_floating(word) => ConstantInfo::Double {
+                        bytes: parse_number::<f64>(&operands[0])?.to_bits(),
+                    },
+                    Token::Word(_) if op.op == LDC2_W => ConstantInfo::Long {
+                        bytes: parse_number::<i64>(&operands[0])? as u64,
+                    },
+                    Token::Word(word) if is_floating(word) => ConstantInfo::Float {
+                        bytes: parse_number::<f32>(&operands[0])?.to_bits(),
+                    },
+                    Token::Word(_) => ConstantInfo::Integer {
+                        bytes: parse_number::<i32>(&operands[0])? as u32,
+                    },
+                };
+                let index = self.constant_pool.add(info);
+                if op.op == LDC {
+                    let index = u8::try_from(index).map_err(|_| "constant index too big for ldc, use ldc_w")?;
+                    put_u8(&mut out, index);
+                } else {
+                    put_u16(&mut out, index);
+                }
+            }
+            ILOAD | LLOAD | FLOAD | DLOAD | ALOAD | ISTORE | LSTORE | FSTORE | DSTORE | ASTORE | RET => {
+                expect(1)?;
+                let var = parse_number::<u16>(&operands[0])?;
+                match u8::try_from(var) {
+                    Ok(var) => put_u8(&mut out, var),
+                    Err(_) => {
+                        out = vec![WIDE, op.op];
+                        put_u16(&mut out, var);
+                    }
+                }
+            }
+            IINC => {
+                expect(2)?;
+                let var = parse_number::<u16>(&operands[0])?;
+                let amount = parse_number::<i16>(&operands[1])?;
+                match (u8::try_from(var), i8::try_from(amount)) {
+                    (Ok(var), Ok(amount)) => {
+                        put_u8(&mut out, var);
+                        put_u8(&mut out, amount as u8);
+                    }
+                    _ => {
+                        out = vec![WIDE, op.op];
+                        put_u16(&mut out, var);
+                        put_u16(&mut out, amount as u16);
+                    }
+                }
+            }
+            IFEQ | IFNE | IFLT | IFGE | IFGT | IFLE | IF_ICMPEQ | IF_ICMPNE | IF_ICMPLT
+            | IF_ICMPGE | IF_ICMPGT | IF_ICMPLE | IF_ACMPEQ | IF_ACMPNE | GOTO | JSR | IFNULL
+            | IFNONNULL => {
+                expect(1)?;
+                let offset = jump_offset(operands[0].word()?)?;
+                let offset = i16::try_from(offset).map_err(|_| "jump too far, use goto_w")?;
+                put_u16(&mut out, offset as u16);
+            }
+            GOTO_W | JSR_W => {
+                expect(1)?;
+                put_u32(&mut out, jump_offset(operands[0].word()?)? as u32);
+            }
+            TABLESWITCH | LOOKUPSWITCH => {
+                // padding to a multiple of 4 from the start of the code
+                while !(op_byte + out.len() as u32).is_multiple_of(4) {
+                    put_u8(&mut out, 0);
+                }
+
+                let (cases, default) = match operands {
+                    [cases @ .., Token::Word(default_word), default] if default_word == "default" => {
+                        (cases, jump_offset(default.word()?)?)
+                    }
+                    _ => return Err(format!("{} needs a default label", print_op(&op.op))),
+                };
+                put_u32(&mut out, default as u32);
+
+                if op.op == TABLESWITCH {
+                    let (low, labels) = cases.split_first().ok_or("expected tableswitch <low> <labels>")?;
+                    let low = parse_number::<i32>(low)?;
+                    if labels.is_empty() {
+                        return Err("tableswitch needs at least one label".to_string());
+                    }
+                    // the keys run from low to high, which has to stay within an int
+                    let high = i32::try_from(labels.len() - 1)
+                        .ok()
+                        .and_then(|last| low.checked_add(last))
+                        .ok_or(format!("tableswitch from {} has keys past {}", low, i32::MAX))?;
+                    put_u32(&mut out, low as u32);
+                    put_u32(&mut out, high as u32);
+                    for label in labels {
+                        put_u32(&mut out, jump_offset(label.word()?)? as u32);
+                    }
+                } else {
+                    let mut pairs = Vec::with_capacity(cases.len());
+                    for case in cases {
+                        let case = case.word()?;
+                        let (key, label) = case
+                            .split_once(':')
+                            .ok_or(format!("expected <key>:<label>, found {}", case))?;
+                        let key: i32 = key.parse().map_err(|_| format!("invalid number {}", key))?;
+                        pairs.push((key, jump_offset(label)?));
+                    }
+                    // lookupswitch keys have to be sorted
+                    pairs.sort_by_key(|(key, _)| *key);
+                    put_u32(&mut out, pairs.len() as u32);
+                    for (key, offset) in pairs {
+                        put_u32(&mut out, key as u32);
+                        put_u32(&mut out, offset as u32);
+                    }
+                }
+            }
+            GETSTATIC | PUTSTATIC | GETFIELD | PUTFIELD => {
+                expect(2)?;
+                let (owner, name) = split_owner(operands[0].word()?)?;
+                let descriptor = operands[1].word()?;
+                let index = self.constant_pool.add_field_ref(MemberRef {
+                    owner,
+                    name,
+                    descriptor,
+                });
+                put_u16(&mut out, index);
+            }
+            INVOKEVIRTUAL | INVOKESPECIAL | INVOKESTATIC => {
+                expect(1)?;
+                let (owner, rest) = split_owner(operands[0].word()?)?;
+                let (name, descriptor) = split_descriptor(rest)?;
+                let index = self.constant_pool.add_method_ref(MemberRef {
+                    owner,
+                    name,
+                    descriptor,
+                });
+                put_u16(&mut out, index);
+            }
+            INVOKEINTERFACE => {
+                let (owner, rest) = split_owner(operands.first().ok_or("expected a method")?.word()?)?;
+                let (name, descriptor) = split_descriptor(rest)?;
+                // the count includes the object the method is invoked on
+                let count = match operands {
+                    [_] => argument_slots(descriptor)? + 1,
+                    [_, count] => parse_number::<u8>(count)?,
+                    _ => return Err("expected invokeinterface <method> [count]".to_string()),
+                };
+                let index = self.constant_pool.add_interface_method_ref(MemberRef {
+                    owner,
+                    name,
+                    descriptor,
+                });
+                put_u16(&mut out, index);
+                put_u8(&mut out, count);
+                put_u8(&mut out, 0);
+            }
+            INVOKEDYNAMIC => return Err("invokedynamic is not supported".to_string()),
+            NEW | ANEWARRAY | CHECKCAST | INSTANCEOF => {
+                expect(1)?;
+                let index = self.constant_pool.add_class(operands[0].word()?);
+                put_u16(&mut out, index);
+            }
+            NEWARRAY => {
+                expect(1)?;
+                let name = operands[0].word()?;
+                let array_type = (T_BOOLEAN..=T_LONG)
+                    .find(|array_type| print_array_type(array_type) == name)
+                    .ok_or(format!("unknown array type {}", name))?;
+                put_u8(&mut out, array_type);
+            }
+            MULTIANEWARRAY => {
+                expect(2)?;
+                let index = self.constant_pool.add_class(operands[0].word()?);
+                put_u16(&mut out, index);
+                put_u8(&mut out, parse_number::<u8>(&operands[1])?);
+            }
+            WIDE => return Err("wide is added automatically".to_string()),
+            _ => expect(0)?,
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java::AttributeException;
+    use crate::java::AttributeInfo::CodeAttribute;
+    use crate::java_code::Instruction;
+
+    // Assembles a listing and parses the written bytes back, the way any other class is read.
+    fn round_trip(text: &str) -> ClassInfo {
+        let bytes = assemble(text).unwrap().write().unwrap();
+        let (_, class_info) = ClassInfo::parse(&bytes).unwrap();
+        assert_eq!(class_info.write().unwrap(), bytes);
+        class_info
+    }
+
+    fn method_code<'a>(class_info: &'a ClassInfo, name: &str) -> &'a Code {
+        let method = class_info
+            .methods
+            .iter()
+            .find(|method| class_info.constant_pool.utf8(method.name_index) == Ok(name))
+            .unwrap();
+        method
+            .attribute_info
+            .iter()
+            .find_map(|attribute| match &attribute.info {
+                CodeAttribute { code } => Some(code),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn limits() {
+        let class_info = round_trip(
+            ".class public A
+            .super java/lang/Object
+            .method public static f()V
+                .limit stack 3
+                .limit locals 5
+                return
+            .end method",
+        );
+        let code = method_code(&class_info, "f");
+        assert_eq!((code.max_stack, code.max_locals), (3, 5));
+    }
+
+    #[test]
+    fn labels() {
+        let class_info = round_trip(
+            ".class public A
+            .super java/lang/Object
+            .method public static f(I)I
+                .limit stack 1
+                .limit locals 2
+                iload_0
+                istore_1
+            top:
+                iload_1
+                ifeq done
+                iinc 1 -1
+                goto top
+            done:
+                iload_0
+                ireturn
+            .end method",
+        );
+        let code = method_code(&class_info, "f");
+        let targets: Vec<_> = code
+            .code
+            .iter()
+            .filter_map(|op| match &op.inst {
+                Instruction::ZeroComparisonJump { jump } | Instruction::Jump { jump } => {
+                    Some(jump.get_pos())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(targets, [6, 2]);
+    }
+
+    #[test]
+    fn catches() {
+        let class_info = round_trip(
+            ".class public A
+            .super java/lang/Object
+            .method public static f()V
+                .limit stack 1
+                .limit locals 1
+            start:
+                invokestatic A/g()V
+            end:
+                return
+            handler:
+                astore_0
+                return
+            .catch java/lang/Exception from start to end using handler
+            .catch all from start to end using handler
+            .end method",
+        );
+        let code = method_code(&class_info, "f");
+        let handlers: Vec<_> = code
+            .exception_table
+            .iter()
+            .map(|AttributeException { start_pc, end_pc, handler_pc, catch_type }| {
+                (*start_pc, *end_pc, *handler_pc, *catch_type)
+            })
+            .collect();
+        assert_eq!(handlers.len(), 2);
+        assert_eq!((handlers[0].0, handlers[0].1, handlers[0].2), (0, 3, 4));
+        assert_eq!(
+            class_info.constant_pool.class_name(handlers[0].3),
+            Ok("java/lang/Exception")
+        );
+        assert_eq!(handlers[1], (0, 3, 4, 0));
+    }
+
+    // Both switches, the first with two bytes of padding and the second with none.
+    #[test]
+    fn switches() {
+        let class_info = round_trip(
+            ".class public A
+            .super java/lang/Object
+            .method public static f(I)I
+                .limit stack 1
+                .limit locals 1
+                iload_0
+                tableswitch 3 one two default other
+            one:
+                iconst_1
+                ireturn
+            two:
+                iconst_2
+                ireturn
+            other:
+                iconst_0
+                ireturn
+            .end method
+            .method public static g(I)I
+                .limit stack 1
+                .limit locals 1
+                iconst_0
+                pop
+                iload_0
+                lookupswitch 100:big -5:small default other
+            small:
+                iconst_1
+                ireturn
+            big:
+                iconst_2
+                ireturn
+            other:
+                iconst_0
+                ireturn
+            .end method",
+        );
+        let switch = |name| {
+            let code = method_code(&class_info, name);
+            code.code
+                .iter()
+                .find_map(|op| match &op.inst {
+                    Instruction::SwitchJump { default, cases } => Some((
+                        default.get_pos(),
+                        cases.iter().map(|(key, jump)| (*key, jump.get_pos())).collect::<Vec<_>>(),
+                    )),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        assert_eq!(switch("f"), (6, vec![(3, 2), (4, 4)]));
+        assert_eq!(switch("g"), (8, vec![(-5, 4), (100, 6)]));
+    }
+
+    #[test]
+    fn switch_errors() {
+        for (cases, message) in [
+            ("2147483647 a b", "tableswitch from 2147483647 has keys past 2147483647"),
+            ("0", "tableswitch needs at least one label"),
+        ] {
+            let Err(error) = assemble(&format!(
+                ".class public A
+                .super java/lang/Object
+                .method public static f(I)V
+                    .limit stack 1
+                    iload_0
+                    tableswitch {} default a
+                a:
+                b:
+                    return
+                .end method",
+                cases
+            )) else {
+                panic!("{} assembled", cases);
+            };
+            assert_eq!((error.line, error.message.as_str()), (6, message));
+        }
+    }
+}
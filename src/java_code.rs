@@ -1,40 +1,234 @@
 use std::collections::HashSet;
-use std::mem;
 
+use nom::bytes::complete::take;
 use nom::combinator::map;
 use nom::error::{ErrorKind, make_error};
-use nom::multi::length_count;
+use nom::multi::{count, length_count};
 use nom::number::complete::be_i32;
 use nom::number::streaming::{be_i16, be_i8, be_u16, be_u32, be_u8};
-use nom::sequence::pair;
+use nom::sequence::{pair, tuple};
 
 use consts::*;
 
-use crate::{AttributeInfo, consts, java_code};
-use crate::java::{AttributeException, ConstantPool, IResult};
+use crate::consts;
+use crate::java::{
+    Attribute, AttributeException, ConstantPool, IResult, ParseError, put_u16, put_u32, put_u8, Utf8Pool, WriteError,
+};
 
+#[derive(Clone)]
 pub struct Op {
     pub op: u8,
+    // The opcode as it is stored in the class file, as op does not keep shortcuts like ILOAD_0.
+    pub raw_op: u8,
     pub inst: Instruction,
+    // Where the op starts in the code and how many bytes it takes up there.
+    pub pc: u32,
+    pub byte_length: u32,
 }
 
 impl Op {
-    pub fn parse(input: &[u8]) -> IResult<(Self, u8)> {
-        let (input, op) = be_u8(input)?;
-        let (input, (inst, length, op)) = Instruction::parse(input, op)?;
-        Ok((input, (Op { op, inst }, length + 1))) // instructionType length and op
+    // The op's byte position is needed for the padding of switches, jump turns the offset of a
+    // jump into the op it lands on.
+    pub fn parse<'a>(
+        input: &'a [u8],
+        op_byte: u32,
+        jump: &dyn Fn(i32) -> Result<JumpValue, JumpError>,
+    ) -> IResult<'a, (Self, u32)> {
+        let (input, raw_op) = be_u8(input)?;
+        let (input, (inst, length, op)) = Instruction::parse(input, raw_op, op_byte, jump)?;
+        let byte_length = length + 1; // instructionType length and op
+        let op = Op {
+            op,
+            raw_op,
+            inst,
+            pc: op_byte,
+            byte_length,
+        };
+        Ok((input, (op, byte_length)))
+    }
+
+    // The amount of bytes the op takes up when written at op_byte.
+    pub fn length(&self, op_byte: u32) -> u32 {
+        1 + match &self.inst {
+            Instruction::Nop
+            | Instruction::Value
+            | Instruction::GetArrayLength
+            | Instruction::Stack
+            | Instruction::Math
+            | Instruction::Conventions
+            | Instruction::Return
+            | Instruction::Throw
+            | Instruction::ArrayLoad
+            | Instruction::ArrayStore
+            | Instruction::Comparison
+            | Instruction::Monitor => 0,
+            Instruction::ConstantPool { .. }
+            | Instruction::PushByte { .. }
+            | Instruction::NewPrimitiveArray { .. } => 1,
+            Instruction::Load { .. }
+            | Instruction::Store { .. }
+            | Instruction::SubroutineReturn { .. } => {
+                if self.raw_op == WIDE {
+                    3
+                } else if self.raw_op == self.op {
+                    1
+                } else {
+                    0
+                }
+            }
+            Instruction::Increment { .. } if self.raw_op == WIDE => 5,
+            Instruction::Jump { .. } if self.op == GOTO_W => 4,
+            Instruction::Subroutine { .. } if self.op == JSR_W => 4,
+            Instruction::InvokeMethod {
+                kind: InvokeKind::Interface { .. } | InvokeKind::Dynamic,
+                ..
+            } => 4,
+            Instruction::MultiNewArray { .. } => 3,
+            Instruction::SwitchJump { cases, .. } => {
+                let pairs = if self.op == TABLESWITCH {
+                    8 + 4 * cases.len() as u32
+                } else {
+                    4 + 8 * cases.len() as u32
+                };
+                switch_padding(op_byte) + 4 + pairs
+            }
+            _ => 2,
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>, op_pos: u32, op_bytes: &[u32]) -> Result<(), WriteError> {
+        let op_byte = op_bytes[op_pos as usize];
+        let jump_offset = |jump: &JumpValue| {
+            op_bytes
+                .get(jump.get_pos() as usize)
+                .map(|jump_byte| *jump_byte as i64 - op_byte as i64)
+                .ok_or(WriteError::JumpOutOfRange { op_pos })
+        };
+        let short_jump_offset = |jump: &JumpValue| {
+            jump_offset(jump).and_then(|offset| {
+                i16::try_from(offset).map_err(|_| WriteError::JumpOutOfRange { op_pos })
+            })
+        };
+
+        put_u8(out, self.raw_op);
+        if self.raw_op == WIDE {
+            put_u8(out, self.op);
+        }
+        match &self.inst {
+            Instruction::Nop
+            | Instruction::Value
+            | Instruction::GetArrayLength
+            | Instruction::Stack
+            | Instruction::Math
+            | Instruction::Conventions
+            | Instruction::Return
+            | Instruction::Throw
+            | Instruction::ArrayLoad
+            | Instruction::ArrayStore
+            | Instruction::Comparison
+            | Instruction::Monitor => {}
+            Instruction::Cast { pool_pos }
+            | Instruction::Instanceof { pool_pos }
+            | Instruction::New { pool_pos }
+            | Instruction::GetField { pool_pos }
+            | Instruction::GetStaticField { pool_pos }
+            | Instruction::PutField { pool_pos }
+            | Instruction::PutStaticField { pool_pos } => put_u16(out, *pool_pos),
+            Instruction::InvokeMethod { pool_pos, kind } => {
+                put_u16(out, *pool_pos);
+                match kind {
+                    InvokeKind::Interface { count } => {
+                        put_u8(out, *count);
+                        put_u8(out, 0);
+                    }
+                    InvokeKind::Dynamic => put_u16(out, 0),
+                    _ => {}
+                }
+            }
+            Instruction::MultiNewArray {
+                pool_pos,
+                dimensions,
+            } => {
+                put_u16(out, *pool_pos);
+                put_u8(out, *dimensions);
+            }
+            Instruction::ConstantPool { pool } => put_u8(out, *pool),
+            Instruction::ConstantPoolWide { pool } => put_u16(out, *pool),
+            Instruction::PushByte { value } => put_u8(out, *value as u8),
+            Instruction::PushShort { value } => put_u16(out, *value as u16),
+            Instruction::Increment { var, amount } => {
+                if self.raw_op == WIDE {
+                    put_u16(out, *var);
+                    put_u16(out, *amount as u16);
+                } else {
+                    put_u8(out, *var as u8);
+                    put_u8(out, *amount as u8);
+                }
+            }
+            Instruction::Load { var }
+            | Instruction::Store { var }
+            | Instruction::SubroutineReturn { var } => {
+                // shortcuts have the var in the opcode
+                if self.raw_op == WIDE {
+                    put_u16(out, *var);
+                } else if self.raw_op == self.op {
+                    put_u8(out, *var as u8);
+                }
+            }
+            Instruction::ComparisonJump { jump } | Instruction::ZeroComparisonJump { jump } => {
+                put_u16(out, short_jump_offset(jump)? as u16)
+            }
+            Instruction::Jump { jump } | Instruction::Subroutine { jump } => {
+                if self.op == GOTO_W || self.op == JSR_W {
+                    let offset = i32::try_from(jump_offset(jump)?)
+                        .map_err(|_| WriteError::JumpOutOfRange { op_pos })?;
+                    put_u32(out, offset as u32);
+                } else {
+                    put_u16(out, short_jump_offset(jump)? as u16);
+                }
+            }
+            Instruction::SwitchJump { default, cases } => {
+                for _ in 0..switch_padding(op_byte) {
+                    put_u8(out, 0);
+                }
+                let jump_offset = |jump: &JumpValue| {
+                    jump_offset(jump).and_then(|offset| {
+                        i32::try_from(offset).map_err(|_| WriteError::JumpOutOfRange { op_pos })
+                    })
+                };
+                put_u32(out, jump_offset(default)? as u32);
+                if self.op == TABLESWITCH {
+                    let low = cases.first().map_or(0, |(key, _)| *key);
+                    put_u32(out, low as u32);
+                    put_u32(out, (low + cases.len() as i32 - 1) as u32);
+                    for (_, jump) in cases {
+                        put_u32(out, jump_offset(jump)? as u32);
+                    }
+                } else {
+                    put_u32(out, cases.len() as u32);
+                    for (key, jump) in cases {
+                        put_u32(out, *key as u32);
+                        put_u32(out, jump_offset(jump)? as u32);
+                    }
+                }
+            }
+            Instruction::NewPrimitiveArray { array_type } => put_u8(out, *array_type),
+        }
+        Ok(())
     }
 
     pub fn print(&self) -> String {
         match &self.inst {
             Instruction::ComparisonJump { jump } => { format!("{}: {}", consts::print_op(&self.op), jump.get_pos()) }
-            Instruction::SwitchJump { jumps } => { format!("{}: ", consts::print_op(&self.op)) }
+            Instruction::SwitchJump { .. } => { format!("{}: ", consts::print_op(&self.op)) }
             Instruction::Jump { jump } => { format!("{}: {}", consts::print_op(&self.op), jump.get_pos()) }
+            Instruction::Subroutine { jump } => { format!("{}: {}", consts::print_op(&self.op), jump.get_pos()) }
             _ => { format!("{}: ", consts::print_op(&self.op)) }
         }
     }
 }
 
+#[derive(Clone)]
 pub enum Instruction {
     // nop
     Nop,
@@ -66,7 +260,7 @@ pub enum Instruction {
     // ireturn, lreturn, freturn, dreturn, areturn, return
     Return,
     // athrow
-    Throw { pool_pos: u16 },
+    Throw,
     // ldc
     ConstantPool { pool: u8 },
     // ldc_w, ldc2_w
@@ -76,7 +270,7 @@ pub enum Instruction {
     // sipush
     PushShort { value: i16 },
     // iinc
-    Increment { var: u16, amount: u8 },
+    Increment { var: u16, amount: i16 },
     // iload, lload, fload, dload, aload,
     // iload_0, iload_1, iload_2, iload_3,
     // lload_0, lload_1, lload_2, lload_3,
@@ -103,16 +297,25 @@ pub enum Instruction {
     Instanceof { pool_pos: u16 },
     // if_icmpeq, if_icmpne, if_icmplt, if_icmpge, if_icmpgt, if_icmple, if_acmpeq, if_acmpne
     ComparisonJump { jump: JumpValue },
-    // ifeq, ifne, iflt, ifge, ifgt, ifle,
+    // ifeq, ifne, iflt, ifge, ifgt, ifle, ifnull, ifnonnull
     ZeroComparisonJump { jump: JumpValue },
     // tableswitch lookupswitch
-    SwitchJump { jumps: Vec<JumpValue> },
-    // goto, jsr, goto_w, jsr_w
+    // The cases are the keys with their jumps, a tableswitch has one for every key from low to high.
+    SwitchJump { default: JumpValue, cases: Vec<(i32, JumpValue)> },
+    // goto, goto_w
     Jump { jump: JumpValue },
+    // jsr, jsr_w
+    // Pushes the address of the next op and jumps to the subroutine, which comes back with ret.
+    Subroutine { jump: JumpValue },
+    // ret
+    // The var holds the return address stored by the subroutine.
+    SubroutineReturn { var: u16 },
     // new, anewarray
     New { pool_pos: u16 },
     // newarray
     NewPrimitiveArray { array_type: u8 },
+    // multianewarray
+    MultiNewArray { pool_pos: u16, dimensions: u8 },
     // getfield
     GetField { pool_pos: u16 },
     // getstatic
@@ -122,54 +325,85 @@ pub enum Instruction {
     // putstatic
     PutStaticField { pool_pos: u16 },
     // invokevirtual, invokespecial, invokestatic, invokeinterface, invokedynamic
-    InvokeMethod { pool_pos: u16 },
+    InvokeMethod { pool_pos: u16, kind: InvokeKind },
     // monitorenter, monitorexit
     Monitor,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvokeKind {
+    Virtual,
+    Special,
+    Static,
+    // The count is the amount of argument slots including the object, which is stored with the op.
+    Interface { count: u8 },
+    Dynamic,
+}
+
+// The op a jump lands on. Jumps are stored as byte offsets, they are resolved while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct JumpValue {
-    union: JumpUnion,
+    op_pos: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpError {
+    // The target lies outside of the code.
+    OutOfCode { op_byte: u32, offset: i32 },
+    // The target is in the middle of an op.
+    NotOnOp { op_byte: u32, offset: i32 },
 }
 
 impl JumpValue {
-    pub fn new(offset: i32) -> JumpValue {
-        JumpValue {
-            union: JumpUnion {
-                jump_offset: offset,
-            },
-        }
+    pub fn from_pos(op_pos: u32) -> JumpValue {
+        JumpValue { op_pos }
+    }
+
+    // The jump of the op at op_byte, op_bytes holds the byte position of every op in order.
+    pub fn resolve(op_byte: u32, offset: i32, op_bytes: &[u32]) -> Result<JumpValue, JumpError> {
+        let target = u32::try_from(op_byte as i64 + offset as i64)
+            .map_err(|_| JumpError::OutOfCode { op_byte, offset })?;
+        let op_pos = op_bytes.binary_search(&target).map_err(|pos| {
+            if pos < op_bytes.len() {
+                JumpError::NotOnOp { op_byte, offset }
+            } else {
+                JumpError::OutOfCode { op_byte, offset }
+            }
+        })?;
+        Ok(JumpValue::from_pos(op_pos as u32))
     }
 
     pub fn get_pos(&self) -> u32 {
-        unsafe {
-            self.union.jump_pos
-        }
+        self.op_pos
     }
-}
 
-pub union JumpUnion {
-    jump_pos: u32,
-    jump_offset: i32,
+    pub fn set_pos(&mut self, op_pos: u32) {
+        self.op_pos = op_pos;
+    }
 }
 
-impl JumpUnion {
-    pub fn apply(&mut self, op_byte: u32, op_byte_to_op: &Vec<u32>) {
-        unsafe {
-            self.jump_pos = *op_byte_to_op.get(((op_byte as i64) + (self.jump_offset as i64)) as usize).unwrap();
-        }
-    }
+// Switches are padded so their operands start at a multiple of 4 from the start of the code.
+fn switch_padding(op_byte: u32) -> u32 {
+    (4 - (op_byte + 1) % 4) % 4
 }
 
 impl Instruction {
-    pub fn parse(input: &[u8], op: u8) -> IResult<(Self, u8, u8)> {
+    // Returns the instruction, the amount of bytes after the opcode and the canonical opcode.
+    pub fn parse<'a>(
+        input: &'a [u8],
+        op: u8,
+        op_byte: u32,
+        jump: &dyn Fn(i32) -> Result<JumpValue, JumpError>,
+    ) -> IResult<'a, (Self, u32, u8)> {
+        let jump = |offset: i32| jump(offset).map_err(|error| nom::Err::Error(ParseError::Jump(error)));
         match op {
             // nop
-            NOP => Ok((input, (Instruction::Value, 0, op))),
+            NOP => Ok((input, (Instruction::Nop, 0, op))),
             // Constant
             ACONST_NULL | ICONST_M1 | ICONST_0 | ICONST_1 | ICONST_2 | ICONST_3
             | ICONST_4 | ICONST_5 | LCONST_0 | LCONST_1 | FCONST_0 | FCONST_1 | FCONST_2
             | DCONST_0 | DCONST_1 => Ok((input, (Instruction::Value, 0, op))),
-            ARRAYLENGTH => Ok((input, (Instruction::Value, 0, op))),
+            ARRAYLENGTH => Ok((input, (Instruction::GetArrayLength, 0, op))),
             // Stack
             POP | POP2 | DUP | DUP_X1 | DUP_X2 | DUP2 | DUP2_X1 | DUP2_X2 | SWAP => {
                 Ok((input, (Instruction::Stack, 0, op)))
@@ -187,7 +421,7 @@ impl Instruction {
             IRETURN | LRETURN | FRETURN | DRETURN | ARETURN | RETURN => {
                 Ok((input, (Instruction::Return, 0, op)))
             }
-            ATHROW => map(be_u16, |pool_pos| (Instruction::Throw { pool_pos }, 2, op))(input),
+            ATHROW => Ok((input, (Instruction::Throw, 0, op))),
             // Constant Pool related
             LDC => map(be_u8, |pool| (Instruction::ConstantPool { pool }, 1, op))(input),
             LDC_W | LDC2_W => map(be_u16, |pool| {
@@ -197,26 +431,42 @@ impl Instruction {
             BIPUSH => map(be_i8, |value| (Instruction::PushByte { value }, 1, op))(input),
             SIPUSH => map(be_i16, |value| (Instruction::PushShort { value }, 2, op))(input),
             // Increment
-            // TODO wide instruction
-            IINC => map(pair(be_u8, be_u8), |(var, amount)| {
+            IINC => map(pair(be_u8, be_i8), |(var, amount)| {
                 (
                     Instruction::Increment {
                         var: var as u16,
-                        amount,
+                        amount: amount as i16,
                     },
                     2,
                     op,
                 )
             })(input),
+            // Wide, the op it modifies follows with a 16 bit var and increment.
+            // The op returned is the modified one, Op::raw_op stays WIDE.
+            WIDE => {
+                let (input, op) = be_u8(input)?;
+                match op {
+                    IINC => map(pair(be_u16, be_i16), |(var, amount)| {
+                        (Instruction::Increment { var, amount }, 5, op)
+                    })(input),
+                    ILOAD | LLOAD | FLOAD | DLOAD | ALOAD => {
+                        map(be_u16, |var| (Instruction::Load { var }, 3, op))(input)
+                    }
+                    ISTORE | LSTORE | FSTORE | DSTORE | ASTORE => {
+                        map(be_u16, |var| (Instruction::Store { var }, 3, op))(input)
+                    }
+                    RET => map(be_u16, |var| (Instruction::SubroutineReturn { var }, 3, op))(input),
+                    _ => Err(nom::Err::Error(make_error(input, ErrorKind::Fail))),
+                }
+            }
             // Load
-            // TODO wide instruction
             ILOAD | LLOAD | FLOAD | DLOAD | ALOAD => {
                 map(be_u8, |var| (Instruction::Load { var: var as u16 }, 1, op))(input)
             }
             IALOAD | LALOAD | FALOAD | DALOAD | AALOAD | BALOAD | CALOAD | SALOAD => {
                 Ok((input, (Instruction::ArrayLoad, 0, op)))
             }
-            // TODO op instruction should be LOAD not LOAD_<x>
+            // Shortcuts come in fours per type, in the same order as ILOAD to ALOAD.
             ILOAD_0 | ILOAD_1 | ILOAD_2 | ILOAD_3 | LLOAD_0 | LLOAD_1 | LLOAD_2 | LLOAD_3
             | FLOAD_0 | FLOAD_1 | FLOAD_2 | FLOAD_3 | DLOAD_0 | DLOAD_1 | DLOAD_2 | DLOAD_3
             | ALOAD_0 | ALOAD_1 | ALOAD_2 | ALOAD_3 => Ok((input, {
@@ -230,14 +480,13 @@ impl Instruction {
                 )
             })),
             // Store
-            // TODO wide instruction
             ISTORE | LSTORE | FSTORE | DSTORE | ASTORE => {
                 map(be_u8, |var| (Instruction::Store { var: var as u16 }, 1, op))(input)
             }
             IASTORE | LASTORE | FASTORE | DASTORE | AASTORE | BASTORE | CASTORE | SASTORE => {
                 Ok((input, (Instruction::ArrayStore, 0, op)))
             }
-            // TODO op instruction should be STORE not STORE_<x>
+            // Shortcuts come in fours per type, in the same order as ISTORE to ASTORE.
             ISTORE_0 | ISTORE_1 | ISTORE_2 | ISTORE_3 | LSTORE_0 | LSTORE_1 | LSTORE_2
             | LSTORE_3 | FSTORE_0 | FSTORE_1 | FSTORE_2 | FSTORE_3 | DSTORE_0 | DSTORE_1
             | DSTORE_2 | DSTORE_3 | ASTORE_0 | ASTORE_1 | ASTORE_2 | ASTORE_3 => Ok((input, {
@@ -260,37 +509,72 @@ impl Instruction {
             })(input),
             // Jumps
             IF_ICMPEQ | IF_ICMPNE | IF_ICMPLT | IF_ICMPGE | IF_ICMPGT | IF_ICMPLE | IF_ACMPEQ
-            | IF_ACMPNE => map(be_i16, |jump_offset| {
-                (
-                    Instruction::ComparisonJump { jump: JumpValue::new(jump_offset as i32) },
-                    2,
-                    op,
-                )
-            })(input),
+            | IF_ACMPNE => {
+                let (input, offset) = be_i16(input)?;
+                let jump = jump(offset as i32)?;
+                Ok((input, (Instruction::ComparisonJump { jump }, 2, op)))
+            }
 
-            IFEQ | IFNE | IFLT | IFGE | IFGT | IFLE => map(be_i16, |jump_offset| {
-                (
-                    Instruction::ZeroComparisonJump { jump: JumpValue::new(jump_offset as i32) },
-                    2,
-                    op,
-                )
-            })(input),
-            // TODO Switch
+            IFEQ | IFNE | IFLT | IFGE | IFGT | IFLE | IFNULL | IFNONNULL => {
+                let (input, offset) = be_i16(input)?;
+                let jump = jump(offset as i32)?;
+                Ok((input, (Instruction::ZeroComparisonJump { jump }, 2, op)))
+            }
+            // Switch
+            TABLESWITCH | LOOKUPSWITCH => {
+                let padding = switch_padding(op_byte);
+                let (input, _) = take(padding)(input)?;
+                let (input, default) = be_i32(input)?;
+                let (input, cases) = if op == TABLESWITCH {
+                    let (input, (low, high)) = pair(be_i32, be_i32)(input)?;
+                    if high < low {
+                        return Err(nom::Err::Error(make_error(input, ErrorKind::Verify)));
+                    }
+                    let (input, offsets) = count(be_i32, (high as i64 - low as i64 + 1) as usize)(input)?;
+                    let cases: Vec<(i32, i32)> = (low..=high).zip(offsets).collect();
+                    (input, cases)
+                } else {
+                    let (input, pairs) = be_i32(input)?;
+                    if pairs < 0 {
+                        return Err(nom::Err::Error(make_error(input, ErrorKind::Verify)));
+                    }
+                    count(pair(be_i32, be_i32), pairs as usize)(input)?
+                };
 
+                let length = if op == TABLESWITCH {
+                    padding + 12 + 4 * cases.len() as u32
+                } else {
+                    padding + 8 + 8 * cases.len() as u32
+                };
+                let inst = Instruction::SwitchJump {
+                    default: jump(default)?,
+                    cases: cases
+                        .into_iter()
+                        .map(|(key, offset)| Ok((key, jump(offset)?)))
+                        .collect::<Result<_, _>>()?,
+                };
+                Ok((input, (inst, length, op)))
+            }
             // Jump
-            GOTO | JSR => map(be_i16, |jump_offset| {
-                (
-                    Instruction::Jump { jump: JumpValue::new(jump_offset as i32) },
-                    2,
-                    op,
-                )
-            })(input),
-            GOTO_W | JSR_W => map(be_i32, |jump_offset| {
-                (
-                    Instruction::Jump { jump: JumpValue::new(jump_offset) },
-                    4,
-                    op,
-                )
+            GOTO => {
+                let (input, offset) = be_i16(input)?;
+                Ok((input, (Instruction::Jump { jump: jump(offset as i32)? }, 2, op)))
+            }
+            GOTO_W => {
+                let (input, offset) = be_i32(input)?;
+                Ok((input, (Instruction::Jump { jump: jump(offset)? }, 4, op)))
+            }
+            // Subroutine
+            JSR => {
+                let (input, offset) = be_i16(input)?;
+                Ok((input, (Instruction::Subroutine { jump: jump(offset as i32)? }, 2, op)))
+            }
+            JSR_W => {
+                let (input, offset) = be_i32(input)?;
+                Ok((input, (Instruction::Subroutine { jump: jump(offset)? }, 4, op)))
+            }
+            RET => map(be_u8, |var| {
+                (Instruction::SubroutineReturn { var: var as u16 }, 1, op)
             })(input),
             // New
             NEW | ANEWARRAY => {
@@ -299,6 +583,16 @@ impl Instruction {
             NEWARRAY => map(be_u8, |array_type| {
                 (Instruction::NewPrimitiveArray { array_type }, 1, op)
             })(input),
+            MULTIANEWARRAY => map(pair(be_u16, be_u8), |(pool_pos, dimensions)| {
+                (
+                    Instruction::MultiNewArray {
+                        pool_pos,
+                        dimensions,
+                    },
+                    3,
+                    op,
+                )
+            })(input),
             // Get
             GETFIELD => map(be_u16, |pool_pos| (Instruction::GetField { pool_pos }, 2, op))(input),
             GETSTATIC => map(be_u16, |pool_pos| (Instruction::GetStaticField { pool_pos }, 2, op))(input),
@@ -306,8 +600,32 @@ impl Instruction {
             PUTFIELD => map(be_u16, |pool_pos| (Instruction::PutField { pool_pos }, 2, op))(input),
             PUTSTATIC => map(be_u16, |pool_pos| (Instruction::PutStaticField { pool_pos }, 2, op))(input),
             // Invoke
-            INVOKEVIRTUAL | INVOKESPECIAL | INVOKESTATIC | INVOKEINTERFACE | INVOKEDYNAMIC => {
-                map(be_u16, |pool_pos| (Instruction::InvokeMethod { pool_pos }, 2, op))(input)
+            INVOKEVIRTUAL | INVOKESPECIAL | INVOKESTATIC => {
+                let kind = match op {
+                    INVOKEVIRTUAL => InvokeKind::Virtual,
+                    INVOKESPECIAL => InvokeKind::Special,
+                    _ => InvokeKind::Static,
+                };
+                map(be_u16, move |pool_pos| {
+                    (Instruction::InvokeMethod { pool_pos, kind }, 2, op)
+                })(input)
+            }
+            // invokeinterface is followed by the count and a zero, invokedynamic by two zeros
+            INVOKEINTERFACE => {
+                let (input, (pool_pos, count, zero)) = tuple((be_u16, be_u8, be_u8))(input)?;
+                if zero != 0 {
+                    return Err(nom::Err::Error(make_error(input, ErrorKind::Verify)));
+                }
+                let kind = InvokeKind::Interface { count };
+                Ok((input, (Instruction::InvokeMethod { pool_pos, kind }, 4, op)))
+            }
+            INVOKEDYNAMIC => {
+                let (input, (pool_pos, zero)) = pair(be_u16, be_u16)(input)?;
+                if zero != 0 {
+                    return Err(nom::Err::Error(make_error(input, ErrorKind::Verify)));
+                }
+                let kind = InvokeKind::Dynamic;
+                Ok((input, (Instruction::InvokeMethod { pool_pos, kind }, 4, op)))
             }
             MONITORENTER | MONITOREXIT => Ok((input, (Instruction::Monitor, 0, op))),
             _ => Err(nom::Err::Error(make_error(input, ErrorKind::Fail))),
@@ -315,13 +633,113 @@ impl Instruction {
     }
 }
 
+impl Instruction {
+    // Every jump of the instruction, so they can be resolved or moved.
+    pub fn jumps_mut(&mut self) -> Vec<&mut JumpValue> {
+        match self {
+            Instruction::ComparisonJump { jump }
+            | Instruction::ZeroComparisonJump { jump }
+            | Instruction::Jump { jump }
+            | Instruction::Subroutine { jump } => vec![jump],
+            Instruction::SwitchJump { default, cases } => {
+                let mut jumps = vec![default];
+                jumps.extend(cases.iter_mut().map(|(_, jump)| jump));
+                jumps
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfgError {
+    // The op is not in exactly one chunk, or a chunk is empty.
+    Coverage { op_pos: u32 },
+    // The last op of the chunk does not match its target, or an op before it ends a chunk.
+    Terminator { chunk_pos: u32 },
+    // The last chunk would continue after the end of the code.
+    FallsOffEnd { chunk_pos: u32 },
+    // The handlers do not match the protected ranges around the chunk.
+    Handlers { chunk_pos: u32 },
+    MissingSource { from: u32, to: u32 },
+    ExtraSource { from: u32, to: u32 },
+}
+
+// Ops which have to be the last one of their chunk.
+fn ends_chunk(inst: &Instruction) -> bool {
+    matches!(
+        inst,
+        Instruction::ComparisonJump { .. }
+            | Instruction::ZeroComparisonJump { .. }
+            | Instruction::SwitchJump { .. }
+            | Instruction::Jump { .. }
+            | Instruction::Subroutine { .. }
+            | Instruction::SubroutineReturn { .. }
+            | Instruction::Return
+            | Instruction::Throw
+    )
+}
+
+fn successors(code_chunks: &[CodeChunk], chunk_pos: u32, exceptional: bool) -> Vec<u32> {
+    let chunk = &code_chunks[chunk_pos as usize];
+    let next = Some(chunk_pos + 1).filter(|next| (*next as usize) < code_chunks.len());
+    let mut successors: Vec<u32> = match &chunk.target {
+        CodeChunkTarget::Basic => next.into_iter().collect(),
+        CodeChunkTarget::Condition { true_chunk_pos } => {
+            next.into_iter().chain([*true_chunk_pos]).collect()
+        }
+        CodeChunkTarget::Goto { chunk_pos } => vec![*chunk_pos],
+        CodeChunkTarget::Switch {
+            default_chunk_pos,
+            targets,
+        } => [*default_chunk_pos].into_iter().chain(targets.iter().copied()).collect(),
+        // the subroutine comes back to the next chunk
+        CodeChunkTarget::Subroutine { chunk_pos } => {
+            [*chunk_pos].into_iter().chain(next).collect()
+        }
+        CodeChunkTarget::Return | CodeChunkTarget::Throw | CodeChunkTarget::SubroutineReturn => {
+            Vec::new()
+        }
+    };
+    if exceptional {
+        successors.extend(chunk.handlers.iter().map(|handler| handler.chunk_pos));
+    }
+    let mut seen = HashSet::new();
+    successors.retain(|successor| seen.insert(*successor));
+    successors
+}
+
+// The protected ranges and handlers as ops, entries with pcs which are not at an op are left out.
+fn protected_ranges(code: &[Op], exception_table: &[AttributeException]) -> Vec<(u32, u32, u32, u16)> {
+    exception_table
+        .iter()
+        .filter_map(|exception| {
+            Some((
+                op_at_pc(code, exception.start_pc as u32)?,
+                op_at_pc(code, exception.end_pc as u32)?,
+                op_at_pc(code, exception.handler_pc as u32).filter(|op_pos| (*op_pos as usize) < code.len())?,
+                exception.catch_type,
+            ))
+        })
+        .collect()
+}
+
+fn op_at_pc(code: &[Op], pc: u32) -> Option<u32> {
+    if pc == code.last().map_or(0, |op| op.pc + op.byte_length) {
+        return Some(code.len() as u32);
+    }
+    code.binary_search_by_key(&pc, |op| op.pc)
+        .ok()
+        .map(|op_pos| op_pos as u32)
+}
+
 pub struct Code {
     pub max_stack: u16,
     pub max_locals: u16,
     pub code: Vec<Op>,
     pub code_chunks: Vec<CodeChunk>,
     pub exception_table: Vec<AttributeException>,
-    pub attribute_info: Vec<AttributeInfo>,
+    pub attribute_info: Vec<Attribute>,
 }
 
 pub struct CodeChunk {
@@ -329,6 +747,15 @@ pub struct CodeChunk {
     pub stop: u32,
     pub source: Vec<u32>,
     pub target: CodeChunkTarget,
+    // The handlers of the ranges the chunk is in, in the order of the exception table.
+    pub handlers: Vec<CodeChunkHandler>,
+}
+
+// An exceptional edge to the chunk which starts the handler.
+pub struct CodeChunkHandler {
+    pub chunk_pos: u32,
+    // The caught class in the constant pool, 0 catches anything.
+    pub catch_type: u16,
 }
 
 #[derive(Debug)]
@@ -338,152 +765,660 @@ pub enum CodeChunkTarget {
     Throw,
     Condition { true_chunk_pos: u32 },
     Goto { chunk_pos: u32 },
-    Switch { targets: Vec<u32> },
+    // One target for every case of the switch op.
+    Switch { default_chunk_pos: u32, targets: Vec<u32> },
+    // Calls the subroutine at chunk_pos, which returns to the next chunk.
+    Subroutine { chunk_pos: u32 },
+    SubroutineReturn,
 }
 
 impl Code {
-    pub fn add_source(code: &mut Vec<Op>, from: u32, to: u32) {
-        let option: &mut Op = code.get_mut(to as usize).unwrap();
-        option.source.push(from);
-    }
-
-    pub fn parse<'a>(input: &'a [u8], constant_pool: &ConstantPool) -> IResult<'a, Self> {
-        println!("Code");
+    pub fn parse<'a, P: Utf8Pool>(input: &'a [u8], constant_pool: &P) -> IResult<'a, Self> {
         let (input, max_stack) = be_u16(input)?;
         let (input, max_locals) = be_u16(input)?;
         let (input, code_length) = be_u32(input)?;
-        let mut input = input;
-
-        let mut op_byte_to_op: Vec<u32> = Vec::with_capacity(code_length as usize);
-        let mut op_byte_ops: Vec<(u32, Op)> = Vec::new(); //
+        let (input, code_bytes) = take(code_length)(input)?;
 
+        // Jumps are relative to where the op starts, so they can only be resolved to the op they
+        // land on once it is known where every op starts. The first read only keeps the lengths.
+        let mut op_bytes: Vec<u32> = Vec::new();
+        let mut rest = code_bytes;
+        while !rest.is_empty() {
+            let op_byte = (code_bytes.len() - rest.len()) as u32;
+            let (next, _) = Op::parse(rest, op_byte, &|_| Ok(JumpValue::from_pos(0)))?;
+            op_bytes.push(op_byte);
+            rest = next;
+        }
 
-        let mut op_byte_pos: usize = 0;
-        let mut op_pos: u32 = 0;
+        let mut code: Vec<Op> = Vec::with_capacity(op_bytes.len());
+        let mut rest = code_bytes;
+        for &op_byte in &op_bytes {
+            let jump = |offset| JumpValue::resolve(op_byte, offset, &op_bytes);
+            let (next, (op, _)) = Op::parse(rest, op_byte, &jump)?;
+            code.push(op);
+            rest = next;
+        }
 
-        // Read code and create opbyte to op vec
-        while op_byte_pos < code_length as usize {
-            let (input2, (op, op_byte_length)) = Op::parse(input)?;
-            op_byte_ops.push((op_byte_pos as u32, op));
-            for _i in 0..op_byte_length {
-                op_byte_to_op.push(op_pos);
-            }
-            input = input2;
-            op_byte_pos += op_byte_length as usize;
-            op_pos += 1;
+        let (input, exception_table) = length_count(be_u16, |input| AttributeException::parse(input))(input)?;
+        // the handler has to be an op, the range can end with the code
+        if protected_ranges(&code, &exception_table).len() != exception_table.len() {
+            return Err(nom::Err::Error(make_error(input, ErrorKind::Verify)));
         }
+        let code_chunks = Code::build_chunks(&code, &exception_table);
+
+        let (input, attribute_info) = length_count(be_u16, |input| Attribute::parse(input, constant_pool))(input)?;
+        Ok((
+            input,
+            Code {
+                max_stack,
+                max_locals,
+                code,
+                code_chunks,
+                exception_table,
+                attribute_info,
+            },
+        ))
+    }
+
+    // Splits the ops into chunks on every jump, jump target, return and protected range, and
+    // links them up.
+    pub fn build_chunks(code: &[Op], exception_table: &[AttributeException]) -> Vec<CodeChunk> {
+        let ranges = protected_ranges(code, exception_table);
 
-        // Apply all jumps, as jumps are relative to byte location not op location,
-        // This also adds stuff to the split vec which is all of the spots which it should split the code on.
+        // These are all of the spots which it should split the code on.
         let mut splits: Vec<u32> = Vec::new();
-        let mut code: Vec<Op> = Vec::with_capacity(op_byte_ops.len());
-        op_pos = 0;
-        for (op_byte, mut op) in op_byte_ops {
-            match &mut op.inst {
-                Instruction::ComparisonJump { jump } => {
-                    jump.union.apply(op_byte, &op_byte_to_op);
-                    let next_op = op_pos + 1;
-                    let jump_op = jump.get_pos();
-                    splits.push(next_op);
-                    splits.push(jump_op);
+        for (op_pos, op) in code.iter().enumerate() {
+            let op_pos = op_pos as u32;
+            match &op.inst {
+                Instruction::ComparisonJump { jump }
+                | Instruction::ZeroComparisonJump { jump }
+                | Instruction::Jump { jump }
+                | Instruction::Subroutine { jump } => {
+                    splits.push(op_pos + 1);
+                    splits.push(jump.get_pos());
                 }
-                Instruction::ZeroComparisonJump { jump } => {
-                    jump.union.apply(op_byte, &op_byte_to_op);
-                    let next_op = op_pos + 1;
-                    let jump_op = jump.get_pos();
-                    splits.push(next_op);
-                    splits.push(jump_op);
+                Instruction::SwitchJump { default, cases } => {
+                    splits.push(op_pos + 1);
+                    splits.push(default.get_pos());
+                    for (_, jump) in cases {
+                        splits.push(jump.get_pos());
+                    }
                 }
-                Instruction::Jump { jump } => {
-                    jump.union.apply(op_byte, &op_byte_to_op);
-                    let next_op = op_pos + 1;
-                    let jump_op = jump.get_pos();
-                    splits.push(next_op);
-                    splits.push(jump_op);
-                }
-                Instruction::Return => {
-                    let next_op = op_pos + 1;
-                    splits.push(next_op);
+                Instruction::Return | Instruction::Throw | Instruction::SubroutineReturn { .. } => {
+                    splits.push(op_pos + 1);
                 }
                 _ => {}
             };
-
-            code.insert(op_pos as usize, op);
-            op_pos += 1;
         }
+        for (start, end, handler, _) in &ranges {
+            splits.push(*start);
+            splits.push(*end);
+            splits.push(*handler);
+        }
+        // the last chunk ends with the code, whether or not its last op splits
+        splits.push(code.len() as u32);
 
         // sorts and deduplicate all of the split locations so we don't split twice as that might lead to issues
         splits.sort();
         splits.dedup();
+        // a split at the first op would only make an empty chunk
+        splits.retain(|split| *split != 0 && *split <= code.len() as u32);
         let mut code_chunks: Vec<CodeChunk> = Vec::with_capacity(splits.len());
-
-        // op_byte_to_op now becomes op_to_chunk to save memory and computation
-        let mut op_to_chunk = op_byte_to_op;
+        let mut op_to_chunk: Vec<u32> = vec![0; code.len()];
 
         // create chunks and fill op_to_chunk
         let mut last_split = 0;
-        for i in 0..splits.len() {
-            let split = splits[i];
-            let chunk = CodeChunk {
+        for split in splits {
+            let chunk_pos = code_chunks.len() as u32;
+            for op_pos in last_split..split {
+                op_to_chunk[op_pos as usize] = chunk_pos;
+            }
+            code_chunks.push(CodeChunk {
                 start: last_split,
                 stop: split,
                 source: Vec::new(),
                 target: CodeChunkTarget::Basic,
-            };
-            code_chunks.push(chunk);
-            op_to_chunk.insert(split as usize, i as u32);
-            last_split = split as u32;
+                handlers: Vec::new(),
+            });
+            last_split = split;
         }
 
         // calculate targets
+        let chunk_at = |jump: &JumpValue| op_to_chunk[jump.get_pos() as usize];
         for chunk in 0..code_chunks.len() {
-            let i = (&(code_chunks[chunk].stop - 1));
-
-            match &code[*i as usize].inst {
-                Instruction::ComparisonJump { jump } => {
-                    let jump_chunk_pos = op_to_chunk[jump.get_pos() as usize];
-                    &mut code_chunks[jump_chunk_pos as usize].source.push(chunk as u32);
-                    &mut code_chunks[(chunk + 1) as usize].source.push(chunk as u32);
-                    code_chunks[chunk].target = CodeChunkTarget::Condition { true_chunk_pos: jump_chunk_pos };
+            let last_op = &code[code_chunks[chunk].stop as usize - 1];
+            code_chunks[chunk].target = match &last_op.inst {
+                Instruction::ComparisonJump { jump } | Instruction::ZeroComparisonJump { jump } => {
+                    CodeChunkTarget::Condition {
+                        true_chunk_pos: chunk_at(jump),
+                    }
+                }
+                Instruction::SwitchJump { default, cases } => CodeChunkTarget::Switch {
+                    default_chunk_pos: chunk_at(default),
+                    targets: cases.iter().map(|(_, jump)| chunk_at(jump)).collect(),
+                },
+                Instruction::Jump { jump } => CodeChunkTarget::Goto {
+                    chunk_pos: chunk_at(jump),
+                },
+                Instruction::Subroutine { jump } => CodeChunkTarget::Subroutine {
+                    chunk_pos: chunk_at(jump),
+                },
+                Instruction::SubroutineReturn { .. } => CodeChunkTarget::SubroutineReturn,
+                Instruction::Return => CodeChunkTarget::Return,
+                Instruction::Throw => CodeChunkTarget::Throw,
+                _ => CodeChunkTarget::Basic,
+            };
+        }
+
+        // every chunk within a protected range can throw into its handler
+        for (start, end, handler, catch_type) in ranges {
+            let handler_chunk_pos = op_to_chunk[handler as usize];
+            for chunk in &mut code_chunks {
+                if start <= chunk.start && chunk.stop <= end {
+                    chunk.handlers.push(CodeChunkHandler {
+                        chunk_pos: handler_chunk_pos,
+                        catch_type,
+                    });
+                }
+            }
+        }
+
+        for chunk in 0..code_chunks.len() as u32 {
+            for successor in successors(&code_chunks, chunk, true) {
+                code_chunks[successor as usize].source.push(chunk);
+            }
+        }
+        code_chunks
+    }
+
+    // The chunks control can go to after the chunk, the exceptional edges come last.
+    pub fn successors(&self, chunk_pos: u32) -> Vec<u32> {
+        successors(&self.code_chunks, chunk_pos, true)
+    }
+
+    // The successors without the exceptional edges to handlers.
+    pub fn normal_successors(&self, chunk_pos: u32) -> Vec<u32> {
+        successors(&self.code_chunks, chunk_pos, false)
+    }
+
+    // Checks that the chunks cover all ops exactly once, that every edge has a matching source
+    // entry, and that every chunk ends with the op its target says.
+    pub fn validate_cfg(&self) -> Result<(), CfgError> {
+        let chunks = &self.code_chunks;
+        let mut next_op = 0;
+        for chunk in chunks {
+            if chunk.start != next_op || chunk.stop <= chunk.start {
+                return Err(CfgError::Coverage { op_pos: next_op });
+            }
+            next_op = chunk.stop;
+        }
+        if next_op != self.code.len() as u32 {
+            return Err(CfgError::Coverage { op_pos: next_op });
+        }
+
+        // an edge has to land on the start of the chunk it names
+        let starts_chunk = |chunk_pos: u32, jump: &JumpValue| {
+            chunks
+                .get(chunk_pos as usize)
+                .is_some_and(|chunk| chunk.start == jump.get_pos())
+        };
+        for (chunk_pos, chunk) in chunks.iter().enumerate() {
+            let chunk_pos = chunk_pos as u32;
+            for op_pos in chunk.start..chunk.stop - 1 {
+                if ends_chunk(&self.code[op_pos as usize].inst) {
+                    return Err(CfgError::Terminator { chunk_pos });
+                }
+            }
+            let matches = match (&self.code[chunk.stop as usize - 1].inst, &chunk.target) {
+                (
+                    Instruction::ComparisonJump { jump } | Instruction::ZeroComparisonJump { jump },
+                    CodeChunkTarget::Condition { true_chunk_pos },
+                ) => starts_chunk(*true_chunk_pos, jump),
+                (Instruction::Jump { jump }, CodeChunkTarget::Goto { chunk_pos })
+                | (Instruction::Subroutine { jump }, CodeChunkTarget::Subroutine { chunk_pos }) => {
+                    starts_chunk(*chunk_pos, jump)
+                }
+                (
+                    Instruction::SwitchJump { default, cases },
+                    CodeChunkTarget::Switch {
+                        default_chunk_pos,
+                        targets,
+                    },
+                ) => {
+                    starts_chunk(*default_chunk_pos, default)
+                        && cases.len() == targets.len()
+                        && cases
+                            .iter()
+                            .zip(targets)
+                            .all(|((_, jump), target)| starts_chunk(*target, jump))
                 }
-                Instruction::ZeroComparisonJump { jump } => {
-                    let jump_chunk_pos = op_to_chunk[jump.get_pos() as usize];
-                    &mut code_chunks[jump_chunk_pos as usize].source.push(chunk as u32);
-                    &mut code_chunks[(chunk + 1) as usize].source.push(chunk as u32);
-                    code_chunks[chunk].target = CodeChunkTarget::Condition { true_chunk_pos: jump_chunk_pos };
+                (Instruction::SubroutineReturn { .. }, CodeChunkTarget::SubroutineReturn)
+                | (Instruction::Return, CodeChunkTarget::Return)
+                | (Instruction::Throw, CodeChunkTarget::Throw) => true,
+                (inst, CodeChunkTarget::Basic) => !ends_chunk(inst),
+                _ => false,
+            };
+            if !matches {
+                return Err(CfgError::Terminator { chunk_pos });
+            }
+            let falls_through = matches!(
+                chunk.target,
+                CodeChunkTarget::Basic
+                    | CodeChunkTarget::Condition { .. }
+                    | CodeChunkTarget::Subroutine { .. }
+            );
+            if falls_through && chunk_pos as usize + 1 == chunks.len() {
+                return Err(CfgError::FallsOffEnd { chunk_pos });
+            }
+        }
+
+        // the handlers are exactly those of the ranges around the chunk, which never cut it
+        let ranges = protected_ranges(&self.code, &self.exception_table);
+        for (chunk_pos, chunk) in chunks.iter().enumerate() {
+            let chunk_pos = chunk_pos as u32;
+            let mut handlers = Vec::new();
+            for (start, end, handler, catch_type) in &ranges {
+                if *start <= chunk.start && chunk.stop <= *end {
+                    handlers.push((*handler, *catch_type));
+                } else if *start < chunk.stop && chunk.start < *end {
+                    return Err(CfgError::Handlers { chunk_pos });
                 }
-                // TODO Switch
-                Instruction::Jump { jump } => {
-                    let jump_chunk_pos = op_to_chunk[jump.get_pos() as usize];
-                    &mut code_chunks[jump_chunk_pos as usize].source.push(chunk as u32);
-                    code_chunks[chunk].target = CodeChunkTarget::Goto {
-                        chunk_pos: jump_chunk_pos,
-                    };
+            }
+            let matches = chunk.handlers.len() == handlers.len()
+                && chunk.handlers.iter().zip(handlers).all(|(edge, (handler, catch_type))| {
+                    edge.catch_type == catch_type
+                        && chunks
+                            .get(edge.chunk_pos as usize)
+                            .is_some_and(|chunk| chunk.start == handler)
+                });
+            if !matches {
+                return Err(CfgError::Handlers { chunk_pos });
+            }
+        }
+
+        for chunk_pos in 0..chunks.len() as u32 {
+            let successors = self.successors(chunk_pos);
+            for to in &successors {
+                if !chunks[*to as usize].source.contains(&chunk_pos) {
+                    return Err(CfgError::MissingSource { from: chunk_pos, to: *to });
                 }
-                Instruction::Return => {
-                    code_chunks[chunk].target = CodeChunkTarget::Return;
+            }
+            for from in &chunks[chunk_pos as usize].source {
+                let has_edge = chunks.get(*from as usize).is_some()
+                    && self.successors(*from).contains(&chunk_pos);
+                if !has_edge {
+                    return Err(CfgError::ExtraSource { from: *from, to: chunk_pos });
                 }
-                Instruction::Throw { .. } => {
-                    code_chunks[chunk].target = CodeChunkTarget::Throw;
+            }
+        }
+        Ok(())
+    }
+
+    // The op starting at pc. Ranges of the tables end at the pc after their last op, so the end
+    // of the code gives the position after the last op.
+    pub fn op_at(&self, pc: u32) -> Option<u32> {
+        op_at_pc(&self.code, pc)
+    }
+
+    // The byte position of every op, as it would be written.
+    pub fn op_bytes(&self) -> Vec<u32> {
+        let mut op_bytes: Vec<u32> = Vec::with_capacity(self.code.len());
+        let mut op_byte: u32 = 0;
+        for op in &self.code {
+            op_bytes.push(op_byte);
+            op_byte += op.length(op_byte);
+        }
+        op_bytes
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>, constant_pool: &ConstantPool) -> Result<(), WriteError> {
+        put_u16(out, self.max_stack);
+        put_u16(out, self.max_locals);
+
+        // Jumps are written as byte offsets, so every op's byte position is needed up front.
+        let op_bytes = self.op_bytes();
+        let code_length = match (op_bytes.last(), self.code.last()) {
+            (Some(op_byte), Some(op)) => op_byte + op.length(*op_byte),
+            _ => 0,
+        };
+
+        put_u32(out, code_length);
+        for (op_pos, op) in self.code.iter().enumerate() {
+            op.write(out, op_pos as u32, &op_bytes)?;
+        }
+
+        put_u16(out, self.exception_table.len() as u16);
+        for exception in &self.exception_table {
+            exception.write(out);
+        }
+        Attribute::write_all(&self.attribute_info, out, constant_pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java::{AttributeInfo, ClassInfo};
+
+    // One encoding of every op, or several for wide, placed at op_byte. Every jump lands on the
+    // op right after it, so the code stays valid whatever the op is.
+    fn encodings(op_byte: u32) -> Vec<Vec<u8>> {
+        let mut encodings = Vec::new();
+        for op in NOP..=JSR_W {
+            let encoding = match op {
+                BIPUSH | LDC | ILOAD..=ALOAD | ISTORE..=ASTORE | RET | NEWARRAY => vec![op, 7],
+                SIPUSH | LDC_W | LDC2_W | GETSTATIC..=INVOKESTATIC | NEW | ANEWARRAY | CHECKCAST
+                | INSTANCEOF => vec![op, 1, 7],
+                IINC => vec![op, 7, 0xff],
+                IFEQ..=JSR | IFNULL | IFNONNULL => vec![op, 0, 3],
+                GOTO_W | JSR_W => vec![op, 0, 0, 0, 5],
+                INVOKEINTERFACE => vec![op, 1, 7, 2, 0],
+                INVOKEDYNAMIC => vec![op, 1, 7, 0, 0],
+                MULTIANEWARRAY => vec![op, 1, 7, 3],
+                WIDE => {
+                    for wide_op in (ILOAD..=ALOAD).chain(ISTORE..=ASTORE).chain([RET]) {
+                        encodings.push(vec![WIDE, wide_op, 1, 7]);
+                    }
+                    vec![WIDE, IINC, 1, 7, 0xff, 0xfe]
                 }
-                _ => {
-                    &mut code_chunks[(chunk + 1) as usize].source.push(chunk as u32);
-                    code_chunks[chunk].target = CodeChunkTarget::Basic;
+                TABLESWITCH | LOOKUPSWITCH => {
+                    let padding = switch_padding(op_byte) as usize;
+                    // both take 24 bytes after the padding, with three cases and two pairs
+                    let length = 1 + padding + 24;
+                    let mut encoding = vec![op];
+                    encoding.resize(1 + padding, 0);
+                    put_u32(&mut encoding, length as u32);
+                    if op == TABLESWITCH {
+                        // low -1 and high 1
+                        put_u32(&mut encoding, -1i32 as u32);
+                        put_u32(&mut encoding, 1);
+                        for _ in 0..3 {
+                            put_u32(&mut encoding, length as u32);
+                        }
+                    } else {
+                        put_u32(&mut encoding, 2);
+                        for key in [-1i32, 5] {
+                            put_u32(&mut encoding, key as u32);
+                            put_u32(&mut encoding, length as u32);
+                        }
+                    }
+                    assert_eq!(encoding.len(), length);
+                    encoding
                 }
+                _ => vec![op],
             };
+            encodings.push(encoding);
         }
-        let (input, exception_table) = length_count(be_u16, |input| AttributeException::parse(input))(input)?;
-        let (input, attribute_info) = length_count(be_u16, |input| AttributeInfo::parse(input, &constant_pool))(input)?;
-        Ok((
-            input,
-            Code {
-                max_stack,
-                max_locals,
-                code,
-                code_chunks,
-                exception_table,
-                attribute_info,
-            },
-        ))
+        encodings
+    }
+
+    // A Code attribute without exception table and attributes.
+    fn code_attribute(code: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        put_u16(&mut bytes, 4);
+        put_u16(&mut bytes, 4);
+        put_u32(&mut bytes, code.len() as u32);
+        bytes.extend_from_slice(code);
+        put_u16(&mut bytes, 0);
+        put_u16(&mut bytes, 0);
+        bytes
+    }
+
+    // Parses code holding the op after op_byte nops and followed by a return, then writes it
+    // back. Returns the decoded op.
+    fn round_trip(op_byte: u32, encoding: &[u8]) -> Op {
+        let mut code = vec![NOP; op_byte as usize];
+        code.extend_from_slice(encoding);
+        code.push(RETURN);
+        let bytes = code_attribute(&code);
+
+        let constant_pool = ConstantPool::default();
+        let (rest, mut parsed) = Code::parse(&bytes, &constant_pool)
+            .unwrap_or_else(|error| panic!("{:?}: {:?}", encoding, error));
+        assert!(rest.is_empty(), "{:?}", encoding);
+        assert_eq!(parsed.code.len(), op_byte as usize + 2, "{:?}", encoding);
+
+        let mut out = Vec::new();
+        parsed.write(&mut out, &constant_pool).unwrap();
+        assert_eq!(out, bytes, "{:?}", encoding);
+
+        let op = parsed.code.remove(op_byte as usize);
+        assert_eq!(op.raw_op, encoding[0], "{:?}", encoding);
+        assert_eq!(op.length(op_byte) as usize, encoding.len(), "{:?}", encoding);
+        op
+    }
+
+    // The instruction with its operands, jumps as the op they land on.
+    fn describe(inst: &Instruction) -> String {
+        match inst {
+            Instruction::Nop => "Nop".to_string(),
+            Instruction::Value => "Value".to_string(),
+            Instruction::GetArrayLength => "GetArrayLength".to_string(),
+            Instruction::Stack => "Stack".to_string(),
+            Instruction::Math => "Math".to_string(),
+            Instruction::Conventions => "Conventions".to_string(),
+            Instruction::Return => "Return".to_string(),
+            Instruction::Throw => "Throw".to_string(),
+            Instruction::ArrayLoad => "ArrayLoad".to_string(),
+            Instruction::ArrayStore => "ArrayStore".to_string(),
+            Instruction::Comparison => "Comparison".to_string(),
+            Instruction::Monitor => "Monitor".to_string(),
+            Instruction::ConstantPool { pool } => format!("ConstantPool {}", pool),
+            Instruction::ConstantPoolWide { pool } => format!("ConstantPoolWide {}", pool),
+            Instruction::PushByte { value } => format!("PushByte {}", value),
+            Instruction::PushShort { value } => format!("PushShort {}", value),
+            Instruction::Increment { var, amount } => format!("Increment {} {}", var, amount),
+            Instruction::Load { var } => format!("Load {}", var),
+            Instruction::Store { var } => format!("Store {}", var),
+            Instruction::Cast { pool_pos } => format!("Cast {}", pool_pos),
+            Instruction::Instanceof { pool_pos } => format!("Instanceof {}", pool_pos),
+            Instruction::ComparisonJump { jump } => format!("ComparisonJump {}", jump.get_pos()),
+            Instruction::ZeroComparisonJump { jump } => {
+                format!("ZeroComparisonJump {}", jump.get_pos())
+            }
+            Instruction::SwitchJump { default, cases } => {
+                let cases: Vec<_> = cases.iter().map(|(key, jump)| (*key, jump.get_pos())).collect();
+                format!("SwitchJump {} {:?}", default.get_pos(), cases)
+            }
+            Instruction::Jump { jump } => format!("Jump {}", jump.get_pos()),
+            Instruction::Subroutine { jump } => format!("Subroutine {}", jump.get_pos()),
+            Instruction::SubroutineReturn { var } => format!("SubroutineReturn {}", var),
+            Instruction::New { pool_pos } => format!("New {}", pool_pos),
+            Instruction::NewPrimitiveArray { array_type } => format!("NewPrimitiveArray {}", array_type),
+            Instruction::MultiNewArray { pool_pos, dimensions } => {
+                format!("MultiNewArray {} {}", pool_pos, dimensions)
+            }
+            Instruction::GetField { pool_pos } => format!("GetField {}", pool_pos),
+            Instruction::GetStaticField { pool_pos } => format!("GetStaticField {}", pool_pos),
+            Instruction::PutField { pool_pos } => format!("PutField {}", pool_pos),
+            Instruction::PutStaticField { pool_pos } => format!("PutStaticField {}", pool_pos),
+            Instruction::InvokeMethod { pool_pos, kind } => format!("InvokeMethod {} {:?}", pool_pos, kind),
+        }
+    }
+
+    // What each encoding from encodings() has to decode to, the canonical op and the
+    // instruction. The operands are the 7, 263 (1, 7) and -1 the encodings use.
+    fn canonical(op_byte: u32, encoding: &[u8]) -> (u8, String) {
+        let op = encoding[0];
+        let next = op_byte + 1;
+        let inst = match op {
+            NOP => "Nop".to_string(),
+            ACONST_NULL..=DCONST_1 => "Value".to_string(),
+            BIPUSH => "PushByte 7".to_string(),
+            SIPUSH => "PushShort 263".to_string(),
+            LDC => "ConstantPool 7".to_string(),
+            LDC_W | LDC2_W => "ConstantPoolWide 263".to_string(),
+            ILOAD..=ALOAD => "Load 7".to_string(),
+            // the shortcuts become the op they stand for, like DLOAD_3 for DLOAD 3
+            ILOAD_0..=ALOAD_3 => {
+                let shortcut = op - ILOAD_0;
+                return (ILOAD + shortcut / 4, format!("Load {}", shortcut % 4));
+            }
+            IALOAD..=SALOAD => "ArrayLoad".to_string(),
+            ISTORE..=ASTORE => "Store 7".to_string(),
+            ISTORE_0..=ASTORE_3 => {
+                let shortcut = op - ISTORE_0;
+                return (ISTORE + shortcut / 4, format!("Store {}", shortcut % 4));
+            }
+            IASTORE..=SASTORE => "ArrayStore".to_string(),
+            POP..=SWAP => "Stack".to_string(),
+            IADD..=LXOR => "Math".to_string(),
+            IINC => "Increment 7 -1".to_string(),
+            I2L..=I2S => "Conventions".to_string(),
+            LCMP..=DCMPG => "Comparison".to_string(),
+            IFEQ..=IFLE | IFNULL | IFNONNULL => format!("ZeroComparisonJump {}", next),
+            IF_ICMPEQ..=IF_ACMPNE => format!("ComparisonJump {}", next),
+            GOTO | GOTO_W => format!("Jump {}", next),
+            JSR | JSR_W => format!("Subroutine {}", next),
+            RET => "SubroutineReturn 7".to_string(),
+            TABLESWITCH => format!("SwitchJump {0} [(-1, {0}), (0, {0}), (1, {0})]", next),
+            LOOKUPSWITCH => format!("SwitchJump {0} [(-1, {0}), (5, {0})]", next),
+            IRETURN..=RETURN => "Return".to_string(),
+            GETSTATIC => "GetStaticField 263".to_string(),
+            PUTSTATIC => "PutStaticField 263".to_string(),
+            GETFIELD => "GetField 263".to_string(),
+            PUTFIELD => "PutField 263".to_string(),
+            INVOKEVIRTUAL => "InvokeMethod 263 Virtual".to_string(),
+            INVOKESPECIAL => "InvokeMethod 263 Special".to_string(),
+            INVOKESTATIC => "InvokeMethod 263 Static".to_string(),
+            INVOKEINTERFACE => "InvokeMethod 263 Interface { count: 2 }".to_string(),
+            INVOKEDYNAMIC => "InvokeMethod 263 Dynamic".to_string(),
+            NEW | ANEWARRAY => "New 263".to_string(),
+            NEWARRAY => "NewPrimitiveArray 7".to_string(),
+            ARRAYLENGTH => "GetArrayLength".to_string(),
+            ATHROW => "Throw".to_string(),
+            CHECKCAST => "Cast 263".to_string(),
+            INSTANCEOF => "Instanceof 263".to_string(),
+            MONITORENTER | MONITOREXIT => "Monitor".to_string(),
+            MULTIANEWARRAY => "MultiNewArray 263 3".to_string(),
+            // wide gives the op it modifies
+            WIDE => {
+                let inst = match encoding[1] {
+                    IINC => "Increment 263 -2",
+                    ILOAD..=ALOAD => "Load 263",
+                    RET => "SubroutineReturn 263",
+                    _ => "Store 263",
+                };
+                return (encoding[1], inst.to_string());
+            }
+            _ => panic!("no encoding for {}", print_op(&op)),
+        };
+        (op, inst)
+    }
+
+    #[test]
+    fn every_op() {
+        // Every padding a switch can need, the other ops do not care where they are.
+        for op_byte in 0..4 {
+            for encoding in encodings(op_byte) {
+                let op = round_trip(op_byte, &encoding);
+                let (canonical_op, canonical_inst) = canonical(op_byte, &encoding);
+                assert_eq!(
+                    (print_op(&op.op), describe(&op.inst)),
+                    (print_op(&canonical_op), canonical_inst),
+                    "{:?}",
+                    encoding
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn operands() {
+        let op = round_trip(0, &[WIDE, IINC, 1, 7, 0xff, 0xfe]);
+        assert!(matches!(op.inst, Instruction::Increment { var: 263, amount: -2 }));
+        assert_eq!(op.op, IINC);
+
+        let op = round_trip(0, &[WIDE, ALOAD, 1, 7]);
+        assert!(matches!(op.inst, Instruction::Load { var: 263 }));
+        assert_eq!(op.op, ALOAD);
+
+        let op = round_trip(0, &[ALOAD_2]);
+        assert!(matches!(op.inst, Instruction::Load { var: 2 }));
+        assert_eq!(op.op, ALOAD);
+
+        let op = round_trip(0, &[INVOKEINTERFACE, 1, 7, 2, 0]);
+        assert!(matches!(
+            op.inst,
+            Instruction::InvokeMethod {
+                pool_pos: 263,
+                kind: InvokeKind::Interface { count: 2 },
+            }
+        ));
+
+        let op = round_trip(0, &[INVOKEDYNAMIC, 1, 7, 0, 0]);
+        assert!(matches!(
+            op.inst,
+            Instruction::InvokeMethod {
+                pool_pos: 263,
+                kind: InvokeKind::Dynamic,
+            }
+        ));
+
+        let encodings = encodings(2);
+        let tableswitch = encodings.iter().find(|encoding| encoding[0] == TABLESWITCH).unwrap();
+        let op = round_trip(2, tableswitch);
+        match op.inst {
+            Instruction::SwitchJump { default, cases } => {
+                assert_eq!(default.get_pos(), 3);
+                let keys: Vec<_> = cases.iter().map(|(key, jump)| (*key, jump.get_pos())).collect();
+                assert_eq!(keys, [(-1, 3), (0, 3), (1, 3)]);
+            }
+            _ => panic!("not a switch"),
+        }
+    }
+
+    #[test]
+    fn jump_errors() {
+        let constant_pool = ConstantPool::default();
+        // goto lands in the middle of the sipush, before the code and after it
+        for (offset, error) in [
+            (4, JumpError::NotOnOp { op_byte: 0, offset: 4 }),
+            (-1, JumpError::OutOfCode { op_byte: 0, offset: -1 }),
+            (7, JumpError::OutOfCode { op_byte: 0, offset: 7 }),
+        ] {
+            let [high, low] = (offset as i16).to_be_bytes();
+            let bytes = code_attribute(&[GOTO, high, low, SIPUSH, 0, 1, RETURN]);
+            match Code::parse(&bytes, &constant_pool) {
+                Err(nom::Err::Error(ParseError::Jump(jump_error))) => assert_eq!(jump_error, error),
+                _ => panic!("goto {} is not a jump error", offset),
+            }
+        }
+    }
+
+    #[test]
+    fn cfg() {
+        for path in crate::java::tests::corpus() {
+            let buffer = std::fs::read(&path).unwrap();
+            let class_info = match ClassInfo::parse(&buffer) {
+                Ok((_, class_info)) => class_info,
+                Err(error) => panic!("{}: {:?}", path.display(), error),
+            };
+            for method in &class_info.methods {
+                for attribute in &method.attribute_info {
+                    if let AttributeInfo::CodeAttribute { code } = &attribute.info {
+                        let name = class_info.constant_pool.utf8(method.name_index).unwrap_or("?");
+                        assert_eq!(code.validate_cfg(), Ok(()), "{}: {}", path.display(), name);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cfg_errors() {
+        let constant_pool = ConstantPool::default();
+        // iload_0; ifeq +5; iconst_1; ireturn; iconst_0; ireturn
+        let bytes = code_attribute(&[ILOAD_0, IFEQ, 0, 5, ICONST_1, IRETURN, ICONST_0, IRETURN]);
+        let (_, code) = Code::parse(&bytes, &constant_pool).unwrap();
+        assert_eq!(code.validate_cfg(), Ok(()));
+
+        let mut broken = Code::parse(&bytes, &constant_pool).unwrap().1;
+        broken.code_chunks[0].stop -= 1;
+        assert!(matches!(broken.validate_cfg(), Err(CfgError::Coverage { .. })));
+
+        let mut broken = Code::parse(&bytes, &constant_pool).unwrap().1;
+        broken.code_chunks.last_mut().unwrap().source.clear();
+        assert!(matches!(broken.validate_cfg(), Err(CfgError::MissingSource { .. })));
     }
 }
@@ -1,25 +1,56 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::mem;
 
-use crate::consts::RETURN;
-use crate::java_code::{Instruction, Op};
+use crate::consts::*;
+use crate::java::{ConstantInfo, ConstantPool};
+use crate::java_code::{CodeChunkTarget, Instruction, InvokeKind, Op};
 use crate::java_decomp::ExpressionType::{
     ArrayLengthEx, ArrayLoadEx, CommentEx, ConstantEx, ConvertEx, NumberConstant, OperatorEx,
     PoolConstantEx, ReturnEx, ReturnValueEx,
 };
+use crate::java_structure::{
+    joined_pcs, pulls_stack, reachable_successors, short_circuits, structure, Node, NodeExit,
+    Statement,
+};
+use crate::java_subroutine::{inline_subroutines, SubroutineError};
+use crate::java_type::MethodType;
 use crate::Code;
-use crate::java::ConstantPool;
 
 pub struct ExStack {
     stack: VecDeque<Expression>,
 }
 
 impl ExStack {
+    pub fn new() -> Self {
+        ExStack {
+            stack: VecDeque::new(),
+        }
+    }
+
     pub fn push(&mut self, op: &Op, ex: ExpressionType) {
-        self.stack.push_front(Expression { op: op.op, ex });
+        self.stack.push_front(Expression::new(op, ex));
     }
 
+    pub fn put(&mut self, expression: Expression) {
+        self.stack.push_front(expression);
+    }
+
+    // Values pushed before the chunk started are not known here, they come out as a StackEx.
     pub fn pull(&mut self) -> Expression {
-        self.stack.pop_front().unwrap()
+        self.stack.pop_front().unwrap_or(Expression {
+            op: NOP,
+            pc: 0,
+            ex: ExpressionType::StackEx,
+        })
+    }
+
+    pub fn top(&self) -> Option<&Expression> {
+        self.stack.front()
+    }
+
+    // The values which are still there, from the bottom up.
+    pub fn values(self) -> Vec<Expression> {
+        self.stack.into_iter().rev().collect()
     }
 }
 
@@ -28,16 +59,29 @@ pub struct ExList {
 }
 
 impl ExList {
+    pub fn new() -> Self {
+        ExList { list: Vec::new() }
+    }
+
     pub fn add(&mut self, op: &Op, ex: ExpressionType) {
-        self.list.push(Expression { op: op.op, ex });
+        self.list.push(Expression::new(op, ex));
+    }
+
+    pub fn put(&mut self, expression: Expression) {
+        self.list.push(expression);
     }
 }
 
+// The op and pc of the op which made the expression. A copy made by dup keeps them, so two
+// expressions are only equal when one is a copy of the other.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Expression {
-    op: u8,
-    ex: ExpressionType,
+    pub op: u8,
+    pub pc: u32,
+    pub ex: ExpressionType,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExpressionType {
     ConstantEx {
         op: u8,
@@ -50,87 +94,146 @@ pub enum ExpressionType {
         number: i16,
     },
     OperatorEx {
-        left: Expression,
-        right: Expression,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    NegateEx {
+        value: Box<Expression>,
     },
     ConvertEx {
-        original: Expression,
+        original: Box<Expression>,
     },
     IncrementEx {
         var: u16,
-        amount: u8,
+        amount: i16,
+    },
+    // A var which is loaded and then incremented, like i in a[i++].
+    PostIncrementEx {
+        var: u16,
+        amount: i16,
     },
     LoadVarEx {
         var: u16,
     },
+    // Stores are statements, unless the value was copied with a dup and the copy is used.
     StoreVarEx {
         var: u16,
-        value: Expression,
+        value: Box<Expression>,
     },
     ArrayLoadEx {
-        array: Expression,
-        index: Expression,
+        array: Box<Expression>,
+        index: Box<Expression>,
     },
     ArrayStoreEx {
-        array: Expression,
-        index: Expression,
-        value: Expression,
+        array: Box<Expression>,
+        index: Box<Expression>,
+        value: Box<Expression>,
     },
     CompareEx {
-        left: Expression,
-        right: Expression,
+        left: Box<Expression>,
+        right: Box<Expression>,
     },
     InstanceOfEx {
-        value: Expression,
+        value: Box<Expression>,
         pool_pos: u16,
     },
+    // The op of the expression is the jump, true is when the jump is taken.
     IfEx {
-        left: Expression,
-        right: Expression,
+        left: Box<Expression>,
+        right: Box<Expression>,
     },
     If0Ex {
-        value: Expression,
+        value: Box<Expression>,
+    },
+    // Conditions which are made of jumps over each other, the op is a nop.
+    AndEx {
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    OrEx {
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    // A ?: which is left on the stack where both ways meet again, the op is a nop.
+    TernaryEx {
+        condition: Box<Expression>,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
     },
+    // An object which is not constructed yet.
     NewEx {
         pool_pos: u16,
     },
+    // The class and the constructor which is called.
+    ConstructEx {
+        pool_pos: u16,
+        method_pos: u16,
+        arguments: Vec<Expression>,
+    },
     NewPrimArrayEx {
         array_type: u8,
+        count: Box<Expression>,
+    },
+    NewArrayEx {
+        pool_pos: u16,
+        count: Box<Expression>,
+    },
+    MultiNewArrayEx {
+        pool_pos: u16,
+        counts: Vec<Expression>,
+    },
+    // A new array which gets its values stored right away, like new int[] {1, 2}.
+    ArrayInitEx {
+        array: Box<Expression>,
+        values: Vec<Expression>,
     },
     // needs more impl
     StackEx,
-    MonitorEx,
+    // The exception a handler starts with on the stack.
+    CatchEx,
+    // A monitorenter or monitorexit, by the op.
+    MonitorEx {
+        lock: Box<Expression>,
+    },
     CastEx {
-        value: Expression,
+        value: Box<Expression>,
         pool_pos: u16,
     },
-    // TODO Switch
+    SwitchEx {
+        value: Box<Expression>,
+    },
     ReturnEx,
     ReturnValueEx {
-        value: Expression,
+        value: Box<Expression>,
     },
     ThrowEx {
-        throwable: Expression,
+        throwable: Box<Expression>,
     },
     GetFieldEx {
-        object: Expression,
+        object: Box<Expression>,
         pool_pos: u16,
     },
     PutFieldEx {
-        object: Expression,
-        value: Expression,
+        object: Box<Expression>,
+        value: Box<Expression>,
         pool_pos: u16,
     },
     GetStaticFieldEx {
         pool_pos: u16,
     },
     PutStaticFieldEx {
-        value: Expression,
+        value: Box<Expression>,
         pool_pos: u16,
     },
-    MethodEx,
+    // Static and dynamic calls have no object.
+    MethodEx {
+        object: Option<Box<Expression>>,
+        arguments: Vec<Expression>,
+        pool_pos: u16,
+        kind: InvokeKind,
+    },
     ArrayLengthEx {
-        array: Expression,
+        array: Box<Expression>,
     },
 
     // comments for extra info and such
@@ -141,10 +244,19 @@ pub enum ExpressionType {
 
 impl Expression {
     fn new(op: &Op, ex: ExpressionType) -> Self {
-        Expression { op: op.op, ex }
+        Expression {
+            op: op.op,
+            pc: op.pc,
+            ex,
+        }
     }
 
-    pub fn create(op: &Op, ex_stack: &mut ExStack, ex_list: &mut ExList, const_pool: &ConstantPool) {
+    pub fn create(
+        op: &Op,
+        ex_stack: &mut ExStack,
+        ex_list: &mut ExList,
+        const_pool: &ConstantPool,
+    ) {
         match &op.inst {
             Instruction::Nop => ex_list.add(
                 op,
@@ -152,42 +264,48 @@ impl Expression {
                     comment: "nop".to_string(),
                 },
             ),
-            Instruction::Value => ex_stack.push(op, ConstantEx),
+            Instruction::Value => ex_stack.push(op, ConstantEx { op: op.op }),
             Instruction::GetArrayLength => {
-                let array = ex_stack.pull();
+                let array = Box::new(ex_stack.pull());
                 ex_stack.push(op, ArrayLengthEx { array })
             }
-            // TODO stack
-            Instruction::Stack => {}
+            Instruction::Stack => Self::stack(op, ex_stack, ex_list, const_pool),
             Instruction::Math => {
+                if matches!(op.op, INEG | LNEG | FNEG | DNEG) {
+                    let value = Box::new(ex_stack.pull());
+                    ex_stack.push(op, ExpressionType::NegateEx { value });
+                    return;
+                }
                 let value2 = ex_stack.pull();
                 let value1 = ex_stack.pull();
                 ex_stack.push(
                     op,
                     OperatorEx {
-                        left: value1,
-                        right: value2,
+                        left: Box::new(value1),
+                        right: Box::new(value2),
                     },
                 )
             }
             Instruction::Conventions => {
-                let original = ex_stack.pull();
+                let original = Box::new(ex_stack.pull());
                 ex_stack.push(op, ConvertEx { original })
             }
             Instruction::Return => {
                 if op.op == RETURN {
                     ex_list.add(op, ReturnEx)
                 } else {
-                    let value = ex_stack.pull();
+                    let value = Box::new(ex_stack.pull());
                     ex_list.add(op, ReturnValueEx { value })
                 }
             }
-            // TODO Throw
-            Instruction::Throw { .. } => {}
+            Instruction::Throw => {
+                let throwable = Box::new(ex_stack.pull());
+                ex_list.add(op, ExpressionType::ThrowEx { throwable })
+            }
             Instruction::ConstantPool { pool: pool_pos } => ex_stack.push(
                 op,
                 PoolConstantEx {
-                    pool_pos: pool_pos as u16,
+                    pool_pos: *pool_pos as u16,
                 },
             ),
             Instruction::ConstantPoolWide { pool: pool_pos } => ex_stack.push(
@@ -199,41 +317,60 @@ impl Expression {
             Instruction::PushByte { value } => ex_stack.push(
                 op,
                 NumberConstant {
-                    number: value as i16,
+                    number: *value as i16,
                 },
             ),
             Instruction::PushShort { value } => {
                 ex_stack.push(op, NumberConstant { number: *value })
             }
-            Instruction::Increment { var, amount } => ex_list.add(
-                op,
-                ExpressionType::IncrementEx {
-                    var: *var,
-                    amount: *amount,
-                },
-            ),
+            Instruction::Increment { var, amount } => {
+                // a load of the var right before is the old value, like i in a[i++]
+                let loaded = matches!(ex_stack.top(), Some(Expression { ex: ExpressionType::LoadVarEx { var: loaded }, .. }) if loaded == var);
+                if loaded {
+                    let load = ex_stack.pull();
+                    ex_stack.put(Expression {
+                        ex: ExpressionType::PostIncrementEx {
+                            var: *var,
+                            amount: *amount,
+                        },
+                        ..load
+                    });
+                } else {
+                    ex_list.add(
+                        op,
+                        ExpressionType::IncrementEx {
+                            var: *var,
+                            amount: *amount,
+                        },
+                    )
+                }
+            }
             Instruction::Load { var } => ex_stack.push(op, ExpressionType::LoadVarEx { var: *var }),
             Instruction::Store { var } => {
                 let value = ex_stack.pull();
-                ex_list.push(op, ExpressionType::StoreVarEx { var: *var, value })
+                Self::assign(op, ex_stack, ex_list, &value, |value| {
+                    ExpressionType::StoreVarEx { var: *var, value }
+                })
             }
             Instruction::ArrayLoad => {
-                let index = ex_stack.pull();
-                let array = ex_stack.pull();
-                ex_stack.push(op, ExpressionType::ArrayLoadEx { array, index })
+                let index = Box::new(ex_stack.pull());
+                let array = Box::new(ex_stack.pull());
+                ex_stack.push(op, ArrayLoadEx { array, index })
             }
             Instruction::ArrayStore => {
                 let value = ex_stack.pull();
                 let index = ex_stack.pull();
                 let array = ex_stack.pull();
-                ex_stack.push(
-                    op,
+                if Self::initialize(&array, &index, &value, ex_stack) {
+                    return;
+                }
+                Self::assign(op, ex_stack, ex_list, &value, |value| {
                     ExpressionType::ArrayStoreEx {
-                        array,
-                        index,
+                        array: Box::new(array),
+                        index: Box::new(index),
                         value,
-                    },
-                )
+                    }
+                })
             }
             Instruction::Comparison => {
                 let value2 = ex_stack.pull();
@@ -241,13 +378,13 @@ impl Expression {
                 ex_stack.push(
                     op,
                     ExpressionType::CompareEx {
-                        left: value1,
-                        right: value2,
+                        left: Box::new(value1),
+                        right: Box::new(value2),
                     },
                 )
             }
             Instruction::Instanceof { pool_pos } => {
-                let value = ex_stack.pull();
+                let value = Box::new(ex_stack.pull());
                 ex_stack.push(
                     op,
                     ExpressionType::InstanceOfEx {
@@ -257,7 +394,7 @@ impl Expression {
                 )
             }
             Instruction::Cast { pool_pos } => {
-                let value = ex_stack.pull();
+                let value = Box::new(ex_stack.pull());
                 ex_stack.push(
                     op,
                     ExpressionType::CastEx {
@@ -266,41 +403,75 @@ impl Expression {
                     },
                 )
             }
-            // TODO compute chunk target
-            Instruction::ComparisonJump { jump } => {
+            // the structuring takes the condition which ends the chunk out of the list
+            Instruction::ComparisonJump { .. } => {
                 let value2 = ex_stack.pull();
                 let value1 = ex_stack.pull();
                 ex_list.add(
                     op,
                     ExpressionType::IfEx {
-                        left: value1,
-                        right: value2,
+                        left: Box::new(value1),
+                        right: Box::new(value2),
                     },
                 )
             }
-            // TODO compute chunk target
-            Instruction::ZeroComparisonJump { jump } => {
-                let value = ex_stack.pull();
+            Instruction::ZeroComparisonJump { .. } => {
+                let value = Box::new(ex_stack.pull());
                 ex_list.add(op, ExpressionType::If0Ex { value })
             }
-            // TODO compute chunk target
             Instruction::Jump { .. } => {}
-            // TODO switch
-            Instruction::SwitchJump { .. } => {}
-            Instruction::New { pool_pos } => ex_stack.push(
-                op,
-                ExpressionType::NewEx {
-                    pool_pos: *pool_pos,
-                },
-            ),
-            Instruction::NewPrimitiveArray { array_type } => ex_stack.push(
-                op,
-                ExpressionType::NewPrimArrayEx {
-                    array_type: *array_type,
-                },
-            ),
+            // Subroutines are inlined into their callers before this
+            Instruction::Subroutine { .. } | Instruction::SubroutineReturn { .. } => {}
+            Instruction::SwitchJump { .. } => {
+                let value = Box::new(ex_stack.pull());
+                ex_list.add(op, ExpressionType::SwitchEx { value })
+            }
+            Instruction::New { pool_pos } => {
+                if op.op == ANEWARRAY {
+                    let count = Box::new(ex_stack.pull());
+                    ex_stack.push(
+                        op,
+                        ExpressionType::NewArrayEx {
+                            pool_pos: *pool_pos,
+                            count,
+                        },
+                    )
+                } else {
+                    ex_stack.push(
+                        op,
+                        ExpressionType::NewEx {
+                            pool_pos: *pool_pos,
+                        },
+                    )
+                }
+            }
+            Instruction::NewPrimitiveArray { array_type } => {
+                let count = Box::new(ex_stack.pull());
+                ex_stack.push(
+                    op,
+                    ExpressionType::NewPrimArrayEx {
+                        array_type: *array_type,
+                        count,
+                    },
+                )
+            }
+            Instruction::MultiNewArray {
+                pool_pos,
+                dimensions,
+            } => {
+                let mut counts: Vec<Expression> =
+                    (0..*dimensions).map(|_| ex_stack.pull()).collect();
+                counts.reverse();
+                ex_stack.push(
+                    op,
+                    ExpressionType::MultiNewArrayEx {
+                        pool_pos: *pool_pos,
+                        counts,
+                    },
+                )
+            }
             Instruction::GetField { pool_pos } => {
-                let object = ex_stack.pull();
+                let object = Box::new(ex_stack.pull());
                 ex_stack.push(
                     op,
                     ExpressionType::GetFieldEx {
@@ -318,46 +489,811 @@ impl Expression {
             Instruction::PutField { pool_pos } => {
                 let value = ex_stack.pull();
                 let object = ex_stack.pull();
-                ex_list.add(
-                    op,
+                Self::assign(op, ex_stack, ex_list, &value, |value| {
                     ExpressionType::PutFieldEx {
-                        object,
+                        object: Box::new(object),
                         value,
                         pool_pos: *pool_pos,
-                    },
-                )
+                    }
+                })
             }
             Instruction::PutStaticField { pool_pos } => {
                 let value = ex_stack.pull();
-                ex_list.add(
-                    op,
+                Self::assign(op, ex_stack, ex_list, &value, |value| {
                     ExpressionType::PutStaticFieldEx {
                         value,
                         pool_pos: *pool_pos,
-                    },
-                )
+                    }
+                })
+            }
+            Instruction::InvokeMethod { pool_pos, kind } => {
+                Self::invoke(op, *pool_pos, *kind, ex_stack, ex_list, const_pool)
+            }
+            Instruction::Monitor => {
+                let lock = Box::new(ex_stack.pull());
+                ex_list.add(op, ExpressionType::MonitorEx { lock })
+            }
+        }
+    }
+
+    // A store whose value is still on the stack was done on a copy, so the store is used as a
+    // value like in a = b = 0 or while ((line = in.readLine()) != null).
+    fn assign<F>(
+        op: &Op,
+        ex_stack: &mut ExStack,
+        ex_list: &mut ExList,
+        value: &Expression,
+        store: F,
+    ) where
+        F: FnOnce(Box<Expression>) -> ExpressionType,
+    {
+        if ex_stack.top() == Some(value) {
+            let value = ex_stack.pull();
+            ex_stack.push(op, store(Box::new(value)));
+        } else {
+            ex_list.add(op, store(Box::new(value.clone())));
+        }
+    }
+
+    // Stores into a copy of a new array at the next index fill its initializer.
+    fn initialize(
+        array: &Expression,
+        index: &Expression,
+        value: &Expression,
+        ex_stack: &mut ExStack,
+    ) -> bool {
+        let (new_array, values) = match &array.ex {
+            ExpressionType::NewPrimArrayEx { .. } | ExpressionType::NewArrayEx { .. } => {
+                (array.clone(), Vec::new())
             }
-            Instruction::InvokeMethod { pool_pos } => {
-                const_pool.get_method_descriptor(pool_pos)
+            ExpressionType::ArrayInitEx { array, values } => (*array.clone(), values.clone()),
+            _ => return false,
+        };
+        let next = match index.ex {
+            ExpressionType::ConstantEx { op } if (ICONST_0..=ICONST_5).contains(&op) => {
+                (op - ICONST_0) as usize
+            }
+            ExpressionType::NumberConstant { number } if number >= 0 => number as usize,
+            _ => return false,
+        };
+        if next != values.len() || ex_stack.top() != Some(array) {
+            return false;
+        }
+        let copy = ex_stack.pull();
+        let mut values = values;
+        values.push(value.clone());
+        ex_stack.put(Expression {
+            ex: ExpressionType::ArrayInitEx {
+                array: Box::new(new_array),
+                values,
+            },
+            ..copy
+        });
+        true
+    }
+
+    fn invoke(
+        op: &Op,
+        pool_pos: u16,
+        kind: InvokeKind,
+        ex_stack: &mut ExStack,
+        ex_list: &mut ExList,
+        const_pool: &ConstantPool,
+    ) {
+        let (name, descriptor) = if kind == InvokeKind::Dynamic {
+            match const_pool.invoke_dynamic(pool_pos) {
+                Ok(dynamic) => (dynamic.name, dynamic.descriptor),
+                Err(_) => ("?", "()V"),
             }
-            Instruction::Monitor => {}
+        } else {
+            match const_pool.member_ref(pool_pos) {
+                Ok(member) => (member.name, member.descriptor),
+                Err(_) => ("?", "()V"),
+            }
+        };
+        let method_type = MethodType::parse_str(descriptor);
+        let count = method_type
+            .as_ref()
+            .map_or(0, |method_type| method_type.parameters.len());
+        let mut arguments: Vec<Expression> = (0..count).map(|_| ex_stack.pull()).collect();
+        arguments.reverse();
+        let object = match kind {
+            InvokeKind::Static | InvokeKind::Dynamic => None,
+            _ => Some(Box::new(ex_stack.pull())),
+        };
+
+        // new, dup and a call to the constructor make one expression
+        if name == "<init>" {
+            if let Some(object) = &object {
+                if let ExpressionType::NewEx {
+                    pool_pos: class_pos,
+                } = object.ex
+                {
+                    let construct = ExpressionType::ConstructEx {
+                        pool_pos: class_pos,
+                        method_pos: pool_pos,
+                        arguments,
+                    };
+                    if ex_stack.top() == Some(object) {
+                        let copy = ex_stack.pull();
+                        ex_stack.put(Expression {
+                            ex: construct,
+                            ..copy
+                        });
+                    } else {
+                        ex_list.put(Expression {
+                            ex: construct,
+                            ..*object.clone()
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+
+        let method = ExpressionType::MethodEx {
+            object,
+            arguments,
+            pool_pos,
+            kind,
+        };
+        let returns = method_type
+            .is_some_and(|method_type| method_type.return_type != crate::java_type::Type::Void);
+        if returns {
+            ex_stack.push(op, method);
+        } else {
+            ex_list.add(op, method);
         }
     }
+
+    // pop, dup and swap move values around, long and double values take two slots.
+    fn stack(op: &Op, ex_stack: &mut ExStack, ex_list: &mut ExList, const_pool: &ConstantPool) {
+        let wide = |ex_stack: &ExStack| ex_stack.top().is_some_and(|top| top.is_wide(const_pool));
+        match op.op {
+            POP => Self::discard(ex_stack.pull(), ex_list),
+            POP2 => {
+                if !wide(ex_stack) {
+                    Self::discard(ex_stack.pull(), ex_list);
+                }
+                Self::discard(ex_stack.pull(), ex_list);
+            }
+            DUP => {
+                let value = ex_stack.pull();
+                ex_stack.put(value.clone());
+                ex_stack.put(value);
+            }
+            DUP_X1 => {
+                let value1 = ex_stack.pull();
+                let value2 = ex_stack.pull();
+                ex_stack.put(value1.clone());
+                ex_stack.put(value2);
+                ex_stack.put(value1);
+            }
+            DUP_X2 => {
+                let value1 = ex_stack.pull();
+                let value2 = ex_stack.pull();
+                let value3 = if value2.is_wide(const_pool) {
+                    None
+                } else {
+                    Some(ex_stack.pull())
+                };
+                ex_stack.put(value1.clone());
+                if let Some(value3) = value3 {
+                    ex_stack.put(value3);
+                }
+                ex_stack.put(value2);
+                ex_stack.put(value1);
+            }
+            DUP2 => {
+                if wide(ex_stack) {
+                    let value = ex_stack.pull();
+                    ex_stack.put(value.clone());
+                    ex_stack.put(value);
+                } else {
+                    let value1 = ex_stack.pull();
+                    let value2 = ex_stack.pull();
+                    ex_stack.put(value2.clone());
+                    ex_stack.put(value1.clone());
+                    ex_stack.put(value2);
+                    ex_stack.put(value1);
+                }
+            }
+            DUP2_X1 => {
+                if wide(ex_stack) {
+                    let value1 = ex_stack.pull();
+                    let value2 = ex_stack.pull();
+                    ex_stack.put(value1.clone());
+                    ex_stack.put(value2);
+                    ex_stack.put(value1);
+                } else {
+                    let value1 = ex_stack.pull();
+                    let value2 = ex_stack.pull();
+                    let value3 = ex_stack.pull();
+                    ex_stack.put(value2.clone());
+                    ex_stack.put(value1.clone());
+                    ex_stack.put(value3);
+                    ex_stack.put(value2);
+                    ex_stack.put(value1);
+                }
+            }
+            DUP2_X2 => {
+                // the top one or two values are copied below the next one or two
+                let mut top = vec![ex_stack.pull()];
+                if !top[0].is_wide(const_pool) {
+                    top.push(ex_stack.pull());
+                }
+                let mut below = vec![ex_stack.pull()];
+                if !below[0].is_wide(const_pool) {
+                    below.push(ex_stack.pull());
+                }
+                for value in top.iter().rev() {
+                    ex_stack.put(value.clone());
+                }
+                for value in below.into_iter().rev() {
+                    ex_stack.put(value);
+                }
+                for value in top.into_iter().rev() {
+                    ex_stack.put(value);
+                }
+            }
+            SWAP => {
+                let value1 = ex_stack.pull();
+                let value2 = ex_stack.pull();
+                ex_stack.put(value1);
+                ex_stack.put(value2);
+            }
+            _ => {}
+        }
+    }
+
+    // A popped value is only kept when it does something, like a call whose result is unused.
+    fn discard(value: Expression, ex_list: &mut ExList) {
+        match value.ex {
+            ExpressionType::ConstantEx { .. }
+            | ExpressionType::NumberConstant { .. }
+            | ExpressionType::PoolConstantEx { .. }
+            | ExpressionType::LoadVarEx { .. }
+            | ExpressionType::CatchEx => {}
+            _ => ex_list.put(value),
+        }
+    }
+
+    // Whether a value which was pushed before the chunk started is used.
+    pub fn pulls_stack(&self) -> bool {
+        matches!(self.ex, ExpressionType::StackEx)
+            || self.children().iter().any(|child| child.pulls_stack())
+    }
+
+    // The expressions right inside this one, in the order they are evaluated.
+    pub fn children(&self) -> Vec<&Expression> {
+        match &self.ex {
+            ExpressionType::OperatorEx { left, right }
+            | ExpressionType::CompareEx { left, right }
+            | ExpressionType::IfEx { left, right }
+            | ExpressionType::AndEx { left, right }
+            | ExpressionType::OrEx { left, right } => vec![left, right],
+            ExpressionType::NegateEx { value }
+            | ExpressionType::StoreVarEx { value, .. }
+            | ExpressionType::InstanceOfEx { value, .. }
+            | ExpressionType::If0Ex { value }
+            | ExpressionType::CastEx { value, .. }
+            | ExpressionType::SwitchEx { value }
+            | ExpressionType::ReturnValueEx { value }
+            | ExpressionType::PutStaticFieldEx { value, .. } => vec![value],
+            ExpressionType::ConvertEx { original } => vec![original],
+            ExpressionType::NewPrimArrayEx { count, .. }
+            | ExpressionType::NewArrayEx { count, .. } => vec![count],
+            ExpressionType::ThrowEx { throwable } => vec![throwable],
+            ExpressionType::MonitorEx { lock } => vec![lock],
+            ExpressionType::GetFieldEx { object, .. } => vec![object],
+            ExpressionType::ArrayLengthEx { array } => vec![array],
+            ExpressionType::ArrayLoadEx { array, index } => vec![array, index],
+            ExpressionType::ArrayStoreEx {
+                array,
+                index,
+                value,
+            } => vec![array, index, value],
+            ExpressionType::PutFieldEx { object, value, .. } => vec![object, value],
+            ExpressionType::TernaryEx {
+                condition,
+                then,
+                otherwise,
+            } => vec![condition, then, otherwise],
+            ExpressionType::ConstructEx { arguments, .. } => arguments.iter().collect(),
+            ExpressionType::MultiNewArrayEx { counts, .. } => counts.iter().collect(),
+            ExpressionType::ArrayInitEx { array, values } => {
+                let mut children = vec![array.as_ref()];
+                children.extend(values);
+                children
+            }
+            ExpressionType::MethodEx {
+                object, arguments, ..
+            } => {
+                let mut children: Vec<&Expression> =
+                    object.iter().map(|object| object.as_ref()).collect();
+                children.extend(arguments);
+                children
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // The same, to change them.
+    pub fn children_mut(&mut self) -> Vec<&mut Expression> {
+        match &mut self.ex {
+            ExpressionType::OperatorEx { left, right }
+            | ExpressionType::CompareEx { left, right }
+            | ExpressionType::IfEx { left, right }
+            | ExpressionType::AndEx { left, right }
+            | ExpressionType::OrEx { left, right } => vec![left, right],
+            ExpressionType::NegateEx { value }
+            | ExpressionType::StoreVarEx { value, .. }
+            | ExpressionType::InstanceOfEx { value, .. }
+            | ExpressionType::If0Ex { value }
+            | ExpressionType::CastEx { value, .. }
+            | ExpressionType::SwitchEx { value }
+            | ExpressionType::ReturnValueEx { value }
+            | ExpressionType::PutStaticFieldEx { value, .. } => vec![value],
+            ExpressionType::ConvertEx { original } => vec![original],
+            ExpressionType::NewPrimArrayEx { count, .. }
+            | ExpressionType::NewArrayEx { count, .. } => vec![count],
+            ExpressionType::ThrowEx { throwable } => vec![throwable],
+            ExpressionType::MonitorEx { lock } => vec![lock],
+            ExpressionType::GetFieldEx { object, .. } => vec![object],
+            ExpressionType::ArrayLengthEx { array } => vec![array],
+            ExpressionType::ArrayLoadEx { array, index } => vec![array, index],
+            ExpressionType::ArrayStoreEx {
+                array,
+                index,
+                value,
+            } => vec![array, index, value],
+            ExpressionType::PutFieldEx { object, value, .. } => vec![object, value],
+            ExpressionType::TernaryEx {
+                condition,
+                then,
+                otherwise,
+            } => vec![condition, then, otherwise],
+            ExpressionType::ConstructEx { arguments, .. } => arguments.iter_mut().collect(),
+            ExpressionType::MultiNewArrayEx { counts, .. } => counts.iter_mut().collect(),
+            ExpressionType::ArrayInitEx { array, values } => {
+                let mut children = vec![array.as_mut()];
+                children.extend(values);
+                children
+            }
+            ExpressionType::MethodEx {
+                object, arguments, ..
+            } => {
+                let mut children: Vec<&mut Expression> =
+                    object.iter_mut().map(|object| object.as_mut()).collect();
+                children.extend(arguments);
+                children
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Whether the value is a long or a double, which take two slots on the stack.
+    pub fn is_wide(&self, const_pool: &ConstantPool) -> bool {
+        let descriptor_wide =
+            |descriptor: &str| descriptor.ends_with('J') || descriptor.ends_with('D');
+        match &self.ex {
+            ExpressionType::ConstantEx { op } => {
+                matches!(*op, LCONST_0 | LCONST_1 | DCONST_0 | DCONST_1)
+            }
+            ExpressionType::PoolConstantEx { pool_pos } => matches!(
+                const_pool.get(*pool_pos),
+                Some(ConstantInfo::Long { .. } | ConstantInfo::Double { .. })
+            ),
+            ExpressionType::OperatorEx { .. } | ExpressionType::NegateEx { .. } => match self.op {
+                IADD..=DNEG => (self.op - IADD) % 4 == 1 || (self.op - IADD) % 4 == 3,
+                _ => (self.op - ISHL) % 2 == 1,
+            },
+            ExpressionType::ConvertEx { .. } => {
+                matches!(self.op, I2L | I2D | L2D | F2L | F2D | D2L)
+            }
+            ExpressionType::LoadVarEx { .. } => matches!(self.op, LLOAD | DLOAD),
+            ExpressionType::ArrayLoadEx { .. } => matches!(self.op, LALOAD | DALOAD),
+            ExpressionType::StoreVarEx { value, .. }
+            | ExpressionType::ArrayStoreEx { value, .. }
+            | ExpressionType::PutFieldEx { value, .. }
+            | ExpressionType::PutStaticFieldEx { value, .. } => value.is_wide(const_pool),
+            // both ways leave the same type
+            ExpressionType::TernaryEx { then, .. } => then.is_wide(const_pool),
+            ExpressionType::GetFieldEx { pool_pos, .. }
+            | ExpressionType::GetStaticFieldEx { pool_pos } => const_pool
+                .member_ref(*pool_pos)
+                .is_ok_and(|member| descriptor_wide(member.descriptor)),
+            ExpressionType::MethodEx { pool_pos, kind, .. } => {
+                let descriptor = if *kind == InvokeKind::Dynamic {
+                    const_pool
+                        .invoke_dynamic(*pool_pos)
+                        .map(|dynamic| dynamic.descriptor)
+                } else {
+                    const_pool
+                        .member_ref(*pool_pos)
+                        .map(|member| member.descriptor)
+                };
+                descriptor.is_ok_and(descriptor_wide)
+            }
+            _ => false,
+        }
+    }
+}
+
+// The condition of a jump which is taken when the original is not.
+pub fn negate(condition: Expression) -> Expression {
+    // !(a && b) is !a || !b and the other way around, and !(a ? b : c) is a ? !b : !c
+    let ex = match condition.ex {
+        ExpressionType::AndEx { left, right } => ExpressionType::OrEx {
+            left: Box::new(negate(*left)),
+            right: Box::new(negate(*right)),
+        },
+        ExpressionType::OrEx { left, right } => ExpressionType::AndEx {
+            left: Box::new(negate(*left)),
+            right: Box::new(negate(*right)),
+        },
+        ExpressionType::TernaryEx {
+            condition,
+            then,
+            otherwise,
+        } => ExpressionType::TernaryEx {
+            condition,
+            then: Box::new(negate(*then)),
+            otherwise: Box::new(negate(*otherwise)),
+        },
+        ex => ex,
+    };
+    let condition = Expression { ex, ..condition };
+    let op = match condition.op {
+        IFEQ => IFNE,
+        IFNE => IFEQ,
+        IFLT => IFGE,
+        IFGE => IFLT,
+        IFGT => IFLE,
+        IFLE => IFGT,
+        IF_ICMPEQ => IF_ICMPNE,
+        IF_ICMPNE => IF_ICMPEQ,
+        IF_ICMPLT => IF_ICMPGE,
+        IF_ICMPGE => IF_ICMPLT,
+        IF_ICMPGT => IF_ICMPLE,
+        IF_ICMPLE => IF_ICMPGT,
+        IF_ACMPEQ => IF_ACMPNE,
+        IF_ACMPNE => IF_ACMPEQ,
+        IFNULL => IFNONNULL,
+        IFNONNULL => IFNULL,
+        op => op,
+    };
+    Expression { op, ..condition }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompError {
+    Subroutine { error: SubroutineError },
+    // The control flow has loops with more than one entry which could not be split apart.
+    Irreducible,
+    // A value on the stack at the start of a chunk which was never found, like one left by a
+    // construct which is not known.
+    UnresolvedStack,
+    // The nodes of a try are not nested with the loops and the other tries.
+    TangledTry,
+    // A try has code in its body which its handlers do not protect, like a copy of a finally
+    // which was not found.
+    UnprotectedCode,
+    // A monitor is entered or exited other than around a synchronized block.
+    UnpairedMonitor,
+}
+
+pub fn compute(code: &mut Code, const_pool: &ConstantPool) -> Result<Vec<Statement>, DecompError> {
+    // jsr and ret can not be structured, so old finally blocks are copied into their callers
+    inline_subroutines(code).map_err(|error| DecompError::Subroutine { error })?;
+    // The values a chunk starts with, the exception for a handler and the values left by a ?:
+    // or by the only chunk before it once they are found. The nodes are built again every time
+    // some are found, so nothing done to that chunk before is lost.
+    let mut seeds: HashMap<u32, Vec<Expression>> = HashMap::new();
+    for chunk in &code.code_chunks {
+        for handler in &chunk.handlers {
+            let start = code.code_chunks[handler.chunk_pos as usize].start;
+            let caught = Expression {
+                op: NOP,
+                pc: code.code[start as usize].pc,
+                ex: ExpressionType::CatchEx,
+            };
+            seeds.insert(handler.chunk_pos, vec![caught]);
+        }
+    }
+    let mut builds = 0;
+    let nodes = 'build: loop {
+        builds += 1;
+        let mut nodes = Vec::with_capacity(code.code_chunks.len());
+        let mut stacks = Vec::with_capacity(code.code_chunks.len());
+        for chunk_pos in 0..code.code_chunks.len() as u32 {
+            let values = seeds.get(&chunk_pos).cloned().unwrap_or_default();
+            let (node, stack) = build_node(code, const_pool, chunk_pos, values);
+            nodes.push(node);
+            stacks.push(stack);
+        }
+        skip_gotos(&mut nodes, &stacks);
+        // a ?: can have a && in its condition, and a condition can test a ?:
+        loop {
+            short_circuits(&mut nodes);
+            match ternaries(&mut nodes, &mut stacks, &mut seeds) {
+                Pass::Same
+                    if builds <= code.code_chunks.len() && carry(&nodes, &stacks, &mut seeds) =>
+                {
+                    continue 'build
+                }
+                Pass::Same => break 'build nodes,
+                Pass::Changed => {}
+                Pass::Seeded if builds > code.code_chunks.len() => break 'build nodes,
+                Pass::Seeded => continue 'build,
+            }
+        }
+    };
+
+    let body = structure(nodes, const_pool)?;
+    if pulls_stack(&body) {
+        return Err(DecompError::UnresolvedStack);
+    }
+    Ok(body)
 }
 
-pub fn compute(code: &Code) {
+// The node of a chunk, which starts with the given values on the stack. The values which are
+// left at the end of the chunk are given back.
+fn build_node(
+    code: &Code,
+    const_pool: &ConstantPool,
+    chunk_pos: u32,
+    values: Vec<Expression>,
+) -> (Node, Vec<Expression>) {
     let chunks = &code.code_chunks;
+    let chunk = &chunks[chunk_pos as usize];
 
     // The stack holds expressions basically like the jvm executes values.
     // Every expression is here until it finds a home at another expression.
     // which later goes to the ex_list.
-    let mut ex_stack: VecDeque<ExpressionType> = VecDeque::new();
+    let mut ex_stack = ExStack::new();
+    for value in values {
+        ex_stack.put(value);
+    }
 
     // Holds all of the expressions which are final and will be included in the final print.
-    let mut ex_list: Vec<ExpressionType> = Vec::new();
+    let mut ex_list = ExList::new();
 
-    // Iterate through all of the chunks
-    for chunk in chunks {
-        for i in chunk.start..chunk.stop {}
+    for op_pos in chunk.start..chunk.stop {
+        Expression::create(
+            &code.code[op_pos as usize],
+            &mut ex_stack,
+            &mut ex_list,
+            const_pool,
+        );
+    }
+
+    let next = chunk_pos + 1;
+    let has_next = (next as usize) < chunks.len();
+    let mut list = ex_list.list;
+    let exit = match &chunk.target {
+        CodeChunkTarget::Basic if has_next => NodeExit::Goto { target: next },
+        CodeChunkTarget::Goto { chunk_pos } => NodeExit::Goto { target: *chunk_pos },
+        CodeChunkTarget::Condition { true_chunk_pos } => NodeExit::Condition {
+            condition: list.pop().unwrap(),
+            true_target: *true_chunk_pos,
+            false_target: next,
+        },
+        CodeChunkTarget::Switch {
+            default_chunk_pos,
+            targets,
+        } => {
+            let value = match list.pop().unwrap().ex {
+                ExpressionType::SwitchEx { value } => *value,
+                _ => unreachable!(),
+            };
+            let keys = match &code.code[chunk.stop as usize - 1].inst {
+                Instruction::SwitchJump { cases, .. } => {
+                    cases.iter().map(|(key, _)| *key).collect()
+                }
+                _ => Vec::new(),
+            };
+            NodeExit::Switch {
+                value,
+                cases: keys.into_iter().zip(targets.iter().copied()).collect(),
+                default: *default_chunk_pos,
+            }
+        }
+        _ => NodeExit::End,
+    };
+    // javac has the handler of a finally cover its own store of the exception, which can not throw
+    let handlers = chunk
+        .handlers
+        .iter()
+        .filter(|handler| handler.chunk_pos != chunk_pos)
+        .map(|handler| (handler.chunk_pos, handler.catch_type))
+        .collect();
+    let pcs = code.code[chunk.start as usize].pc..code.code[chunk.stop as usize - 1].pc + 1;
+    (
+        Node {
+            list,
+            exit,
+            handlers,
+            pcs,
+        },
+        ex_stack.values(),
+    )
+}
+
+// Jumps to a node which only jumps on go right where it jumps to, so the ways out of a
+// condition can be compared. A node which pushes values is kept, those are a part of a ?:.
+fn skip_gotos(nodes: &mut [Node], stacks: &[Vec<Expression>]) {
+    let forward = |node: u32| match &nodes[node as usize] {
+        Node {
+            list,
+            exit: NodeExit::Goto { target },
+            ..
+        } if list.is_empty() && stacks[node as usize].is_empty() => Some(*target),
+        _ => None,
+    };
+    let mut destinations: Vec<u32> = (0..nodes.len() as u32).collect();
+    for (node, destination) in destinations.iter_mut().enumerate() {
+        // a loop of gotos never gets anywhere, so it is left alone
+        for _ in 0..nodes.len() {
+            match forward(*destination) {
+                Some(target) if target != node as u32 => *destination = target,
+                _ => break,
+            }
+        }
+    }
+    for node in nodes.iter_mut() {
+        for target in node.exit.targets_mut() {
+            *target = destinations[*target as usize];
+        }
+    }
+}
+
+// What a look for ?: did to the nodes.
+enum Pass {
+    Same,
+    Changed,
+    // The values at the start of a chunk were found, so it has to be built again.
+    Seeded,
+}
+
+// A condition where both ways only push a value and then go to the same node is a ?:. The
+// condition leaves the ?: on its stack and goes right there, and when nothing else goes there
+// that node is joined to the condition, once it is built with the values it was missing.
+fn ternaries(
+    nodes: &mut [Node],
+    stacks: &mut [Vec<Expression>],
+    seeds: &mut HashMap<u32, Vec<Expression>>,
+) -> Pass {
+    let mut predecessors = vec![0; nodes.len()];
+    for targets in reachable_successors(nodes) {
+        for target in targets {
+            predecessors[target as usize] += 1;
+        }
+    }
+    let mut pass = Pass::Same;
+    for node in 0..nodes.len() {
+        let (true_target, false_target) = match &nodes[node].exit {
+            NodeExit::Condition {
+                true_target,
+                false_target,
+                ..
+            } if predecessors[node] > 0 || node == 0 => (*true_target, *false_target),
+            _ => continue,
+        };
+        let join = match (
+            arm(nodes, stacks, &predecessors, true_target),
+            arm(nodes, stacks, &predecessors, false_target),
+        ) {
+            (Some(join), Some(other)) if join == other => join,
+            _ => continue,
+        };
+        if [node as u32, true_target, false_target].contains(&join)
+            || true_target == false_target
+            || [true_target, false_target]
+                .iter()
+                .any(|target| nodes[*target as usize].handlers != nodes[node].handlers)
+        {
+            continue;
+        }
+        let condition = match mem::replace(&mut nodes[node].exit, NodeExit::Goto { target: join }) {
+            NodeExit::Condition { condition, .. } => condition,
+            _ => unreachable!(),
+        };
+        // the compiler jumps over the then part, like it does for an if
+        let then = stacks[false_target as usize].pop().unwrap();
+        let otherwise = stacks[true_target as usize].pop().unwrap();
+        let pc = condition.pc;
+        stacks[node].push(Expression {
+            op: NOP,
+            pc,
+            ex: ExpressionType::TernaryEx {
+                condition: Box::new(negate(condition)),
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+            },
+        });
+        for target in [true_target, false_target] {
+            nodes[target as usize].exit = NodeExit::End;
+            predecessors[target as usize] = 0;
+        }
+        predecessors[join as usize] -= 1;
+        // a node in another try can't be joined
+        if predecessors[join as usize] == 1
+            && join != 0
+            && nodes[join as usize].handlers == nodes[node].handlers
+        {
+            if seeds.get(&join) != Some(&stacks[node]) {
+                seeds.insert(join, stacks[node].clone());
+                return Pass::Seeded;
+            }
+            let joined = mem::replace(
+                &mut nodes[join as usize],
+                Node {
+                    list: Vec::new(),
+                    exit: NodeExit::End,
+                    handlers: Vec::new(),
+                    pcs: 0..0,
+                },
+            );
+            nodes[node].list.extend(joined.list);
+            nodes[node].exit = joined.exit;
+            nodes[node].pcs = joined_pcs(&nodes[node].pcs, &joined.pcs);
+            stacks[node] = mem::take(&mut stacks[join as usize]);
+            predecessors[join as usize] = 0;
+        }
+        pass = Pass::Changed;
+    }
+    pass
+}
+
+// Values which are left on the stack when a node goes on to a node which nothing else goes to,
+// like when a protected range ends right before the return of a value. Tells if any were found.
+fn carry(
+    nodes: &[Node],
+    stacks: &[Vec<Expression>],
+    seeds: &mut HashMap<u32, Vec<Expression>>,
+) -> bool {
+    let mut predecessors = vec![0; nodes.len()];
+    for targets in reachable_successors(nodes) {
+        for target in targets {
+            predecessors[target as usize] += 1;
+        }
+    }
+    let mut found = false;
+    for (node, stack) in stacks.iter().enumerate() {
+        if let NodeExit::Goto { target } = nodes[node].exit {
+            if !stack.is_empty()
+                && predecessors[target as usize] == 1
+                && target != 0
+                && seeds.get(&target) != Some(stack)
+            {
+                seeds.insert(target, stack.clone());
+                found = true;
+            }
+        }
+    }
+    found
+}
+
+// Where a node goes when it only pushes one value, which doesn't need a value pushed before it.
+fn arm(
+    nodes: &[Node],
+    stacks: &[Vec<Expression>],
+    predecessors: &[usize],
+    node: u32,
+) -> Option<u32> {
+    match &nodes[node as usize] {
+        Node {
+            list,
+            exit: NodeExit::Goto { target },
+            ..
+        } if list.is_empty()
+            && predecessors[node as usize] == 1
+            && stacks[node as usize].len() == 1
+            && !stacks[node as usize][0].pulls_stack() =>
+        {
+            Some(*target)
+        }
+        _ => None,
     }
 }
@@ -0,0 +1,1085 @@
+use std::fmt::{self, Write};
+
+use crate::consts::{self, *};
+use crate::java::{
+    Attribute, AttributeInfo, ClassInfo, ConstantInfo, ConstantPool, Literal, PoolError, StackMapFrame,
+    VerificationType,
+};
+use crate::java_code::{Code, Instruction, InvokeKind, Op};
+use crate::java_type::{MethodType, Type};
+
+// Prints a class like `javap -c -v -p` does, minus the file information at the top.
+// Generic signatures are not applied to the declarations, they show up as Signature attributes.
+pub fn disassemble(class_info: &ClassInfo) -> String {
+    let mut out = String::new();
+    Disassembler::new(class_info)
+        .class(&mut out)
+        .expect("writing to a String can not fail");
+    out
+}
+
+// One op the way it shows up in the code listing, with its comment but without the pc.
+pub fn disassemble_op(class_info: &ClassInfo, op: &Op, op_bytes: &[u32]) -> String {
+    let disassembler = Disassembler::new(class_info);
+    let line = disassembler.op(op, op_bytes);
+    match disassembler.op_comment(op) {
+        Some(comment) => format!("{} // {}", line, comment),
+        None => line,
+    }
+}
+
+const CLASS_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "ACC_PUBLIC"),
+    (0x0010, "ACC_FINAL"),
+    (0x0020, "ACC_SUPER"),
+    (0x0200, "ACC_INTERFACE"),
+    (0x0400, "ACC_ABSTRACT"),
+    (0x1000, "ACC_SYNTHETIC"),
+    (0x2000, "ACC_ANNOTATION"),
+    (0x4000, "ACC_ENUM"),
+];
+
+const FIELD_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "ACC_PUBLIC"),
+    (0x0002, "ACC_PRIVATE"),
+    (0x0004, "ACC_PROTECTED"),
+    (0x0008, "ACC_STATIC"),
+    (0x0010, "ACC_FINAL"),
+    (0x0040, "ACC_VOLATILE"),
+    (0x0080, "ACC_TRANSIENT"),
+    (0x1000, "ACC_SYNTHETIC"),
+    (0x4000, "ACC_ENUM"),
+];
+
+const METHOD_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "ACC_PUBLIC"),
+    (0x0002, "ACC_PRIVATE"),
+    (0x0004, "ACC_PROTECTED"),
+    (0x0008, "ACC_STATIC"),
+    (0x0010, "ACC_FINAL"),
+    (0x0020, "ACC_SYNCHRONIZED"),
+    (0x0040, "ACC_BRIDGE"),
+    (0x0080, "ACC_VARARGS"),
+    (0x0100, "ACC_NATIVE"),
+    (0x0400, "ACC_ABSTRACT"),
+    (0x0800, "ACC_STRICT"),
+    (0x1000, "ACC_SYNTHETIC"),
+];
+
+pub const FIELD_MODIFIERS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0040, "volatile"),
+    (0x0080, "transient"),
+];
+
+pub const METHOD_MODIFIERS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0020, "synchronized"),
+    (0x0100, "native"),
+    (0x0400, "abstract"),
+];
+
+const INNER_CLASS_MODIFIERS: &[(u16, &str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0400, "abstract"),
+];
+
+fn flag_names(bits: u16, names: &[(u16, &'static str)]) -> Vec<&'static str> {
+    names
+        .iter()
+        .filter(|(flag, _)| bits & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+// "flags: (0x0021) ACC_PUBLIC, ACC_SUPER"
+fn flags_line(bits: u16, names: &[(u16, &'static str)]) -> String {
+    let names = flag_names(bits, names);
+    if names.is_empty() {
+        format!("flags: (0x{:04x})", bits)
+    } else {
+        format!("flags: (0x{:04x}) {}", bits, names.join(", "))
+    }
+}
+
+pub fn modifiers(bits: u16, names: &[(u16, &'static str)]) -> String {
+    flag_names(bits, names)
+        .iter()
+        .map(|name| format!("{} ", name))
+        .collect()
+}
+
+// Comments start at a fixed column unless the text before them is too long.
+fn with_comment(text: &str, width: usize, comment: &str) -> String {
+    format!(
+        "{:<width$} // {}",
+        text,
+        comment,
+        width = width.max(text.len() + 1) - 1
+    )
+}
+
+// Escapes text the way javap prints Utf8 constants.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\'"),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Java's Double.toString, plain between 10^-3 and 10^7 and scientific otherwise.
+pub fn java_double(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if value == 0.0 || (1e-3..1e7).contains(&value.abs()) {
+        format!("{:?}", value)
+    } else {
+        java_scientific(format!("{:e}", value))
+    }
+}
+
+pub fn java_float(value: f32) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if value == 0.0 || (1e-3..1e7).contains(&value.abs()) {
+        format!("{:?}", value)
+    } else {
+        java_scientific(format!("{:e}", value))
+    }
+}
+
+// 1.5e20 becomes 1.5E20 and 1e20 becomes 1.0E20.
+fn java_scientific(text: String) -> String {
+    match text.split_once('e') {
+        Some((mantissa, exponent)) if mantissa.contains('.') => {
+            format!("{}E{}", mantissa, exponent)
+        }
+        Some((mantissa, exponent)) => format!("{}.0E{}", mantissa, exponent),
+        None => text,
+    }
+}
+
+fn literal(literal: Literal) -> String {
+    match literal {
+        Literal::Integer(value) => value.to_string(),
+        Literal::Float(value) => format!("{}f", java_float(value)),
+        Literal::Long(value) => format!("{}l", value),
+        Literal::Double(value) => format!("{}d", java_double(value)),
+    }
+}
+
+fn reference_kind_name(kind: ReferenceKind) -> &'static str {
+    match kind {
+        ReferenceKind::GetField => "REF_getField",
+        ReferenceKind::GetStatic => "REF_getStatic",
+        ReferenceKind::PutField => "REF_putField",
+        ReferenceKind::PutStatic => "REF_putStatic",
+        ReferenceKind::InvokeVirtual => "REF_invokeVirtual",
+        ReferenceKind::InvokeStatic => "REF_invokeStatic",
+        ReferenceKind::InvokeSpecial => "REF_invokeSpecial",
+        ReferenceKind::NewInvokeSpecial => "REF_newInvokeSpecial",
+        ReferenceKind::InvokeInterface => "REF_invokeInterface",
+    }
+}
+
+// Jumps are printed as the byte position of the op they land on.
+fn jump_target(op_bytes: &[u32], op_pos: u32) -> String {
+    op_bytes
+        .get(op_pos as usize)
+        .map(|op_byte| op_byte.to_string())
+        .unwrap_or_else(|| format!("<invalid op {}>", op_pos))
+}
+
+// Array class names are quoted, as are special method names like "<init>".
+fn quote(name: &str) -> String {
+    if name.starts_with('[') || name.starts_with('<') {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+struct Disassembler<'a> {
+    class_info: &'a ClassInfo,
+    pool: &'a ConstantPool,
+    this_name: &'a str,
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(class_info: &'a ClassInfo) -> Self {
+        Disassembler {
+            class_info,
+            pool: &class_info.constant_pool,
+            this_name: class_info
+                .constant_pool
+                .class_name(class_info.this_class)
+                .unwrap_or(""),
+        }
+    }
+
+    fn invalid(error: PoolError) -> String {
+        format!("<invalid: {:?}>", error)
+    }
+
+    fn class_name(&self, index: u16) -> String {
+        self.pool
+            .class_name(index)
+            .map(quote)
+            .unwrap_or_else(Self::invalid)
+    }
+
+    fn java_class_name(&self, index: u16) -> String {
+        self.pool
+            .class_name(index)
+            .map(|name| name.replace('/', "."))
+            .unwrap_or_else(Self::invalid)
+    }
+
+    fn utf8(&self, index: u16) -> String {
+        self.pool
+            .utf8(index)
+            .map(escape)
+            .unwrap_or_else(Self::invalid)
+    }
+
+    // owner.name:descriptor, the owner is left out for members of this class inside code.
+    fn member(&self, index: u16, in_code: bool) -> String {
+        match self.pool.member_ref(index) {
+            Ok(member) if in_code && member.owner == self.this_name => {
+                format!("{}:{}", quote(member.name), member.descriptor)
+            }
+            Ok(member) => format!(
+                "{}.{}:{}",
+                quote(member.owner),
+                quote(member.name),
+                member.descriptor
+            ),
+            Err(error) => Self::invalid(error),
+        }
+    }
+
+    fn name_and_type(&self, index: u16) -> String {
+        match self.pool.name_and_type(index) {
+            Ok((name, descriptor)) => format!("{}:{}", quote(name), descriptor),
+            Err(error) => Self::invalid(error),
+        }
+    }
+
+    fn method_handle(&self, index: u16, in_code: bool) -> String {
+        match (self.pool.method_handle(index), self.pool.get(index)) {
+            (
+                Ok(handle),
+                Some(ConstantInfo::MethodHandle {
+                    reference_index, ..
+                }),
+            ) => format!(
+                "{} {}",
+                reference_kind_name(handle.kind),
+                self.member(*reference_index, in_code)
+            ),
+            (Err(error), _) => Self::invalid(error),
+            _ => Self::invalid(PoolError::InvalidIndex { index }),
+        }
+    }
+
+    fn invoke_dynamic(&self, index: u16) -> String {
+        match self.pool.invoke_dynamic(index) {
+            Ok(dynamic) => format!(
+                "#{}:{}:{}",
+                dynamic.bootstrap_method_attr_index,
+                quote(dynamic.name),
+                dynamic.descriptor
+            ),
+            Err(error) => Self::invalid(error),
+        }
+    }
+
+    // A loadable constant, as it is shown behind ldc and in ConstantValue.
+    fn constant(&self, index: u16) -> String {
+        match self.pool.get(index) {
+            Some(ConstantInfo::Integer { .. }) => format!("int {}", self.literal(index)),
+            Some(ConstantInfo::Float { .. }) => format!("float {}", self.literal(index)),
+            Some(ConstantInfo::Long { .. }) => format!("long {}", self.literal(index)),
+            Some(ConstantInfo::Double { .. }) => format!("double {}", self.literal(index)),
+            Some(ConstantInfo::String { string_index }) => {
+                format!("String {}", self.utf8(*string_index))
+            }
+            Some(ConstantInfo::Class { .. }) => format!("class {}", self.class_name(index)),
+            Some(ConstantInfo::MethodType { descriptor_index }) => {
+                format!("MethodType {}", self.utf8(*descriptor_index))
+            }
+            Some(ConstantInfo::MethodHandle { .. }) => {
+                format!("MethodHandle {}", self.method_handle(index, true))
+            }
+            Some(info) => Self::invalid(PoolError::KindMismatch {
+                index,
+                expected: "loadable constant",
+                found: info.kind_name(),
+            }),
+            None => Self::invalid(PoolError::InvalidIndex { index }),
+        }
+    }
+
+    fn literal(&self, index: u16) -> String {
+        self.pool
+            .literal(index)
+            .map(literal)
+            .unwrap_or_else(Self::invalid)
+    }
+
+    fn class(&self, out: &mut String) -> fmt::Result {
+        let class_info = self.class_info;
+        let access_flags = class_info.access_flags.bits();
+
+        for attribute in &class_info.attributes {
+            if let AttributeInfo::SourceFile { source_file_index } = &attribute.info {
+                writeln!(out, "  Compiled from \"{}\"", self.utf8(*source_file_index))?;
+            }
+        }
+
+        let mut declaration = String::new();
+        if access_flags & 0x0001 != 0 {
+            declaration.push_str("public ");
+        }
+        if access_flags & 0x0200 != 0 {
+            declaration.push_str("interface ");
+        } else {
+            if access_flags & 0x0400 != 0 {
+                declaration.push_str("abstract ");
+            }
+            if access_flags & 0x0010 != 0 {
+                declaration.push_str("final ");
+            }
+            declaration.push_str("class ");
+        }
+        declaration.push_str(&self.java_class_name(class_info.this_class));
+        if class_info.super_class != 0
+            && self.pool.class_name(class_info.super_class) != Ok("java/lang/Object")
+        {
+            write!(
+                declaration,
+                " extends {}",
+                self.java_class_name(class_info.super_class)
+            )?;
+        }
+        if !class_info.interfaces.is_empty() {
+            let interfaces: Vec<String> = class_info
+                .interfaces
+                .iter()
+                .map(|interface| self.java_class_name(*interface))
+                .collect();
+            let keyword = if access_flags & 0x0200 != 0 {
+                "extends"
+            } else {
+                "implements"
+            };
+            write!(declaration, " {} {}", keyword, interfaces.join(", "))?;
+        }
+        writeln!(out, "{}", declaration)?;
+
+        writeln!(out, "  minor version: {}", class_info.minor_version)?;
+        writeln!(out, "  major version: {}", class_info.major_version)?;
+        writeln!(out, "  {}", flags_line(access_flags, CLASS_FLAGS))?;
+        writeln!(
+            out,
+            "  {}",
+            with_comment(
+                &format!("this_class: #{}", class_info.this_class),
+                40,
+                &self.class_name(class_info.this_class)
+            )
+        )?;
+        if class_info.super_class == 0 {
+            writeln!(out, "  super_class: #0")?;
+        } else {
+            writeln!(
+                out,
+                "  {}",
+                with_comment(
+                    &format!("super_class: #{}", class_info.super_class),
+                    40,
+                    &self.class_name(class_info.super_class)
+                )
+            )?;
+        }
+        writeln!(
+            out,
+            "  interfaces: {}, fields: {}, methods: {}, attributes: {}",
+            class_info.interfaces.len(),
+            class_info.fields.len(),
+            class_info.methods.len(),
+            class_info.attributes.len()
+        )?;
+
+        self.constant_pool(out)?;
+
+        writeln!(out, "{{")?;
+        let mut first = true;
+        for field in &class_info.fields {
+            if !first {
+                writeln!(out)?;
+            }
+            first = false;
+
+            let bits = field.access_flags.bits();
+            let typ = self
+                .pool
+                .utf8(field.descriptor_index)
+                .ok()
+                .and_then(Type::parse_str)
+                .map(|typ| typ.to_string())
+                .unwrap_or_else(|| self.utf8(field.descriptor_index));
+            writeln!(
+                out,
+                "  {}{} {};",
+                modifiers(bits, FIELD_MODIFIERS),
+                typ,
+                self.utf8(field.name_index)
+            )?;
+            writeln!(out, "    descriptor: {}", self.utf8(field.descriptor_index))?;
+            writeln!(out, "    {}", flags_line(bits, FIELD_FLAGS))?;
+            for attribute in &field.attribute_info {
+                self.attribute(out, attribute, 4, None)?;
+            }
+        }
+        for method in &class_info.methods {
+            if !first {
+                writeln!(out)?;
+            }
+            first = false;
+
+            let bits = method.access_flags.bits();
+            let name = self.pool.utf8(method.name_index).unwrap_or("");
+            let descriptor = self.pool.utf8(method.descriptor_index).unwrap_or("");
+            let method_type = MethodType::parse_str(descriptor);
+
+            let exceptions: Vec<String> = method
+                .attribute_info
+                .iter()
+                .filter_map(|attribute| match &attribute.info {
+                    AttributeInfo::Exceptions {
+                        exception_index_table,
+                    } => Some(exception_index_table),
+                    _ => None,
+                })
+                .flatten()
+                .map(|exception| self.java_class_name(*exception))
+                .collect();
+
+            let mut declaration = modifiers(bits, METHOD_MODIFIERS);
+            if name == "<clinit>" {
+                declaration = "static {}".to_string();
+            } else {
+                let parameters = match &method_type {
+                    Some(method_type) => {
+                        let mut parameters: Vec<String> = method_type
+                            .parameters
+                            .iter()
+                            .map(|parameter| parameter.to_string())
+                            .collect();
+                        // varargs are only shown as such on the last array parameter
+                        if bits & 0x0080 != 0 {
+                            if let Some(last) = parameters.last_mut() {
+                                if last.ends_with("[]") {
+                                    last.truncate(last.len() - 2);
+                                    last.push_str("...");
+                                }
+                            }
+                        }
+                        parameters.join(", ")
+                    }
+                    None => escape(descriptor),
+                };
+                if name == "<init>" {
+                    write!(
+                        declaration,
+                        "{}({})",
+                        self.java_class_name(class_info.this_class),
+                        parameters
+                    )?;
+                } else {
+                    let return_type = method_type
+                        .as_ref()
+                        .map(|method_type| method_type.return_type.to_string())
+                        .unwrap_or_default();
+                    write!(
+                        declaration,
+                        "{} {}({})",
+                        return_type,
+                        escape(name),
+                        parameters
+                    )?;
+                }
+                if !exceptions.is_empty() {
+                    write!(declaration, " throws {}", exceptions.join(", "))?;
+                }
+            }
+            writeln!(out, "  {};", declaration)?;
+            writeln!(out, "    descriptor: {}", escape(descriptor))?;
+            writeln!(out, "    {}", flags_line(bits, METHOD_FLAGS))?;
+
+            // javap counts the parameters, not the slots they take up.
+            let args_size = method_type
+                .map(|method_type| method_type.parameters.len() as u16)
+                .unwrap_or(0)
+                + if bits & 0x0008 != 0 { 0 } else { 1 };
+            for attribute in &method.attribute_info {
+                self.attribute(out, attribute, 4, Some(args_size))?;
+            }
+        }
+        writeln!(out, "}}")?;
+
+        for attribute in &class_info.attributes {
+            self.attribute(out, attribute, 0, None)?;
+        }
+        Ok(())
+    }
+
+    fn constant_pool(&self, out: &mut String) -> fmt::Result {
+        writeln!(out, "Constant pool:")?;
+        let count = self.pool.len();
+        // The index column is as wide as the largest index.
+        let index_width = format!("#{}", count).len() + 2;
+        for index in 1..=count {
+            let info = match self.pool.get(index) {
+                Some(ConstantInfo::Unusable) | None => continue,
+                Some(info) => info,
+            };
+            let (operand, comment) = match info {
+                ConstantInfo::Class { name_index } => {
+                    (format!("#{}", name_index), Some(self.class_name(index)))
+                }
+                ConstantInfo::Field {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantInfo::Method {
+                    class_index,
+                    name_and_type_index,
+                }
+                | ConstantInfo::Interface {
+                    class_index,
+                    name_and_type_index,
+                } => (
+                    format!("#{}.#{}", class_index, name_and_type_index),
+                    Some(self.member(index, false)),
+                ),
+                ConstantInfo::String { string_index } => {
+                    (format!("#{}", string_index), Some(self.utf8(*string_index)))
+                }
+                ConstantInfo::Integer { .. }
+                | ConstantInfo::Float { .. }
+                | ConstantInfo::Long { .. }
+                | ConstantInfo::Double { .. } => (self.literal(index), None),
+                ConstantInfo::NameAndType {
+                    name_index,
+                    descriptor_index,
+                } => (
+                    format!("#{}:#{}", name_index, descriptor_index),
+                    Some(self.name_and_type(index)),
+                ),
+                ConstantInfo::UTF8 { text, .. } => (escape(text), None),
+                ConstantInfo::MethodHandle {
+                    reference_kind,
+                    reference_index,
+                } => (
+                    format!("{}:#{}", reference_kind, reference_index),
+                    Some(self.method_handle(index, false)),
+                ),
+                // javap puts an extra space before method types
+                ConstantInfo::MethodType { descriptor_index } => (
+                    format!("#{}", descriptor_index),
+                    Some(format!(" {}", self.utf8(*descriptor_index))),
+                ),
+                ConstantInfo::InvokeDynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                } => (
+                    format!("#{}:#{}", bootstrap_method_attr_index, name_and_type_index),
+                    Some(self.invoke_dynamic(index)),
+                ),
+                ConstantInfo::Unusable => unreachable!(),
+            };
+            let line = format!(
+                "{:>width$} = {:<18} {}",
+                format!("#{}", index),
+                info.kind_name(),
+                operand,
+                width = index_width
+            );
+            match comment {
+                Some(comment) => writeln!(out, "{}", with_comment(&line, 42, &comment))?,
+                None => writeln!(out, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+
+    // args_size is only known for method attributes, it is printed along with the Code.
+    fn attribute(
+        &self,
+        out: &mut String,
+        attribute: &Attribute,
+        indent: usize,
+        args_size: Option<u16>,
+    ) -> fmt::Result {
+        let pad = " ".repeat(indent);
+        match &attribute.info {
+            AttributeInfo::ConstantValue { constant_index } => {
+                writeln!(
+                    out,
+                    "{}ConstantValue: {}",
+                    pad,
+                    self.constant(*constant_index)
+                )?;
+            }
+            AttributeInfo::CodeAttribute { code } => {
+                writeln!(out, "{}Code:", pad)?;
+                self.code(out, code, indent + 2, args_size.unwrap_or(0))?;
+            }
+            AttributeInfo::StackMapTable { entries } => {
+                writeln!(
+                    out,
+                    "{}StackMapTable: number_of_entries = {}",
+                    pad,
+                    entries.len()
+                )?;
+                for entry in entries {
+                    self.stack_map_frame(out, entry, indent + 2)?;
+                }
+            }
+            AttributeInfo::Exceptions {
+                exception_index_table,
+            } => {
+                writeln!(out, "{}Exceptions:", pad)?;
+                let exceptions: Vec<String> = exception_index_table
+                    .iter()
+                    .map(|exception| self.java_class_name(*exception))
+                    .collect();
+                writeln!(out, "{}  throws {}", pad, exceptions.join(", "))?;
+            }
+            AttributeInfo::InnerClasses { classes } => {
+                writeln!(out, "{}InnerClasses:", pad)?;
+                for class in classes {
+                    let mut line = modifiers(class.inner_class_access_flags, INNER_CLASS_MODIFIERS);
+                    let mut comment = String::new();
+                    if class.inner_name_index != 0 {
+                        write!(line, "#{}= ", class.inner_name_index)?;
+                        write!(comment, "{}=", self.utf8(class.inner_name_index))?;
+                    }
+                    write!(line, "#{}", class.inner_class_info_index)?;
+                    write!(
+                        comment,
+                        "class {}",
+                        self.class_name(class.inner_class_info_index)
+                    )?;
+                    if class.outer_class_info_index != 0 {
+                        write!(line, " of #{}", class.outer_class_info_index)?;
+                        write!(
+                            comment,
+                            " of class {}",
+                            self.class_name(class.outer_class_info_index)
+                        )?;
+                    }
+                    line.push(';');
+                    writeln!(out, "{}  {}", pad, with_comment(&line, 40, &comment))?;
+                }
+            }
+            AttributeInfo::EnclosingMethod {
+                class_index,
+                method_index,
+            } => {
+                let mut comment = self.class_name(*class_index);
+                if *method_index != 0 {
+                    write!(comment, ".{}", self.name_and_type(*method_index))?;
+                }
+                let line = format!("EnclosingMethod: #{}.#{}", class_index, method_index);
+                writeln!(out, "{}{}", pad, with_comment(&line, 40, &comment))?;
+            }
+            AttributeInfo::Synthetic => writeln!(out, "{}Synthetic: true", pad)?,
+            AttributeInfo::Deprecated => writeln!(out, "{}Deprecated: true", pad)?,
+            AttributeInfo::Signature { signature_index } => {
+                let line = format!("Signature: #{}", signature_index);
+                writeln!(
+                    out,
+                    "{}{}",
+                    pad,
+                    with_comment(&line, 40, &self.utf8(*signature_index))
+                )?;
+            }
+            AttributeInfo::SourceFile { source_file_index } => {
+                writeln!(
+                    out,
+                    "{}SourceFile: \"{}\"",
+                    pad,
+                    self.utf8(*source_file_index)
+                )?;
+            }
+            AttributeInfo::SourceDebugExtension { debug_extension } => {
+                writeln!(out, "{}SourceDebugExtension:", pad)?;
+                for line in String::from_utf8_lossy(debug_extension).lines() {
+                    writeln!(out, "{}  {}", pad, line)?;
+                }
+            }
+            AttributeInfo::LineNumberTable { line_number_table } => {
+                writeln!(out, "{}LineNumberTable:", pad)?;
+                for line in line_number_table {
+                    writeln!(out, "{}  line {}: {}", pad, line.line_number, line.start_pc)?;
+                }
+            }
+            AttributeInfo::LocalVariableTable {
+                local_variable_table,
+            } => {
+                writeln!(out, "{}LocalVariableTable:", pad)?;
+                writeln!(out, "{}  Start  Length  Slot  Name   Signature", pad)?;
+                for local in local_variable_table {
+                    writeln!(
+                        out,
+                        "{}  {:>5} {:>7} {:>5} {:>5}   {}",
+                        pad,
+                        local.start_pc,
+                        local.length,
+                        local.index,
+                        self.utf8(local.name_index),
+                        self.utf8(local.descriptor_index)
+                    )?;
+                }
+            }
+            AttributeInfo::LocalVariableTypeTable {
+                local_variable_type_table,
+            } => {
+                writeln!(out, "{}LocalVariableTypeTable:", pad)?;
+                writeln!(out, "{}  Start  Length  Slot  Name   Signature", pad)?;
+                for local in local_variable_type_table {
+                    writeln!(
+                        out,
+                        "{}  {:>5} {:>7} {:>5} {:>5}   {}",
+                        pad,
+                        local.start_pc,
+                        local.length,
+                        local.index,
+                        self.utf8(local.name_index),
+                        self.utf8(local.signature_index)
+                    )?;
+                }
+            }
+            AttributeInfo::BootstrapMethods { bootstrap_methods } => {
+                writeln!(out, "{}BootstrapMethods:", pad)?;
+                for (i, method) in bootstrap_methods.iter().enumerate() {
+                    writeln!(
+                        out,
+                        "{}  {}: #{} {}",
+                        pad,
+                        i,
+                        method.bootstrap_method_ref,
+                        self.method_handle(method.bootstrap_method_ref, false)
+                    )?;
+                    writeln!(out, "{}    Method arguments:", pad)?;
+                    for argument in &method.bootstrap_arguments {
+                        let value = match self.pool.get(*argument) {
+                            Some(ConstantInfo::String { string_index }) => self.utf8(*string_index),
+                            Some(ConstantInfo::Class { .. }) => self.class_name(*argument),
+                            Some(ConstantInfo::MethodType { descriptor_index }) => {
+                                self.utf8(*descriptor_index)
+                            }
+                            Some(ConstantInfo::MethodHandle { .. }) => {
+                                self.method_handle(*argument, false)
+                            }
+                            _ => self.literal(*argument),
+                        };
+                        writeln!(out, "{}      #{} {}", pad, argument, value)?;
+                    }
+                }
+            }
+            AttributeInfo::Unknown { info } => {
+                let name = self.utf8(attribute.name_index);
+                match name.as_str() {
+                    "NestHost" if info.len() == 2 => {
+                        let host = u16::from_be_bytes([info[0], info[1]]);
+                        writeln!(out, "{}NestHost: class {}", pad, self.class_name(host))?;
+                    }
+                    "NestMembers" | "PermittedSubclasses" if info.len() >= 2 => {
+                        writeln!(out, "{}{}:", pad, name)?;
+                        for member in info[2..].chunks_exact(2) {
+                            let member = u16::from_be_bytes([member[0], member[1]]);
+                            writeln!(out, "{}  {}", pad, self.class_name(member))?;
+                        }
+                    }
+                    _ => {
+                        writeln!(
+                            out,
+                            "{}{}: length = 0x{:X} (unknown attribute)",
+                            pad,
+                            name,
+                            info.len()
+                        )?;
+                        for chunk in info.chunks(16) {
+                            let bytes: Vec<String> =
+                                chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+                            writeln!(out, "{}   {}", pad, bytes.join(" "))?;
+                        }
+                    }
+                }
+            }
+            AttributeInfo::RuntimeInvisibleAnnotations
+            | AttributeInfo::RuntimeVisibleParameterAnnotations
+            | AttributeInfo::RuntimeInvisibleParameterAnnotations
+            | AttributeInfo::AnnotationDefault
+            | AttributeInfo::RuntimeVisibleAnnotations => {
+                writeln!(out, "{}{}", pad, attribute.info.name().unwrap())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn code(&self, out: &mut String, code: &Code, indent: usize, args_size: u16) -> fmt::Result {
+        let pad = " ".repeat(indent);
+        writeln!(
+            out,
+            "{}stack={}, locals={}, args_size={}",
+            pad, code.max_stack, code.max_locals, args_size
+        )?;
+
+        let op_bytes = code.op_bytes();
+        for (op, op_byte) in code.code.iter().zip(&op_bytes) {
+            let line = format!("{}{:>4}: {}", pad, op_byte, self.op(op, &op_bytes));
+            match self.op_comment(op) {
+                Some(comment) => writeln!(out, "{}", with_comment(&line, 46, &comment))?,
+                None => writeln!(out, "{}", line)?,
+            }
+
+            if let Instruction::SwitchJump { default, cases } = &op.inst {
+                for (key, jump) in cases {
+                    writeln!(
+                        out,
+                        "{}{:>18}: {}",
+                        pad,
+                        key,
+                        jump_target(&op_bytes, jump.get_pos())
+                    )?;
+                }
+                writeln!(
+                    out,
+                    "{}{:>18}: {}",
+                    pad,
+                    "default",
+                    jump_target(&op_bytes, default.get_pos())
+                )?;
+                writeln!(out, "{}      }}", pad)?;
+            }
+        }
+
+        if !code.exception_table.is_empty() {
+            writeln!(out, "{}Exception table:", pad)?;
+            writeln!(out, "{}   from    to  target type", pad)?;
+            for exception in &code.exception_table {
+                let catch_type = if exception.catch_type == 0 {
+                    "any".to_string()
+                } else {
+                    format!("Class {}", self.class_name(exception.catch_type))
+                };
+                writeln!(
+                    out,
+                    "{}   {:>5} {:>5} {:>5}   {}",
+                    pad, exception.start_pc, exception.end_pc, exception.handler_pc, catch_type
+                )?;
+            }
+        }
+
+        for attribute in &code.attribute_info {
+            self.attribute(out, attribute, indent, None)?;
+        }
+        Ok(())
+    }
+
+    // The mnemonic and its operands, jump targets are printed as byte positions.
+    fn op(&self, op: &Op, op_bytes: &[u32]) -> String {
+        // javap shows wide ops as the op they modify with a _w suffix
+        let name = if op.raw_op == WIDE {
+            format!("{}_w", consts::print_op(&op.op).to_ascii_lowercase())
+        } else {
+            consts::print_op(&op.raw_op).to_ascii_lowercase()
+        };
+        let operands = match &op.inst {
+            Instruction::Load { var } | Instruction::Store { var }
+                if op.raw_op == op.op || op.raw_op == WIDE =>
+            {
+                var.to_string()
+            }
+            Instruction::SubroutineReturn { var } => var.to_string(),
+            Instruction::Increment { var, amount } => format!("{}, {}", var, amount),
+            Instruction::PushByte { value } => value.to_string(),
+            Instruction::PushShort { value } => value.to_string(),
+            Instruction::ConstantPool { pool } => format!("#{}", pool),
+            Instruction::ConstantPoolWide { pool } => format!("#{}", pool),
+            Instruction::Cast { pool_pos }
+            | Instruction::Instanceof { pool_pos }
+            | Instruction::New { pool_pos }
+            | Instruction::GetField { pool_pos }
+            | Instruction::GetStaticField { pool_pos }
+            | Instruction::PutField { pool_pos }
+            | Instruction::PutStaticField { pool_pos } => format!("#{}", pool_pos),
+            Instruction::InvokeMethod { pool_pos, kind } => match kind {
+                InvokeKind::Interface { count } => format!("#{},  {}", pool_pos, count),
+                InvokeKind::Dynamic => format!("#{},  0", pool_pos),
+                _ => format!("#{}", pool_pos),
+            },
+            Instruction::MultiNewArray {
+                pool_pos,
+                dimensions,
+            } => format!("#{},  {}", pool_pos, dimensions),
+            Instruction::ComparisonJump { jump }
+            | Instruction::ZeroComparisonJump { jump }
+            | Instruction::Jump { jump }
+            | Instruction::Subroutine { jump } => jump_target(op_bytes, jump.get_pos()),
+            Instruction::NewPrimitiveArray { array_type } => {
+                format!(" {}", consts::print_array_type(array_type))
+            }
+            // the cases follow on their own lines
+            Instruction::SwitchJump { cases, .. } => match (op.op, cases.first(), cases.last()) {
+                (TABLESWITCH, Some((low, _)), Some((high, _))) => {
+                    format!("{{ // {} to {}", low, high)
+                }
+                _ => format!("{{ // {}", cases.len()),
+            },
+            _ => String::new(),
+        };
+        if operands.is_empty() {
+            name
+        } else {
+            format!("{:<13} {}", name, operands)
+        }
+    }
+
+    fn op_comment(&self, op: &Op) -> Option<String> {
+        match &op.inst {
+            Instruction::ConstantPool { pool } => Some(self.constant(*pool as u16)),
+            Instruction::ConstantPoolWide { pool } => Some(self.constant(*pool)),
+            Instruction::Cast { pool_pos }
+            | Instruction::Instanceof { pool_pos }
+            | Instruction::New { pool_pos }
+            | Instruction::MultiNewArray { pool_pos, .. } => {
+                Some(format!("class {}", self.class_name(*pool_pos)))
+            }
+            Instruction::GetField { pool_pos }
+            | Instruction::GetStaticField { pool_pos }
+            | Instruction::PutField { pool_pos }
+            | Instruction::PutStaticField { pool_pos } => {
+                Some(format!("Field {}", self.member(*pool_pos, true)))
+            }
+            Instruction::InvokeMethod { pool_pos, .. } => Some(match self.pool.get(*pool_pos) {
+                Some(ConstantInfo::InvokeDynamic { .. }) => {
+                    format!("InvokeDynamic {}", self.invoke_dynamic(*pool_pos))
+                }
+                Some(ConstantInfo::Interface { .. }) => {
+                    format!("InterfaceMethod {}", self.member(*pool_pos, true))
+                }
+                _ => format!("Method {}", self.member(*pool_pos, true)),
+            }),
+            _ => None,
+        }
+    }
+
+    fn verification_type(&self, typ: &VerificationType) -> String {
+        match typ {
+            VerificationType::Top => "top".to_string(),
+            VerificationType::Integer => "int".to_string(),
+            VerificationType::Float => "float".to_string(),
+            VerificationType::Double => "double".to_string(),
+            VerificationType::Long => "long".to_string(),
+            VerificationType::Null => "null".to_string(),
+            VerificationType::UninitializedThis => "uninitialized_this".to_string(),
+            VerificationType::Object { pool_pos } => {
+                format!("class {}", self.class_name(*pool_pos))
+            }
+            VerificationType::Uninitialized { offset } => format!("uninitialized {}", offset),
+        }
+    }
+
+    fn verification_types(&self, types: &[VerificationType]) -> String {
+        if types.is_empty() {
+            return "[]".to_string();
+        }
+        let types: Vec<String> = types
+            .iter()
+            .map(|typ| self.verification_type(typ))
+            .collect();
+        format!("[ {} ]", types.join(", "))
+    }
+
+    fn stack_map_frame(
+        &self,
+        out: &mut String,
+        frame: &StackMapFrame,
+        indent: usize,
+    ) -> fmt::Result {
+        let pad = " ".repeat(indent);
+        let kind = match frame.frame_type {
+            0..=63 => "same",
+            64..=127 => "same_locals_1_stack_item",
+            247 => "same_locals_1_stack_item_frame_extended",
+            248..=250 => "chop",
+            251 => "same_frame_extended",
+            252..=254 => "append",
+            _ => "full_frame",
+        };
+        writeln!(
+            out,
+            "{}frame_type = {} /* {} */",
+            pad, frame.frame_type, kind
+        )?;
+        if frame.frame_type >= 247 {
+            writeln!(out, "{}  offset_delta = {}", pad, frame.offset_delta)?;
+        }
+        if (252..=255).contains(&frame.frame_type) {
+            writeln!(
+                out,
+                "{}  locals = {}",
+                pad,
+                self.verification_types(&frame.locals)
+            )?;
+        }
+        if (64..=127).contains(&frame.frame_type)
+            || frame.frame_type == 247
+            || frame.frame_type == 255
+        {
+            writeln!(
+                out,
+                "{}  stack = {}",
+                pad,
+                self.verification_types(&frame.stack)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use crate::java_code::Code;
+
+// A chunk dominates another when every path from the entry to the other goes through it, and
+// post dominates it when every path from the other to an exit does. The trees are computed with
+// the iterative algorithm of Cooper, Harvey and Kennedy, which also handles irreducible graphs.
+pub struct DominatorTree {
+    // The immediate dominator of every node, the root has itself and nodes which can not be
+    // reached from the root have none.
+    pub idom: Vec<Option<u32>>,
+    // The nodes where the dominance of every node ends.
+    pub frontier: Vec<Vec<u32>>,
+    pub root: u32,
+}
+
+impl Code {
+    // Dominators over all edges including the exceptional ones, rooted at the first chunk.
+    pub fn dominators(&self) -> DominatorTree {
+        let successors: Vec<Vec<u32>> = (0..self.code_chunks.len() as u32)
+            .map(|chunk_pos| self.successors(chunk_pos))
+            .collect();
+        DominatorTree::build(&successors, 0)
+    }
+
+    // Post dominators over the normal edges, as a chunk in a try block would otherwise only be
+    // post dominated by the exit. The root is a virtual exit after the last chunk which every
+    // chunk without successors goes to, chunks which never reach an exit have no post dominator.
+    pub fn post_dominators(&self) -> DominatorTree {
+        let exit = self.code_chunks.len() as u32;
+        let mut predecessors: Vec<Vec<u32>> = vec![Vec::new(); exit as usize + 1];
+        for chunk_pos in 0..exit {
+            let successors = self.normal_successors(chunk_pos);
+            if successors.is_empty() {
+                predecessors[exit as usize].push(chunk_pos);
+            }
+            for successor in successors {
+                predecessors[successor as usize].push(chunk_pos);
+            }
+        }
+        DominatorTree::build(&predecessors, exit)
+    }
+}
+
+impl DominatorTree {
+    pub fn build(successors: &[Vec<u32>], root: u32) -> DominatorTree {
+        let count = successors.len();
+        let mut predecessors: Vec<Vec<u32>> = vec![Vec::new(); count];
+        for (node, targets) in successors.iter().enumerate() {
+            for target in targets {
+                predecessors[*target as usize].push(node as u32);
+            }
+        }
+
+        // nodes are handled in reverse postorder, so most predecessors come first
+        let order = postorder(successors, root);
+        let mut number = vec![usize::MAX; count];
+        for (position, node) in order.iter().enumerate() {
+            number[*node as usize] = position;
+        }
+
+        let mut idom: Vec<Option<u32>> = vec![None; count];
+        idom[root as usize] = Some(root);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in order.iter().rev().copied().filter(|node| *node != root) {
+                let mut new_idom: Option<u32> = None;
+                for predecessor in &predecessors[node as usize] {
+                    if idom[*predecessor as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *predecessor,
+                        Some(other) => intersect(&idom, &number, *predecessor, other),
+                    });
+                }
+                if new_idom.is_some() && new_idom != idom[node as usize] {
+                    idom[node as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        // walk up from every predecessor until the dominator of the node is found, the node is
+        // in the frontier of everything passed on the way
+        let mut frontier: Vec<Vec<u32>> = vec![Vec::new(); count];
+        for node in 0..count {
+            let node_idom = match idom[node] {
+                Some(node_idom) => node_idom,
+                None => continue,
+            };
+            for predecessor in &predecessors[node] {
+                let mut runner = *predecessor;
+                if idom[runner as usize].is_none() {
+                    continue;
+                }
+                while runner != node_idom || node as u32 == root {
+                    if !frontier[runner as usize].contains(&(node as u32)) {
+                        frontier[runner as usize].push(node as u32);
+                    }
+                    if runner == root {
+                        break;
+                    }
+                    runner = idom[runner as usize].unwrap_or(root);
+                }
+            }
+        }
+
+        DominatorTree {
+            idom,
+            frontier,
+            root,
+        }
+    }
+
+    // The immediate dominator, None for the root and nodes which can not be reached.
+    pub fn immediate(&self, node: u32) -> Option<u32> {
+        self.idom[node as usize].filter(|_| node != self.root)
+    }
+
+    // Whether every path to node goes through dominator, a node dominates itself.
+    pub fn dominates(&self, dominator: u32, node: u32) -> bool {
+        if self.idom[node as usize].is_none() {
+            return false;
+        }
+        let mut runner = node;
+        loop {
+            if runner == dominator {
+                return true;
+            }
+            match self.immediate(runner) {
+                Some(parent) => runner = parent,
+                None => return false,
+            }
+        }
+    }
+
+    // The nodes which node is the immediate dominator of.
+    pub fn children(&self, node: u32) -> Vec<u32> {
+        (0..self.idom.len() as u32)
+            .filter(|child| self.immediate(*child) == Some(node))
+            .collect()
+    }
+}
+
+fn postorder(successors: &[Vec<u32>], root: u32) -> Vec<u32> {
+    let mut order = Vec::with_capacity(successors.len());
+    let mut visited = vec![false; successors.len()];
+    let mut stack: Vec<(u32, usize)> = vec![(root, 0)];
+    visited[root as usize] = true;
+    while let Some((node, next)) = stack.last_mut() {
+        let node = *node;
+        match successors[node as usize].get(*next) {
+            Some(successor) => {
+                *next += 1;
+                if !visited[*successor as usize] {
+                    visited[*successor as usize] = true;
+                    stack.push((*successor, 0));
+                }
+            }
+            None => {
+                order.push(node);
+                stack.pop();
+            }
+        }
+    }
+    order
+}
+
+fn intersect(idom: &[Option<u32>], number: &[usize], mut first: u32, mut second: u32) -> u32 {
+    while first != second {
+        while number[first as usize] < number[second as usize] {
+            first = idom[first as usize].unwrap_or(first);
+        }
+        while number[second as usize] < number[first as usize] {
+            second = idom[second as usize].unwrap_or(second);
+        }
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java::AttributeInfo::CodeAttribute;
+    use crate::java::ClassInfo;
+    use crate::java_asm::assemble;
+
+    // The code of the only method of an assembled class, read back so the chunks are built.
+    fn code(text: &str) -> Code {
+        let bytes = assemble(text).unwrap().write().unwrap();
+        let (_, mut class_info) = ClassInfo::parse(&bytes).unwrap();
+        let method = class_info.methods.pop().unwrap();
+        method
+            .attribute_info
+            .into_iter()
+            .find_map(|attribute| match attribute.info {
+                CodeAttribute { code } => Some(code),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn idoms(tree: &DominatorTree) -> Vec<Option<u32>> {
+        (0..tree.idom.len() as u32).map(|node| tree.immediate(node)).collect()
+    }
+
+    #[test]
+    fn irreducible() {
+        // 1 and 2 form a loop which is entered at both of them, 4 is never reached
+        let successors = vec![vec![1, 2], vec![2, 3], vec![1, 3], vec![], vec![3]];
+        let tree = DominatorTree::build(&successors, 0);
+        assert_eq!(idoms(&tree), [None, Some(0), Some(0), Some(0), None]);
+        assert_eq!(tree.frontier, [vec![], vec![2, 3], vec![1, 3], vec![], vec![]]);
+        assert_eq!(tree.children(0), [1, 2, 3]);
+        assert!(tree.dominates(0, 3));
+        assert!(!tree.dominates(1, 2));
+        assert!(!tree.dominates(4, 3));
+        assert!(!tree.dominates(0, 4));
+    }
+
+    #[test]
+    fn irreducible_code() {
+        let code = code(
+            ".class public A
+            .super java/lang/Object
+            .method public static f(II)V
+                .limit stack 1
+                .limit locals 2
+                iload_0
+                ifeq second
+            first:
+                iinc 1 1
+            second:
+                iinc 1 -1
+                iload_1
+                ifne first
+                return
+            .end method",
+        );
+        assert_eq!(code.code_chunks.len(), 4);
+
+        let dominators = code.dominators();
+        assert_eq!(idoms(&dominators), [None, Some(0), Some(0), Some(2)]);
+        assert_eq!(dominators.frontier, [vec![], vec![2], vec![1], vec![]]);
+
+        // the exit comes after the last chunk
+        let post_dominators = code.post_dominators();
+        assert_eq!(idoms(&post_dominators), [Some(2), Some(2), Some(3), Some(4), None]);
+        assert_eq!(post_dominators.frontier[1], [0, 2]);
+        assert_eq!(post_dominators.frontier[2], [2]);
+        assert_eq!(post_dominators.children(2), [0, 1]);
+    }
+
+    #[test]
+    fn exceptions() {
+        let code = code(
+            ".class public A
+            .super java/lang/Object
+            .method public static g()V
+                .limit stack 1
+                .limit locals 1
+            start:
+                invokestatic A/a()V
+            inner:
+                invokestatic A/b()V
+            inner_end:
+                invokestatic A/c()V
+            end:
+                return
+            catch_inner:
+                astore_0
+                return
+            catch_all:
+                astore_0
+                aload_0
+                athrow
+            .catch java/lang/Exception from inner to inner_end using catch_inner
+            .catch all from start to end using catch_all
+            .end method",
+        );
+        assert_eq!(code.code_chunks.len(), 6);
+
+        // the handlers are only reached through the exceptional edges, the one of the outer
+        // range can be entered from every chunk in it
+        let dominators = code.dominators();
+        assert_eq!(idoms(&dominators), [None, Some(0), Some(1), Some(2), Some(1), Some(0)]);
+        assert_eq!(dominators.children(1), [2, 4]);
+        assert_eq!(dominators.frontier[1], [5]);
+        assert_eq!(dominators.frontier[2], [5]);
+        assert!(dominators.frontier[0].is_empty());
+
+        // without the exceptional edges every chunk reaches the exit on its own path
+        let post_dominators = code.post_dominators();
+        assert_eq!(
+            idoms(&post_dominators),
+            [Some(1), Some(2), Some(3), Some(6), Some(6), Some(6), None]
+        );
+        assert_eq!(post_dominators.children(6), [3, 4, 5]);
+        assert!(post_dominators.dominates(3, 0));
+        assert!(!post_dominators.dominates(4, 1));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::java::{AttributeInfo, ClassInfo, MethodInfo};
+use crate::java_code::{CodeChunkTarget, Instruction};
+use crate::java_disasm::disassemble_op;
+
+// Writes the control flow graph of a method as a Graphviz digraph with one node per chunk,
+// or None when the method has no code. Conditions take their green true or red false edge,
+// switch edges are labeled with their cases, exceptional edges are dashed and labeled with the
+// caught class, loop headers are filled blue and the entries of irreducible regions orange.
+pub fn method_dot(class_info: &ClassInfo, method: &MethodInfo) -> Option<String> {
+    let code = method.attribute_info.iter().find_map(|attribute| match &attribute.info {
+        AttributeInfo::CodeAttribute { code } => Some(code),
+        _ => None,
+    })?;
+    let pool = &class_info.constant_pool;
+    let name = format!(
+        "{}.{}{}",
+        pool.class_name(class_info.this_class).unwrap_or("?"),
+        pool.utf8(method.name_index).unwrap_or("?"),
+        pool.utf8(method.descriptor_index).unwrap_or("?"),
+    );
+
+    let mut out = String::new();
+    writeln!(out, "digraph \"{}\" {{", escape(&name)).unwrap();
+    writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+    let op_bytes = code.op_bytes();
+    let loops = code.loops();
+    for (chunk_pos, chunk) in code.code_chunks.iter().enumerate() {
+        let mut label = String::new();
+        for op_pos in chunk.start..chunk.stop {
+            let op = &code.code[op_pos as usize];
+            let text = disassemble_op(class_info, op, &op_bytes);
+            write!(label, "{}: {}\\l", op_bytes[op_pos as usize], escape(&text)).unwrap();
+        }
+        let irreducible = loops
+            .irreducible
+            .iter()
+            .any(|region| region.entries.contains(&(chunk_pos as u32)));
+        let style = if loops.is_header(chunk_pos as u32) {
+            ", style=filled, fillcolor=\"lightblue\""
+        } else if irreducible {
+            ", style=filled, fillcolor=\"orange\""
+        } else {
+            ""
+        };
+        writeln!(out, "    {} [label=\"{}\"{}];", chunk_pos, label, style).unwrap();
+    }
+
+    for (chunk_pos, chunk) in code.code_chunks.iter().enumerate() {
+        let next = chunk_pos + 1;
+        let mut edge = |to: usize, attributes: &str| {
+            if attributes.is_empty() {
+                writeln!(out, "    {} -> {};", chunk_pos, to).unwrap();
+            } else {
+                writeln!(out, "    {} -> {} [{}];", chunk_pos, to, attributes).unwrap();
+            }
+        };
+        let has_next = next < code.code_chunks.len();
+        match &chunk.target {
+            CodeChunkTarget::Basic if has_next => edge(next, ""),
+            CodeChunkTarget::Condition { true_chunk_pos } => {
+                edge(*true_chunk_pos as usize, "label=\"true\", color=\"darkgreen\"");
+                if has_next {
+                    edge(next, "label=\"false\", color=\"red\"");
+                }
+            }
+            CodeChunkTarget::Goto { chunk_pos } => edge(*chunk_pos as usize, ""),
+            CodeChunkTarget::Switch {
+                default_chunk_pos,
+                targets,
+            } => {
+                // cases going to the same chunk share an edge
+                let op = &code.code[chunk.stop as usize - 1];
+                let keys: Vec<i32> = match &op.inst {
+                    Instruction::SwitchJump { cases, .. } => {
+                        cases.iter().map(|(key, _)| *key).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                let mut seen = HashSet::new();
+                for target in targets {
+                    if !seen.insert(*target) || target == default_chunk_pos {
+                        continue;
+                    }
+                    let cases: Vec<String> = targets
+                        .iter()
+                        .zip(&keys)
+                        .filter(|(other, _)| *other == target)
+                        .map(|(_, key)| key.to_string())
+                        .collect();
+                    let label = format!("label=\"case {}\", color=\"blue\"", cases.join(", "));
+                    edge(*target as usize, &label);
+                }
+                edge(*default_chunk_pos as usize, "label=\"default\", color=\"blue\"");
+            }
+            CodeChunkTarget::Subroutine { chunk_pos } => {
+                edge(*chunk_pos as usize, "label=\"jsr\"");
+                if has_next {
+                    edge(next, "style=\"dotted\"");
+                }
+            }
+            _ => {}
+        }
+        for handler in &chunk.handlers {
+            let caught = if handler.catch_type == 0 {
+                "any".to_string()
+            } else {
+                pool.class_name(handler.catch_type).unwrap_or("?").to_string()
+            };
+            let label = format!(
+                "label=\"{}\", style=\"dashed\", color=\"gray\"",
+                escape(&caught)
+            );
+            edge(handler.chunk_pos as usize, &label);
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    Some(out)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
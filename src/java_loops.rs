@@ -0,0 +1,388 @@
+use crate::java_code::Code;
+use crate::java_dominators::DominatorTree;
+
+// A natural loop, made of a back edge to a header which dominates the chunk the edge comes from.
+// Back edges to the same header make one loop.
+pub struct Loop {
+    pub header: u32,
+    // The chunks with a back edge to the header.
+    pub latches: Vec<u32>,
+    // Every chunk of the loop in order, including the header and nested loops.
+    pub body: Vec<u32>,
+    // The normal edges leaving the loop as (from, to), jumping to a handler does not exit.
+    pub exits: Vec<(u32, u32)>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+// A cycle which can be entered at more than one chunk, so it has no header and no natural loop.
+pub struct IrreducibleRegion {
+    // The chunks with an edge from outside the region.
+    pub entries: Vec<u32>,
+    pub body: Vec<u32>,
+}
+
+pub struct LoopForest {
+    // Outer loops come before the loops nested in them.
+    pub loops: Vec<Loop>,
+    pub irreducible: Vec<IrreducibleRegion>,
+}
+
+impl Code {
+    // The loops over all edges, so a loop around a try block includes its handlers. Only normal
+    // edges close a loop though, a catch-any handler covering its own astore is no loop.
+    pub fn loops(&self) -> LoopForest {
+        let successors: Vec<Vec<u32>> = (0..self.code_chunks.len() as u32)
+            .map(|chunk_pos| self.successors(chunk_pos))
+            .collect();
+        let normal_successors: Vec<Vec<u32>> = (0..self.code_chunks.len() as u32)
+            .map(|chunk_pos| self.normal_successors(chunk_pos))
+            .collect();
+        let mut forest = LoopForest::build_with(&successors, &normal_successors, 0);
+        for inner in &mut forest.loops {
+            inner.exits.clear();
+            for from in &inner.body {
+                for to in self.normal_successors(*from) {
+                    if inner.body.binary_search(&to).is_err() {
+                        inner.exits.push((*from, to));
+                    }
+                }
+            }
+        }
+        forest
+    }
+}
+
+impl LoopForest {
+    pub fn build(successors: &[Vec<u32>], root: u32) -> LoopForest {
+        LoopForest::build_with(successors, successors, root)
+    }
+
+    // Like build, but only the edges in cyclic can be back edges or close an irreducible cycle.
+    // The other edges still count for the dominators and for which nodes are in a body.
+    pub fn build_with(successors: &[Vec<u32>], cyclic: &[Vec<u32>], root: u32) -> LoopForest {
+        let count = successors.len();
+        // without a root there is nothing to walk, which is the case for a graph with no nodes
+        if root as usize >= count {
+            return LoopForest {
+                loops: Vec::new(),
+                irreducible: Vec::new(),
+            };
+        }
+        let dominators = DominatorTree::build(successors, root);
+        let mut predecessors: Vec<Vec<u32>> = vec![Vec::new(); count];
+        for (node, targets) in successors.iter().enumerate() {
+            for target in targets {
+                predecessors[*target as usize].push(node as u32);
+            }
+        }
+
+        // find the back edges, grouped by header
+        let mut latches: Vec<Vec<u32>> = vec![Vec::new(); count];
+        for (node, targets) in cyclic.iter().enumerate() {
+            for target in targets {
+                if dominators.dominates(*target, node as u32)
+                    && !latches[*target as usize].contains(&(node as u32))
+                {
+                    latches[*target as usize].push(node as u32);
+                }
+            }
+        }
+
+        // the body is everything which reaches a latch without going through the header, dead
+        // nodes jumping in are left out
+        let mut loops: Vec<Loop> = Vec::new();
+        for (header, header_latches) in latches.iter().enumerate() {
+            if header_latches.is_empty() {
+                continue;
+            }
+            let header = header as u32;
+            let mut in_body = vec![false; count];
+            in_body[header as usize] = true;
+            let mut todo = header_latches.clone();
+            while let Some(node) = todo.pop() {
+                if !in_body[node as usize] && dominators.idom[node as usize].is_some() {
+                    in_body[node as usize] = true;
+                    todo.extend(&predecessors[node as usize]);
+                }
+            }
+            let body: Vec<u32> = (0..count as u32)
+                .filter(|node| in_body[*node as usize])
+                .collect();
+            let mut exits = Vec::new();
+            for from in &body {
+                for to in &successors[*from as usize] {
+                    if !in_body[*to as usize] {
+                        exits.push((*from, *to));
+                    }
+                }
+            }
+            loops.push(Loop {
+                header,
+                latches: header_latches.clone(),
+                body,
+                exits,
+                parent: None,
+                children: Vec::new(),
+            });
+        }
+
+        // natural loops are either nested or apart, so the parent is the smallest loop around
+        loops.sort_by_key(|inner| (std::cmp::Reverse(inner.body.len()), inner.header));
+        let mut innermost: Vec<Option<usize>> = vec![None; count];
+        for loop_pos in 0..loops.len() {
+            if let Some(parent) = innermost[loops[loop_pos].header as usize] {
+                loops[loop_pos].parent = Some(parent);
+                loops[parent].children.push(loop_pos);
+            }
+            for node in &loops[loop_pos].body {
+                innermost[*node as usize] = Some(loop_pos);
+            }
+        }
+
+        let irreducible = irreducible_regions(cyclic, &predecessors, &latches, root);
+        LoopForest { loops, irreducible }
+    }
+
+    pub fn is_header(&self, node: u32) -> bool {
+        self.loops.iter().any(|inner| inner.header == node)
+    }
+}
+
+// With the back edges of the natural loops taken out, any cycle left is irreducible. Those are
+// found as the strongly connected components of what is left, with Tarjan's algorithm.
+fn irreducible_regions(
+    successors: &[Vec<u32>],
+    predecessors: &[Vec<u32>],
+    latches: &[Vec<u32>],
+    root: u32,
+) -> Vec<IrreducibleRegion> {
+    let count = successors.len();
+    let forward: Vec<Vec<u32>> = successors
+        .iter()
+        .enumerate()
+        .map(|(chunk_pos, targets)| {
+            targets
+                .iter()
+                .copied()
+                .filter(|target| !latches[*target as usize].contains(&(chunk_pos as u32)))
+                .collect()
+        })
+        .collect();
+
+    let mut index = vec![usize::MAX; count];
+    let mut low = vec![0; count];
+    let mut on_stack = vec![false; count];
+    let mut stack: Vec<u32> = Vec::new();
+    let mut next_index = 0;
+    let mut regions = Vec::new();
+    for start in 0..count as u32 {
+        if index[start as usize] != usize::MAX {
+            continue;
+        }
+        let mut work: Vec<(u32, usize)> = vec![(start, 0)];
+        index[start as usize] = next_index;
+        low[start as usize] = next_index;
+        next_index += 1;
+        stack.push(start);
+        on_stack[start as usize] = true;
+        while let Some((node, next)) = work.last_mut() {
+            let node = *node;
+            if let Some(target) = forward[node as usize].get(*next).copied() {
+                *next += 1;
+                if index[target as usize] == usize::MAX {
+                    index[target as usize] = next_index;
+                    low[target as usize] = next_index;
+                    next_index += 1;
+                    stack.push(target);
+                    on_stack[target as usize] = true;
+                    work.push((target, 0));
+                } else if on_stack[target as usize] {
+                    low[node as usize] = low[node as usize].min(index[target as usize]);
+                }
+                continue;
+            }
+            work.pop();
+            if let Some((parent, _)) = work.last() {
+                low[*parent as usize] = low[*parent as usize].min(low[node as usize]);
+            }
+            if low[node as usize] != index[node as usize] {
+                continue;
+            }
+            let mut component = Vec::new();
+            while let Some(member) = stack.pop() {
+                on_stack[member as usize] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            let cyclic = component.len() > 1 || forward[node as usize].contains(&node);
+            if cyclic {
+                component.sort();
+                let entries = component
+                    .iter()
+                    .copied()
+                    .filter(|member| {
+                        *member == root
+                            || predecessors[*member as usize]
+                                .iter()
+                                .any(|from| component.binary_search(from).is_err())
+                    })
+                    .collect();
+                regions.push(IrreducibleRegion {
+                    entries,
+                    body: component,
+                });
+            }
+        }
+    }
+    regions.sort_by_key(|region| region.body[0]);
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java::AttributeInfo::CodeAttribute;
+    use crate::java::ClassInfo;
+    use crate::java_asm::assemble;
+
+    #[test]
+    fn nested() {
+        // 2 and 3 loop inside the loop of 1 to 4, 1 leaves to 5
+        let successors = vec![vec![1], vec![2, 5], vec![3], vec![2, 4], vec![1], vec![]];
+        let forest = LoopForest::build(&successors, 0);
+        assert_eq!(forest.loops.len(), 2);
+        let (outer, inner) = (&forest.loops[0], &forest.loops[1]);
+        assert_eq!((outer.header, &outer.latches, &outer.body), (1, &vec![4], &vec![1, 2, 3, 4]));
+        assert_eq!(outer.exits, [(1, 5)]);
+        assert_eq!((outer.parent, &outer.children), (None, &vec![1]));
+        assert_eq!((inner.header, &inner.latches, &inner.body), (2, &vec![3], &vec![2, 3]));
+        assert_eq!(inner.exits, [(3, 4)]);
+        assert_eq!(inner.parent, Some(0));
+        assert!(forest.is_header(1) && forest.is_header(2) && !forest.is_header(3));
+        assert!(forest.irreducible.is_empty());
+    }
+
+    #[test]
+    fn duplicate_edges() {
+        // both targets of 2 go back to the header, which is still one latch
+        let successors = vec![vec![1], vec![2, 3], vec![1, 1], vec![]];
+        let forest = LoopForest::build(&successors, 0);
+        assert_eq!(forest.loops.len(), 1);
+        assert_eq!(forest.loops[0].latches, [2]);
+    }
+
+    #[test]
+    fn irreducible() {
+        // the root 3 enters the cycle of 0, 1 and 2 at 1 and 2, but never at 0
+        let successors = vec![vec![2], vec![0], vec![1], vec![1, 2]];
+        let forest = LoopForest::build(&successors, 3);
+        assert!(forest.loops.is_empty());
+        assert_eq!(forest.irreducible.len(), 1);
+        assert_eq!(forest.irreducible[0].body, [0, 1, 2]);
+        assert_eq!(forest.irreducible[0].entries, [1, 2]);
+    }
+
+    #[test]
+    fn empty() {
+        let forest = LoopForest::build(&[], 0);
+        assert!(forest.loops.is_empty() && forest.irreducible.is_empty());
+    }
+
+    #[test]
+    fn handlers() {
+        let bytes = assemble(
+            ".class public A
+            .super java/lang/Object
+            .method public static f(I)V
+                .limit stack 1
+                .limit locals 2
+            top:
+                iload_0
+                ifeq done
+            start:
+                invokestatic A/a()V
+            end:
+                iinc 0 -1
+                goto top
+            handler:
+                astore_1
+                goto top
+            done:
+                return
+            .catch all from start to end using handler
+            .end method",
+        )
+        .unwrap()
+        .write()
+        .unwrap();
+        let (_, class_info) = ClassInfo::parse(&bytes).unwrap();
+        let code = class_info.methods[0]
+            .attribute_info
+            .iter()
+            .find_map(|attribute| match &attribute.info {
+                CodeAttribute { code } => Some(code),
+                _ => None,
+            })
+            .unwrap();
+
+        // the handler is in the loop, and only leaving at the condition is an exit
+        let forest = code.loops();
+        assert_eq!(forest.loops.len(), 1);
+        assert_eq!(forest.loops[0].header, 0);
+        assert_eq!(forest.loops[0].latches, [2, 3]);
+        assert_eq!(forest.loops[0].body, [0, 1, 2, 3]);
+        assert_eq!(forest.loops[0].exits, [(0, 4)]);
+    }
+
+    #[test]
+    fn finally() {
+        // javac's synchronized, where the catch-any handler covers its own astore so it has an
+        // exceptional edge to itself
+        let bytes = assemble(
+            ".class public A
+            .super java/lang/Object
+            .method public static f(Ljava/lang/Object;)V
+                .limit stack 2
+                .limit locals 2
+                aload_0
+                monitorenter
+            start:
+                invokestatic A/a()V
+                aload_0
+                monitorexit
+            end:
+                return
+            handler:
+                astore_1
+                aload_0
+                monitorexit
+            handler_end:
+                aload_1
+                athrow
+            .catch all from start to end using handler
+            .catch all from handler to handler_end using handler
+            .end method",
+        )
+        .unwrap()
+        .write()
+        .unwrap();
+        let (_, class_info) = ClassInfo::parse(&bytes).unwrap();
+        let code = class_info.methods[0]
+            .attribute_info
+            .iter()
+            .find_map(|attribute| match &attribute.info {
+                CodeAttribute { code } => Some(code),
+                _ => None,
+            })
+            .unwrap();
+
+        let handler = code.code_chunks.iter().position(|chunk| chunk.start == 6).unwrap() as u32;
+        assert!(code.successors(handler).contains(&handler));
+        let forest = code.loops();
+        assert!(forest.loops.is_empty());
+        assert!(forest.irreducible.is_empty());
+    }
+}
@@ -0,0 +1,3205 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+
+use crate::consts::*;
+use crate::java::{
+    AttributeBootstrapMethod, AttributeInfo, AttributeLocalVariable, ClassInfo, ConstantInfo,
+    ConstantPool, FieldInfo, Literal, MethodInfo,
+};
+use crate::java_code::{Code, InvokeKind};
+use crate::java_decomp::{compute, negate, DecompError, Expression, ExpressionType};
+use crate::java_disasm::{
+    disassemble_op, java_double, java_float, modifiers, FIELD_MODIFIERS, METHOD_MODIFIERS,
+};
+use crate::java_structure::{
+    enum_switches, switch_maps, CaseKey, Catch, Statement, SwitchCase, SwitchMaps,
+};
+use crate::java_type::{MethodType, Type};
+use crate::java_variables::Variables;
+
+// Decompiles every method of the class and prints it as Java source. The switch maps are the
+// ones of the classes which javac put the enum switches of the class in.
+pub fn class_source(class_info: &mut ClassInfo, switch_maps: &SwitchMaps) -> String {
+    let mut bodies = Vec::with_capacity(class_info.methods.len());
+    for method in &mut class_info.methods {
+        let body = method_code(method).map(|code| {
+            let mut body = compute(code, &class_info.constant_pool)?;
+            enum_switches(&mut body, &class_info.constant_pool, switch_maps);
+            Ok(body)
+        });
+        bodies.push(body);
+    }
+    let mut out = String::new();
+    Source::new(class_info)
+        .class(&mut out, &bodies)
+        .expect("writing to a String can not fail");
+    out
+}
+
+// The classes with the switch maps which the enum switches of the class look up.
+pub fn switch_map_classes(class_info: &ClassInfo) -> Vec<String> {
+    let pool = &class_info.constant_pool;
+    let mut classes: Vec<String> = (1..pool.len())
+        .filter_map(|index| match pool.get(index) {
+            Some(ConstantInfo::Field { .. }) => pool.member_ref(index).ok(),
+            _ => None,
+        })
+        .filter(|field| field.name.starts_with("$SwitchMap$"))
+        .map(|field| field.owner.to_string())
+        .collect();
+    classes.sort_unstable();
+    classes.dedup();
+    classes
+}
+
+// The switch maps which the static initializer of the class fills in.
+pub fn class_switch_maps(class_info: &mut ClassInfo) -> SwitchMaps {
+    let mut maps = SwitchMaps::new();
+    for method in &mut class_info.methods {
+        if class_info.constant_pool.utf8(method.name_index) != Ok("<clinit>") {
+            continue;
+        }
+        if let Some(Ok(body)) =
+            method_code(method).map(|code| compute(code, &class_info.constant_pool))
+        {
+            switch_maps(&body, &class_info.constant_pool, &mut maps);
+        }
+    }
+    maps
+}
+
+fn method_code(method: &mut MethodInfo) -> Option<&mut Code> {
+    method
+        .attribute_info
+        .iter_mut()
+        .find_map(|attribute| match &mut attribute.info {
+            AttributeInfo::CodeAttribute { code } => Some(code),
+            _ => None,
+        })
+}
+
+// How tightly an expression binds, an operand which binds less needs parentheses.
+const ASSIGN: u8 = 1;
+const TERNARY: u8 = 2;
+const LOGICAL_OR: u8 = 3;
+const LOGICAL_AND: u8 = 4;
+const BIT_OR: u8 = 5;
+const BIT_XOR: u8 = 6;
+const BIT_AND: u8 = 7;
+const EQUALITY: u8 = 8;
+const RELATION: u8 = 9;
+const SHIFT: u8 = 10;
+const ADDITIVE: u8 = 11;
+const MULTIPLICATIVE: u8 = 12;
+const UNARY: u8 = 13;
+const POSTFIX: u8 = 14;
+const PRIMARY: u8 = 15;
+
+// The cases of a switch all share the scope of the switch, so their lists count as one.
+const CASE: usize = 1 << 16;
+// The resources of a try are after its lists, each is a list of its own store.
+const RESOURCE: usize = 1 << 17;
+
+struct Source<'a> {
+    class_info: &'a ClassInfo,
+    const_pool: &'a ConstantPool,
+    class_name: &'a str,
+    bootstrap_methods: &'a [AttributeBootstrapMethod],
+}
+
+impl<'a> Source<'a> {
+    fn new(class_info: &'a ClassInfo) -> Source<'a> {
+        let bootstrap_methods = class_info
+            .attributes
+            .iter()
+            .find_map(|attribute| match &attribute.info {
+                AttributeInfo::BootstrapMethods { bootstrap_methods } => {
+                    Some(bootstrap_methods.as_slice())
+                }
+                _ => None,
+            })
+            .unwrap_or(&[]);
+        Source {
+            class_info,
+            const_pool: &class_info.constant_pool,
+            class_name: class_info
+                .constant_pool
+                .class_name(class_info.this_class)
+                .unwrap_or("?"),
+            bootstrap_methods,
+        }
+    }
+
+    fn class(
+        &self,
+        out: &mut String,
+        bodies: &[Option<Result<Vec<Statement>, DecompError>>],
+    ) -> fmt::Result {
+        if let Some((package, _)) = self.class_name.rsplit_once('/') {
+            writeln!(out, "package {};", package.replace('/', "."))?;
+            writeln!(out)?;
+        }
+
+        let flags = self.class_info.access_flags;
+        let interface = flags.contains(ClassAccessFlags::INTERFACE);
+        let mut header = String::new();
+        if flags.contains(ClassAccessFlags::PUBLIC) {
+            header.push_str("public ");
+        }
+        if flags.contains(ClassAccessFlags::ANNOTATION) {
+            header.push_str("@interface ");
+        } else if interface {
+            header.push_str("interface ");
+        } else {
+            if flags.contains(ClassAccessFlags::ABSTRACT) {
+                header.push_str("abstract ");
+            }
+            if flags.contains(ClassAccessFlags::FINAL) {
+                header.push_str("final ");
+            }
+            header.push_str("class ");
+        }
+        header.push_str(self.simple_name());
+        if !interface && self.class_info.super_class != 0 {
+            let super_name = self
+                .const_pool
+                .class_name(self.class_info.super_class)
+                .unwrap_or("?");
+            if super_name != "java/lang/Object" {
+                write!(header, " extends {}", self.class_ref(super_name))?;
+            }
+        }
+        let interfaces: Vec<String> = self
+            .class_info
+            .interfaces
+            .iter()
+            .map(|index| self.class_ref(self.const_pool.class_name(*index).unwrap_or("?")))
+            .collect();
+        if !interfaces.is_empty() {
+            let keyword = if interface { "extends" } else { "implements" };
+            write!(header, " {} {}", keyword, interfaces.join(", "))?;
+        }
+        writeln!(out, "{} {{", header)?;
+
+        for field in &self.class_info.fields {
+            self.field(out, field)?;
+        }
+        for (method, body) in self.class_info.methods.iter().zip(bodies) {
+            // bridges are made again by the compiler
+            if method.access_flags.contains(MethodAccessFlags::BRIDGE) {
+                continue;
+            }
+            writeln!(out)?;
+            self.method(out, method, body.as_ref())?;
+        }
+        writeln!(out, "}}")
+    }
+
+    fn simple_name(&self) -> &str {
+        self.class_name
+            .rsplit('/')
+            .next()
+            .unwrap_or(self.class_name)
+    }
+
+    fn field(&self, out: &mut String, field: &FieldInfo) -> fmt::Result {
+        let name = self.const_pool.utf8(field.name_index).unwrap_or("?");
+        let descriptor = self.const_pool.utf8(field.descriptor_index).unwrap_or("?");
+        let typ = Type::parse_str(descriptor).unwrap_or(Type::Class {
+            name: "java/lang/Object".to_string(),
+        });
+        write!(
+            out,
+            "    {}{} {}",
+            modifiers(field.access_flags.bits(), FIELD_MODIFIERS),
+            self.type_name(&typ),
+            name
+        )?;
+        let value = field
+            .attribute_info
+            .iter()
+            .find_map(|attribute| match &attribute.info {
+                AttributeInfo::ConstantValue { constant_index } => Some(*constant_index),
+                _ => None,
+            });
+        if let Some(index) = value {
+            write!(out, " = {}", self.pool_constant(index, Some(&typ)).0)?;
+        }
+        writeln!(out, ";")
+    }
+
+    fn method(
+        &self,
+        out: &mut String,
+        method: &MethodInfo,
+        body: Option<&Result<Vec<Statement>, DecompError>>,
+    ) -> fmt::Result {
+        let name = self.const_pool.utf8(method.name_index).unwrap_or("?");
+        let descriptor = self
+            .const_pool
+            .utf8(method.descriptor_index)
+            .unwrap_or("()V");
+        let method_type = MethodType::parse_str(descriptor).unwrap_or(MethodType {
+            parameters: Vec::new(),
+            return_type: Type::Void,
+        });
+        let mut code = None;
+        let mut exceptions: &[u16] = &[];
+        for attribute in &method.attribute_info {
+            match &attribute.info {
+                AttributeInfo::CodeAttribute { code: method_code } => code = Some(method_code),
+                AttributeInfo::Exceptions {
+                    exception_index_table,
+                } => exceptions = exception_index_table,
+                _ => {}
+            }
+        }
+        let statements: &[Statement] = match body {
+            Some(Ok(statements)) => statements,
+            _ => &[],
+        };
+        let flags = method.access_flags;
+        let is_static = flags.contains(MethodAccessFlags::STATIC);
+        let source = MethodSource::new(self, is_static, method_type, code, statements);
+
+        if name == "<clinit>" {
+            writeln!(out, "    static {{")?;
+        } else {
+            let interface = self
+                .class_info
+                .access_flags
+                .contains(ClassAccessFlags::INTERFACE);
+            let mut header = modifiers(flags.bits(), METHOD_MODIFIERS);
+            let instance = !(flags.contains(MethodAccessFlags::ABSTRACT)
+                || flags.contains(MethodAccessFlags::STATIC)
+                || flags.contains(MethodAccessFlags::PRIVATE));
+            if interface && instance {
+                header.push_str("default ");
+            }
+            if name == "<init>" {
+                header.push_str(self.simple_name().rsplit('$').next().unwrap_or("?"));
+            } else {
+                write!(
+                    header,
+                    "{} {}",
+                    self.type_name(&source.method_type.return_type),
+                    name
+                )?;
+            }
+            let parameters: Vec<String> = source
+                .parameters
+                .iter()
+                .zip(&source.method_type.parameters)
+                .map(|(key, typ)| format!("{} {}", self.type_name(typ), source.names[key]))
+                .collect();
+            write!(header, "({})", parameters.join(", "))?;
+            if !exceptions.is_empty() {
+                let names: Vec<String> = exceptions
+                    .iter()
+                    .map(|index| self.class_ref(self.const_pool.class_name(*index).unwrap_or("?")))
+                    .collect();
+                write!(header, " throws {}", names.join(", "))?;
+            }
+            match body {
+                None => return writeln!(out, "    {};", header),
+                Some(_) => writeln!(out, "    {} {{", header)?,
+            }
+        }
+        match body {
+            // the ops are listed instead, so the method can still be read
+            Some(Err(error)) => {
+                writeln!(out, "        // could not decompile: {:?}", error)?;
+                if let Some(code) = code {
+                    let op_bytes = code.op_bytes();
+                    for (op, op_byte) in code.code.iter().zip(&op_bytes) {
+                        let text = disassemble_op(self.class_info, op, &op_bytes);
+                        writeln!(out, "        // {:5}: {}", op_byte, text)?;
+                    }
+                }
+            }
+            _ => source.list(out, statements, &mut Vec::new(), 2)?,
+        }
+        writeln!(out, "    }}")
+    }
+
+    // java/lang/String is String, a/b/C is a.b.C and the member class a/B$C is a.B.C.
+    fn class_ref(&self, name: &str) -> String {
+        if name.starts_with('[') {
+            return match Type::parse_str(name) {
+                Some(typ) => self.type_name(&typ),
+                None => name.to_string(),
+            };
+        }
+        if name == self.class_name {
+            return self.simple_name().to_string();
+        }
+        let name = match name.strip_prefix("java/lang/") {
+            Some(simple) if !simple.contains('/') => simple,
+            _ => name,
+        };
+        let mut text = String::with_capacity(name.len());
+        let mut chars = name.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '/' => text.push('.'),
+                '$' if chars.peek().is_some_and(|next| next.is_alphabetic()) => text.push('.'),
+                c => text.push(c),
+            }
+        }
+        text
+    }
+
+    fn type_name(&self, typ: &Type) -> String {
+        match typ {
+            Type::Class { name } => self.class_ref(name),
+            Type::Array { component } => format!("{}[]", self.type_name(component)),
+            typ => typ.to_string(),
+        }
+    }
+
+    // A constant from the pool, ints become booleans or chars when that is what is expected.
+    fn pool_constant(&self, index: u16, expected: Option<&Type>) -> (String, u8) {
+        match self.const_pool.get(index) {
+            Some(ConstantInfo::String { .. }) => (
+                string_literal(self.const_pool.string(index).unwrap_or("")),
+                PRIMARY,
+            ),
+            Some(ConstantInfo::Class { .. }) => {
+                let name = self.const_pool.class_name(index).unwrap_or("?");
+                (format!("{}.class", self.class_ref(name)), PRIMARY)
+            }
+            _ => match self.const_pool.literal(index) {
+                Ok(Literal::Integer(value)) => int_literal(value, expected),
+                Ok(Literal::Long(value)) => (
+                    format!("{}L", value),
+                    if value < 0 { UNARY } else { PRIMARY },
+                ),
+                Ok(Literal::Float(value)) => float_literal(value),
+                Ok(Literal::Double(value)) => double_literal(value),
+                Err(_) => (format!("/* constant #{} */ null", index), PRIMARY),
+            },
+        }
+    }
+}
+
+// A boolean value of a condition is a ?: of 1 and 0, true when it is the condition itself and
+// false when it is the negated one.
+fn flag(expression: &Expression) -> Option<bool> {
+    let one = |value: &Expression| match value.ex {
+        ExpressionType::ConstantEx { op: ICONST_1 } => Some(true),
+        ExpressionType::ConstantEx { op: ICONST_0 } => Some(false),
+        _ => None,
+    };
+    match &expression.ex {
+        ExpressionType::TernaryEx {
+            then, otherwise, ..
+        } => match (one(then), one(otherwise)) {
+            (Some(true), Some(false)) => Some(true),
+            (Some(false), Some(true)) => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn int_literal(value: i32, expected: Option<&Type>) -> (String, u8) {
+    match expected {
+        Some(Type::Boolean) if value == 0 || value == 1 => ((value == 1).to_string(), PRIMARY),
+        Some(Type::Char) if (0..=0xffff).contains(&value) => (char_literal(value as u16), PRIMARY),
+        _ => (value.to_string(), if value < 0 { UNARY } else { PRIMARY }),
+    }
+}
+
+fn float_literal(value: f32) -> (String, u8) {
+    if value.is_nan() {
+        ("Float.NaN".to_string(), PRIMARY)
+    } else if value.is_infinite() {
+        let name = if value > 0.0 {
+            "POSITIVE_INFINITY"
+        } else {
+            "NEGATIVE_INFINITY"
+        };
+        (format!("Float.{}", name), PRIMARY)
+    } else {
+        let precedence = if value.is_sign_negative() {
+            UNARY
+        } else {
+            PRIMARY
+        };
+        (format!("{}f", java_float(value)), precedence)
+    }
+}
+
+fn double_literal(value: f64) -> (String, u8) {
+    if value.is_nan() {
+        ("Double.NaN".to_string(), PRIMARY)
+    } else if value.is_infinite() {
+        let name = if value > 0.0 {
+            "POSITIVE_INFINITY"
+        } else {
+            "NEGATIVE_INFINITY"
+        };
+        (format!("Double.{}", name), PRIMARY)
+    } else {
+        let precedence = if value.is_sign_negative() {
+            UNARY
+        } else {
+            PRIMARY
+        };
+        (java_double(value), precedence)
+    }
+}
+
+// Control characters are written in octal with all three digits, so a digit after one is not
+// read as part of it. \u escapes are read before anything else by javac so \u000a would end
+// the line.
+fn escape_char(out: &mut String, c: u16, quote: char) {
+    match c {
+        0x08 => out.push_str("\\b"),
+        0x09 => out.push_str("\\t"),
+        0x0a => out.push_str("\\n"),
+        0x0c => out.push_str("\\f"),
+        0x0d => out.push_str("\\r"),
+        0x5c => out.push_str("\\\\"),
+        c if c == quote as u16 => {
+            out.push('\\');
+            out.push(quote);
+        }
+        0x20..=0x7e => out.push(c as u8 as char),
+        0..=0xff => {
+            let _ = write!(out, "\\{:03o}", c);
+        }
+        c => {
+            let _ = write!(out, "\\u{:04x}", c);
+        }
+    }
+}
+
+fn string_literal(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.encode_utf16() {
+        escape_char(&mut out, c, '"');
+    }
+    out.push('"');
+    out
+}
+
+fn char_literal(c: u16) -> String {
+    let mut out = String::from("'");
+    escape_char(&mut out, c, '\'');
+    out.push('\'');
+    out
+}
+
+// A local var, see Variables. Ops which were not looked at get one var for their slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct VarKey(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UseKind {
+    Read,
+    // A statement which stores the var and does nothing else.
+    Store,
+    // The only store in the init of a for loop.
+    ForInit,
+    // The store of a resource of a try, which declares it.
+    Resource,
+}
+
+struct Use {
+    path: Vec<usize>,
+    kind: UseKind,
+}
+
+// Where a var is declared: before the statement at the index of the list at the path.
+type Scope = (Vec<usize>, usize);
+
+// What the body tells about its vars, in the order they are first used.
+#[derive(Default)]
+struct Collected<'b> {
+    order: Vec<VarKey>,
+    uses: HashMap<VarKey, Vec<Use>>,
+    // The values stored, their types are known once the vars in them have theirs.
+    values: HashMap<VarKey, Vec<&'b Expression>>,
+    // The slot of the first op on the var and the load op for its type.
+    kinds: HashMap<VarKey, (u16, u8)>,
+    // The vars which are read as numbers, by arithmetic, a compare or an index.
+    numbers: HashSet<VarKey>,
+}
+
+struct MethodSource<'a> {
+    source: &'a Source<'a>,
+    method_type: MethodType,
+    locals: &'a [AttributeLocalVariable],
+    variables: Option<Variables>,
+    this: Option<VarKey>,
+    parameters: Vec<VarKey>,
+    names: HashMap<VarKey, String>,
+    types: HashMap<VarKey, Type>,
+    // Vars declared on a line of their own before the statement at the path.
+    declare_before: HashMap<Vec<usize>, Vec<VarKey>>,
+    // Vars declared by the store which is the statement at the path.
+    declare_at: HashMap<Vec<usize>, VarKey>,
+    labels: HashMap<u32, String>,
+}
+
+impl<'a> MethodSource<'a> {
+    fn new(
+        source: &'a Source<'a>,
+        is_static: bool,
+        method_type: MethodType,
+        code: Option<&'a Code>,
+        body: &[Statement],
+    ) -> MethodSource<'a> {
+        let mut locals: &[AttributeLocalVariable] = &[];
+        for attribute in code.iter().flat_map(|code| &code.attribute_info) {
+            if let AttributeInfo::LocalVariableTable {
+                local_variable_table,
+            } = &attribute.info
+            {
+                locals = local_variable_table;
+            }
+        }
+        let mut slots = Vec::new();
+        if !is_static {
+            slots.push((0, ALOAD));
+        }
+        let mut slot = slots.len() as u16;
+        for typ in &method_type.parameters {
+            slots.push((slot, load_kind(typ)));
+            slot += if matches!(typ, Type::Long | Type::Double) {
+                2
+            } else {
+                1
+            };
+        }
+        let variables = code.map(|code| code.variables(&slots));
+
+        let mut method = MethodSource {
+            source,
+            method_type,
+            locals,
+            variables,
+            this: None,
+            parameters: Vec::new(),
+            names: HashMap::new(),
+            types: HashMap::new(),
+            declare_before: HashMap::new(),
+            declare_at: HashMap::new(),
+            labels: HashMap::new(),
+        };
+        method.declare(&slots, body);
+        method.find_labels(body, &mut Vec::new());
+        method
+    }
+
+    fn var_key(&self, slot: u16, pc: u32) -> VarKey {
+        match self
+            .variables
+            .as_ref()
+            .and_then(|variables| variables.vars.get(&pc))
+        {
+            Some(var) => VarKey(*var),
+            None => VarKey(usize::MAX - slot as usize),
+        }
+    }
+
+    fn expression_key(&self, expression: &Expression) -> Option<VarKey> {
+        match &expression.ex {
+            ExpressionType::LoadVarEx { var }
+            | ExpressionType::StoreVarEx { var, .. }
+            | ExpressionType::IncrementEx { var, .. }
+            | ExpressionType::PostIncrementEx { var, .. } => {
+                Some(self.var_key(*var, expression.pc))
+            }
+            _ => None,
+        }
+    }
+
+    // The entry in the local variable table for the var.
+    fn local(&self, key: &VarKey) -> Option<&'a AttributeLocalVariable> {
+        let entry = self.variables.as_ref()?.locals.get(&key.0)?;
+        self.locals.get(*entry)
+    }
+
+    fn local_name(&self, local: &AttributeLocalVariable) -> String {
+        self.source
+            .const_pool
+            .utf8(local.name_index)
+            .unwrap_or("?")
+            .to_string()
+    }
+
+    // Names, types and declarations of the parameters and the vars of the body.
+    fn declare(&mut self, slots: &[(u16, u8)], body: &[Statement]) {
+        let mut scopes: Vec<(VarKey, Scope)> = Vec::new();
+        let this_count = slots.len() - self.method_type.parameters.len();
+        for (position, (slot, _)) in slots.iter().enumerate() {
+            let key = match &self.variables {
+                Some(variables) => VarKey(variables.parameters[position]),
+                None => VarKey(usize::MAX - *slot as usize),
+            };
+            if position < this_count {
+                self.this = Some(key);
+                self.names.insert(key, "this".to_string());
+            } else {
+                let parameter = position - this_count;
+                let name = match self.local(&key) {
+                    Some(local) => self.local_name(local),
+                    None => format!("arg{}", parameter),
+                };
+                let name = self.unique(name, &key, &(Vec::new(), 0), &scopes);
+                self.names.insert(key, name);
+                self.types
+                    .insert(key, self.method_type.parameters[parameter].clone());
+                self.parameters.push(key);
+            }
+            scopes.push((key, (Vec::new(), 0)));
+        }
+
+        let mut collected = Collected::default();
+        self.collect_list(body, &mut Vec::new(), &mut collected);
+
+        for key in &collected.order {
+            if self.names.contains_key(key) {
+                continue;
+            }
+            let key_uses = &collected.uses[key];
+            let mut common = key_uses[0].path.clone();
+            for var_use in key_uses {
+                let same = common
+                    .iter()
+                    .zip(&var_use.path)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                common.truncate(same);
+            }
+            let scope = if common.len() % 2 == 1 {
+                let index = common.pop().unwrap();
+                (common, index)
+            } else {
+                let index = key_uses
+                    .iter()
+                    .map(|var_use| var_use.path[common.len()])
+                    .min()
+                    .unwrap();
+                (common, index)
+            };
+            let mut statement = scope.0.clone();
+            statement.push(scope.1);
+            let at: Vec<&Use> = key_uses
+                .iter()
+                .filter(|var_use| var_use.path == statement)
+                .collect();
+            // the init of a for only declares a var which is not used after the loop
+            let in_statement = key_uses
+                .iter()
+                .all(|var_use| var_use.path.starts_with(&statement));
+            let inline = (in_statement
+                && at.iter().any(|var_use| var_use.kind == UseKind::ForInit))
+                || (at.len() == 1 && at[0].kind == UseKind::Store);
+            let resource = key_uses
+                .iter()
+                .find(|var_use| var_use.kind == UseKind::Resource);
+            if let Some(resource) = resource {
+                self.declare_at.insert(resource.path.clone(), *key);
+            } else if inline {
+                self.declare_at.insert(statement, *key);
+            } else {
+                self.declare_before.entry(statement).or_default().push(*key);
+            }
+
+            let local = self.local(key);
+            let typ = local.and_then(|local| {
+                let descriptor = self
+                    .source
+                    .const_pool
+                    .utf8(local.descriptor_index)
+                    .unwrap_or("");
+                Type::parse_str(descriptor)
+            });
+            let values = collected.values.get(key).map_or(&[][..], Vec::as_slice);
+            let value = values.iter().find_map(|value| self.expression_type(value));
+            let (slot, kind) = collected.kinds[key];
+            let typ = typ.unwrap_or_else(|| match kind {
+                LLOAD => Type::Long,
+                FLOAD => Type::Float,
+                DLOAD => Type::Double,
+                ILOAD => self.int_var_type(values, collected.numbers.contains(key)),
+                _ => match value {
+                    Some(typ @ (Type::Class { .. } | Type::Array { .. })) => typ,
+                    _ => class_type("java/lang/Object"),
+                },
+            });
+            self.types.insert(*key, typ);
+
+            let name = match local {
+                Some(local) => self.local_name(local),
+                None => format!("var{}", slot),
+            };
+            let name = self.unique(name, key, &scope, &scopes);
+            self.names.insert(*key, name);
+            scopes.push((*key, scope));
+        }
+    }
+
+    // An int var is a boolean, char, byte or short when the values stored in it are, constants
+    // only have to fit in it. A condition is stored as a ?: of 1 and 0, so a var which only gets
+    // those is a boolean unless it is used as a number.
+    fn int_var_type(&self, values: &[&Expression], number: bool) -> Type {
+        let mut narrow = None;
+        let mut conditions = false;
+        for value in values {
+            if self.int_constant(value).is_some() {
+                continue;
+            }
+            if flag(value).is_some() {
+                conditions = true;
+                continue;
+            }
+            match self.expression_type(value) {
+                Some(typ @ (Type::Boolean | Type::Char | Type::Byte | Type::Short))
+                    if narrow.as_ref().is_none_or(|narrow| *narrow == typ) =>
+                {
+                    narrow = Some(typ)
+                }
+                _ => return Type::Int,
+            }
+        }
+        if narrow.is_none() && conditions && !number {
+            narrow = Some(Type::Boolean);
+        }
+        let range = match narrow {
+            Some(Type::Boolean) => 0..=1,
+            Some(Type::Char) => 0..=0xffff,
+            Some(Type::Byte) => -0x80..=0x7f,
+            Some(Type::Short) => -0x8000..=0x7fff,
+            _ => return Type::Int,
+        };
+        let fits = values
+            .iter()
+            .filter_map(|value| self.int_constant(value))
+            .all(|constant| range.contains(&constant));
+        match narrow {
+            Some(typ) if fits => typ,
+            _ => Type::Int,
+        }
+    }
+
+    fn int_constant(&self, expression: &Expression) -> Option<i32> {
+        match &expression.ex {
+            ExpressionType::ConstantEx { op } if (ICONST_M1..=ICONST_5).contains(op) => {
+                Some(*op as i32 - ICONST_0 as i32)
+            }
+            ExpressionType::NumberConstant { number } => Some(*number as i32),
+            ExpressionType::PoolConstantEx { pool_pos } => {
+                match self.source.const_pool.literal(*pool_pos) {
+                    Ok(Literal::Integer(value)) => Some(value),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Two vars can have the same name when neither is declared where the other one is seen.
+    fn unique(
+        &self,
+        name: String,
+        key: &VarKey,
+        scope: &Scope,
+        scopes: &[(VarKey, Scope)],
+    ) -> String {
+        let taken = |candidate: &str| {
+            scopes.iter().any(|(other, other_scope)| {
+                other != key
+                    && self
+                        .names
+                        .get(other)
+                        .is_some_and(|other_name| other_name == candidate)
+                    && (within(scope, other_scope) || within(other_scope, scope))
+            })
+        };
+        if !taken(&name) {
+            return name;
+        }
+        (2..)
+            .map(|number| format!("{}{}", name, number))
+            .find(|candidate| !taken(candidate))
+            .unwrap()
+    }
+
+    fn collect_list<'b>(
+        &self,
+        list: &'b [Statement],
+        path: &mut Vec<usize>,
+        collected: &mut Collected<'b>,
+    ) {
+        for (index, statement) in list.iter().enumerate() {
+            path.push(index);
+            let mut record = |expression: &'b Expression, kind: UseKind| {
+                self.collect(expression, kind, path, collected)
+            };
+            match statement {
+                Statement::Expression { expression } => record(expression, UseKind::Store),
+                Statement::If { condition, .. } => record(condition, UseKind::Read),
+                Statement::While { condition, .. } => {
+                    if let Some(condition) = condition {
+                        record(condition, UseKind::Read);
+                    }
+                }
+                Statement::DoWhile { condition, .. } => record(condition, UseKind::Read),
+                Statement::For {
+                    init,
+                    condition,
+                    update,
+                    ..
+                } => {
+                    let kind = if init.len() == 1 {
+                        UseKind::ForInit
+                    } else {
+                        UseKind::Read
+                    };
+                    for expression in init {
+                        record(expression, kind);
+                    }
+                    for expression in condition.iter().chain(update) {
+                        record(expression, UseKind::Read);
+                    }
+                }
+                Statement::Switch { value, .. } | Statement::Synchronized { lock: value, .. } => {
+                    record(value, UseKind::Read)
+                }
+                Statement::Try { resources, .. } => {
+                    for (position, resource) in resources.iter().enumerate() {
+                        path.extend([RESOURCE + position, 0]);
+                        self.collect(resource, UseKind::Resource, path, collected);
+                        path.truncate(path.len() - 2);
+                    }
+                }
+                Statement::Block { .. } | Statement::Break { .. } | Statement::Continue { .. } => {}
+            }
+            for (selector, body) in sub_lists(statement) {
+                path.push(selector);
+                self.collect_list(body, path, collected);
+                path.pop();
+            }
+            path.pop();
+        }
+    }
+
+    // Only a store at the top of the expression can declare its var.
+    fn collect<'b>(
+        &self,
+        expression: &'b Expression,
+        kind: UseKind,
+        path: &[usize],
+        collected: &mut Collected<'b>,
+    ) {
+        let slot = match &expression.ex {
+            ExpressionType::LoadVarEx { var }
+            | ExpressionType::StoreVarEx { var, .. }
+            | ExpressionType::IncrementEx { var, .. }
+            | ExpressionType::PostIncrementEx { var, .. } => Some(*var),
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            let key = self.var_key(slot, expression.pc);
+            let kind = match expression.ex {
+                ExpressionType::StoreVarEx { .. } => kind,
+                _ => UseKind::Read,
+            };
+            if !collected.uses.contains_key(&key) {
+                collected.order.push(key);
+                collected.kinds.insert(key, (slot, op_kind(expression.op)));
+            }
+            collected.uses.entry(key).or_default().push(Use {
+                path: path.to_vec(),
+                kind,
+            });
+            match &expression.ex {
+                ExpressionType::StoreVarEx { value, .. } => {
+                    collected.values.entry(key).or_default().push(value);
+                }
+                ExpressionType::IncrementEx { .. } | ExpressionType::PostIncrementEx { .. } => {
+                    collected.numbers.insert(key);
+                }
+                _ => {}
+            }
+        }
+        let number = match &expression.ex {
+            ExpressionType::OperatorEx { .. }
+            | ExpressionType::NegateEx { .. }
+            | ExpressionType::ConvertEx { .. }
+            | ExpressionType::CompareEx { .. }
+            | ExpressionType::IfEx { .. } => expression.children(),
+            ExpressionType::ArrayLoadEx { index, .. }
+            | ExpressionType::ArrayStoreEx { index, .. } => vec![index.as_ref()],
+            _ => Vec::new(),
+        };
+        for child in number {
+            if let ExpressionType::LoadVarEx { var } = child.ex {
+                collected.numbers.insert(self.var_key(var, child.pc));
+            }
+        }
+        for child in expression.children() {
+            self.collect(child, UseKind::Read, path, collected);
+        }
+    }
+
+    // Labels are printed for blocks and for jumps which don't go to the innermost loop.
+    fn find_labels(&mut self, list: &[Statement], targets: &mut Vec<(u32, bool)>) {
+        for statement in list {
+            match statement {
+                Statement::Break { label }
+                    if targets.last().map(|(target, _)| target) != Some(label) =>
+                {
+                    self.add_label(*label);
+                }
+                Statement::Continue { label } => {
+                    let innermost = targets.iter().rev().find(|(_, is_loop)| *is_loop);
+                    if innermost.map(|(target, _)| target) != Some(label) {
+                        self.add_label(*label);
+                    }
+                }
+                Statement::Block { label, .. } => self.add_label(*label),
+                _ => {}
+            }
+            let target = match statement {
+                Statement::While { label, .. }
+                | Statement::DoWhile { label, .. }
+                | Statement::For { label, .. } => Some((*label, true)),
+                Statement::Switch { label, .. } => Some((*label, false)),
+                _ => None,
+            };
+            if let Some(target) = target {
+                targets.push(target);
+            }
+            for (_, body) in sub_lists(statement) {
+                self.find_labels(body, targets);
+            }
+            if target.is_some() {
+                targets.pop();
+            }
+        }
+    }
+
+    fn add_label(&mut self, label: u32) {
+        if !self.labels.contains_key(&label) {
+            let name = format!("label{}", self.labels.len() + 1);
+            self.labels.insert(label, name);
+        }
+    }
+
+    fn label_prefix(&self, label: u32) -> String {
+        match self.labels.get(&label) {
+            Some(name) => format!("{}: ", name),
+            None => String::new(),
+        }
+    }
+
+    fn list(
+        &self,
+        out: &mut String,
+        list: &[Statement],
+        path: &mut Vec<usize>,
+        depth: usize,
+    ) -> fmt::Result {
+        for (index, statement) in list.iter().enumerate() {
+            path.push(index);
+            if let Some(keys) = self.declare_before.get(path.as_slice()) {
+                for key in keys {
+                    writeln!(
+                        out,
+                        "{}{} {};",
+                        indent(depth),
+                        self.type_name(&self.types[key]),
+                        self.names[key]
+                    )?;
+                }
+            }
+            self.statement(out, statement, path, depth)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    fn sub_list(
+        &self,
+        out: &mut String,
+        list: &[Statement],
+        path: &mut Vec<usize>,
+        selector: usize,
+        depth: usize,
+    ) -> fmt::Result {
+        path.push(selector);
+        let result = self.list(out, list, path, depth);
+        path.pop();
+        result
+    }
+
+    fn statement(
+        &self,
+        out: &mut String,
+        statement: &Statement,
+        path: &mut Vec<usize>,
+        depth: usize,
+    ) -> fmt::Result {
+        let pad = indent(depth);
+        match statement {
+            // the catch declares it
+            Statement::Expression { expression } if caught(expression) => Ok(()),
+            Statement::Expression { expression } => {
+                let text = match self.declare_at.get(path.as_slice()) {
+                    Some(key) => self.declaration(key, expression),
+                    None => self.statement_expression(expression),
+                };
+                writeln!(out, "{}{}", pad, text)
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                writeln!(out, "{}if ({}) {{", pad, self.expression(condition, None).0)?;
+                self.sub_list(out, then, path, 0, depth + 1)?;
+                self.otherwise(out, otherwise, path, depth)
+            }
+            Statement::While {
+                label,
+                condition,
+                body,
+            } => {
+                let condition = match condition {
+                    Some(condition) => self.expression(condition, None).0,
+                    None => "true".to_string(),
+                };
+                writeln!(
+                    out,
+                    "{}{}while ({}) {{",
+                    pad,
+                    self.label_prefix(*label),
+                    condition
+                )?;
+                self.sub_list(out, body, path, 0, depth + 1)?;
+                writeln!(out, "{}}}", pad)
+            }
+            Statement::DoWhile {
+                label,
+                body,
+                condition,
+            } => {
+                writeln!(out, "{}{}do {{", pad, self.label_prefix(*label))?;
+                self.sub_list(out, body, path, 0, depth + 1)?;
+                writeln!(
+                    out,
+                    "{}}} while ({});",
+                    pad,
+                    self.expression(condition, None).0
+                )
+            }
+            Statement::For {
+                label,
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let init = match (self.declare_at.get(path.as_slice()), init.first()) {
+                    (Some(key), Some(expression)) => {
+                        let declaration = self.declaration(key, expression);
+                        declaration.trim_end_matches(';').to_string()
+                    }
+                    _ => self.expressions(init),
+                };
+                let condition = match condition {
+                    Some(condition) => format!(" {}", self.expression(condition, None).0),
+                    None => String::new(),
+                };
+                let update = self.expressions(update);
+                let update = if update.is_empty() {
+                    update
+                } else {
+                    format!(" {}", update)
+                };
+                writeln!(
+                    out,
+                    "{}{}for ({};{};{}) {{",
+                    pad,
+                    self.label_prefix(*label),
+                    init,
+                    condition,
+                    update
+                )?;
+                self.sub_list(out, body, path, 0, depth + 1)?;
+                writeln!(out, "{}}}", pad)
+            }
+            Statement::Block { label, body } => {
+                writeln!(out, "{}{}{{", pad, self.label_prefix(*label))?;
+                self.sub_list(out, body, path, 0, depth + 1)?;
+                writeln!(out, "{}}}", pad)
+            }
+            Statement::Synchronized { lock, body } => {
+                writeln!(
+                    out,
+                    "{}synchronized ({}) {{",
+                    pad,
+                    self.expression(lock, None).0
+                )?;
+                self.sub_list(out, body, path, 0, depth + 1)?;
+                writeln!(out, "{}}}", pad)
+            }
+            Statement::Switch {
+                label,
+                value,
+                cases,
+            } => {
+                let value_type = self.expression_type(value);
+                writeln!(
+                    out,
+                    "{}{}switch ({}) {{",
+                    pad,
+                    self.label_prefix(*label),
+                    self.expression(value, None).0
+                )?;
+                for (position, case) in cases.iter().enumerate() {
+                    self.case(out, case, value_type.as_ref(), depth + 1)?;
+                    self.sub_list(out, &case.body, path, CASE + position, depth + 2)?;
+                }
+                writeln!(out, "{}}}", pad)
+            }
+            Statement::Break { label } => match self.labels.get(label) {
+                Some(name) => writeln!(out, "{}break {};", pad, name),
+                None => writeln!(out, "{}break;", pad),
+            },
+            Statement::Continue { label } => match self.labels.get(label) {
+                Some(name) => writeln!(out, "{}continue {};", pad, name),
+                None => writeln!(out, "{}continue;", pad),
+            },
+            Statement::Try {
+                resources,
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                let mut declarations = Vec::new();
+                for (position, resource) in resources.iter().enumerate() {
+                    path.extend([RESOURCE + position, 0]);
+                    let declaration = match self.declare_at.get(path.as_slice()) {
+                        Some(key) => self.declaration(key, resource),
+                        None => self.statement_expression(resource),
+                    };
+                    declarations.push(declaration.trim_end_matches(';').to_string());
+                    path.truncate(path.len() - 2);
+                }
+                if declarations.is_empty() {
+                    writeln!(out, "{}try {{", pad)?;
+                } else {
+                    writeln!(out, "{}try ({}) {{", pad, declarations.join("; "))?;
+                }
+                self.sub_list(out, body, path, 0, depth + 1)?;
+                for (position, catch) in catches.iter().enumerate() {
+                    writeln!(out, "{}}} catch ({}) {{", pad, self.catch_parameter(catch))?;
+                    self.sub_list(out, &catch.body, path, 1 + position, depth + 1)?;
+                }
+                if !finally.is_empty() || catches.is_empty() && resources.is_empty() {
+                    writeln!(out, "{}}} finally {{", pad)?;
+                    self.sub_list(out, finally, path, 1 + catches.len(), depth + 1)?;
+                }
+                writeln!(out, "{}}}", pad)
+            }
+        }
+    }
+
+    // The caught classes and the var the exception is stored to.
+    fn catch_parameter(&self, catch: &Catch) -> String {
+        let types: Vec<String> = catch
+            .types
+            .iter()
+            .map(|catch_type| match catch_type {
+                0 => self.source.class_ref("java/lang/Throwable"),
+                _ => self.source.class_ref(
+                    self.source
+                        .const_pool
+                        .class_name(*catch_type)
+                        .unwrap_or("?"),
+                ),
+            })
+            .collect();
+        let name = match catch.body.first() {
+            Some(Statement::Expression { expression }) if caught(expression) => {
+                self.var_name(expression)
+            }
+            _ => "ignored".to_string(),
+        };
+        format!("{} {}", types.join(" | "), name)
+    }
+
+    // An else which is only an if is printed as else if.
+    fn otherwise(
+        &self,
+        out: &mut String,
+        otherwise: &[Statement],
+        path: &mut Vec<usize>,
+        depth: usize,
+    ) -> fmt::Result {
+        let pad = indent(depth);
+        if otherwise.is_empty() {
+            return writeln!(out, "{}}}", pad);
+        }
+        path.push(1);
+        let result = match otherwise {
+            [Statement::If {
+                condition,
+                then,
+                otherwise,
+            }] if !self
+                .declare_before
+                .contains_key(&[path.as_slice(), &[0]].concat()) =>
+            {
+                path.push(0);
+                writeln!(
+                    out,
+                    "{}}} else if ({}) {{",
+                    pad,
+                    self.expression(condition, None).0
+                )?;
+                self.sub_list(out, then, path, 0, depth + 1)?;
+                let result = self.otherwise(out, otherwise, path, depth);
+                path.pop();
+                result
+            }
+            _ => {
+                writeln!(out, "{}}} else {{", pad)?;
+                self.list(out, otherwise, path, depth + 1)?;
+                writeln!(out, "{}}}", pad)
+            }
+        };
+        path.pop();
+        result
+    }
+
+    fn case(
+        &self,
+        out: &mut String,
+        case: &SwitchCase,
+        value_type: Option<&Type>,
+        depth: usize,
+    ) -> fmt::Result {
+        for key in &case.keys {
+            let key = match key {
+                CaseKey::Int(key) => int_literal(*key, value_type).0,
+                CaseKey::String(index) => self.source.pool_constant(*index, None).0,
+                CaseKey::Enum(name) => name.clone(),
+            };
+            writeln!(out, "{}case {}:", indent(depth), key)?;
+        }
+        if case.default {
+            writeln!(out, "{}default:", indent(depth))?;
+        }
+        Ok(())
+    }
+
+    fn declaration(&self, key: &VarKey, expression: &Expression) -> String {
+        match &expression.ex {
+            ExpressionType::StoreVarEx { value, .. } => {
+                let typ = &self.types[key];
+                format!(
+                    "{} {} = {};",
+                    self.type_name(typ),
+                    self.names[key],
+                    self.value(value, Some(typ), ASSIGN)
+                )
+            }
+            _ => self.statement_expression(expression),
+        }
+    }
+
+    fn statement_expression(&self, expression: &Expression) -> String {
+        match &expression.ex {
+            ExpressionType::ReturnEx => "return;".to_string(),
+            ExpressionType::ReturnValueEx { value } => {
+                format!(
+                    "return {};",
+                    self.value(value, Some(&self.method_type.return_type), ASSIGN)
+                )
+            }
+            ExpressionType::ThrowEx { throwable } => {
+                format!("throw {};", self.expression(throwable, None).0)
+            }
+            ExpressionType::CommentEx { comment } => format!("// {}", comment),
+            ExpressionType::MonitorEx { .. } => "// monitor".to_string(),
+            _ => format!("{};", self.expression(expression, None).0),
+        }
+    }
+
+    fn expressions(&self, list: &[Expression]) -> String {
+        let texts: Vec<String> = list
+            .iter()
+            .map(|expression| self.expression(expression, None).0)
+            .collect();
+        texts.join(", ")
+    }
+
+    fn type_name(&self, typ: &Type) -> String {
+        self.source.type_name(typ)
+    }
+
+    fn var_name(&self, expression: &Expression) -> String {
+        match self.expression_key(expression) {
+            Some(key) => self
+                .names
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| "?".to_string()),
+            None => "?".to_string(),
+        }
+    }
+
+    fn var_type(&self, expression: &Expression) -> Option<Type> {
+        self.expression_key(expression)
+            .and_then(|key| self.types.get(&key).cloned())
+    }
+
+    // An operand, in parentheses when it binds less than the place it is put in needs.
+    fn value(&self, expression: &Expression, expected: Option<&Type>, precedence: u8) -> String {
+        let (text, own) = self.expression(expression, expected);
+        if own < precedence {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    // Arguments are not converted like assignments are, so small constants need a cast.
+    fn argument(&self, expression: &Expression, expected: &Type) -> String {
+        let text = self.value(expression, Some(expected), ASSIGN);
+        let constant = matches!(
+            expression.ex,
+            ExpressionType::ConstantEx { .. } | ExpressionType::NumberConstant { .. }
+        );
+        match expected {
+            Type::Byte | Type::Short if constant => format!("({}) {}", expected, text),
+            _ => text,
+        }
+    }
+
+    fn arguments(&self, arguments: &[Expression], descriptor: &str) -> String {
+        let types = MethodType::parse_str(descriptor).map(|method_type| method_type.parameters);
+        let texts: Vec<String> = arguments
+            .iter()
+            .enumerate()
+            .map(|(position, argument)| {
+                match types.as_ref().and_then(|types| types.get(position)) {
+                    Some(typ) => self.argument(argument, typ),
+                    None => self.value(argument, None, ASSIGN),
+                }
+            })
+            .collect();
+        texts.join(", ")
+    }
+
+    fn binary(
+        &self,
+        left: &Expression,
+        symbol: &str,
+        right: &Expression,
+        precedence: u8,
+    ) -> (String, u8) {
+        let text = format!(
+            "{} {} {}",
+            self.value(left, None, precedence),
+            symbol,
+            self.value(right, None, precedence + 1)
+        );
+        (text, precedence)
+    }
+
+    fn expression(&self, expression: &Expression, expected: Option<&Type>) -> (String, u8) {
+        let pool = self.source.const_pool;
+        match &expression.ex {
+            ExpressionType::ConstantEx { op } => match *op {
+                ACONST_NULL => ("null".to_string(), PRIMARY),
+                ICONST_M1..=ICONST_5 => int_literal(*op as i32 - ICONST_0 as i32, expected),
+                LCONST_0 | LCONST_1 => (format!("{}L", op - LCONST_0), PRIMARY),
+                FCONST_0..=FCONST_2 => (format!("{}.0f", op - FCONST_0), PRIMARY),
+                _ => (format!("{}.0", op - DCONST_0), PRIMARY),
+            },
+            ExpressionType::PoolConstantEx { pool_pos } => {
+                self.source.pool_constant(*pool_pos, expected)
+            }
+            ExpressionType::NumberConstant { number } => int_literal(*number as i32, expected),
+            ExpressionType::OperatorEx { left, right } => {
+                let (symbol, precedence) = operator(expression.op);
+                let boolean =
+                    |value: &Expression| self.expression_type(value) == Some(Type::Boolean);
+                if precedence <= BIT_AND && (boolean(left) || boolean(right)) {
+                    let typ = Some(&Type::Boolean);
+                    let text = format!(
+                        "{} {} {}",
+                        self.value(left, typ, precedence),
+                        symbol,
+                        self.value(right, typ, precedence + 1)
+                    );
+                    return (text, precedence);
+                }
+                self.binary(left, symbol, right, precedence)
+            }
+            ExpressionType::NegateEx { value } => {
+                let text = self.value(value, None, UNARY);
+                if text.starts_with('-') {
+                    (format!("-({})", text), UNARY)
+                } else {
+                    (format!("-{}", text), UNARY)
+                }
+            }
+            ExpressionType::ConvertEx { original } => {
+                let typ = match expression.op {
+                    I2L | F2L | D2L => "long",
+                    I2F | L2F | D2F => "float",
+                    I2D | L2D | F2D => "double",
+                    I2B => "byte",
+                    I2C => "char",
+                    I2S => "short",
+                    _ => "int",
+                };
+                (
+                    format!("({}) {}", typ, self.value(original, None, UNARY)),
+                    UNARY,
+                )
+            }
+            ExpressionType::IncrementEx { amount, .. } => {
+                let name = self.var_name(expression);
+                match amount {
+                    1 => (format!("{}++", name), POSTFIX),
+                    -1 => (format!("{}--", name), POSTFIX),
+                    amount if *amount < 0 => (format!("{} -= {}", name, -(*amount as i32)), ASSIGN),
+                    amount => (format!("{} += {}", name, amount), ASSIGN),
+                }
+            }
+            ExpressionType::PostIncrementEx { amount, .. } => {
+                let name = self.var_name(expression);
+                match amount {
+                    1 => (format!("{}++", name), POSTFIX),
+                    -1 => (format!("{}--", name), POSTFIX),
+                    amount => (format!("({} += {}) - {}", name, amount, amount), ADDITIVE),
+                }
+            }
+            ExpressionType::LoadVarEx { .. } => (self.var_name(expression), PRIMARY),
+            ExpressionType::StoreVarEx { value, .. } => {
+                let typ = self.var_type(expression);
+                let text = format!(
+                    "{} = {}",
+                    self.var_name(expression),
+                    self.value(value, typ.as_ref(), ASSIGN)
+                );
+                (text, ASSIGN)
+            }
+            ExpressionType::ArrayLoadEx { array, index } => {
+                let text = format!(
+                    "{}[{}]",
+                    self.value(array, None, POSTFIX),
+                    self.value(index, None, ASSIGN)
+                );
+                (text, POSTFIX)
+            }
+            ExpressionType::ArrayStoreEx {
+                array,
+                index,
+                value,
+            } => {
+                let component = match self.expression_type(array) {
+                    Some(Type::Array { component }) => Some(*component),
+                    _ => None,
+                };
+                let text = format!(
+                    "{}[{}] = {}",
+                    self.value(array, None, POSTFIX),
+                    self.value(index, None, ASSIGN),
+                    self.value(value, component.as_ref(), ASSIGN)
+                );
+                (text, ASSIGN)
+            }
+            ExpressionType::CompareEx { left, right } => {
+                let class = match expression.op {
+                    LCMP => "Long",
+                    FCMPL | FCMPG => "Float",
+                    _ => "Double",
+                };
+                let text = format!(
+                    "{}.compare({}, {})",
+                    class,
+                    self.value(left, None, ASSIGN),
+                    self.value(right, None, ASSIGN)
+                );
+                (text, POSTFIX)
+            }
+            ExpressionType::InstanceOfEx { value, pool_pos } => {
+                let name = self
+                    .source
+                    .class_ref(pool.class_name(*pool_pos).unwrap_or("?"));
+                (
+                    format!("{} instanceof {}", self.value(value, None, RELATION), name),
+                    RELATION,
+                )
+            }
+            ExpressionType::IfEx { left, right } => {
+                let (symbol, precedence) = comparison(expression.op);
+                // a boolean compared to a constant shows the constant as a boolean too
+                let left_type = self.expression_type(left);
+                let right_type = self.expression_type(right);
+                let text = format!(
+                    "{} {} {}",
+                    self.value(left, right_type.as_ref(), precedence),
+                    symbol,
+                    self.value(right, left_type.as_ref(), precedence + 1)
+                );
+                (text, precedence)
+            }
+            ExpressionType::If0Ex { value } => self.zero_condition(expression.op, value),
+            ExpressionType::AndEx { left, right } => self.binary(left, "&&", right, LOGICAL_AND),
+            ExpressionType::OrEx { left, right } => self.binary(left, "||", right, LOGICAL_OR),
+            ExpressionType::TernaryEx {
+                condition,
+                then,
+                otherwise,
+            } => {
+                match (expected, flag(expression)) {
+                    (Some(Type::Boolean), Some(true)) => {
+                        return self.expression(condition, None);
+                    }
+                    (Some(Type::Boolean), Some(false)) => {
+                        return self.expression(&negate(condition.as_ref().clone()), None);
+                    }
+                    _ => {}
+                }
+                let text = format!(
+                    "{} ? {} : {}",
+                    self.value(condition, None, TERNARY + 1),
+                    self.value(then, expected, TERNARY + 1),
+                    self.value(otherwise, expected, TERNARY)
+                );
+                (text, TERNARY)
+            }
+            ExpressionType::NewEx { pool_pos } => {
+                let name = self
+                    .source
+                    .class_ref(pool.class_name(*pool_pos).unwrap_or("?"));
+                (format!("/* uninitialized */ new {}", name), PRIMARY)
+            }
+            ExpressionType::ConstructEx {
+                pool_pos,
+                method_pos,
+                arguments,
+            } => {
+                let name = self
+                    .source
+                    .class_ref(pool.class_name(*pool_pos).unwrap_or("?"));
+                let descriptor = pool
+                    .member_ref(*method_pos)
+                    .map_or("()V", |member| member.descriptor);
+                (
+                    format!("new {}({})", name, self.arguments(arguments, descriptor)),
+                    POSTFIX,
+                )
+            }
+            ExpressionType::NewPrimArrayEx { array_type, count } => {
+                let typ = primitive_array_type(*array_type);
+                (
+                    format!("new {}[{}]", typ, self.value(count, None, ASSIGN)),
+                    POSTFIX,
+                )
+            }
+            ExpressionType::NewArrayEx { pool_pos, count } => {
+                let component =
+                    class_type(pool.class_name(*pool_pos).unwrap_or("java/lang/Object"));
+                let (base, dimensions) = self.array_parts(&component);
+                let text = format!(
+                    "new {}[{}]{}",
+                    base,
+                    self.value(count, None, ASSIGN),
+                    "[]".repeat(dimensions)
+                );
+                (text, POSTFIX)
+            }
+            ExpressionType::MultiNewArrayEx { pool_pos, counts } => {
+                let typ = class_type(pool.class_name(*pool_pos).unwrap_or("[Ljava/lang/Object;"));
+                let (base, dimensions) = self.array_parts(&typ);
+                let mut text = format!("new {}", base);
+                for count in counts {
+                    write!(text, "[{}]", self.value(count, None, ASSIGN)).unwrap();
+                }
+                text.push_str(&"[]".repeat(dimensions.saturating_sub(counts.len())));
+                (text, POSTFIX)
+            }
+            ExpressionType::ArrayInitEx { array, values } => {
+                let typ = self.expression_type(array);
+                let component = match &typ {
+                    Some(Type::Array { component }) => Some(component.as_ref()),
+                    _ => None,
+                };
+                let texts: Vec<String> = values
+                    .iter()
+                    .map(|value| self.value(value, component, ASSIGN))
+                    .collect();
+                let name = typ
+                    .as_ref()
+                    .map_or("Object[]".to_string(), |typ| self.type_name(typ));
+                (format!("new {} {{{}}}", name, texts.join(", ")), POSTFIX)
+            }
+            // structure fails on these, check reports any which still get here
+            ExpressionType::StackEx => ("$stack".to_string(), PRIMARY),
+            // a catch which doesn't store the exception calls it ignored
+            ExpressionType::CatchEx => ("ignored".to_string(), PRIMARY),
+            ExpressionType::MonitorEx { .. } => ("/* monitor */".to_string(), PRIMARY),
+            ExpressionType::CastEx { value, pool_pos } => {
+                let name = self
+                    .source
+                    .class_ref(pool.class_name(*pool_pos).unwrap_or("?"));
+                // (T) -x would be read as a subtraction
+                (
+                    format!("({}) {}", name, self.value(value, None, POSTFIX)),
+                    UNARY,
+                )
+            }
+            ExpressionType::SwitchEx { value } => self.expression(value, None),
+            ExpressionType::ReturnEx => ("return".to_string(), ASSIGN),
+            ExpressionType::ReturnValueEx { value } => {
+                let text = self.value(value, Some(&self.method_type.return_type), ASSIGN);
+                (format!("return {}", text), ASSIGN)
+            }
+            ExpressionType::ThrowEx { throwable } => (
+                format!("throw {}", self.value(throwable, None, ASSIGN)),
+                ASSIGN,
+            ),
+            ExpressionType::GetFieldEx { object, pool_pos } => match pool.member_ref(*pool_pos) {
+                Ok(member) => (
+                    format!("{}.{}", self.value(object, None, POSTFIX), member.name),
+                    POSTFIX,
+                ),
+                Err(_) => ("?".to_string(), PRIMARY),
+            },
+            ExpressionType::PutFieldEx {
+                object,
+                value,
+                pool_pos,
+            } => match pool.member_ref(*pool_pos) {
+                Ok(member) => {
+                    let typ = Type::parse_str(member.descriptor);
+                    let text = format!(
+                        "{}.{} = {}",
+                        self.value(object, None, POSTFIX),
+                        member.name,
+                        self.value(value, typ.as_ref(), ASSIGN)
+                    );
+                    (text, ASSIGN)
+                }
+                Err(_) => ("?".to_string(), PRIMARY),
+            },
+            ExpressionType::GetStaticFieldEx { pool_pos } => match pool.member_ref(*pool_pos) {
+                Ok(member) => (
+                    format!("{}.{}", self.source.class_ref(member.owner), member.name),
+                    POSTFIX,
+                ),
+                Err(_) => ("?".to_string(), PRIMARY),
+            },
+            ExpressionType::PutStaticFieldEx { value, pool_pos } => {
+                match pool.member_ref(*pool_pos) {
+                    Ok(member) => {
+                        let typ = Type::parse_str(member.descriptor);
+                        let text = format!(
+                            "{}.{} = {}",
+                            self.source.class_ref(member.owner),
+                            member.name,
+                            self.value(value, typ.as_ref(), ASSIGN)
+                        );
+                        (text, ASSIGN)
+                    }
+                    Err(_) => ("?".to_string(), PRIMARY),
+                }
+            }
+            ExpressionType::MethodEx {
+                object,
+                arguments,
+                pool_pos,
+                kind,
+            } => self.call(object.as_deref(), arguments, *pool_pos, *kind),
+            ExpressionType::ArrayLengthEx { array } => (
+                format!("{}.length", self.value(array, None, POSTFIX)),
+                POSTFIX,
+            ),
+            ExpressionType::CommentEx { comment } => (format!("/* {} */", comment), PRIMARY),
+        }
+    }
+
+    // A comparison with zero, or the comparison of two longs, floats or doubles before it.
+    fn zero_condition(&self, op: u8, value: &Expression) -> (String, u8) {
+        match op {
+            IFNULL => {
+                return (
+                    format!("{} == null", self.value(value, None, EQUALITY)),
+                    EQUALITY,
+                )
+            }
+            IFNONNULL => {
+                return (
+                    format!("{} != null", self.value(value, None, EQUALITY)),
+                    EQUALITY,
+                )
+            }
+            _ => {}
+        }
+        if let ExpressionType::CompareEx { left, right } = &value.ex {
+            // the compare gives 1 or -1 for NaN, so one side of it is a comparison which is false
+            let nan_false = match (value.op, op) {
+                (FCMPG | DCMPG, IFGT) => Some("<="),
+                (FCMPG | DCMPG, IFGE) => Some("<"),
+                (FCMPL | DCMPL, IFLT) => Some(">="),
+                (FCMPL | DCMPL, IFLE) => Some(">"),
+                _ => None,
+            };
+            if let Some(symbol) = nan_false {
+                let (text, _) = self.binary(left, symbol, right, RELATION);
+                return (format!("!({})", text), UNARY);
+            }
+            let (symbol, precedence) = comparison(op);
+            return self.binary(left, symbol, right, precedence);
+        }
+        if self.expression_type(value) == Some(Type::Boolean) {
+            return match op {
+                IFEQ => (format!("!{}", self.value(value, None, UNARY)), UNARY),
+                _ => self.expression(value, None),
+            };
+        }
+        let (symbol, precedence) = comparison(op);
+        (
+            format!("{} {} 0", self.value(value, None, precedence), symbol),
+            precedence,
+        )
+    }
+
+    fn call(
+        &self,
+        object: Option<&Expression>,
+        arguments: &[Expression],
+        pool_pos: u16,
+        kind: InvokeKind,
+    ) -> (String, u8) {
+        let pool = self.source.const_pool;
+        if kind == InvokeKind::Dynamic {
+            return self.dynamic_call(arguments, pool_pos);
+        }
+        let member = match pool.member_ref(pool_pos) {
+            Ok(member) => member,
+            Err(_) => return ("?".to_string(), PRIMARY),
+        };
+        let arguments = self.arguments(arguments, member.descriptor);
+        let own = member.owner == self.source.class_name;
+        let this = object.is_some_and(|object| self.is_this(object));
+        let text = match object {
+            None if own => format!("{}({})", member.name, arguments),
+            None => format!(
+                "{}.{}({})",
+                self.source.class_ref(member.owner),
+                member.name,
+                arguments
+            ),
+            // a constructor called on this is the first line of another constructor
+            Some(_) if member.name == "<init>" && this => {
+                let keyword = if own { "this" } else { "super" };
+                format!("{}({})", keyword, arguments)
+            }
+            Some(_) if kind == InvokeKind::Special && this && !own => {
+                format!("super.{}({})", member.name, arguments)
+            }
+            Some(_) if this => format!("{}({})", member.name, arguments),
+            Some(object) => format!(
+                "{}.{}({})",
+                self.value(object, None, POSTFIX),
+                member.name,
+                arguments
+            ),
+        };
+        (text, POSTFIX)
+    }
+
+    // String concatenation follows the recipe of makeConcatWithConstants, other dynamic calls
+    // can't be written in Java and are shown by their name.
+    fn dynamic_call(&self, arguments: &[Expression], pool_pos: u16) -> (String, u8) {
+        let pool = self.source.const_pool;
+        let dynamic = match pool.invoke_dynamic(pool_pos) {
+            Ok(dynamic) => dynamic,
+            Err(_) => return ("?".to_string(), PRIMARY),
+        };
+        let bootstrap = self
+            .source
+            .bootstrap_methods
+            .get(dynamic.bootstrap_method_attr_index as usize);
+        let bootstrap_name = bootstrap
+            .and_then(|bootstrap| pool.method_handle(bootstrap.bootstrap_method_ref).ok())
+            .map(|handle| handle.member.name);
+        let bootstrap_arguments: &[u16] =
+            bootstrap.map_or(&[], |bootstrap| &bootstrap.bootstrap_arguments);
+        let recipe = match (bootstrap_name, bootstrap_arguments.first()) {
+            (Some("makeConcatWithConstants"), Some(recipe)) => {
+                pool.string(*recipe).ok().map(str::to_string)
+            }
+            (Some("makeConcat"), _) => Some("\u{1}".repeat(arguments.len())),
+            _ => None,
+        };
+        let recipe = match recipe {
+            Some(recipe) => recipe,
+            None => {
+                let name = bootstrap_name.unwrap_or("?");
+                let text = format!(
+                    "/* {} */ {}({})",
+                    name,
+                    dynamic.name,
+                    self.arguments(arguments, dynamic.descriptor)
+                );
+                return (text, POSTFIX);
+            }
+        };
+
+        let string = Some(Type::Class {
+            name: "java/lang/String".to_string(),
+        });
+        let mut parts: Vec<(String, bool)> = Vec::new();
+        let mut text = String::new();
+        let mut arguments = arguments.iter();
+        let mut constants = bootstrap_arguments.iter().skip(1);
+        for c in recipe.chars() {
+            match c {
+                '\u{1}' | '\u{2}' => {
+                    if !text.is_empty() {
+                        parts.push((string_literal(&text), true));
+                        text.clear();
+                    }
+                    let part = if c == '\u{1}' {
+                        arguments.next().map(|argument| {
+                            (
+                                self.value(argument, None, ADDITIVE + 1),
+                                self.expression_type(argument) == string,
+                            )
+                        })
+                    } else {
+                        constants.next().map(|constant| {
+                            let (text, precedence) = self.source.pool_constant(*constant, None);
+                            let text = if precedence <= ADDITIVE {
+                                format!("({})", text)
+                            } else {
+                                text
+                            };
+                            (
+                                text,
+                                matches!(pool.get(*constant), Some(ConstantInfo::String { .. })),
+                            )
+                        })
+                    };
+                    parts.extend(part);
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push((string_literal(&text), true));
+        }
+        // the first part has to be a string or the first two parts are added as numbers
+        if !parts.first().is_some_and(|(_, string)| *string) {
+            parts.insert(0, ("\"\"".to_string(), true));
+        }
+        let texts: Vec<String> = parts.into_iter().map(|(text, _)| text).collect();
+        (texts.join(" + "), ADDITIVE)
+    }
+
+    fn is_this(&self, expression: &Expression) -> bool {
+        matches!(expression.ex, ExpressionType::LoadVarEx { .. })
+            && self.expression_key(expression) == self.this
+    }
+
+    // The element type of an array without its brackets and how many brackets there are.
+    fn array_parts(&self, typ: &Type) -> (String, usize) {
+        match typ {
+            Type::Array { component } => {
+                let (base, dimensions) = self.array_parts(component);
+                (base, dimensions + 1)
+            }
+            typ => (self.type_name(typ), 0),
+        }
+    }
+
+    // The type of the value, as far as it can be told from the expression.
+    fn expression_type(&self, expression: &Expression) -> Option<Type> {
+        let pool = self.source.const_pool;
+        let member_type = |pool_pos: u16| {
+            pool.member_ref(pool_pos)
+                .ok()
+                .and_then(|member| Type::parse_str(member.descriptor))
+        };
+        let op = expression.op;
+        match &expression.ex {
+            ExpressionType::ConstantEx { op } => match *op {
+                ACONST_NULL => None,
+                ICONST_M1..=ICONST_5 => Some(Type::Int),
+                LCONST_0 | LCONST_1 => Some(Type::Long),
+                FCONST_0..=FCONST_2 => Some(Type::Float),
+                _ => Some(Type::Double),
+            },
+            ExpressionType::NumberConstant { .. } => Some(Type::Int),
+            ExpressionType::PoolConstantEx { pool_pos } => match pool.get(*pool_pos) {
+                Some(ConstantInfo::Integer { .. }) => Some(Type::Int),
+                Some(ConstantInfo::Float { .. }) => Some(Type::Float),
+                Some(ConstantInfo::Long { .. }) => Some(Type::Long),
+                Some(ConstantInfo::Double { .. }) => Some(Type::Double),
+                Some(ConstantInfo::String { .. }) => Some(class_type("java/lang/String")),
+                Some(ConstantInfo::Class { .. }) => Some(class_type("java/lang/Class")),
+                _ => None,
+            },
+            ExpressionType::OperatorEx { left, right } => {
+                let boolean = self.expression_type(left) == Some(Type::Boolean)
+                    && self.expression_type(right) == Some(Type::Boolean);
+                match op {
+                    IAND | IOR | IXOR if boolean => Some(Type::Boolean),
+                    IADD..=DREM => Some(numeric_type((op - IADD) % 4)),
+                    _ => Some(if (op - ISHL).is_multiple_of(2) {
+                        Type::Int
+                    } else {
+                        Type::Long
+                    }),
+                }
+            }
+            ExpressionType::NegateEx { .. } => Some(numeric_type((op - INEG) % 4)),
+            ExpressionType::ConvertEx { .. } => Some(match op {
+                I2L | F2L | D2L => Type::Long,
+                I2F | L2F | D2F => Type::Float,
+                I2D | L2D | F2D => Type::Double,
+                I2B => Type::Byte,
+                I2C => Type::Char,
+                I2S => Type::Short,
+                _ => Type::Int,
+            }),
+            ExpressionType::LoadVarEx { .. } | ExpressionType::PostIncrementEx { .. } => {
+                self.var_type(expression)
+            }
+            ExpressionType::StoreVarEx { value, .. } => self
+                .var_type(expression)
+                .or_else(|| self.expression_type(value)),
+            ExpressionType::ArrayLoadEx { array, .. } => match self.expression_type(array) {
+                Some(Type::Array { component }) => Some(*component),
+                _ => match op {
+                    IALOAD => Some(Type::Int),
+                    LALOAD => Some(Type::Long),
+                    FALOAD => Some(Type::Float),
+                    DALOAD => Some(Type::Double),
+                    BALOAD => Some(Type::Byte),
+                    CALOAD => Some(Type::Char),
+                    SALOAD => Some(Type::Short),
+                    _ => None,
+                },
+            },
+            ExpressionType::CompareEx { .. } | ExpressionType::ArrayLengthEx { .. } => {
+                Some(Type::Int)
+            }
+            ExpressionType::InstanceOfEx { .. }
+            | ExpressionType::IfEx { .. }
+            | ExpressionType::If0Ex { .. }
+            | ExpressionType::AndEx { .. }
+            | ExpressionType::OrEx { .. } => Some(Type::Boolean),
+            ExpressionType::TernaryEx {
+                then, otherwise, ..
+            } => self
+                .expression_type(then)
+                .or_else(|| self.expression_type(otherwise)),
+            ExpressionType::NewEx { pool_pos } | ExpressionType::ConstructEx { pool_pos, .. } => {
+                pool.class_name(*pool_pos).ok().map(class_type)
+            }
+            ExpressionType::NewPrimArrayEx { array_type, .. } => Some(Type::Array {
+                component: Box::new(Type::parse_str(primitive_array_descriptor(*array_type))?),
+            }),
+            ExpressionType::NewArrayEx { pool_pos, .. } => {
+                pool.class_name(*pool_pos).ok().map(|name| Type::Array {
+                    component: Box::new(class_type(name)),
+                })
+            }
+            ExpressionType::MultiNewArrayEx { pool_pos, .. }
+            | ExpressionType::CastEx { pool_pos, .. } => {
+                pool.class_name(*pool_pos).ok().map(class_type)
+            }
+            ExpressionType::ArrayInitEx { array, .. } => self.expression_type(array),
+            ExpressionType::GetFieldEx { pool_pos, .. }
+            | ExpressionType::GetStaticFieldEx { pool_pos } => member_type(*pool_pos),
+            ExpressionType::PutFieldEx { value, .. }
+            | ExpressionType::PutStaticFieldEx { value, .. }
+            | ExpressionType::ArrayStoreEx { value, .. } => self.expression_type(value),
+            ExpressionType::MethodEx { pool_pos, kind, .. } => {
+                let descriptor = if *kind == InvokeKind::Dynamic {
+                    pool.invoke_dynamic(*pool_pos).ok()?.descriptor
+                } else {
+                    pool.member_ref(*pool_pos).ok()?.descriptor
+                };
+                MethodType::parse_str(descriptor).map(|method_type| method_type.return_type)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The lists of statements inside a statement, with the selector their paths go through.
+fn sub_lists(statement: &Statement) -> Vec<(usize, &Vec<Statement>)> {
+    match statement {
+        Statement::If {
+            then, otherwise, ..
+        } => vec![(0, then), (1, otherwise)],
+        Statement::While { body, .. }
+        | Statement::DoWhile { body, .. }
+        | Statement::For { body, .. }
+        | Statement::Block { body, .. }
+        | Statement::Synchronized { body, .. } => vec![(0, body)],
+        Statement::Switch { cases, .. } => cases
+            .iter()
+            .enumerate()
+            .map(|(position, case)| (CASE + position, &case.body))
+            .collect(),
+        Statement::Try {
+            body,
+            catches,
+            finally,
+            ..
+        } => {
+            let mut lists = vec![(0, body)];
+            for (position, catch) in catches.iter().enumerate() {
+                lists.push((1 + position, &catch.body));
+            }
+            lists.push((1 + catches.len(), finally));
+            lists
+        }
+        _ => Vec::new(),
+    }
+}
+
+// The store of the exception a catch starts with.
+fn caught(expression: &Expression) -> bool {
+    matches!(&expression.ex, ExpressionType::StoreVarEx { value, .. } if value.ex == ExpressionType::CatchEx)
+}
+
+// Whether a var declared at the inner scope would be seen from the outer one.
+fn within(outer: &Scope, inner: &Scope) -> bool {
+    let ((outer_list, outer_index), (inner_list, inner_index)) = (outer, inner);
+    if inner_list.len() < outer_list.len() {
+        return false;
+    }
+    let same = |a: &usize, b: &usize| a == b || (*a >= CASE && *b >= CASE);
+    if !outer_list
+        .iter()
+        .zip(inner_list.iter())
+        .all(|(a, b)| same(a, b))
+    {
+        return false;
+    }
+    match inner_list.get(outer_list.len()) {
+        Some(index) => index >= outer_index,
+        None => inner_index >= outer_index,
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+fn load_kind(typ: &Type) -> u8 {
+    match typ {
+        Type::Long => LLOAD,
+        Type::Float => FLOAD,
+        Type::Double => DLOAD,
+        Type::Class { .. } | Type::Array { .. } => ALOAD,
+        _ => ILOAD,
+    }
+}
+
+// The load op for the type of the var a load, store or iinc is on.
+fn op_kind(op: u8) -> u8 {
+    match op {
+        ISTORE..=ASTORE => op - ISTORE + ILOAD,
+        IINC => ILOAD,
+        op => op,
+    }
+}
+
+fn class_type(name: &str) -> Type {
+    if name.starts_with('[') {
+        Type::parse_str(name).unwrap_or(Type::Class {
+            name: name.to_string(),
+        })
+    } else {
+        Type::Class {
+            name: name.to_string(),
+        }
+    }
+}
+
+fn numeric_type(position: u8) -> Type {
+    match position {
+        0 => Type::Int,
+        1 => Type::Long,
+        2 => Type::Float,
+        _ => Type::Double,
+    }
+}
+
+fn primitive_array_descriptor(array_type: u8) -> &'static str {
+    match array_type {
+        4 => "Z",
+        5 => "C",
+        6 => "F",
+        7 => "D",
+        8 => "B",
+        9 => "S",
+        11 => "J",
+        _ => "I",
+    }
+}
+
+fn primitive_array_type(array_type: u8) -> Type {
+    Type::parse_str(primitive_array_descriptor(array_type)).unwrap_or(Type::Int)
+}
+
+fn operator(op: u8) -> (&'static str, u8) {
+    match op {
+        IADD..=DADD => ("+", ADDITIVE),
+        ISUB..=DSUB => ("-", ADDITIVE),
+        IMUL..=DMUL => ("*", MULTIPLICATIVE),
+        IDIV..=DDIV => ("/", MULTIPLICATIVE),
+        IREM..=DREM => ("%", MULTIPLICATIVE),
+        ISHL | LSHL => ("<<", SHIFT),
+        ISHR | LSHR => (">>", SHIFT),
+        IUSHR | LUSHR => (">>>", SHIFT),
+        IAND | LAND => ("&", BIT_AND),
+        IOR | LOR => ("|", BIT_OR),
+        _ => ("^", BIT_XOR),
+    }
+}
+
+fn comparison(op: u8) -> (&'static str, u8) {
+    match op {
+        IFEQ | IF_ICMPEQ | IF_ACMPEQ => ("==", EQUALITY),
+        IFNE | IF_ICMPNE | IF_ACMPNE => ("!=", EQUALITY),
+        IFLT | IF_ICMPLT => ("<", RELATION),
+        IFGE | IF_ICMPGE => (">=", RELATION),
+        IFGT | IF_ICMPGT => (">", RELATION),
+        _ => ("<=", RELATION),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java_asm::assemble;
+
+    // Assembles a listing and decompiles the class read back from its bytes.
+    fn source(text: &str) -> String {
+        let bytes = assemble(text).unwrap().write().unwrap();
+        let (_, mut class_info) = ClassInfo::parse(&bytes).unwrap();
+        class_source(&mut class_info, &HashMap::new())
+    }
+
+    #[test]
+    fn escapes() {
+        // a digit after an octal escape is not part of it
+        assert_eq!(string_literal("\u{1}2"), "\"\\0012\"");
+        assert_eq!(string_literal("\u{0}7\u{7f}"), "\"\\0007\\177\"");
+        assert_eq!(string_literal("a\tb\u{2028}"), "\"a\\tb\\u2028\"");
+        assert_eq!(char_literal(0x1b), "'\\033'");
+        assert_eq!(char_literal('\'' as u16), "'\\''");
+    }
+
+    #[test]
+    fn unresolved_stack() {
+        // the value kept on the stack around the loop can not be put anywhere
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static f()I
+                .limit stack 2
+                .limit locals 0
+                iconst_0
+            top:
+                dup
+                ifne out
+                pop
+                iconst_1
+                goto top
+            out:
+                ireturn
+            .end method",
+        );
+        assert!(
+            source.contains("// could not decompile: UnresolvedStack"),
+            "{}",
+            source
+        );
+        assert!(source.contains("//     7: goto          1"), "{}", source);
+        assert!(!source.contains("$stack"), "{}", source);
+    }
+
+    #[test]
+    fn for_var_read_after() {
+        // i is the result, so it can not be declared in the for
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static f([I)I
+                .limit stack 2
+                .limit locals 2
+                iconst_0
+                istore_1
+            top:
+                iload_1
+                aload_0
+                arraylength
+                if_icmpge out
+                aload_0
+                iload_1
+                iaload
+                ifne next
+                goto out
+            next:
+                iinc 1 1
+                goto top
+            out:
+                iload_1
+                ireturn
+            .end method
+            .method public static g(I)I
+                .limit stack 2
+                .limit locals 3
+                iconst_0
+                istore_1
+                iconst_0
+                istore_2
+            top:
+                iload_2
+                iload_0
+                if_icmpge out
+                iload_1
+                iload_2
+                iadd
+                istore_1
+                iinc 2 1
+                goto top
+            out:
+                iload_1
+                ireturn
+            .end method",
+        );
+        assert!(source.contains("int var1;\n"), "{}", source);
+        assert!(
+            source.contains("for (var1 = 0; var1 < arg0.length && arg0[var1] != 0; var1++) {"),
+            "{}",
+            source
+        );
+        assert!(
+            source.contains("for (int var2 = 0; var2 < arg0; var2++) {"),
+            "{}",
+            source
+        );
+    }
+
+    #[test]
+    fn narrow_locals() {
+        // the var gets the type of the values stored in it, constants only have to fit
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static h(C)C
+                .limit stack 2
+                .limit locals 1
+                iload_0
+                iconst_1
+                iadd
+                i2c
+                istore_0
+                iload_0
+                ireturn
+            .end method
+            .method public static b([BZ)B
+                .limit stack 2
+                .limit locals 3
+                iconst_5
+                istore_2
+                iload_1
+                ifeq done
+                aload_0
+                iconst_0
+                baload
+                istore_2
+            done:
+                iload_2
+                ireturn
+            .end method
+            .method public static s(I)I
+                .limit stack 2
+                .limit locals 2
+                sipush 1000
+                istore_1
+                iload_0
+                ifeq done
+                iload_0
+                i2b
+                istore_1
+            done:
+                iload_1
+                ireturn
+            .end method",
+        );
+        assert!(
+            source.contains("char var0 = (char) (arg0 + 1);"),
+            "{}",
+            source
+        );
+        assert!(source.contains("byte var2 = 5;"), "{}", source);
+        assert!(source.contains("int var1 = 1000;"), "{}", source);
+    }
+
+    #[test]
+    fn short_circuit() {
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static mixed(IIII)I
+                .limit stack 1
+                .limit locals 4
+                iload_0
+                ifle second
+                iload_1
+                ifgt yes
+            second:
+                iload_2
+                ifle no
+                iload_3
+                ifle no
+            yes:
+                iconst_1
+                ireturn
+            no:
+                iconst_0
+                ireturn
+            .end method
+            .method public static nested(III)I
+                .limit stack 1
+                .limit locals 3
+                iload_0
+                ifle no
+                iload_1
+                ifgt yes
+                iload_2
+                ifle no
+            yes:
+                iconst_1
+                ireturn
+            no:
+                iconst_0
+                ireturn
+            .end method
+            .method public static negated(II)I
+                .limit stack 1
+                .limit locals 2
+                iload_0
+                ifle yes
+                iload_1
+                ifgt no
+            yes:
+                iconst_1
+                ireturn
+            no:
+                iconst_0
+                ireturn
+            .end method
+            .method public static nan(DDD)I
+                .limit stack 4
+                .limit locals 6
+                dload_0
+                dload_2
+                dcmpl
+                ifle no
+                dload_2
+                dload 4
+                dcmpg
+                ifle no
+                iconst_1
+                ireturn
+            no:
+                iconst_0
+                ireturn
+            .end method
+            .method public static less(FF)I
+                .limit stack 2
+                .limit locals 2
+                fload_0
+                fload_1
+                fcmpg
+                iflt yes
+                fload_1
+                fload_1
+                fcmpl
+                ifeq no
+            yes:
+                iconst_1
+                ireturn
+            no:
+                iconst_0
+                ireturn
+            .end method",
+        );
+        let conditions = [
+            "if (arg0 > 0 && arg1 > 0 || arg2 > 0 && arg3 > 0) {",
+            "if (arg0 > 0 && (arg1 > 0 || arg2 > 0)) {",
+            // the negated chain is the one the jumps test
+            "if (arg0 <= 0 || arg1 <= 0) {",
+            // a compare is only turned around when it gives the same for NaN
+            "if (arg0 > arg1 && !(arg1 <= arg2)) {",
+            "if (arg0 < arg1 || arg1 != arg1) {",
+        ];
+        for condition in conditions {
+            assert!(source.contains(condition), "{}", source);
+        }
+    }
+
+    #[test]
+    fn wide_ternaries() {
+        // a long or double ?: is one value to dup2_x1, dup2_x2 and pop2, not two
+        let source = source(
+            ".class public W
+            .super java/lang/Object
+            .field d D
+            .method static f([JZ)J
+                .limit stack 6
+                .limit locals 2
+                aload_0
+                iconst_0
+                iload_1
+                ifeq two
+                lconst_1
+                goto set
+            two:
+                ldc2_w 2
+            set:
+                dup2_x2
+                lastore
+                lreturn
+            .end method
+            .method static g(Z)V
+                .limit stack 5
+                .limit locals 3
+                getstatic java/lang/System/out Ljava/io/PrintStream;
+                iload_0
+                ifeq two
+                dconst_1
+                goto print
+            two:
+                ldc2_w 2.0
+            print:
+                dup2
+                dstore_1
+                invokevirtual java/io/PrintStream/println(D)V
+                return
+            .end method
+            .method h(Z)D
+                .limit stack 5
+                .limit locals 2
+                aload_0
+                iload_1
+                ifeq two
+                dconst_1
+                goto set
+            two:
+                ldc2_w 2.0
+            set:
+                dup2_x1
+                putfield W/d D
+                dreturn
+            .end method
+            .method static k(ZJ)J
+                .limit stack 4
+                .limit locals 3
+                iload_0
+                ifeq two
+                lload_1
+                goto pop
+            two:
+                ldc2_w 2
+            pop:
+                dup2
+                lstore_1
+                pop2
+                lload_1
+                lreturn
+            .end method",
+        );
+        assert!(source.contains("return arg0[0] = arg1 ? 1L : 2L;"), "{}", source);
+        assert!(source.contains("System.out.println(var1 = arg0 ? 1.0 : 2.0);"), "{}", source);
+        assert!(source.contains("return this.d = arg0 ? 1.0 : 2.0;"), "{}", source);
+        assert!(source.contains("long var1 = arg0 ? arg1 : 2L;"), "{}", source);
+        assert!(!source.contains("could not decompile"), "{}", source);
+    }
+
+    #[test]
+    fn instanceof_pattern() {
+        // javac stores the pattern var between the two tests of o instanceof P p && p.v == v
+        let source = source(
+            ".class public W
+            .super java/lang/Object
+            .method static k(Ljava/lang/Object;I)Z
+                .limit stack 2
+                .limit locals 3
+                aload_0
+                instanceof W$P
+                ifeq no
+                aload_0
+                checkcast W$P
+                astore_2
+                aload_2
+                getfield W$P/v I
+                iload_1
+                if_icmpne no
+                iconst_1
+                goto done
+            no:
+                iconst_0
+            done:
+                ireturn
+            .end method",
+        );
+        assert!(
+            source.contains("return arg0 instanceof W.P && (var2 = (W.P) arg0).v == arg1;"),
+            "{}",
+            source
+        );
+    }
+
+    #[test]
+    fn boolean_locals() {
+        // a stored condition is a boolean, unless the var is used as a number
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static flag(I)Z
+                .limit stack 2
+                .limit locals 2
+                iload_0
+                iconst_3
+                if_icmple no
+                iconst_1
+                goto store
+            no:
+                iconst_0
+            store:
+                istore_1
+                iload_1
+                ifeq done
+                iinc 0 1
+            done:
+                iload_1
+                ireturn
+            .end method
+            .method public static count(II)I
+                .limit stack 2
+                .limit locals 3
+                iload_0
+                iload_1
+                if_icmple no
+                iconst_1
+                goto store
+            no:
+                iconst_0
+            store:
+                istore_2
+                iload_2
+                iload_1
+                iadd
+                ireturn
+            .end method",
+        );
+        assert!(source.contains("boolean var1 = arg0 > 3;"), "{}", source);
+        assert!(source.contains("if (var1) {"), "{}", source);
+        assert!(
+            source.contains("int var2 = arg0 > arg1 ? 1 : 0;"),
+            "{}",
+            source
+        );
+    }
+
+    #[test]
+    fn try_blocks() {
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static g()V
+                .limit stack 0
+                .limit locals 0
+                return
+            .end method
+            .method public static f(I)I
+                .limit stack 2
+                .limit locals 2
+            start:
+                iinc 0 1
+                invokestatic A/g()V
+            end:
+                iload_0
+                iconst_3
+                imul
+                istore_0
+                goto done
+            any:
+                astore_1
+                iload_0
+                iconst_3
+                imul
+                istore_0
+                aload_1
+                athrow
+            done:
+                iload_0
+                ireturn
+            .catch all from start to end using any
+            .end method
+            .method public static multi(Ljava/lang/String;)I
+                .limit stack 1
+                .limit locals 2
+            start:
+                aload_0
+                invokestatic java/lang/Integer/parseInt(Ljava/lang/String;)I
+            end:
+                ireturn
+            handler:
+                astore_1
+                iconst_m1
+                ireturn
+            .catch java/lang/NumberFormatException from start to end using handler
+            .catch java/lang/NullPointerException from start to end using handler
+            .end method
+            .method public static nested(I)I
+                .limit stack 2
+                .limit locals 3
+            start:
+                iload_0
+                iconst_2
+                idiv
+                istore_0
+            inner_end:
+                goto after_inner
+            arithmetic:
+                astore_1
+                iconst_1
+                istore_0
+            after_inner:
+                invokestatic A/g()V
+            end:
+                invokestatic A/g()V
+                goto done
+            runtime:
+                astore_1
+                iconst_2
+                istore_0
+            runtime_end:
+                invokestatic A/g()V
+                goto done
+            any:
+                astore_2
+                invokestatic A/g()V
+                aload_2
+                athrow
+            done:
+                iload_0
+                ireturn
+            .catch java/lang/ArithmeticException from start to inner_end using arithmetic
+            .catch java/lang/RuntimeException from start to end using runtime
+            .catch all from start to end using any
+            .catch all from runtime to runtime_end using any
+            .end method",
+        );
+        // the finally is the copy which stores the var read after the try
+        let finally = "int var0;
+        try {
+            arg0++;
+            g();
+        } finally {
+            var0 = arg0 * 3;
+        }
+        return var0;";
+        assert!(source.contains(finally), "{}", source);
+        assert!(
+            source.contains("} catch (NumberFormatException | NullPointerException var1) {"),
+            "{}",
+            source
+        );
+        let nested = "try {
+            try {
+                var0 = arg0 / 2;
+            } catch (ArithmeticException var1) {
+                var0 = 1;
+            }
+            g();
+        } catch (RuntimeException var1) {
+            var0 = 2;
+        } finally {
+            g();
+        }
+        return var0;";
+        assert!(source.contains(nested), "{}", source);
+    }
+
+    // try (FileInputStream in = new FileInputStream(p)) { return in.read(); } as javac 11 and
+    // later expand it, the resource is closed on the way out and in a catch of anything.
+    #[test]
+    fn resources() {
+        let source = source(
+            ".class public R
+            .super java/lang/Object
+            .method static f(Ljava/lang/String;)I
+                .limit stack 3
+                .limit locals 4
+                .catch java/lang/Throwable from body to close using failed
+                .catch java/lang/Throwable from close_failed to closed using suppress
+                new java/io/FileInputStream
+                dup
+                aload_0
+                invokespecial java/io/FileInputStream/<init>(Ljava/lang/String;)V
+                astore_1
+            body:
+                aload_1
+                invokevirtual java/io/FileInputStream/read()I
+                istore_2
+            close:
+                aload_1
+                invokevirtual java/io/FileInputStream/close()V
+                iload_2
+                ireturn
+            failed:
+                astore_2
+            close_failed:
+                aload_1
+                invokevirtual java/io/FileInputStream/close()V
+            closed:
+                goto rethrow
+            suppress:
+                astore_3
+                aload_2
+                aload_3
+                invokevirtual java/lang/Throwable/addSuppressed(Ljava/lang/Throwable;)V
+            rethrow:
+                aload_2
+                athrow
+            .end method",
+        );
+        assert!(
+            source.contains(
+                "try (java.io.FileInputStream var1 = new java.io.FileInputStream(arg0)) {
+            return var1.read();
+        }"
+            ),
+            "{}",
+            source
+        );
+    }
+
+    // javac 9 and 10 keep the exception in a var and close in a finally through $closeResource,
+    // which adds what close throws to it as suppressed.
+    #[test]
+    fn close_resource() {
+        let source = source(
+            ".class public R
+            .super java/lang/Object
+            .method static f(Ljava/lang/String;)V
+                .limit stack 3
+                .limit locals 5
+                .catch java/lang/Throwable from body to close using failed
+                .catch all from body to close using finally
+                .catch all from failed to finally using finally
+                new java/io/FileInputStream
+                dup
+                aload_0
+                invokespecial java/io/FileInputStream/<init>(Ljava/lang/String;)V
+                astore_1
+                aconst_null
+                astore_2
+            body:
+                aload_1
+                invokevirtual java/io/FileInputStream/read()I
+                pop
+            close:
+                aload_2
+                aload_1
+                invokestatic R/$closeResource(Ljava/lang/Throwable;Ljava/lang/AutoCloseable;)V
+                goto done
+            failed:
+                astore_3
+                aload_3
+                astore_2
+                aload_3
+                athrow
+            finally:
+                astore 4
+                aload_2
+                aload_1
+                invokestatic R/$closeResource(Ljava/lang/Throwable;Ljava/lang/AutoCloseable;)V
+                aload 4
+                athrow
+            done:
+                return
+            .end method
+            .method private static synthetic $closeResource(Ljava/lang/Throwable;Ljava/lang/AutoCloseable;)V
+                .limit stack 2
+                .limit locals 3
+                .catch java/lang/Throwable from close to closed using failed
+                aload_0
+                ifnull plain
+            close:
+                aload_1
+                invokeinterface java/lang/AutoCloseable/close()V
+            closed:
+                goto done
+            failed:
+                astore_2
+                aload_0
+                aload_2
+                invokevirtual java/lang/Throwable/addSuppressed(Ljava/lang/Throwable;)V
+                goto done
+            plain:
+                aload_1
+                invokeinterface java/lang/AutoCloseable/close()V
+            done:
+                return
+            .end method",
+        );
+        assert!(
+            source.contains(
+                "try (java.io.FileInputStream var1 = new java.io.FileInputStream(arg0)) {
+            var1.read();
+        }"
+            ),
+            "{}",
+            source
+        );
+    }
+
+    // javac 7 and 8 write out the closing of the resource after the body and again in the
+    // finally, each checking the resource and the exception against null.
+    #[test]
+    fn resource_finally() {
+        let source = source(
+            ".class public R
+            .super java/lang/Object
+            .method static f(Ljava/lang/String;)V
+                .limit stack 3
+                .limit locals 6
+                .catch java/lang/Throwable from close to closed using suppress
+                .catch java/lang/Throwable from body to after using failed
+                .catch all from body to after using finally
+                .catch java/lang/Throwable from close_again to closed_again using suppress_again
+                .catch all from failed to finally using finally
+                new java/io/FileInputStream
+                dup
+                aload_0
+                invokespecial java/io/FileInputStream/<init>(Ljava/lang/String;)V
+                astore_1
+                aconst_null
+                astore_2
+            body:
+                aload_1
+                invokevirtual java/io/FileInputStream/read()I
+                pop
+            after:
+                aload_1
+                ifnull done
+                aload_2
+                ifnull plain
+            close:
+                aload_1
+                invokevirtual java/io/FileInputStream/close()V
+            closed:
+                goto done
+            suppress:
+                astore_3
+                aload_2
+                aload_3
+                invokevirtual java/lang/Throwable/addSuppressed(Ljava/lang/Throwable;)V
+                goto done
+            plain:
+                aload_1
+                invokevirtual java/io/FileInputStream/close()V
+                goto done
+            failed:
+                astore_3
+                aload_3
+                astore_2
+                aload_3
+                athrow
+            finally:
+                astore 4
+                aload_1
+                ifnull rethrow
+                aload_2
+                ifnull plain_again
+            close_again:
+                aload_1
+                invokevirtual java/io/FileInputStream/close()V
+            closed_again:
+                goto rethrow
+            suppress_again:
+                astore 5
+                aload_2
+                aload 5
+                invokevirtual java/lang/Throwable/addSuppressed(Ljava/lang/Throwable;)V
+                goto rethrow
+            plain_again:
+                aload_1
+                invokevirtual java/io/FileInputStream/close()V
+            rethrow:
+                aload 4
+                athrow
+            done:
+                return
+            .end method",
+        );
+        assert!(
+            source.contains(
+                "try (java.io.FileInputStream var1 = new java.io.FileInputStream(arg0)) {
+            var1.read();
+        }"
+            ),
+            "{}",
+            source
+        );
+    }
+
+    #[test]
+    fn nested_synchronized() {
+        // the return in the inner block exits both monitors before it returns
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static f(Ljava/lang/Object;Ljava/lang/Object;I)I
+                .limit stack 2
+                .limit locals 7
+                aload_0
+                dup
+                astore_3
+                monitorenter
+            outer:
+                iload_2
+                ifle after_inner
+                aload_1
+                dup
+                astore 4
+                monitorenter
+            inner:
+                iload_2
+                iconst_5
+                if_icmple more
+                iload_2
+                aload 4
+                monitorexit
+            inner_return:
+                aload_3
+                monitorexit
+            outer_return:
+                ireturn
+            more:
+                iinc 2 1
+                aload 4
+                monitorexit
+            inner_end:
+                goto after_inner
+            inner_any:
+                astore 5
+                aload 4
+                monitorexit
+            inner_any_end:
+                aload 5
+                athrow
+            after_inner:
+                iinc 2 -1
+                aload_3
+                monitorexit
+            outer_end:
+                goto done
+            outer_any:
+                astore 6
+                aload_3
+                monitorexit
+            outer_any_end:
+                aload 6
+                athrow
+            done:
+                iload_2
+                ireturn
+            .catch all from inner to inner_return using inner_any
+            .catch all from more to inner_end using inner_any
+            .catch all from inner_any to inner_any_end using inner_any
+            .catch all from outer to outer_return using outer_any
+            .catch all from more to outer_end using outer_any
+            .catch all from outer_any to outer_any_end using outer_any
+            .end method",
+        );
+        let body = "synchronized (arg0) {
+            if (arg2 > 0) {
+                synchronized (arg1) {
+                    if (arg2 > 5) {
+                        return arg2;
+                    }
+                    arg2++;
+                }
+            }
+            arg2--;
+        }
+        return arg2;";
+        assert!(source.contains(body), "{}", source);
+    }
+
+    #[test]
+    fn switches() {
+        // cases fall through, share a body and the default is not the last one
+        let source = source(
+            ".class public A
+            .super java/lang/Object
+            .method public static g(I)V
+                .limit stack 0
+                .limit locals 1
+                return
+            .end method
+            .method public static table(I)I
+                .limit stack 1
+                .limit locals 2
+                iconst_0
+                istore_1
+                iload_0
+                tableswitch 1 one two three three five default other
+            one:
+                bipush 10
+                istore_1
+            two:
+                iinc 1 20
+                goto done
+            three:
+                bipush 30
+                istore_1
+                goto done
+            other:
+                iconst_m1
+                istore_1
+                goto done
+            five:
+                bipush 50
+                istore_1
+            done:
+                iload_1
+                ireturn
+            .end method
+            .method public static lookup(I)V
+                .limit stack 1
+                .limit locals 1
+                iload_0
+                lookupswitch -7:second 100:first 1000:last 2000:last default other
+            first:
+                iconst_1
+                invokestatic A/g(I)V
+            second:
+                iconst_2
+                invokestatic A/g(I)V
+                goto done
+            other:
+                iconst_0
+                invokestatic A/g(I)V
+            last:
+                iconst_3
+                invokestatic A/g(I)V
+            done:
+                return
+            .end method",
+        );
+        let table = "switch (arg0) {
+            case 1:
+                var1 = 10;
+            case 2:
+                var1 += 20;
+                break;
+            case 3:
+            case 4:
+                var1 = 30;
+                break;
+            default:
+                var1 = -1;
+                break;
+            case 5:
+                var1 = 50;
+        }
+        return var1;";
+        assert!(source.contains(table), "{}", source);
+        let lookup = "switch (arg0) {
+            case 100:
+                g(1);
+            case -7:
+                g(2);
+                break;
+            default:
+                g(0);
+            case 1000:
+            case 2000:
+                g(3);
+        }";
+        assert!(source.contains(lookup), "{}", source);
+    }
+
+    #[test]
+    fn empty_string_case() {
+        // index 1 of "a" goes where the default goes, right after the switch
+        let source = source(
+            ".class public T
+            .super java/lang/Object
+            .method static f(Ljava/lang/String;)I
+                .limit stack 2
+                .limit locals 4
+                iconst_0
+                istore_1
+                aload_0
+                astore_2
+                iconst_m1
+                istore_3
+                aload_2
+                invokevirtual java/lang/String/hashCode()I
+                lookupswitch 97:a 98:b default index
+            b:
+                aload_2
+                ldc \"b\"
+                invokevirtual java/lang/String/equals(Ljava/lang/Object;)Z
+                ifeq index
+                iconst_0
+                istore_3
+                goto index
+            a:
+                aload_2
+                ldc \"a\"
+                invokevirtual java/lang/String/equals(Ljava/lang/Object;)Z
+                ifeq index
+                iconst_1
+                istore_3
+            index:
+                iload_3
+                lookupswitch 0:set 1:done default done
+            set:
+                iconst_2
+                istore_1
+                goto done
+            done:
+                iload_1
+                ireturn
+            .end method",
+        );
+        assert!(source.contains("case \"b\":"), "{}", source);
+        assert!(source.contains("case \"a\":"), "{}", source);
+        assert!(!source.contains("default:"), "{}", source);
+    }
+}
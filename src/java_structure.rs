@@ -0,0 +1,3190 @@
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Range;
+use std::slice;
+
+use crate::consts::{
+    ACONST_NULL, ICONST_0, ICONST_5, ICONST_M1, IDIV, IFEQ, IFNE, IFNONNULL, IFNULL, IREM, LDIV,
+    LREM, MONITORENTER, MONITOREXIT, NOP,
+};
+use crate::java::{ConstantPool, Literal, MemberRef};
+use crate::java_code::InvokeKind;
+use crate::java_decomp::{negate, DecompError, Expression, ExpressionType};
+use crate::java_dominators::DominatorTree;
+use crate::java_loops::LoopForest;
+
+// A chunk with its expressions, ready to be placed in the statement tree.
+#[derive(Clone)]
+pub struct Node {
+    pub list: Vec<Expression>,
+    pub exit: NodeExit,
+    // The handlers the node is protected by with their caught class, 0 catches anything.
+    pub handlers: Vec<(u32, u16)>,
+    // The pcs of its ops, values it starts with on the stack may come from before.
+    pub pcs: Range<u32>,
+}
+
+#[derive(Clone)]
+pub enum NodeExit {
+    // The list ends with a return or a throw.
+    End,
+    Goto {
+        target: u32,
+    },
+    Condition {
+        condition: Expression,
+        true_target: u32,
+        false_target: u32,
+    },
+    Switch {
+        value: Expression,
+        cases: Vec<(i32, u32)>,
+        default: u32,
+    },
+}
+
+impl NodeExit {
+    fn targets(&self) -> Vec<u32> {
+        match self {
+            NodeExit::End => Vec::new(),
+            NodeExit::Goto { target } => vec![*target],
+            NodeExit::Condition {
+                true_target,
+                false_target,
+                ..
+            } => vec![*true_target, *false_target],
+            NodeExit::Switch { cases, default, .. } => {
+                let mut targets: Vec<u32> = cases.iter().map(|(_, target)| *target).collect();
+                targets.push(*default);
+                targets
+            }
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut u32> {
+        match self {
+            NodeExit::End => Vec::new(),
+            NodeExit::Goto { target } => vec![target],
+            NodeExit::Condition {
+                true_target,
+                false_target,
+                ..
+            } => vec![true_target, false_target],
+            NodeExit::Switch { cases, default, .. } => {
+                let mut targets: Vec<&mut u32> =
+                    cases.iter_mut().map(|(_, target)| target).collect();
+                targets.push(default);
+                targets
+            }
+        }
+    }
+}
+
+// The structured body of a method. Loops, blocks and switches have a label which breaks and
+// continues refer to, it is only printed when a jump needs it.
+pub enum Statement {
+    Expression {
+        expression: Expression,
+    },
+    If {
+        condition: Expression,
+        then: Vec<Statement>,
+        otherwise: Vec<Statement>,
+    },
+    // No condition is while (true).
+    While {
+        label: u32,
+        condition: Option<Expression>,
+        body: Vec<Statement>,
+    },
+    DoWhile {
+        label: u32,
+        body: Vec<Statement>,
+        condition: Expression,
+    },
+    For {
+        label: u32,
+        init: Vec<Expression>,
+        condition: Option<Expression>,
+        update: Vec<Expression>,
+        body: Vec<Statement>,
+    },
+    // A block which is only there to be broken out of.
+    Block {
+        label: u32,
+        body: Vec<Statement>,
+    },
+    Switch {
+        label: u32,
+        value: Expression,
+        cases: Vec<SwitchCase>,
+    },
+    Break {
+        label: u32,
+    },
+    Continue {
+        label: u32,
+    },
+    // There is always a resource, a catch or a finally, which is printed even when it is empty
+    // then. The resources are the stores of their vars, they are closed the other way around.
+    Try {
+        resources: Vec<Expression>,
+        body: Vec<Statement>,
+        catches: Vec<Catch>,
+        finally: Vec<Statement>,
+        // The pcs of the ops in the body which can throw but which the handlers do not protect,
+        // javac leaves copies of a finally there. They have to be taken out of the body.
+        unprotected: Vec<u32>,
+    },
+    // The var javac keeps the lock in to exit its monitor again is gone.
+    Synchronized {
+        lock: Expression,
+        body: Vec<Statement>,
+    },
+}
+
+// The body of a catch starts with the store of the caught exception to its var.
+pub struct Catch {
+    // The caught classes in the constant pool, 0 catches anything.
+    pub types: Vec<u16>,
+    pub body: Vec<Statement>,
+}
+
+pub struct SwitchCase {
+    pub keys: Vec<CaseKey>,
+    pub default: bool,
+    pub body: Vec<Statement>,
+}
+
+// What a case of a switch is labeled with. javac only switches on ints, a switch on a string or
+// an enum is made of those again.
+#[derive(Clone, PartialEq)]
+pub enum CaseKey {
+    Int(i32),
+    // A string constant in the constant pool.
+    String(u16),
+    // The name of the enum constant.
+    Enum(String),
+}
+
+// Turns the nodes into statements without any goto, or fails when the flow is too tangled.
+//
+// Every node is placed in the dominator tree, see "Beyond Relooper" by Norman Ramsey. A node
+// with one way in is put right where it is jumped to, a node with more ways in is put after a
+// block around its dominator which is broken out of to get there, and a loop header gets a loop
+// which is continued to get back to it. The first node of a try gets a try with its handlers
+// as catches. Nodes after a loop or a try are put after the one they leave.
+// Loops with more than one entry are first made into natural loops by copying them.
+pub fn structure(
+    mut nodes: Vec<Node>,
+    const_pool: &ConstantPool,
+) -> Result<Vec<Statement>, DecompError> {
+    if nodes.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !split_irreducible(&mut nodes) {
+        return Err(DecompError::Irreducible);
+    }
+    let mut structurer = Structurer::new(nodes)?;
+    let mut body = structurer.tree(0);
+    simplify(&mut body);
+    if finally_blocks(&mut body) {
+        simplify(&mut body);
+    }
+    if resources(&mut body, const_pool) {
+        simplify(&mut body);
+    }
+    if monitors(&mut body) {
+        simplify(&mut body);
+    }
+    if string_switches(&mut body, const_pool) {
+        simplify(&mut body);
+    }
+    if !protected(&body) {
+        return Err(DecompError::UnprotectedCode);
+    }
+    if monitored(&body) {
+        return Err(DecompError::UnpairedMonitor);
+    }
+    Ok(body)
+}
+
+// Successors of the nodes which can be reached from the first one, the others have none. The
+// handlers come after the targets of the exit.
+pub fn reachable_successors(nodes: &[Node]) -> Vec<Vec<u32>> {
+    let mut successors = vec![Vec::new(); nodes.len()];
+    let mut seen = vec![false; nodes.len()];
+    let mut todo = vec![0];
+    seen[0] = true;
+    while let Some(node) = todo.pop() {
+        let mut targets = nodes[node as usize].exit.targets();
+        for (handler, _) in &nodes[node as usize].handlers {
+            if !targets.contains(handler) {
+                targets.push(*handler);
+            }
+        }
+        for target in &targets {
+            if !seen[*target as usize] {
+                seen[*target as usize] = true;
+                todo.push(*target);
+            }
+        }
+        successors[node as usize] = targets;
+    }
+    successors
+}
+
+// The position of every node in reverse postorder, usize::MAX for nodes which can't be reached.
+fn reverse_postorder(successors: &[Vec<u32>]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(successors.len());
+    let mut visited = vec![false; successors.len()];
+    let mut stack: Vec<(u32, usize)> = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((node, next)) = stack.last_mut() {
+        let node = *node;
+        match successors[node as usize].get(*next) {
+            Some(successor) => {
+                *next += 1;
+                if !visited[*successor as usize] {
+                    visited[*successor as usize] = true;
+                    stack.push((*successor, 0));
+                }
+            }
+            None => {
+                order.push(node);
+                stack.pop();
+            }
+        }
+    }
+    let mut number = vec![usize::MAX; successors.len()];
+    for (position, node) in order.iter().rev().enumerate() {
+        number[*node as usize] = position;
+    }
+    number
+}
+
+// Joins conditions which javac made from a && or a ||. A node which only tests a condition and
+// is only jumped to from another condition is folded into it, when either way out of the first
+// condition goes to the same place as one of the ways out of the second.
+pub fn short_circuits(nodes: &mut [Node]) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let successors = reachable_successors(nodes);
+        let mut predecessors = vec![0; nodes.len()];
+        for targets in &successors {
+            for target in targets {
+                predecessors[*target as usize] += 1;
+            }
+        }
+        let mut first = 0;
+        while first < nodes.len() {
+            let (true_target, false_target) = match &nodes[first].exit {
+                NodeExit::Condition {
+                    true_target,
+                    false_target,
+                    ..
+                } if !successors[first].is_empty() => (*true_target, *false_target),
+                _ => {
+                    first += 1;
+                    continue;
+                }
+            };
+            let test = |node: u32| tested(nodes, &predecessors, first as u32, node);
+            // the second condition is where the first one goes when it is false, or else true
+            let (second, other, flip) = match (test(false_target), test(true_target)) {
+                (Some(then), Some(otherwise))
+                    if then == otherwise || then == (otherwise.1, otherwise.0) =>
+                {
+                    conditional(nodes, first, then == otherwise);
+                    // both tests can't be reached anymore, and each place has one way less in
+                    predecessors[false_target as usize] = 0;
+                    predecessors[true_target as usize] = 0;
+                    predecessors[then.0 as usize] -= 1;
+                    predecessors[then.1 as usize] -= 1;
+                    changed = true;
+                    continue;
+                }
+                (Some(second), _) if true_target == second.0 || true_target == second.1 => {
+                    (false_target, true_target, false)
+                }
+                (_, Some(second)) if false_target == second.0 || false_target == second.1 => {
+                    (true_target, false_target, true)
+                }
+                _ => {
+                    first += 1;
+                    continue;
+                }
+            };
+            let (second_true, second_false) = match &nodes[second as usize].exit {
+                NodeExit::Condition {
+                    true_target,
+                    false_target,
+                    ..
+                } => (*true_target, *false_target),
+                _ => unreachable!(),
+            };
+            let exit = mem::replace(&mut nodes[first].exit, NodeExit::End);
+            let condition = match exit {
+                NodeExit::Condition { condition, .. } if flip => negate(condition),
+                NodeExit::Condition { condition, .. } => condition,
+                _ => unreachable!(),
+            };
+            let (second_condition, _, _) = take_condition(&mut nodes[second as usize], &condition);
+            let (op, pc) = (NOP, condition.pc);
+            // jumping to where the second goes when true makes an ||, else it is a && with
+            // the first one negated
+            let ex = if other == second_true {
+                ExpressionType::OrEx {
+                    left: Box::new(condition),
+                    right: Box::new(second_condition),
+                }
+            } else {
+                ExpressionType::AndEx {
+                    left: Box::new(negate(condition)),
+                    right: Box::new(second_condition),
+                }
+            };
+            nodes[first].exit = NodeExit::Condition {
+                condition: Expression { op, pc, ex },
+                true_target: second_true,
+                false_target: second_false,
+            };
+            nodes[first].pcs = joined_pcs(&nodes[first].pcs, &nodes[second as usize].pcs);
+            // the second node can't be reached anymore, and the first can be joined again
+            predecessors[second as usize] = 0;
+            predecessors[other as usize] -= 1;
+            changed = true;
+        }
+    }
+}
+
+// The condition of a node with the store it makes first moved into it, or None when that can't
+// be done. javac stores the var of a pattern before the test which uses it, so the second test of
+// o instanceof P p && p.v == v is p = (P) o; p.v == v, which becomes (p = (P) o).v == v. Only the
+// cast of such a pattern is moved, and only in place of the load which is evaluated first.
+fn fold_stores(
+    list: &[Expression],
+    condition: &Expression,
+    before: &Expression,
+) -> Option<Expression> {
+    fn first_evaluated(expression: &mut Expression) -> &mut Expression {
+        if expression.children().is_empty() {
+            expression
+        } else {
+            first_evaluated(expression.children_mut().remove(0))
+        }
+    }
+    fn instance_of(expression: &Expression, class: u16) -> bool {
+        matches!(expression.ex, ExpressionType::InstanceOfEx { pool_pos, .. } if pool_pos == class)
+            || expression
+                .children()
+                .into_iter()
+                .any(|child| instance_of(child, class))
+    }
+    let mut condition = condition.clone();
+    match list {
+        [] => {}
+        [store] => {
+            let var = match &store.ex {
+                ExpressionType::StoreVarEx { var, value } => match value.ex {
+                    ExpressionType::CastEx { pool_pos, .. } if instance_of(before, pool_pos) => {
+                        *var
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            let first = first_evaluated(&mut condition);
+            if !matches!(first.ex, ExpressionType::LoadVarEx { var: load } if load == var) {
+                return None;
+            }
+            *first = store.clone();
+        }
+        _ => return None,
+    }
+    Some(condition)
+}
+
+// Takes the condition and its targets out of a node which tested gave, with its store in it.
+fn take_condition(node: &mut Node, before: &Expression) -> (Expression, u32, u32) {
+    let list = mem::take(&mut node.list);
+    match mem::replace(&mut node.exit, NodeExit::End) {
+        NodeExit::Condition {
+            condition,
+            true_target,
+            false_target,
+        } => match fold_stores(&list, &condition, before) {
+            Some(condition) => (condition, true_target, false_target),
+            None => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+// The targets of a node which only tests a condition and is only jumped to from the given node.
+fn tested(nodes: &[Node], predecessors: &[usize], from: u32, node: u32) -> Option<(u32, u32)> {
+    let before = match &nodes[from as usize].exit {
+        NodeExit::Condition { condition, .. } => condition,
+        _ => return None,
+    };
+    match &nodes[node as usize] {
+        Node {
+            list,
+            exit:
+                NodeExit::Condition {
+                    condition,
+                    true_target,
+                    false_target,
+                },
+            handlers,
+            ..
+        } if (list.is_empty() || fold_stores(list, condition, before).is_some())
+            && !condition.pulls_stack()
+            && *handlers == nodes[from as usize].handlers
+            && predecessors[node as usize] == 1
+            && node != 0
+            && node != from =>
+        {
+            Some((*true_target, *false_target))
+        }
+        _ => None,
+    }
+}
+
+// A ?: in a condition, javac tests the then or the otherwise part right after the condition and
+// both go to the same two places. Same tells if they go there for the same outcome.
+fn conditional(nodes: &mut [Node], first: usize, same: bool) {
+    let (condition, then, otherwise) = match mem::replace(&mut nodes[first].exit, NodeExit::End) {
+        NodeExit::Condition {
+            condition,
+            true_target,
+            false_target,
+        } => (condition, false_target, true_target),
+        _ => unreachable!(),
+    };
+    let mut take = |node: u32| take_condition(&mut nodes[node as usize], &condition);
+    let (then_condition, _, _) = take(then);
+    let (otherwise_condition, true_target, false_target) = take(otherwise);
+    for node in [then, otherwise] {
+        nodes[first].pcs = joined_pcs(&nodes[first].pcs, &nodes[node as usize].pcs);
+    }
+    let (then, otherwise) = (then_condition, otherwise_condition);
+    let then = if same { then } else { negate(then) };
+    let pc = condition.pc;
+    nodes[first].exit = NodeExit::Condition {
+        condition: Expression {
+            op: NOP,
+            pc,
+            ex: ExpressionType::TernaryEx {
+                condition: Box::new(negate(condition)),
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+            },
+        },
+        true_target,
+        false_target,
+    };
+}
+
+// The pcs of two nodes which are joined, with the nodes which were folded in between.
+pub fn joined_pcs(first: &Range<u32>, second: &Range<u32>) -> Range<u32> {
+    first.start.min(second.start)..first.end.max(second.end)
+}
+
+// Copies the part of an irreducible region after a second entry, so that entry gets its own
+// copy and the region is left with one. Gives up when the copies get out of hand.
+fn split_irreducible(nodes: &mut Vec<Node>) -> bool {
+    let limit = nodes.len() * 4 + 64;
+    loop {
+        let successors = reachable_successors(nodes);
+        let forest = LoopForest::build(&successors, 0);
+        let region = match forest.irreducible.first() {
+            Some(region) => region,
+            None => return true,
+        };
+        if nodes.len() + region.body.len() > limit {
+            return false;
+        }
+        let rpo = reverse_postorder(&successors);
+        let mut entries = region.entries.clone();
+        entries.sort_by_key(|entry| rpo[*entry as usize]);
+        let (main, other) = (entries[0], entries[1]);
+
+        let mut copies: HashMap<u32, u32> = HashMap::new();
+        for node in &region.body {
+            if *node != main {
+                copies.insert(*node, nodes.len() as u32 + copies.len() as u32);
+            }
+        }
+        let mut originals: Vec<u32> = copies.keys().copied().collect();
+        originals.sort_by_key(|node| copies[node]);
+        for original in originals {
+            let mut copy = nodes[original as usize].clone();
+            for target in copy.exit.targets_mut() {
+                if let Some(copied) = copies.get(target) {
+                    *target = *copied;
+                }
+            }
+            nodes.push(copy);
+        }
+        for (node, targets) in successors.iter().enumerate() {
+            if targets.contains(&other) && region.body.binary_search(&(node as u32)).is_err() {
+                for target in nodes[node].exit.targets_mut() {
+                    if *target == other {
+                        *target = copies[&other];
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Whether control never leaves the nodes the node dominates once it gets there.
+fn closed(successors: &[Vec<u32>], dominators: &DominatorTree, node: u32) -> bool {
+    let mut seen = vec![false; successors.len()];
+    let mut todo = vec![node];
+    seen[node as usize] = true;
+    while let Some(current) = todo.pop() {
+        for target in &successors[current as usize] {
+            if !dominators.dominates(node, *target) {
+                return false;
+            }
+            if !seen[*target as usize] {
+                seen[*target as usize] = true;
+                todo.push(*target);
+            }
+        }
+    }
+    true
+}
+
+// The try statements, from the handlers which protect the same nodes. javac puts the body of a
+// try first and then every catch, so a try has the nodes after its first one up to where the
+// last handler stops dominating the nodes after it.
+fn tries(
+    nodes: &[Node],
+    rpo: &[usize],
+    dominators: &DominatorTree,
+) -> Result<Vec<Construct>, DecompError> {
+    let reachable = |node: u32| rpo[node as usize] != usize::MAX;
+    let mut covered: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut types: HashMap<u32, Vec<u16>> = HashMap::new();
+    for (node, node_info) in nodes.iter().enumerate() {
+        if !reachable(node as u32) {
+            continue;
+        }
+        for (handler, catch_type) in &node_info.handlers {
+            let list = covered.entry(*handler).or_default();
+            if list.last() != Some(&(node as u32)) {
+                list.push(node as u32);
+            }
+            let list = types.entry(*handler).or_default();
+            if !list.contains(catch_type) {
+                list.push(*catch_type);
+            }
+        }
+    }
+    let mut handlers: Vec<u32> = covered.keys().copied().collect();
+    handlers.sort_unstable();
+    let mut groups: Vec<(&Vec<u32>, Vec<u32>)> = Vec::new();
+    for handler in handlers {
+        let protected = &covered[&handler];
+        match groups.iter_mut().find(|(other, _)| *other == protected) {
+            Some((_, list)) => list.push(handler),
+            None => groups.push((protected, vec![handler])),
+        }
+    }
+
+    let mut constructs = Vec::new();
+    for (protected, handlers) in groups {
+        let start = *protected
+            .iter()
+            .min_by_key(|node| rpo[**node as usize])
+            .unwrap();
+        let last = *handlers.last().unwrap();
+        let mut stop = last;
+        while (stop as usize + 1) < nodes.len()
+            && (!reachable(stop + 1) || dominators.dominates(last, stop + 1))
+        {
+            stop += 1;
+        }
+        let body: Vec<u32> = (protected[0]..=stop)
+            .filter(|node| reachable(*node) && dominators.dominates(start, *node))
+            .collect();
+        if protected
+            .iter()
+            .chain(&handlers)
+            .any(|node| body.binary_search(node).is_err())
+        {
+            return Err(DecompError::TangledTry);
+        }
+        let mut unprotected = Vec::new();
+        for node in &body {
+            let in_catch = handlers
+                .iter()
+                .any(|handler| dominators.dominates(*handler, *node));
+            if protected.contains(node) || in_catch {
+                continue;
+            }
+            let Node {
+                list, exit, pcs, ..
+            } = &nodes[*node as usize];
+            let values = match exit {
+                NodeExit::Condition { condition, .. } => Some(condition),
+                NodeExit::Switch { value, .. } => Some(value),
+                _ => None,
+            };
+            for expression in list.iter().chain(values) {
+                throwing(expression, pcs, &mut unprotected);
+            }
+        }
+        constructs.push(Construct {
+            start,
+            handlers: Some(
+                handlers
+                    .into_iter()
+                    .map(|handler| (handler, types[&handler].clone()))
+                    .collect(),
+            ),
+            body,
+            unprotected,
+            parent: None,
+        });
+    }
+    Ok(constructs)
+}
+
+// The pcs of the ops in the expression which can throw, of the ops in the range.
+fn throwing(expression: &Expression, range: &Range<u32>, pcs: &mut Vec<u32>) {
+    let throws = match expression.ex {
+        ExpressionType::OperatorEx { .. } => matches!(expression.op, IDIV | LDIV | IREM | LREM),
+        ExpressionType::ArrayLoadEx { .. }
+        | ExpressionType::ArrayStoreEx { .. }
+        | ExpressionType::NewEx { .. }
+        | ExpressionType::ConstructEx { .. }
+        | ExpressionType::NewPrimArrayEx { .. }
+        | ExpressionType::NewArrayEx { .. }
+        | ExpressionType::MultiNewArrayEx { .. }
+        | ExpressionType::ArrayInitEx { .. }
+        | ExpressionType::MonitorEx { .. }
+        | ExpressionType::CastEx { .. }
+        | ExpressionType::ThrowEx { .. }
+        | ExpressionType::GetFieldEx { .. }
+        | ExpressionType::PutFieldEx { .. }
+        | ExpressionType::GetStaticFieldEx { .. }
+        | ExpressionType::PutStaticFieldEx { .. }
+        | ExpressionType::MethodEx { .. }
+        | ExpressionType::ArrayLengthEx { .. } => true,
+        _ => false,
+    };
+    if throws && range.contains(&expression.pc) {
+        pcs.push(expression.pc);
+    }
+    for child in expression.children() {
+        throwing(child, range, pcs);
+    }
+}
+
+// A loop or a try. Nodes outside of it which are reached from inside are placed after it.
+struct Construct {
+    // The loop header or the first node of the try.
+    start: u32,
+    // The handlers of a try with their caught classes, a loop has none.
+    handlers: Option<Vec<(u32, Vec<u16>)>>,
+    // The nodes in it, sorted.
+    body: Vec<u32>,
+    // The pcs of the ops in the body of a try which its handlers do not protect.
+    unprotected: Vec<u32>,
+    parent: Option<usize>,
+}
+
+struct Structurer {
+    nodes: Vec<Option<Node>>,
+    rpo: Vec<usize>,
+    // The dominator tree children of every node in reverse postorder.
+    children: Vec<Vec<u32>>,
+    // Nodes with more than one forward edge going in.
+    merge: Vec<bool>,
+    constructs: Vec<Construct>,
+    // The constructs which start at every node, the outermost first.
+    starts: Vec<Vec<usize>>,
+    // The outermost construct the node is placed after, when it is left from inside.
+    follows: Vec<Option<usize>>,
+    followers: Vec<Vec<u32>>,
+    // Targets of a switch which are placed as its cases.
+    case: Vec<bool>,
+    // Cases which the case before them falls into.
+    fallthrough: Vec<bool>,
+    loop_label: Vec<Option<u32>>,
+    block_label: Vec<Option<u32>>,
+    next_label: u32,
+}
+
+impl Structurer {
+    fn new(nodes: Vec<Node>) -> Result<Structurer, DecompError> {
+        let count = nodes.len();
+        let successors = reachable_successors(&nodes);
+        let rpo = reverse_postorder(&successors);
+        let dominators = DominatorTree::build(&successors, 0);
+        let forest = LoopForest::build(&successors, 0);
+
+        // handlers are only placed by their try, so only the exits count as ways in
+        let mut children = vec![Vec::new(); count];
+        let mut forward = vec![0; count];
+        let mut forward_from = vec![Vec::new(); count];
+        for node in 0..count as u32 {
+            if let Some(idom) = dominators.immediate(node) {
+                children[idom as usize].push(node);
+            }
+            if rpo[node as usize] == usize::MAX {
+                continue;
+            }
+            for target in nodes[node as usize].exit.targets() {
+                if rpo[target as usize] > rpo[node as usize] {
+                    forward[target as usize] += 1;
+                    forward_from[target as usize].push(node);
+                }
+            }
+        }
+        for list in &mut children {
+            list.sort_by_key(|child| rpo[*child as usize]);
+        }
+
+        let mut constructs: Vec<Construct> = forest
+            .loops
+            .iter()
+            .map(|inner| Construct {
+                start: inner.header,
+                handlers: None,
+                body: inner.body.clone(),
+                unprotected: Vec::new(),
+                parent: None,
+            })
+            .collect();
+        // a try which starts in a loop is in it with its handlers, which need not go back to it
+        let tries = tries(&nodes, &rpo, &dominators)?;
+        for construct in &mut constructs {
+            for inner in &tries {
+                let in_try = construct
+                    .body
+                    .iter()
+                    .all(|node| inner.body.binary_search(node).is_ok());
+                if construct.body.binary_search(&inner.start).is_ok() && !in_try {
+                    construct.body.extend(&inner.body);
+                    construct.body.sort_unstable();
+                    construct.body.dedup();
+                }
+            }
+        }
+        constructs.extend(tries);
+        // the bigger one is outside, and a loop is outside a try with the same nodes. Of two
+        // tries with the same nodes the one with the later handlers is outside.
+        constructs.sort_by_key(|construct| {
+            let last = construct
+                .handlers
+                .as_ref()
+                .and_then(|handlers| handlers.last())
+                .map(|(handler, _)| u32::MAX - handler);
+            (usize::MAX - construct.body.len(), last)
+        });
+        let mut innermost: Vec<Option<usize>> = vec![None; count];
+        for position in 0..constructs.len() {
+            let (outer, inner) = constructs.split_at_mut(position);
+            let construct = &mut inner[0];
+            for (other_position, other) in outer.iter().enumerate().rev() {
+                let inside = construct
+                    .body
+                    .iter()
+                    .filter(|node| other.body.binary_search(node).is_ok())
+                    .count();
+                if inside == construct.body.len() && construct.parent.is_none() {
+                    construct.parent = Some(other_position);
+                } else if inside != 0 && inside != construct.body.len() {
+                    return Err(DecompError::TangledTry);
+                }
+            }
+            for node in &construct.body {
+                innermost[*node as usize] = Some(position);
+            }
+        }
+        let mut starts = vec![Vec::new(); count];
+        for (position, construct) in constructs.iter().enumerate() {
+            starts[construct.start as usize].push(position);
+        }
+
+        // a handler is placed by its try, even where it is outside of a try nested in it
+        let mut handler = vec![false; count];
+        for construct in &constructs {
+            for (node, _) in construct.handlers.iter().flatten() {
+                handler[*node as usize] = true;
+            }
+        }
+        let mut follows = vec![None; count];
+        let mut followers = vec![Vec::new(); constructs.len()];
+        for node in 0..count as u32 {
+            let idom = match dominators.immediate(node) {
+                Some(idom) if !handler[node as usize] => idom,
+                _ => continue,
+            };
+            let mut current = innermost[idom as usize];
+            while let Some(position) = current {
+                let construct = &constructs[position];
+                if construct.body.binary_search(&node).is_err() {
+                    follows[node as usize] = Some(position);
+                }
+                current = construct.parent;
+            }
+            if let Some(position) = follows[node as usize] {
+                followers[position].push(node);
+            }
+        }
+        // an exit which only returns or throws can stay where it is jumped to, but a loop keeps
+        // the one its header jumps to so it can still get a condition. Anything which is left
+        // in a try would be caught.
+        for (position, list) in followers.iter_mut().enumerate() {
+            list.sort_by_key(|follower| rpo[*follower as usize]);
+            if constructs[position].handlers.is_some() {
+                continue;
+            }
+            let inline: Vec<bool> = list
+                .iter()
+                .map(|follower| {
+                    forward[*follower as usize] == 1 && closed(&successors, &dominators, *follower)
+                })
+                .collect();
+            let keep = if inline.iter().all(|inline| *inline) {
+                let exits = &successors[constructs[position].start as usize];
+                list.iter()
+                    .position(|follower| exits.contains(follower))
+                    .unwrap_or(0)
+            } else {
+                usize::MAX
+            };
+            for (inline_position, follower) in list.iter().enumerate() {
+                if inline[inline_position] && inline_position != keep {
+                    follows[*follower as usize] = None;
+                }
+            }
+            let mut inline_position = 0;
+            list.retain(|_| {
+                inline_position += 1;
+                !inline[inline_position - 1] || inline_position - 1 == keep
+            });
+        }
+
+        // a target of a switch which it dominates is a case when nothing else jumps to it but
+        // the end of the case before it. The last one is the code after the switch instead
+        // unless some case leaves the switch for anywhere else.
+        let mut case = vec![false; count];
+        let mut fallthrough = vec![false; count];
+        for node in 0..count as u32 {
+            let mut targets = match &nodes[node as usize].exit {
+                NodeExit::Switch { .. } if rpo[node as usize] != usize::MAX => {
+                    nodes[node as usize].exit.targets()
+                }
+                _ => continue,
+            };
+            targets.sort_unstable();
+            targets.dedup();
+            let all = targets.clone();
+            targets.retain(|target| {
+                dominators.immediate(*target) == Some(node)
+                    && !handler[*target as usize]
+                    && follows[*target as usize].is_none()
+            });
+            let leaves = |last: u32| {
+                (0..count as u32).any(|from| {
+                    from != node
+                        && rpo[from as usize] != usize::MAX
+                        && dominators.dominates(node, from)
+                        && !dominators.dominates(last, from)
+                        && nodes[from as usize].exit.targets().iter().any(|to| {
+                            rpo[*to as usize] > rpo[from as usize]
+                                && (!dominators.dominates(node, *to)
+                                    || (dominators.immediate(*to) == Some(node)
+                                        && all.binary_search(to).is_err()))
+                        })
+                })
+            };
+            for (position, target) in targets.iter().enumerate() {
+                let target = *target as usize;
+                let falls: Vec<u32> = forward_from[target]
+                    .iter()
+                    .copied()
+                    .filter(|from| *from != node)
+                    .collect();
+                let previous = position.checked_sub(1).map(|previous| targets[previous]);
+                fallthrough[target] = !falls.is_empty()
+                    && previous.is_some_and(|previous| {
+                        case[previous as usize]
+                            && falls
+                                .iter()
+                                .all(|from| dominators.dominates(previous, *from))
+                    })
+                    && (position + 1 < targets.len() || leaves(target as u32));
+                case[target] = falls.is_empty() || fallthrough[target];
+            }
+        }
+
+        Ok(Structurer {
+            nodes: nodes.into_iter().map(Some).collect(),
+            rpo,
+            children,
+            merge: forward.iter().map(|count| *count > 1).collect(),
+            case,
+            fallthrough,
+            constructs,
+            starts,
+            follows,
+            followers,
+            loop_label: vec![None; count],
+            block_label: vec![None; count],
+            next_label: 0,
+        })
+    }
+
+    fn label(&mut self) -> u32 {
+        self.next_label += 1;
+        self.next_label
+    }
+
+    // The node with everything it dominates.
+    fn tree(&mut self, node: u32) -> Vec<Statement> {
+        self.constructs_at(node, 0)
+    }
+
+    // The constructs which start at the node from the given depth in, with what they contain and
+    // the nodes after them. Leaving a loop for its first follower breaks the loop itself.
+    fn constructs_at(&mut self, node: u32, depth: usize) -> Vec<Statement> {
+        let position = match self.starts[node as usize].get(depth) {
+            Some(position) => *position,
+            None => {
+                let merges = self.merges(node);
+                return self.within(node, &merges);
+            }
+        };
+        let is_loop = self.constructs[position].handlers.is_none();
+        let label = self.label();
+        if is_loop {
+            self.loop_label[node as usize] = Some(label);
+        }
+        let followers = self.followers[position].clone();
+        for (follower_position, follower) in followers.iter().enumerate() {
+            let block_label = if follower_position == 0 && is_loop {
+                label
+            } else {
+                self.label()
+            };
+            self.block_label[*follower as usize] = Some(block_label);
+        }
+        let body = self.constructs_at(node, depth + 1);
+        let mut code = match self.constructs[position].handlers.clone() {
+            None => vec![Statement::While {
+                label,
+                condition: None,
+                body,
+            }],
+            Some(handlers) => {
+                let catches = handlers
+                    .into_iter()
+                    .map(|(handler, types)| Catch {
+                        types,
+                        body: self.tree(handler),
+                    })
+                    .collect();
+                vec![Statement::Try {
+                    resources: Vec::new(),
+                    body,
+                    catches,
+                    finally: Vec::new(),
+                    unprotected: self.constructs[position].unprotected.clone(),
+                }]
+            }
+        };
+        for (follower_position, follower) in followers.iter().enumerate() {
+            if follower_position > 0 || !is_loop {
+                code = vec![Statement::Block {
+                    label: self.block_label[*follower as usize].unwrap(),
+                    body: code,
+                }];
+            }
+            code.extend(self.tree(*follower));
+        }
+        code
+    }
+
+    fn merges(&self, node: u32) -> Vec<u32> {
+        self.children[node as usize]
+            .iter()
+            .copied()
+            .filter(|child| {
+                self.merge[*child as usize]
+                    && self.follows[*child as usize].is_none()
+                    && !self.case[*child as usize]
+            })
+            .collect()
+    }
+
+    // The node inside blocks for the merges it dominates, the last one outermost.
+    fn within(&mut self, node: u32, merges: &[u32]) -> Vec<Statement> {
+        let (merge, inner) = match merges.split_last() {
+            Some(split) => split,
+            None => return self.code(node),
+        };
+        let label = self.label();
+        self.block_label[*merge as usize] = Some(label);
+        let body = self.within(node, inner);
+        let mut code = vec![Statement::Block { label, body }];
+        code.extend(self.tree(*merge));
+        code
+    }
+
+    fn code(&mut self, node: u32) -> Vec<Statement> {
+        let Node { list, exit, .. } = self.nodes[node as usize].take().expect("node placed twice");
+        let mut code: Vec<Statement> = list
+            .into_iter()
+            .map(|expression| Statement::Expression { expression })
+            .collect();
+        match exit {
+            NodeExit::End => {}
+            NodeExit::Goto { target } => code.extend(self.branch(node, target)),
+            NodeExit::Condition {
+                condition,
+                true_target,
+                false_target,
+            } => {
+                // the compiler jumps over the then part, so the code it falls through to comes first
+                let then = self.branch(node, false_target);
+                let otherwise = self.branch(node, true_target);
+                code.push(Statement::If {
+                    condition: negate(condition),
+                    then,
+                    otherwise,
+                });
+            }
+            NodeExit::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                let label = self.label();
+                // the keys which go to the same code are one case, in the order of the code. Keys
+                // which go where the default goes are left out, and so is a default which goes
+                // on after the switch.
+                let mut targets: Vec<u32> = cases.iter().map(|(_, target)| *target).collect();
+                if self.merges(node).first() != Some(&default) {
+                    targets.push(default);
+                }
+                targets.sort_unstable();
+                targets.dedup();
+                // a case which only jumps goes before the first one with code and a bigger key
+                // which is not fallen into
+                let first_key = |target: &u32| {
+                    cases
+                        .iter()
+                        .filter(|(_, key_target)| key_target == target)
+                        .map(|(key, _)| i64::from(*key))
+                        .min()
+                        .unwrap_or(i64::MAX)
+                };
+                let (placed, mut jumps): (Vec<u32>, Vec<u32>) = targets
+                    .into_iter()
+                    .partition(|target| self.case[*target as usize]);
+                jumps.sort_by_key(first_key);
+                let mut jumps = jumps.into_iter().peekable();
+                let mut targets = Vec::new();
+                for target in placed {
+                    if !self.fallthrough[target as usize] {
+                        while let Some(jump) =
+                            jumps.next_if(|jump| first_key(jump) < first_key(&target))
+                        {
+                            targets.push(jump);
+                        }
+                    }
+                    targets.push(target);
+                }
+                targets.extend(jumps);
+                let mut switch_cases = Vec::new();
+                for (position, target) in targets.iter().enumerate() {
+                    let keys = cases
+                        .iter()
+                        .filter(|(_, key_target)| key_target == target && *target != default)
+                        .map(|(key, _)| CaseKey::Int(*key))
+                        .collect();
+                    let body = if self.case[*target as usize] {
+                        // falling out of a case into the next one breaks out of a block around it
+                        match targets.get(position + 1) {
+                            Some(next) if self.fallthrough[*next as usize] => {
+                                let block_label = self.label();
+                                self.block_label[*next as usize] = Some(block_label);
+                                vec![Statement::Block {
+                                    label: block_label,
+                                    body: self.tree(*target),
+                                }]
+                            }
+                            _ => self.tree(*target),
+                        }
+                    } else {
+                        self.branch(node, *target)
+                    };
+                    switch_cases.push(SwitchCase {
+                        keys,
+                        default: *target == default,
+                        body,
+                    });
+                }
+                code.push(Statement::Switch {
+                    label,
+                    value,
+                    cases: switch_cases,
+                });
+            }
+        }
+        code
+    }
+
+    fn branch(&mut self, from: u32, to: u32) -> Vec<Statement> {
+        if self.rpo[to as usize] <= self.rpo[from as usize] {
+            let label =
+                self.loop_label[to as usize].expect("back edge to a node which is no loop header");
+            vec![Statement::Continue { label }]
+        } else if self.merge[to as usize] || self.follows[to as usize].is_some() {
+            let label =
+                self.block_label[to as usize].expect("jump to a merge outside of its block");
+            vec![Statement::Break { label }]
+        } else {
+            self.tree(to)
+        }
+    }
+}
+
+// Cleans up until nothing changes, the raw tree has a jump at the end of every node.
+fn simplify(body: &mut Vec<Statement>) {
+    loop {
+        let mut jumps = Jumps {
+            after: HashMap::new(),
+            breakables: Vec::new(),
+            next_point: 0,
+            changed: false,
+        };
+        jumps.list(body, Dest::Return);
+        let mut changed = jumps.changed;
+        changed |= flatten_blocks(body);
+        changed |= tidy_ifs(body);
+        changed |= make_loops(body);
+        if !changed {
+            break;
+        }
+    }
+}
+
+// Where control goes next, a point is right before some statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dest {
+    Point(u32),
+    Continue(u32),
+    Return,
+}
+
+struct Jumps {
+    // Where control goes after the statement with the label.
+    after: HashMap<u32, Dest>,
+    // The loops and switches around the current statement, innermost last.
+    breakables: Vec<u32>,
+    next_point: u32,
+    changed: bool,
+}
+
+impl Jumps {
+    // Removes jumps to where the list goes anyway and breaks out of the innermost loop or switch
+    // instead of an outer label when that ends up at the same place.
+    fn list(&mut self, list: &mut Vec<Statement>, fall: Dest) {
+        for position in 0..list.len() {
+            // going on to a jump goes where it jumps to
+            let next = match list.get(position + 1) {
+                None => fall,
+                Some(statement) => self.jumps_to(statement).unwrap_or_else(|| {
+                    self.next_point += 1;
+                    Dest::Point(self.next_point)
+                }),
+            };
+            self.statement(&mut list[position], next);
+        }
+        while let Some(last) = list.last() {
+            if self.jumps_to(last) != Some(fall) {
+                break;
+            }
+            list.pop();
+            self.changed = true;
+        }
+    }
+
+    fn jumps_to(&self, statement: &Statement) -> Option<Dest> {
+        match statement {
+            Statement::Break { label } => self.after.get(label).copied(),
+            Statement::Continue { label } => Some(Dest::Continue(*label)),
+            Statement::Expression {
+                expression:
+                    Expression {
+                        ex: ExpressionType::ReturnEx,
+                        ..
+                    },
+            } => Some(Dest::Return),
+            _ => None,
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement, next: Dest) {
+        match statement {
+            Statement::If {
+                then, otherwise, ..
+            } => {
+                self.list(then, next);
+                self.list(otherwise, next);
+            }
+            Statement::Block { label, body } => {
+                self.after.insert(*label, next);
+                self.list(body, next);
+            }
+            Statement::While { label, body, .. }
+            | Statement::DoWhile { label, body, .. }
+            | Statement::For { label, body, .. } => {
+                self.after.insert(*label, next);
+                self.breakables.push(*label);
+                self.list(body, Dest::Continue(*label));
+                self.breakables.pop();
+            }
+            Statement::Switch { label, cases, .. } => {
+                self.after.insert(*label, next);
+                self.breakables.push(*label);
+                let count = cases.len();
+                for (position, case) in cases.iter_mut().enumerate() {
+                    let fall = if position + 1 == count {
+                        next
+                    } else {
+                        self.next_point += 1;
+                        Dest::Point(self.next_point)
+                    };
+                    self.list(&mut case.body, fall);
+                }
+                self.breakables.pop();
+            }
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                self.list(body, next);
+                for catch in catches {
+                    self.list(&mut catch.body, next);
+                }
+                self.list(finally, next);
+            }
+            Statement::Synchronized { body, .. } => self.list(body, next),
+            Statement::Break { label } => {
+                if let Some(inner) = self.breakables.last() {
+                    if inner != label && self.after.get(inner) == self.after.get(label) {
+                        *label = *inner;
+                        self.changed = true;
+                    }
+                }
+            }
+            Statement::Expression { .. } | Statement::Continue { .. } => {}
+        }
+    }
+}
+
+// The expressions of a statement which are not in its lists.
+fn expressions(statement: &Statement) -> Vec<&Expression> {
+    match statement {
+        Statement::Expression { expression }
+        | Statement::If {
+            condition: expression,
+            ..
+        }
+        | Statement::DoWhile {
+            condition: expression,
+            ..
+        }
+        | Statement::Switch {
+            value: expression, ..
+        }
+        | Statement::Synchronized {
+            lock: expression, ..
+        } => vec![expression],
+        Statement::While { condition, .. } => condition.iter().collect(),
+        Statement::For {
+            init,
+            condition,
+            update,
+            ..
+        } => init.iter().chain(condition).chain(update).collect(),
+        Statement::Try { resources, .. } => resources.iter().collect(),
+        Statement::Block { .. } | Statement::Break { .. } | Statement::Continue { .. } => {
+            Vec::new()
+        }
+    }
+}
+
+// The statement lists right inside a statement.
+fn lists_mut(statement: &mut Statement) -> Vec<&mut Vec<Statement>> {
+    match statement {
+        Statement::If {
+            then, otherwise, ..
+        } => vec![then, otherwise],
+        Statement::While { body, .. }
+        | Statement::DoWhile { body, .. }
+        | Statement::For { body, .. }
+        | Statement::Block { body, .. }
+        | Statement::Synchronized { body, .. } => vec![body],
+        Statement::Switch { cases, .. } => cases.iter_mut().map(|case| &mut case.body).collect(),
+        Statement::Try {
+            body,
+            catches,
+            finally,
+            ..
+        } => {
+            let mut lists = vec![body];
+            lists.extend(catches.iter_mut().map(|catch| &mut catch.body));
+            lists.push(finally);
+            lists
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn lists(statement: &Statement) -> Vec<&Vec<Statement>> {
+    match statement {
+        Statement::If {
+            then, otherwise, ..
+        } => vec![then, otherwise],
+        Statement::While { body, .. }
+        | Statement::DoWhile { body, .. }
+        | Statement::For { body, .. }
+        | Statement::Block { body, .. }
+        | Statement::Synchronized { body, .. } => vec![body],
+        Statement::Switch { cases, .. } => cases.iter().map(|case| &case.body).collect(),
+        Statement::Try {
+            body,
+            catches,
+            finally,
+            ..
+        } => {
+            let mut lists = vec![body];
+            lists.extend(catches.iter().map(|catch| &catch.body));
+            lists.push(finally);
+            lists
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Whether a value which was on the stack before its node started is still used anywhere.
+pub fn pulls_stack(list: &[Statement]) -> bool {
+    list.iter().any(|statement| {
+        expressions(statement)
+            .into_iter()
+            .any(|expression| expression.pulls_stack())
+            || lists(statement).iter().any(|inner| pulls_stack(inner))
+    })
+}
+
+fn jumps_to(list: &[Statement], target: u32, breaks: bool) -> bool {
+    list.iter().any(|statement| match statement {
+        Statement::Break { label } => breaks && *label == target,
+        Statement::Continue { label } => !breaks && *label == target,
+        _ => lists(statement)
+            .iter()
+            .any(|inner| jumps_to(inner, target, breaks)),
+    })
+}
+
+fn retarget_breaks(list: &mut [Statement], from: u32, to: u32) {
+    for statement in list {
+        match statement {
+            Statement::Break { label } if *label == from => {
+                *statement = Statement::Continue { label: to }
+            }
+            _ => {
+                for inner in lists_mut(statement) {
+                    retarget_breaks(inner, from, to);
+                }
+            }
+        }
+    }
+}
+
+// Whether control can get past the end of the list.
+fn completes(list: &[Statement]) -> bool {
+    match list.last() {
+        None => true,
+        Some(Statement::Break { .. } | Statement::Continue { .. }) => false,
+        Some(Statement::Expression { expression }) => !matches!(
+            expression.ex,
+            ExpressionType::ReturnEx
+                | ExpressionType::ReturnValueEx { .. }
+                | ExpressionType::ThrowEx { .. }
+        ),
+        Some(Statement::If {
+            then, otherwise, ..
+        }) => completes(then) || completes(otherwise),
+        Some(Statement::While {
+            label,
+            condition: None,
+            body,
+        })
+        | Some(Statement::For {
+            label,
+            condition: None,
+            body,
+            ..
+        }) => jumps_to(body, *label, true),
+        Some(Statement::Block { label, body }) => completes(body) || jumps_to(body, *label, true),
+        Some(Statement::Try {
+            body,
+            catches,
+            finally,
+            ..
+        }) => {
+            completes(finally)
+                && (completes(body) || catches.iter().any(|catch| completes(&catch.body)))
+        }
+        Some(Statement::Synchronized { body, .. }) => completes(body),
+        Some(_) => true,
+    }
+}
+
+// Try-with-resources, the outer ones first. javac closes a resource before every way out of the
+// body and in a catch of anything, which keeps an exception from closing as suppressed. Before
+// Java 11 it was closed in a finally instead, which needs the exception which is thrown, and
+// before Java 9 that finally did not call $closeResource for it.
+fn resources(list: &mut Vec<Statement>, const_pool: &ConstantPool) -> bool {
+    let mut changed = false;
+    for position in 0..list.len() {
+        if position >= list.len() {
+            break;
+        }
+        changed |= make_resource(list, position, const_pool);
+        for inner in lists_mut(&mut list[position]) {
+            changed |= resources(inner, const_pool);
+        }
+        // try (...) {} catch {} finally {} catches what the resources throw too
+        if let Statement::Try {
+            resources,
+            body,
+            catches,
+            finally,
+            unprotected,
+        } = &mut list[position]
+        {
+            let outer_resources = resources;
+            let outer_unprotected = unprotected;
+            if let [Statement::Try {
+                resources,
+                body: inner_body,
+                catches: inner_catches,
+                finally: inner_finally,
+                unprotected,
+            }] = body.as_mut_slice()
+            {
+                let single = !resources.is_empty()
+                    && inner_catches.is_empty()
+                    && inner_finally.is_empty()
+                    && (outer_resources.is_empty() || catches.is_empty() && finally.is_empty());
+                if single {
+                    outer_resources.append(resources);
+                    outer_unprotected.append(unprotected);
+                    *body = mem::take(inner_body);
+                    changed = true;
+                }
+            }
+        }
+    }
+    changed
+}
+
+// The resource which is stored at the position and closed by the try after it.
+fn make_resource(list: &mut Vec<Statement>, position: usize, const_pool: &ConstantPool) -> bool {
+    let resource = match list.get(position) {
+        Some(Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::StoreVarEx { var, value },
+                    ..
+                },
+        }) if value.ex != ExpressionType::CatchEx => *var,
+        _ => return false,
+    };
+    let is_throwable = |catch: &Catch| match catch.types[..] {
+        [class] => const_pool.class_name(class) == Ok("java/lang/Throwable"),
+        _ => false,
+    };
+    let close = |statement: &Statement| match statement {
+        Statement::Expression { expression } => calls(expression, const_pool, "close", resource),
+        _ => false,
+    };
+    // the exception which is thrown once the try is over, from before Java 11
+    let primary = match list.get(position + 1) {
+        Some(Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::StoreVarEx { var, value },
+                    ..
+                },
+        }) if value.ex == ExpressionType::ConstantEx { op: ACONST_NULL } => Some(*var),
+        _ => None,
+    };
+    let try_position = position + 1 + primary.is_some() as usize;
+    let (body, catches, finally) = match try_at(list, try_position) {
+        Some(Statement::Try {
+            resources,
+            body,
+            catches,
+            finally,
+            ..
+        }) if resources.is_empty() && catches.len() == 1 && is_throwable(&catches[0]) => {
+            (body, catches, finally)
+        }
+        _ => return false,
+    };
+    let closer = match rethrown(&catches[0].body) {
+        Some(closer) => closer,
+        None => return false,
+    };
+    let caught = match &catches[0].body[0] {
+        Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::StoreVarEx { var, .. },
+                    ..
+                },
+        } => *var,
+        _ => return false,
+    };
+    match primary {
+        Some(primary) => {
+            // catch (Throwable t) { primary = t; throw t; } finally { close }
+            let keeps = matches!(closer, [Statement::Expression {
+                expression: Expression { ex: ExpressionType::StoreVarEx { var, value }, .. },
+            }] if *var == primary && value.ex == ExpressionType::LoadVarEx { var: caught });
+            let closes = |list: &[Statement]| match list {
+                [Statement::Expression { expression }] => {
+                    calls(expression, const_pool, "$closeResource", primary)
+                        && loads(expression, 1, resource)
+                }
+                [Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                }] if non_null(condition) == Some((primary, true)) => {
+                    suppressed(then, const_pool, primary, resource)
+                        && matches!(&otherwise[..], [close_statement] if close(close_statement))
+                }
+                [Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                }] if non_null(condition) == Some((primary, false)) => {
+                    suppressed(otherwise, const_pool, primary, resource)
+                        && matches!(&then[..], [close_statement] if close(close_statement))
+                }
+                _ => false,
+            };
+            let closed = match &finally[..] {
+                [Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                }] if non_null(condition) == Some((resource, true)) && otherwise.is_empty() => {
+                    closes(then)
+                }
+                list => closes(list),
+            };
+            if !keeps || !closed {
+                return false;
+            }
+        }
+        None => {
+            // catch (Throwable t) { close and keep what it throws as suppressed; throw t; }
+            let checked = match closer {
+                [Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                }] if non_null(condition) == Some((resource, true)) && otherwise.is_empty() => {
+                    if !suppressed(then, const_pool, caught, resource) {
+                        return false;
+                    }
+                    true
+                }
+                list if suppressed(list, const_pool, caught, resource) => false,
+                _ => return false,
+            };
+            let copy = |list: &[Statement]| match list.last()? {
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                } if checked
+                    && non_null(condition) == Some((resource, true))
+                    && otherwise.is_empty()
+                    && matches!(&then[..], [statement] if close(statement)) =>
+                {
+                    Some(1)
+                }
+                statement if !checked && close(statement) => Some(1),
+                _ => None,
+            };
+            let mut inside = Vec::new();
+            labels_in(body, &mut inside);
+            if !finally_copies(
+                body,
+                &copy,
+                &inside,
+                &mut Vec::new(),
+                true,
+                false,
+                &mut None,
+            ) {
+                return false;
+            }
+            finally_copies(body, &copy, &inside, &mut Vec::new(), true, true, &mut None);
+        }
+    }
+    let body = mem::take(body);
+    let store = match list.drain(position..try_position).next() {
+        Some(Statement::Expression { expression }) => expression,
+        _ => unreachable!(),
+    };
+    let statement = try_at(list, position).unwrap();
+    let unprotected = match statement {
+        Statement::Try { unprotected, .. } => mem::take(unprotected),
+        _ => unreachable!(),
+    };
+    *statement = Statement::Try {
+        resources: vec![store],
+        body,
+        catches: Vec::new(),
+        finally: Vec::new(),
+        unprotected,
+    };
+    true
+}
+
+// The try at the position, or in a block there which a way out of the try breaks out of after
+// the copies in front of it.
+fn try_at(list: &mut [Statement], position: usize) -> Option<&mut Statement> {
+    let wrapped = match list.get(position)? {
+        Statement::Block { body, .. } => matches!(body[..], [Statement::Try { .. }]),
+        _ => false,
+    };
+    let statement = list.get_mut(position)?;
+    if wrapped {
+        return match statement {
+            Statement::Block { body, .. } => body.first_mut(),
+            _ => None,
+        };
+    }
+    matches!(statement, Statement::Try { .. }).then_some(statement)
+}
+
+// Synchronized blocks, the outer ones first. javac enters the monitor of the lock which it
+// keeps in a var, and exits it again in what looks like a finally then.
+fn monitors(list: &mut Vec<Statement>) -> bool {
+    let mut changed = false;
+    for position in 0..list.len() {
+        if position >= list.len() {
+            break;
+        }
+        changed |= make_synchronized(list, position);
+        for inner in lists_mut(&mut list[position]) {
+            changed |= monitors(inner);
+        }
+    }
+    changed
+}
+
+// The monitor which is entered at the position and exited by the finally of the try after it.
+fn make_synchronized(list: &mut Vec<Statement>, position: usize) -> bool {
+    let (var, lock) = match list.get(position) {
+        Some(Statement::Expression {
+            expression:
+                Expression {
+                    op: MONITORENTER,
+                    ex: ExpressionType::MonitorEx { lock },
+                    ..
+                },
+        }) => match &lock.ex {
+            ExpressionType::StoreVarEx { var, value } => (*var, value.as_ref().clone()),
+            _ => return false,
+        },
+        _ => return false,
+    };
+    let statement = match try_at(list, position + 1) {
+        Some(statement) => statement,
+        None => return false,
+    };
+    // the try has to be checked before its pcs are gone
+    if !protected(slice::from_ref(statement)) {
+        return false;
+    }
+    let (resources, body, catches, unprotected) = match statement {
+        Statement::Try {
+            resources,
+            body,
+            catches,
+            finally,
+            unprotected,
+        } if exits(finally, var) => (resources, body, catches, unprotected),
+        _ => return false,
+    };
+    let mut body = mem::take(body);
+    // synchronized (lock) { try {} catch {} } is one try with the exit as its finally
+    if !resources.is_empty() || !catches.is_empty() {
+        body = vec![Statement::Try {
+            resources: mem::take(resources),
+            body,
+            catches: mem::take(catches),
+            finally: Vec::new(),
+            unprotected: mem::take(unprotected),
+        }];
+    }
+    *statement = Statement::Synchronized { lock, body };
+    list.remove(position);
+    true
+}
+
+// Whether the list is only the exit of the monitor of the lock in the var.
+fn exits(list: &[Statement], var: u16) -> bool {
+    match list {
+        [Statement::Expression {
+            expression:
+                Expression {
+                    op: MONITOREXIT,
+                    ex: ExpressionType::MonitorEx { lock },
+                    ..
+                },
+        }] => lock.ex == ExpressionType::LoadVarEx { var },
+        _ => false,
+    }
+}
+
+// Whether a monitor is entered or exited somewhere in the list.
+fn monitored(list: &[Statement]) -> bool {
+    fn monitor(expression: &Expression) -> bool {
+        matches!(expression.ex, ExpressionType::MonitorEx { .. })
+            || expression.children().into_iter().any(monitor)
+    }
+    list.iter().any(|statement| {
+        expressions(statement).into_iter().any(monitor)
+            || lists(statement).iter().any(|inner| monitored(inner))
+    })
+}
+
+// switch (s) on a string is javac's switch on the hashCode() of s in a var, which stores the
+// index of the string it equals, and a switch on that index with the cases.
+fn string_switches(list: &mut Vec<Statement>, const_pool: &ConstantPool) -> bool {
+    let mut changed = false;
+    for position in 0..list.len() {
+        if position >= list.len() {
+            break;
+        }
+        changed |= make_string_switch(list, position, const_pool);
+        for inner in lists_mut(&mut list[position]) {
+            changed |= string_switches(inner, const_pool);
+        }
+    }
+    changed
+}
+
+// The stores of the string and of -1 as the index are at the position, the switches follow.
+fn make_string_switch(
+    list: &mut Vec<Statement>,
+    position: usize,
+    const_pool: &ConstantPool,
+) -> bool {
+    let (string, index) = match list.get(position..position + 2) {
+        Some(
+            [Statement::Expression {
+                expression:
+                    Expression {
+                        ex: ExpressionType::StoreVarEx { var: string, .. },
+                        ..
+                    },
+            }, Statement::Expression {
+                expression:
+                    Expression {
+                        ex: ExpressionType::StoreVarEx { var: index, value },
+                        ..
+                    },
+            }],
+        ) if int_constant(value, const_pool) == Some(-1) => (*string, *index),
+        _ => return false,
+    };
+    let mut strings = Vec::new();
+    match list.get(position + 2) {
+        Some(Statement::Switch {
+            label,
+            value,
+            cases,
+        }) if calls(value, const_pool, "hashCode", string) => {
+            for case in cases {
+                if !string_cases(&case.body, *label, string, index, const_pool, &mut strings) {
+                    return false;
+                }
+            }
+        }
+        _ => return false,
+    }
+    let (label, cases) = match switch_at(list, position + 3) {
+        Some(Statement::Switch {
+            label,
+            value,
+            cases,
+        }) if value.ex == ExpressionType::LoadVarEx { var: index } => (*label, cases),
+        _ => return false,
+    };
+    let text = |key: &CaseKey| match key {
+        CaseKey::Int(key) => strings
+            .iter()
+            .find(|(number, _)| number == key)
+            .map(|(_, text)| *text),
+        _ => None,
+    };
+    if !cases
+        .iter()
+        .flat_map(|case| &case.keys)
+        .all(|key| text(key).is_some())
+    {
+        return false;
+    }
+    // the strings whose index goes where the default goes lost their case, they get it back on
+    // the default, or on their own when the default only breaks out
+    let mut missing: Vec<i32> = strings
+        .iter()
+        .map(|(number, _)| *number)
+        .filter(|number| {
+            !cases
+                .iter()
+                .flat_map(|case| &case.keys)
+                .any(|key| *key == CaseKey::Int(*number))
+        })
+        .collect();
+    missing.sort_unstable();
+    for key in cases.iter_mut().flat_map(|case| &mut case.keys) {
+        *key = CaseKey::String(text(key).expect("every key is a string"));
+    }
+    if !missing.is_empty() {
+        let keys = missing
+            .iter()
+            .map(|number| {
+                CaseKey::String(text(&CaseKey::Int(*number)).expect("every index has a string"))
+            })
+            .collect();
+        match cases.iter_mut().find(|case| case.default) {
+            Some(case) => {
+                let empty = case
+                    .body
+                    .iter()
+                    .all(|statement| matches!(statement, Statement::Break { label: broken } if *broken == label));
+                case.keys = keys;
+                case.default = !empty;
+            }
+            None => cases.push(SwitchCase {
+                keys,
+                default: false,
+                body: vec![Statement::Break { label }],
+            }),
+        }
+    }
+    let stored = list.drain(position..position + 3).next();
+    if let (
+        Some(Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::StoreVarEx { value: stored, .. },
+                    ..
+                },
+        }),
+        Some(Statement::Switch { value, .. }),
+    ) = (stored, switch_at(list, position))
+    {
+        *value = *stored;
+    }
+    true
+}
+
+// The switch at the position, or in a block there which a case breaks out of.
+fn switch_at(list: &mut [Statement], position: usize) -> Option<&mut Statement> {
+    let wrapped = match list.get(position)? {
+        Statement::Block { body, .. } => matches!(body[..], [Statement::Switch { .. }]),
+        _ => false,
+    };
+    let statement = list.get_mut(position)?;
+    if wrapped {
+        return match statement {
+            Statement::Block { body, .. } => body.first_mut(),
+            _ => None,
+        };
+    }
+    matches!(statement, Statement::Switch { .. }).then_some(statement)
+}
+
+// The index which the list of a case of the hashCode() switch stores for each string, the
+// strings with the same hash are tested one after the other.
+fn string_cases(
+    list: &[Statement],
+    label: u32,
+    string: u16,
+    index: u16,
+    const_pool: &ConstantPool,
+    strings: &mut Vec<(i32, u16)>,
+) -> bool {
+    for statement in list {
+        let (condition, then, otherwise) = match statement {
+            Statement::Break { label: broken } if *broken == label => continue,
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => (condition, then, otherwise),
+            _ => return false,
+        };
+        let (equal, other) = match condition.op {
+            IFNE => (then, otherwise),
+            IFEQ => (otherwise, then),
+            _ => return false,
+        };
+        let text = match &condition.ex {
+            ExpressionType::If0Ex { value } if calls(value, const_pool, "equals", string) => {
+                match &value.ex {
+                    ExpressionType::MethodEx { arguments, .. } => match arguments.as_slice() {
+                        [Expression {
+                            ex: ExpressionType::PoolConstantEx { pool_pos },
+                            ..
+                        }] => *pool_pos,
+                        _ => return false,
+                    },
+                    _ => return false,
+                }
+            }
+            _ => return false,
+        };
+        let number = match equal.as_slice() {
+            [Statement::Expression {
+                expression:
+                    Expression {
+                        ex: ExpressionType::StoreVarEx { var, value },
+                        ..
+                    },
+            }, rest @ ..]
+                if *var == index
+                    && string_cases(rest, label, string, index, const_pool, strings) =>
+            {
+                int_constant(value, const_pool)
+            }
+            _ => None,
+        };
+        match number {
+            Some(number) => strings.push((number, text)),
+            None => return false,
+        }
+        if !string_cases(other, label, string, index, const_pool, strings) {
+            return false;
+        }
+    }
+    true
+}
+
+// The enum constants which the $SwitchMap$ arrays of a class give a number, by the class and
+// name of the array and by the number.
+pub type SwitchMaps = HashMap<(String, String), HashMap<i32, String>>;
+
+// The numbers which the list stores in switch maps, each by the ordinal() of its constant.
+pub fn switch_maps(list: &[Statement], const_pool: &ConstantPool, maps: &mut SwitchMaps) {
+    for statement in list {
+        if let Statement::Expression {
+            expression:
+                Expression {
+                    ex:
+                        ExpressionType::ArrayStoreEx {
+                            array,
+                            index,
+                            value,
+                        },
+                    ..
+                },
+        } = statement
+        {
+            let constant = match &index.ex {
+                ExpressionType::MethodEx {
+                    object: Some(object),
+                    pool_pos,
+                    ..
+                } if const_pool
+                    .member_ref(*pool_pos)
+                    .is_ok_and(|member| member.name == "ordinal") =>
+                {
+                    static_field(object, const_pool)
+                }
+                _ => None,
+            };
+            let field = static_field(array, const_pool)
+                .filter(|field| field.name.starts_with("$SwitchMap$"));
+            if let (Some(field), Some(constant), Some(number)) =
+                (field, constant, int_constant(value, const_pool))
+            {
+                maps.entry((field.owner.to_string(), field.name.to_string()))
+                    .or_default()
+                    .insert(number, constant.name.to_string());
+            }
+        }
+        for inner in lists(statement) {
+            switch_maps(inner, const_pool, maps);
+        }
+    }
+}
+
+// switch (e) on an enum is javac's switch on the number which a switch map has for e.ordinal(),
+// the cases are those numbers.
+pub fn enum_switches(list: &mut [Statement], const_pool: &ConstantPool, maps: &SwitchMaps) {
+    for statement in list.iter_mut() {
+        if let Statement::Switch { value, cases, .. } = statement {
+            if let Some((object, map)) = switch_map(value, const_pool, maps) {
+                let name = |key: &CaseKey| match key {
+                    CaseKey::Int(key) => map.get(key).cloned(),
+                    _ => None,
+                };
+                if cases
+                    .iter()
+                    .flat_map(|case| &case.keys)
+                    .all(|key| name(key).is_some())
+                {
+                    for key in cases.iter_mut().flat_map(|case| &mut case.keys) {
+                        *key = CaseKey::Enum(name(key).expect("every key is a constant"));
+                    }
+                    *value = object;
+                }
+            }
+        }
+        for inner in lists_mut(statement) {
+            enum_switches(inner, const_pool, maps);
+        }
+    }
+}
+
+// The enum of a switch on map[e.ordinal()] with the switch map.
+fn switch_map<'a>(
+    value: &Expression,
+    const_pool: &ConstantPool,
+    maps: &'a SwitchMaps,
+) -> Option<(Expression, &'a HashMap<i32, String>)> {
+    let (array, index) = match &value.ex {
+        ExpressionType::ArrayLoadEx { array, index } => (array, index),
+        _ => return None,
+    };
+    let field = static_field(array, const_pool)?;
+    let map = maps.get(&(field.owner.to_string(), field.name.to_string()))?;
+    match &index.ex {
+        ExpressionType::MethodEx {
+            object: Some(object),
+            arguments,
+            pool_pos,
+            ..
+        } if arguments.is_empty()
+            && const_pool
+                .member_ref(*pool_pos)
+                .is_ok_and(|member| member.name == "ordinal") =>
+        {
+            Some((object.as_ref().clone(), map))
+        }
+        _ => None,
+    }
+}
+
+fn static_field<'a>(
+    expression: &Expression,
+    const_pool: &'a ConstantPool,
+) -> Option<MemberRef<'a>> {
+    match &expression.ex {
+        ExpressionType::GetStaticFieldEx { pool_pos } => const_pool.member_ref(*pool_pos).ok(),
+        _ => None,
+    }
+}
+
+// The value of an int constant.
+fn int_constant(expression: &Expression, const_pool: &ConstantPool) -> Option<i32> {
+    match &expression.ex {
+        ExpressionType::ConstantEx { op } if (ICONST_M1..=ICONST_5).contains(op) => {
+            Some(*op as i32 - ICONST_0 as i32)
+        }
+        ExpressionType::NumberConstant { number } => Some(i32::from(*number)),
+        ExpressionType::PoolConstantEx { pool_pos } => match const_pool.literal(*pool_pos) {
+            Ok(Literal::Integer(value)) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether the expression calls the method with the name on the var, or with the var as the
+// first argument of a static method.
+fn calls(expression: &Expression, const_pool: &ConstantPool, name: &str, var: u16) -> bool {
+    match &expression.ex {
+        ExpressionType::MethodEx {
+            object,
+            arguments,
+            pool_pos,
+            kind,
+        } => {
+            let first = match kind {
+                InvokeKind::Static => arguments.first(),
+                _ => object.as_deref(),
+            };
+            matches!(first, Some(Expression { ex: ExpressionType::LoadVarEx { var: loaded }, .. }) if *loaded == var)
+                && const_pool
+                    .member_ref(*pool_pos)
+                    .is_ok_and(|member| member.name == name)
+        }
+        _ => false,
+    }
+}
+
+// Whether the argument at the index of a call loads the var.
+fn loads(expression: &Expression, index: usize, var: u16) -> bool {
+    match &expression.ex {
+        ExpressionType::MethodEx { arguments, .. } => matches!(
+            arguments.get(index),
+            Some(Expression { ex: ExpressionType::LoadVarEx { var: loaded }, .. }) if *loaded == var
+        ),
+        _ => false,
+    }
+}
+
+// try { resource.close(); } catch (Throwable t) { primary.addSuppressed(t); }
+fn suppressed(list: &[Statement], const_pool: &ConstantPool, primary: u16, resource: u16) -> bool {
+    let (body, catches) = match list {
+        [Statement::Try {
+            resources,
+            body,
+            catches,
+            finally,
+            ..
+        }] if resources.is_empty() && finally.is_empty() => (body, catches),
+        _ => return false,
+    };
+    let closes = matches!(&body[..], [Statement::Expression { expression }]
+        if calls(expression, const_pool, "close", resource));
+    let adds = match &catches[..] {
+        [Catch { body, .. }] => match &body[..] {
+            [Statement::Expression {
+                expression:
+                    Expression {
+                        ex: ExpressionType::StoreVarEx { var, value },
+                        ..
+                    },
+            }, Statement::Expression { expression }]
+                if value.ex == ExpressionType::CatchEx =>
+            {
+                calls(expression, const_pool, "addSuppressed", primary)
+                    && loads(expression, 0, *var)
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    closes && adds
+}
+
+// The var a condition compares with null, and whether it is true when the var is not null.
+fn non_null(condition: &Expression) -> Option<(u16, bool)> {
+    match &condition.ex {
+        ExpressionType::If0Ex { value } => match value.ex {
+            ExpressionType::LoadVarEx { var } if condition.op == IFNONNULL => Some((var, true)),
+            ExpressionType::LoadVarEx { var } if condition.op == IFNULL => Some((var, false)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether no try has code in its body which its handlers do not protect.
+fn protected(list: &[Statement]) -> bool {
+    fn has_pc(expression: &Expression, pcs: &[u32]) -> bool {
+        pcs.contains(&expression.pc) || expression.children().iter().any(|child| has_pc(child, pcs))
+    }
+    fn uses_pc(list: &[Statement], pcs: &[u32]) -> bool {
+        list.iter().any(|statement| {
+            expressions(statement)
+                .into_iter()
+                .any(|expression| has_pc(expression, pcs))
+                || lists(statement).iter().any(|inner| uses_pc(inner, pcs))
+        })
+    }
+    list.iter().all(|statement| {
+        let unprotected = match statement {
+            Statement::Try {
+                body,
+                catches,
+                unprotected,
+                ..
+            } => {
+                uses_pc(body, unprotected)
+                    || catches
+                        .iter()
+                        .any(|catch| uses_pc(&catch.body, unprotected))
+            }
+            _ => false,
+        };
+        !unprotected && lists(statement).iter().all(|inner| protected(inner))
+    })
+}
+
+// Finally blocks, the outer ones first. javac puts a copy of the finally before every way out of
+// the try and into a handler for anything else which runs it and throws again.
+fn finally_blocks(list: &mut [Statement]) -> bool {
+    let mut changed = false;
+    for statement in list.iter_mut() {
+        changed |= make_finally(statement);
+        for inner in lists_mut(statement) {
+            changed |= finally_blocks(inner);
+        }
+    }
+    changed
+}
+
+fn make_finally(statement: &mut Statement) -> bool {
+    let (body, catches, unprotected) = match statement {
+        Statement::Try {
+            resources,
+            body,
+            catches,
+            finally,
+            unprotected,
+        } if resources.is_empty()
+            && finally.is_empty()
+            && catches.last().is_some_and(|catch| catch.types == [0]) =>
+        {
+            (body, catches, unprotected)
+        }
+        _ => return false,
+    };
+    // the catches are only covered by the catch all when they have more than the copy
+    let (any, others) = catches.split_last_mut().unwrap();
+    let copy = match rethrown(&any.body) {
+        Some(copy) => copy,
+        None => return false,
+    };
+    let copy = |list: &[Statement]| {
+        let start = list.len().checked_sub(copy.len())?;
+        same_statements(&list[start..], copy, &mut Renames::default()).then_some(copy.len())
+    };
+    let mut lists: Vec<_> = others.iter_mut().map(|catch| &mut catch.body).collect();
+    lists.push(body);
+    let mut kept = None;
+    for strip in [false, true] {
+        for list in lists.iter_mut() {
+            let mut inside = Vec::new();
+            labels_in(list, &mut inside);
+            if !finally_copies(
+                list,
+                &copy,
+                &inside,
+                &mut Vec::new(),
+                true,
+                strip,
+                &mut kept,
+            ) {
+                return false;
+            }
+        }
+    }
+    // the copy in the handler only stores into vars which are thrown away, the ones before a
+    // way out which goes on after the try store into the vars read there
+    let mut finally = catches.pop().unwrap().body;
+    finally.pop();
+    finally.remove(0);
+    let finally = kept.unwrap_or(finally);
+    let mut body = mem::take(body);
+    let mut unprotected = mem::take(unprotected);
+    // try { try {} catch {} } finally {} is one try, unless the inner one has a finally too
+    let mut catches = mem::take(catches);
+    if let [inner @ Statement::Try { .. }] = body.as_mut_slice() {
+        let merge = catches.is_empty()
+            && !make_finally(inner)
+            && matches!(inner, Statement::Try { finally, .. } if finally.is_empty());
+        if merge {
+            if let Some(Statement::Try {
+                body: inner_body,
+                catches: inner_catches,
+                unprotected: inner_unprotected,
+                ..
+            }) = body.pop()
+            {
+                body = inner_body;
+                catches = inner_catches;
+                unprotected.extend(inner_unprotected);
+            }
+        }
+    }
+    *statement = Statement::Try {
+        resources: Vec::new(),
+        body,
+        catches,
+        finally,
+        unprotected,
+    };
+    true
+}
+
+// The statements of a catch which stores what it caught, runs them and throws it again.
+fn rethrown(list: &[Statement]) -> Option<&[Statement]> {
+    let caught = match list.first()? {
+        Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::StoreVarEx { var, value },
+                    ..
+                },
+        } if value.ex == ExpressionType::CatchEx => *var,
+        _ => return None,
+    };
+    match list.last()? {
+        Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::ThrowEx { throwable },
+                    ..
+                },
+        } if list.len() > 1 && throwable.ex == ExpressionType::LoadVarEx { var: caught } => {
+            Some(&list[1..list.len() - 1])
+        }
+        _ => None,
+    }
+}
+
+fn labels_in(list: &[Statement], labels: &mut Vec<u32>) {
+    for statement in list {
+        if let Some(label) = statement_label(statement) {
+            labels.push(label);
+        }
+        for inner in lists(statement) {
+            labels_in(inner, labels);
+        }
+    }
+}
+
+fn statement_label(statement: &Statement) -> Option<u32> {
+    match statement {
+        Statement::While { label, .. }
+        | Statement::DoWhile { label, .. }
+        | Statement::For { label, .. }
+        | Statement::Block { label, .. }
+        | Statement::Switch { label, .. } => Some(*label),
+        _ => None,
+    }
+}
+
+// Whether there is a copy of the finally before every way out of the list which leaves the try,
+// and takes them out when stripping. Copy tells how many statements at the end of a list are
+// one. A value which is returned is stored before the copy and loaded again after it. Leaving is
+// falling out of the end of the list as well, the labels in ends are broken out of to the end
+// of the try. The first copy taken out before a way out which does not return is kept.
+fn finally_copies(
+    list: &mut Vec<Statement>,
+    copy: &dyn Fn(&[Statement]) -> Option<usize>,
+    inside: &[u32],
+    ends: &mut Vec<u32>,
+    leaves: bool,
+    strip: bool,
+    kept: &mut Option<Vec<Statement>>,
+) -> bool {
+    let returns = matches!(list.last(), Some(Statement::Expression { expression }) if matches!(
+        expression.ex,
+        ExpressionType::ReturnEx | ExpressionType::ReturnValueEx { .. }
+    ));
+    let exit = returns
+        || match list.last() {
+            Some(Statement::Break { label }) => !inside.contains(label) || ends.contains(label),
+            Some(Statement::Continue { label }) => !inside.contains(label),
+            _ => false,
+        };
+    let end = list.len() - exit as usize;
+    let copied = copy(&list[..end]);
+    if exit && copied.is_none() {
+        return false;
+    }
+    let mut rest = end;
+    if let Some(length) = copied.filter(|_| exit || leaves) {
+        rest -= length;
+        if strip {
+            let stripped: Vec<Statement> = list.drain(rest..end).collect();
+            if !returns && kept.is_none() {
+                *kept = Some(stripped);
+            }
+            returned_directly(list, rest);
+        }
+    }
+    let count = list.len();
+    for (position, statement) in list.iter_mut().enumerate() {
+        // a copy which is not stripped yet has no copies inside
+        if (rest..end).contains(&position) && !strip {
+            continue;
+        }
+        let last = position + 1 == count && !exit && rest == end;
+        let inner_leaves = leaves && last;
+        if inner_leaves {
+            ends.extend(statement_label(statement));
+        }
+        let lists = match statement {
+            Statement::If {
+                then, otherwise, ..
+            } => vec![(then, inner_leaves), (otherwise, inner_leaves)],
+            Statement::Block { body, .. } | Statement::Synchronized { body, .. } => {
+                vec![(body, inner_leaves)]
+            }
+            Statement::While { body, .. }
+            | Statement::DoWhile { body, .. }
+            | Statement::For { body, .. } => vec![(body, false)],
+            Statement::Switch { cases, .. } => {
+                let cases_count = cases.len();
+                cases
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(case_position, case)| {
+                        (
+                            &mut case.body,
+                            inner_leaves && case_position + 1 == cases_count,
+                        )
+                    })
+                    .collect()
+            }
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                let through = inner_leaves && finally.is_empty();
+                let mut lists = vec![(body, through)];
+                lists.extend(catches.iter_mut().map(|catch| (&mut catch.body, through)));
+                if !through {
+                    lists.push((finally, inner_leaves));
+                }
+                lists
+            }
+            _ => Vec::new(),
+        };
+        for (inner, inner_leaves) in lists {
+            if !finally_copies(inner, copy, inside, ends, inner_leaves, strip, kept) {
+                return false;
+            }
+        }
+        // falling out of the end needs a copy unless the last statement has them inside
+        if inner_leaves
+            && !lists_completing(statement)
+            && completes(std::slice::from_ref(statement))
+        {
+            return false;
+        }
+    }
+    !(leaves && count == 0 && copied.is_none())
+}
+
+// Whether the statement goes on after it only from the end of lists inside it.
+fn lists_completing(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::If { .. }
+            | Statement::Block { .. }
+            | Statement::Switch { .. }
+            | Statement::Try { .. }
+            | Statement::Synchronized { .. }
+    )
+}
+
+// tmp = value; return tmp; is return value; when nothing runs in between.
+fn returned_directly(list: &mut Vec<Statement>, position: usize) {
+    if position == 0 || position >= list.len() {
+        return;
+    }
+    let stored = match &list[position - 1] {
+        Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::StoreVarEx { var, .. },
+                    ..
+                },
+        } => *var,
+        _ => return,
+    };
+    let loads = matches!(&list[position], Statement::Expression {
+        expression: Expression { ex: ExpressionType::ReturnValueEx { value }, .. },
+    } if value.ex == ExpressionType::LoadVarEx { var: stored });
+    if !loads {
+        return;
+    }
+    if let Statement::Expression {
+        expression:
+            Expression {
+                ex: ExpressionType::StoreVarEx { value, .. },
+                ..
+            },
+    } = list.remove(position - 1)
+    {
+        if let Statement::Expression {
+            expression:
+                Expression {
+                    ex: ExpressionType::ReturnValueEx { value: returned },
+                    ..
+                },
+        } = &mut list[position - 1]
+        {
+            *returned = value;
+        }
+    }
+}
+
+// The labels and vars of one copy of some code which are others in another copy. javac gives
+// every copy of a finally its own vars.
+#[derive(Default)]
+struct Renames {
+    labels: HashMap<u32, u32>,
+    vars: HashMap<u16, u16>,
+}
+
+// Whether two lists are the same code, with the labels and vars of the first one renamed.
+fn same_statements(first: &[Statement], second: &[Statement], renames: &mut Renames) -> bool {
+    first.len() == second.len()
+        && first
+            .iter()
+            .zip(second)
+            .all(|(first, second)| same_statement(first, second, renames))
+}
+
+fn same_statement(first: &Statement, second: &Statement, renames: &mut Renames) -> bool {
+    if let (Some(first_label), Some(second_label)) =
+        (statement_label(first), statement_label(second))
+    {
+        renames.labels.insert(first_label, second_label);
+    }
+    let same_label = |labels: &HashMap<u32, u32>, first: &u32, second: &u32| {
+        labels.get(first).unwrap_or(first) == second
+    };
+    let same_lists = match (first, second) {
+        (
+            Statement::Expression { expression: first },
+            Statement::Expression { expression: second },
+        ) => return same_code(first, second, &mut renames.vars),
+        (
+            Statement::If {
+                condition: first, ..
+            },
+            Statement::If {
+                condition: second, ..
+            },
+        )
+        | (
+            Statement::DoWhile {
+                condition: first, ..
+            },
+            Statement::DoWhile {
+                condition: second, ..
+            },
+        )
+        | (Statement::Switch { value: first, .. }, Statement::Switch { value: second, .. }) => {
+            same_code(first, second, &mut renames.vars)
+        }
+        (
+            Statement::While {
+                condition: first, ..
+            },
+            Statement::While {
+                condition: second, ..
+            },
+        ) => match (first, second) {
+            (Some(first), Some(second)) => same_code(first, second, &mut renames.vars),
+            (first, second) => first.is_none() && second.is_none(),
+        },
+        (
+            Statement::For {
+                init: first_init,
+                condition: first_condition,
+                update: first_update,
+                ..
+            },
+            Statement::For {
+                init: second_init,
+                condition: second_condition,
+                update: second_update,
+                ..
+            },
+        ) => {
+            let first_all = first_init.iter().chain(first_condition).chain(first_update);
+            let second_all = second_init
+                .iter()
+                .chain(second_condition)
+                .chain(second_update);
+            first_init.len() == second_init.len()
+                && first_update.len() == second_update.len()
+                && first_condition.is_some() == second_condition.is_some()
+                && first_all
+                    .zip(second_all)
+                    .all(|(first, second)| same_code(first, second, &mut renames.vars))
+        }
+        (Statement::Block { .. }, Statement::Block { .. }) => true,
+        (
+            Statement::Synchronized { lock: first, .. },
+            Statement::Synchronized { lock: second, .. },
+        ) => same_code(first, second, &mut renames.vars),
+        (Statement::Break { label: first }, Statement::Break { label: second })
+        | (Statement::Continue { label: first }, Statement::Continue { label: second }) => {
+            return same_label(&renames.labels, first, second)
+        }
+        (
+            Statement::Try { catches: first, .. },
+            Statement::Try {
+                catches: second, ..
+            },
+        ) => {
+            first.len() == second.len()
+                && first
+                    .iter()
+                    .zip(second)
+                    .all(|(first, second)| first.types == second.types)
+        }
+        _ => false,
+    };
+    let keys = |statement: &Statement| match statement {
+        Statement::Switch { cases, .. } => cases
+            .iter()
+            .map(|case| (case.keys.clone(), case.default))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let (first_lists, second_lists) = (lists(first), lists(second));
+    same_lists
+        && keys(first) == keys(second)
+        && first_lists.len() == second_lists.len()
+        && first_lists
+            .iter()
+            .zip(&second_lists)
+            .all(|(first, second)| same_statements(first, second, renames))
+}
+
+// Whether two expressions are the same code, they are made by different ops. A var which is
+// stored to in the first one is renamed to what is stored to in the second one.
+fn same_code(first: &Expression, second: &Expression, vars: &mut HashMap<u16, u16>) -> bool {
+    fn var_mut(expression: &mut Expression) -> Option<&mut u16> {
+        match &mut expression.ex {
+            ExpressionType::LoadVarEx { var }
+            | ExpressionType::StoreVarEx { var, .. }
+            | ExpressionType::IncrementEx { var, .. }
+            | ExpressionType::PostIncrementEx { var, .. } => Some(var),
+            _ => None,
+        }
+    }
+    // only the expression itself is compared here, the children are left out
+    let hollow = |expression: &Expression| {
+        let mut hollow = expression.clone();
+        hollow.pc = 0;
+        let mut children = Vec::new();
+        for child in hollow.children_mut() {
+            let empty = Expression {
+                op: NOP,
+                pc: 0,
+                ex: ExpressionType::StackEx,
+            };
+            children.push(mem::replace(child, empty));
+        }
+        (hollow, children)
+    };
+    let (mut first_hollow, first_children) = hollow(first);
+    let (mut second_hollow, second_children) = hollow(second);
+    if let (Some(first_var), Some(second_var)) =
+        (var_mut(&mut first_hollow), var_mut(&mut second_hollow))
+    {
+        if matches!(first.ex, ExpressionType::StoreVarEx { .. }) {
+            vars.entry(*first_var).or_insert(*second_var);
+        }
+        if let Some(renamed) = vars.get(first_var) {
+            *first_var = *renamed;
+        }
+    }
+    first_hollow == second_hollow
+        && first_children
+            .iter()
+            .zip(&second_children)
+            .all(|(first, second)| same_code(first, second, vars))
+}
+
+// Blocks which are never broken out of are only their body.
+fn flatten_blocks(list: &mut Vec<Statement>) -> bool {
+    let mut changed = false;
+    for statement in list.iter_mut() {
+        for inner in lists_mut(statement) {
+            changed |= flatten_blocks(inner);
+        }
+    }
+    if !list
+        .iter()
+        .any(|statement| matches!(statement, Statement::Block { label, body } if !jumps_to(body, *label, true)))
+    {
+        return changed;
+    }
+    for statement in mem::take(list) {
+        match statement {
+            Statement::Block { label, body } if !jumps_to(&body, label, true) => list.extend(body),
+            statement => list.push(statement),
+        }
+    }
+    true
+}
+
+// Puts the branch which goes on in the then, and takes an else out of its if when the then
+// never gets past its end.
+fn tidy_ifs(list: &mut Vec<Statement>) -> bool {
+    let mut changed = false;
+    for statement in list.iter_mut() {
+        for inner in lists_mut(statement) {
+            changed |= tidy_ifs(inner);
+        }
+    }
+    let mut position = 0;
+    while position < list.len() {
+        if let Statement::If {
+            condition,
+            then,
+            otherwise,
+        } = &mut list[position]
+        {
+            let swap = !otherwise.is_empty()
+                && (then.is_empty() || (completes(then) && !completes(otherwise)));
+            if swap {
+                let negated = negate(condition.clone());
+                *condition = negated;
+                mem::swap(then, otherwise);
+                changed = true;
+            }
+            if !otherwise.is_empty() && !completes(then) {
+                let rest = mem::take(otherwise);
+                list.splice(position + 1..position + 1, rest);
+                changed = true;
+            }
+        }
+        position += 1;
+    }
+    changed
+}
+
+// while (true) loops which test at the start become while, the ones which test at the end
+// do while, and a while with a var set before it and updated at the end becomes a for.
+fn make_loops(list: &mut Vec<Statement>) -> bool {
+    let mut changed = false;
+    for statement in list.iter_mut() {
+        for inner in lists_mut(statement) {
+            changed |= make_loops(inner);
+        }
+    }
+    for statement in mem::take(list) {
+        let (statement, tested) = tested_loop(statement);
+        changed |= tested;
+        let statement = match list.last() {
+            Some(init) if for_fits(init, &statement) => {
+                changed = true;
+                let init = list.pop().unwrap();
+                for_loop(init, statement)
+            }
+            _ => statement,
+        };
+        list.push(statement);
+    }
+    changed
+}
+
+fn tested_loop(statement: Statement) -> (Statement, bool) {
+    let (label, mut body) = match statement {
+        Statement::While {
+            label,
+            condition: None,
+            body,
+        } => (label, body),
+        statement => return (statement, false),
+    };
+    if let Some(test) = exit_test(body.first(), label) {
+        body.remove(0);
+        let statement = Statement::While {
+            label,
+            condition: Some(negate(test)),
+            body,
+        };
+        return (statement, true);
+    }
+    // a continue would skip the test at the end
+    if !jumps_to(&body, label, false) {
+        if let Some(test) = exit_test(body.last(), label) {
+            body.pop();
+            let statement = Statement::DoWhile {
+                label,
+                body,
+                condition: negate(test),
+            };
+            return (statement, true);
+        }
+    }
+    let statement = Statement::While {
+        label,
+        condition: None,
+        body,
+    };
+    (statement, false)
+}
+
+// The condition of an if which only breaks out of the loop.
+fn exit_test(statement: Option<&Statement>, label: u32) -> Option<Expression> {
+    match statement {
+        Some(Statement::If {
+            condition,
+            then,
+            otherwise,
+        }) if otherwise.is_empty()
+            && matches!(then.as_slice(), [Statement::Break { label: target }] if *target == label) =>
+        {
+            Some(condition.clone())
+        }
+        _ => None,
+    }
+}
+
+// A store to a var before a loop which tests the var and updates it at the end.
+fn for_fits(init: &Statement, statement: &Statement) -> bool {
+    match (init, statement) {
+        (
+            Statement::Expression {
+                expression:
+                    Expression {
+                        ex: ExpressionType::StoreVarEx { var, .. },
+                        ..
+                    },
+            },
+            Statement::While {
+                label,
+                condition: Some(condition),
+                body,
+            },
+        ) => {
+            matches!(body.last(), Some(Statement::Expression { expression }) if updates(expression, *var))
+                && reads(condition, *var)
+                && !jumps_to(body, *label, false)
+        }
+        _ => false,
+    }
+}
+
+fn for_loop(init: Statement, statement: Statement) -> Statement {
+    let (init, label, condition, mut body) = match (init, statement) {
+        (
+            Statement::Expression { expression },
+            Statement::While {
+                label,
+                condition,
+                body,
+            },
+        ) => (expression, label, condition, body),
+        _ => unreachable!(),
+    };
+    let update = match body.pop() {
+        Some(Statement::Expression { expression }) => expression,
+        _ => unreachable!(),
+    };
+    // a continue in the source is a break out of a block which ends right before the update
+    if let Some(Statement::Block { label: inner, .. }) = body.last() {
+        let inner = *inner;
+        if let Some(Statement::Block {
+            body: mut block, ..
+        }) = body.pop()
+        {
+            retarget_breaks(&mut block, inner, label);
+            body.extend(block);
+        }
+    }
+    Statement::For {
+        label,
+        init: vec![init],
+        condition,
+        update: vec![update],
+        body,
+    }
+}
+
+fn updates(expression: &Expression, var: u16) -> bool {
+    match &expression.ex {
+        ExpressionType::IncrementEx { var: updated, .. }
+        | ExpressionType::StoreVarEx { var: updated, .. } => *updated == var,
+        _ => false,
+    }
+}
+
+fn reads(expression: &Expression, var: u16) -> bool {
+    matches!(expression.ex, ExpressionType::LoadVarEx { var: read } if read == var)
+        || expression.children().iter().any(|child| reads(child, var))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::*;
+
+    // A statement which is only told apart by its pc.
+    fn step(pc: u32) -> Expression {
+        Expression {
+            op: INVOKESTATIC,
+            pc,
+            ex: ExpressionType::ConstantEx { op: ICONST_0 },
+        }
+    }
+
+    // A test of var which is true when the jump is taken.
+    fn test(pc: u32, var: u16) -> Expression {
+        let value = Expression {
+            op: ILOAD,
+            pc,
+            ex: ExpressionType::LoadVarEx { var },
+        };
+        Expression {
+            op: IFNE,
+            pc,
+            ex: ExpressionType::If0Ex {
+                value: Box::new(value),
+            },
+        }
+    }
+
+    fn node(list: Vec<Expression>, exit: NodeExit) -> Node {
+        Node {
+            list,
+            exit,
+            handlers: Vec::new(),
+            pcs: 0..0,
+        }
+    }
+
+    fn goto(target: u32) -> NodeExit {
+        NodeExit::Goto { target }
+    }
+
+    fn branch(pc: u32, var: u16, true_target: u32, false_target: u32) -> NodeExit {
+        NodeExit::Condition {
+            condition: test(pc, var),
+            true_target,
+            false_target,
+        }
+    }
+
+    // The statements in a short form, like "s0 L1: while (v1) {s2}".
+    fn shape(list: &[Statement]) -> String {
+        let shapes: Vec<String> = list.iter().map(statement_shape).collect();
+        shapes.join(" ")
+    }
+
+    fn statement_shape(statement: &Statement) -> String {
+        match statement {
+            Statement::Expression { expression } => expression_shape(expression),
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } if otherwise.is_empty() => {
+                format!("if ({}) {{{}}}", expression_shape(condition), shape(then))
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => format!(
+                "if ({}) {{{}}} else {{{}}}",
+                expression_shape(condition),
+                shape(then),
+                shape(otherwise)
+            ),
+            Statement::While {
+                label,
+                condition,
+                body,
+            } => {
+                let condition = condition
+                    .as_ref()
+                    .map_or("true".to_string(), expression_shape);
+                format!("L{}: while ({}) {{{}}}", label, condition, shape(body))
+            }
+            Statement::DoWhile {
+                label,
+                body,
+                condition,
+            } => format!(
+                "L{}: do {{{}}} while ({})",
+                label,
+                shape(body),
+                expression_shape(condition)
+            ),
+            Statement::For {
+                label,
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let init: Vec<String> = init.iter().map(expression_shape).collect();
+                let condition = condition.as_ref().map_or(String::new(), expression_shape);
+                let update: Vec<String> = update.iter().map(expression_shape).collect();
+                format!(
+                    "L{}: for ({}; {}; {}) {{{}}}",
+                    label,
+                    init.join(", "),
+                    condition,
+                    update.join(", "),
+                    shape(body)
+                )
+            }
+            Statement::Block { label, body } => format!("L{}: {{{}}}", label, shape(body)),
+            Statement::Switch { label, .. } => format!("L{}: switch", label),
+            Statement::Break { label } => format!("break L{}", label),
+            Statement::Continue { label } => format!("continue L{}", label),
+            Statement::Try {
+                body,
+                catches,
+                finally,
+                ..
+            } => {
+                let catches: Vec<String> = catches
+                    .iter()
+                    .map(|catch| format!(" catch {:?} {{{}}}", catch.types, shape(&catch.body)))
+                    .collect();
+                format!(
+                    "try {{{}}}{} finally {{{}}}",
+                    shape(body),
+                    catches.concat(),
+                    shape(finally)
+                )
+            }
+            Statement::Synchronized { body, .. } => format!("synchronized {{{}}}", shape(body)),
+        }
+    }
+
+    fn expression_shape(expression: &Expression) -> String {
+        match &expression.ex {
+            ExpressionType::If0Ex { value } if expression.op == IFEQ => {
+                format!("!{}", expression_shape(value))
+            }
+            ExpressionType::If0Ex { value } => expression_shape(value),
+            ExpressionType::LoadVarEx { var } => format!("v{}", var),
+            ExpressionType::StoreVarEx { var, .. } => format!("v{} = 0", var),
+            ExpressionType::IncrementEx { var, .. } => format!("v{}++", var),
+            _ => format!("s{}", expression.pc),
+        }
+    }
+
+    fn structured(nodes: Vec<Node>) -> String {
+        let (_, const_pool) = ConstantPool::parse(&[0, 1]).unwrap();
+        shape(&structure(nodes, &const_pool).unwrap())
+    }
+
+    #[test]
+    fn while_loop() {
+        let nodes = vec![
+            node(vec![step(0)], goto(1)),
+            node(vec![], branch(1, 1, 3, 2)),
+            node(vec![step(2)], goto(1)),
+            node(vec![step(3)], NodeExit::End),
+        ];
+        assert_eq!(structured(nodes), "s0 L1: while (!v1) {s2} s3");
+    }
+
+    #[test]
+    fn do_while_loop() {
+        let nodes = vec![
+            node(vec![step(0)], goto(1)),
+            node(vec![step(1)], branch(1, 1, 1, 2)),
+            node(vec![step(2)], NodeExit::End),
+        ];
+        assert_eq!(structured(nodes), "s0 L1: do {s1} while (v1) s2");
+    }
+
+    #[test]
+    fn for_loop() {
+        let init = Expression {
+            op: ISTORE,
+            pc: 0,
+            ex: ExpressionType::StoreVarEx {
+                var: 1,
+                value: Box::new(step(0)),
+            },
+        };
+        let update = Expression {
+            op: IINC,
+            pc: 4,
+            ex: ExpressionType::IncrementEx { var: 1, amount: 1 },
+        };
+        let nodes = vec![
+            node(vec![init], goto(1)),
+            node(vec![], branch(1, 1, 3, 2)),
+            node(vec![], branch(2, 2, 4, 5)),
+            node(vec![step(3)], NodeExit::End),
+            node(vec![update], goto(1)),
+            node(vec![step(5)], branch(5, 5, 4, 6)),
+            node(vec![step(6)], goto(4)),
+        ];
+        assert_eq!(
+            structured(nodes),
+            "L1: for (v1 = 0; !v1; v1++) {if (!v2) {s5 if (!v5) {s6}}} s3"
+        );
+    }
+
+    #[test]
+    fn break_out_of_nested_loop() {
+        let nodes = vec![
+            node(vec![], branch(0, 0, 5, 1)),
+            node(vec![], branch(1, 1, 4, 2)),
+            node(vec![], branch(2, 2, 5, 3)),
+            node(vec![step(3)], goto(1)),
+            node(vec![step(4)], goto(0)),
+            node(vec![step(5)], NodeExit::End),
+        ];
+        assert_eq!(
+            structured(nodes),
+            "L1: while (!v0) {L2: while (!v1) {if (v2) {break L1} s3} s4} s5"
+        );
+    }
+
+    #[test]
+    fn continue_outer_loop() {
+        let nodes = vec![
+            node(vec![], branch(0, 0, 5, 1)),
+            node(vec![], branch(1, 1, 4, 2)),
+            node(vec![], branch(2, 2, 0, 3)),
+            node(vec![step(3)], goto(1)),
+            node(vec![step(4)], goto(0)),
+            node(vec![step(5)], NodeExit::End),
+        ];
+        assert_eq!(
+            structured(nodes),
+            "L1: while (!v0) {L2: while (!v1) {if (v2) {continue L1} s3} s4} s5"
+        );
+    }
+
+    #[test]
+    fn labeled_block() {
+        let nodes = vec![
+            node(vec![step(0)], branch(0, 0, 2, 1)),
+            node(vec![step(1)], branch(1, 1, 3, 2)),
+            node(vec![step(2)], goto(3)),
+            node(vec![step(3)], NodeExit::End),
+        ];
+        assert_eq!(
+            structured(nodes),
+            "L1: {s0 if (!v0) {s1 if (v1) {break L1}} s2} s3"
+        );
+    }
+}
@@ -0,0 +1,529 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::mem;
+
+use crate::consts::*;
+use crate::java::{
+    Attribute, AttributeException, AttributeInfo, AttributeLineNumber, AttributeLocalVariable,
+    AttributeLocalVariableType,
+};
+use crate::java_code::{Code, Instruction, JumpValue, Op};
+
+// Compilers before Java 6 compile finally blocks into subroutines, which are called with jsr
+// and come back with ret. The structuring can not follow those, so every call is replaced with
+// its own copy of the subroutine, the way newer compilers duplicate finally blocks.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubroutineError {
+    // The subroutine does not start by storing or popping its return address.
+    UnknownEntry { op_pos: u32 },
+    // ret uses another var than the one the return address was stored in.
+    ReturnMismatch { op_pos: u32 },
+    // The subroutine calls itself, directly or through another one.
+    Recursive { op_pos: u32 },
+    // A jump or handler lands inside a subroutine without going through jsr.
+    JumpIntoSubroutine { op_pos: u32 },
+    // The op after the jsr is not part of the code making the call.
+    NoContinuation { op_pos: u32 },
+    // A pc of the exception table or a debug table is not at an op.
+    InvalidPc { pc: u16 },
+    // The inlined code does not fit in the 16 bit pcs of the tables.
+    CodeTooLong,
+}
+
+struct Subroutine {
+    // The ops of the subroutine in code order.
+    body: Vec<u32>,
+    ops: HashSet<u32>,
+}
+
+// Every inlined copy of a subroutine gets its own context, the code outside them is context 0.
+struct Context {
+    subroutine: Option<u32>,
+    parent: usize,
+    call: u32,
+}
+
+struct Inliner<'a> {
+    code: &'a [Op],
+    subroutines: HashMap<u32, Subroutine>,
+    contexts: Vec<Context>,
+    // The context and old op of every new op.
+    emitted: Vec<(usize, u32)>,
+    // Where the old ops of every context ended up, also for the ops that were dropped.
+    positions: HashMap<(usize, u32), u32>,
+    calls: Vec<u32>,
+}
+
+// Replaces every jsr with a copy of its subroutine, in which ret jumps back after the call.
+// The original subroutines are removed, and the exception table and the debug tables are
+// moved along with the ops.
+pub fn inline_subroutines(code: &mut Code) -> Result<(), SubroutineError> {
+    let entries: BTreeSet<u32> = code
+        .code
+        .iter()
+        .filter_map(|op| match &op.inst {
+            Instruction::Subroutine { jump } => Some(jump.get_pos()),
+            _ => None,
+        })
+        .collect();
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let attributes = mem::take(&mut code.attribute_info);
+    let op_at = |pc: u16| {
+        code.op_at(pc as u32)
+            .ok_or(SubroutineError::InvalidPc { pc })
+    };
+    let mut ranges: Vec<(u32, u32, u32)> = Vec::with_capacity(code.exception_table.len());
+    for exception in &code.exception_table {
+        ranges.push((
+            op_at(exception.start_pc)?,
+            op_at(exception.end_pc)?,
+            op_at(exception.handler_pc)?,
+        ));
+    }
+
+    let mut subroutines = HashMap::new();
+    for entry in entries {
+        subroutines.insert(entry, find_subroutine(&code.code, &ranges, entry)?);
+    }
+    let outside: Vec<u32> = (0..code.code.len() as u32)
+        .filter(|op_pos| {
+            subroutines
+                .values()
+                .all(|subroutine| !subroutine.ops.contains(op_pos))
+        })
+        .collect();
+
+    let mut inliner = Inliner {
+        code: &code.code,
+        subroutines,
+        contexts: vec![Context {
+            subroutine: None,
+            parent: 0,
+            call: 0,
+        }],
+        emitted: Vec::new(),
+        positions: HashMap::new(),
+        calls: Vec::new(),
+    };
+    inliner.emit(0, &outside)?;
+    inliner.drop_returns()?;
+    let mut new_code = inliner.build()?;
+
+    // the ops get their new pcs, and the tables are moved with the ops as an op in a copy is
+    // covered when its original was
+    let new_bytes = {
+        let mut new_bytes: Vec<u32> = Vec::with_capacity(new_code.len() + 1);
+        let mut op_byte: u32 = 0;
+        for op in &mut new_code {
+            new_bytes.push(op_byte);
+            op.pc = op_byte;
+            op.byte_length = op.length(op_byte);
+            op_byte += op.byte_length;
+        }
+        new_bytes.push(op_byte);
+        if op_byte > u16::MAX as u32 {
+            return Err(SubroutineError::CodeTooLong);
+        }
+        new_bytes
+    };
+    let pc = |op_pos: u32| new_bytes[op_pos as usize] as u16;
+
+    let mut exception_table = Vec::new();
+    for (exception, (start, end, handler)) in code.exception_table.iter().zip(ranges) {
+        let runs = inliner.runs(|context, origin| {
+            if (start..end).contains(&origin) {
+                inliner.resolve(context, handler)
+            } else {
+                None
+            }
+        });
+        for (start, end, handler) in runs {
+            exception_table.push(AttributeException {
+                start_pc: pc(start),
+                end_pc: pc(end),
+                handler_pc: pc(handler),
+                catch_type: exception.catch_type,
+            });
+        }
+    }
+
+    let mut attribute_info = Vec::with_capacity(attributes.len());
+    for Attribute { name_index, info } in attributes {
+        let info = match info {
+            AttributeInfo::LineNumberTable { line_number_table } => {
+                let mut lines = Vec::new();
+                for line in line_number_table {
+                    let op_pos = op_at(line.start_pc)?;
+                    for new_pos in inliner.positions_of(op_pos) {
+                        lines.push(AttributeLineNumber {
+                            start_pc: pc(new_pos),
+                            line_number: line.line_number,
+                        });
+                    }
+                }
+                lines.sort_by_key(|line| line.start_pc);
+                AttributeInfo::LineNumberTable {
+                    line_number_table: lines,
+                }
+            }
+            AttributeInfo::LocalVariableTable {
+                local_variable_table,
+            } => {
+                let mut variables = Vec::new();
+                for variable in local_variable_table {
+                    let start = op_at(variable.start_pc)?;
+                    let end = op_at(variable.start_pc.wrapping_add(variable.length))?;
+                    for (new_start, new_end, _) in
+                        inliner.runs(|_, origin| (start..end).contains(&origin).then_some(0))
+                    {
+                        variables.push(AttributeLocalVariable {
+                            start_pc: pc(new_start),
+                            length: pc(new_end) - pc(new_start),
+                            ..variable
+                        });
+                    }
+                }
+                AttributeInfo::LocalVariableTable {
+                    local_variable_table: variables,
+                }
+            }
+            AttributeInfo::LocalVariableTypeTable {
+                local_variable_type_table,
+            } => {
+                let mut variables = Vec::new();
+                for variable in local_variable_type_table {
+                    let start = op_at(variable.start_pc)?;
+                    let end = op_at(variable.start_pc.wrapping_add(variable.length))?;
+                    for (new_start, new_end, _) in
+                        inliner.runs(|_, origin| (start..end).contains(&origin).then_some(0))
+                    {
+                        variables.push(AttributeLocalVariableType {
+                            start_pc: pc(new_start),
+                            length: pc(new_end) - pc(new_start),
+                            ..variable
+                        });
+                    }
+                }
+                AttributeInfo::LocalVariableTypeTable {
+                    local_variable_type_table: variables,
+                }
+            }
+            // the frames describe the old code, and classes with jsr do not need them
+            AttributeInfo::StackMapTable { .. } => continue,
+            info => info,
+        };
+        attribute_info.push(Attribute { name_index, info });
+    }
+
+    code.code = new_code;
+    code.code_chunks = Code::build_chunks(&code.code, &exception_table);
+    code.exception_table = exception_table;
+    code.attribute_info = attribute_info;
+    Ok(())
+}
+
+// The ops after the entry until ret, nested subroutines are left out as they are called.
+// Handlers of ranges which lie completely within the subroutine are part of it as well.
+fn find_subroutine(
+    code: &[Op],
+    ranges: &[(u32, u32, u32)],
+    entry: u32,
+) -> Result<Subroutine, SubroutineError> {
+    // The return address is stored away first, or popped when the subroutine never returns.
+    let return_var = match code.get(entry as usize) {
+        Some(Op {
+            op: ASTORE,
+            inst: Instruction::Store { var },
+            ..
+        }) => Some(*var),
+        Some(Op { op: POP, .. }) => None,
+        _ => return Err(SubroutineError::UnknownEntry { op_pos: entry }),
+    };
+
+    let mut ops = HashSet::new();
+    let mut todo = vec![entry];
+    while !todo.is_empty() {
+        while let Some(op_pos) = todo.pop() {
+            if (op_pos as usize) < code.len() && ops.insert(op_pos) {
+                todo.extend(successors(code, op_pos));
+            }
+        }
+        for &(start, end, handler) in ranges {
+            if start < end
+                && !ops.contains(&handler)
+                && (start..end).all(|op_pos| ops.contains(&op_pos))
+            {
+                todo.push(handler);
+            }
+        }
+    }
+
+    let mut body: Vec<u32> = ops.iter().copied().collect();
+    body.sort();
+    for &op_pos in &body {
+        if let Instruction::SubroutineReturn { var } = &code[op_pos as usize].inst {
+            if Some(*var) != return_var {
+                return Err(SubroutineError::ReturnMismatch { op_pos });
+            }
+        }
+    }
+    Ok(Subroutine { body, ops })
+}
+
+// A nested jsr continues after the call, as the subroutine it calls comes back there.
+fn successors(code: &[Op], op_pos: u32) -> Vec<u32> {
+    match &code[op_pos as usize].inst {
+        Instruction::Return | Instruction::Throw | Instruction::SubroutineReturn { .. } => {
+            Vec::new()
+        }
+        Instruction::Jump { jump } => vec![jump.get_pos()],
+        Instruction::ComparisonJump { jump } | Instruction::ZeroComparisonJump { jump } => {
+            vec![op_pos + 1, jump.get_pos()]
+        }
+        Instruction::SwitchJump { default, cases } => {
+            let mut targets = vec![default.get_pos()];
+            targets.extend(cases.iter().map(|(_, jump)| jump.get_pos()));
+            targets
+        }
+        _ => vec![op_pos + 1],
+    }
+}
+
+impl<'a> Inliner<'a> {
+    fn emit(&mut self, context: usize, ops: &[u32]) -> Result<(), SubroutineError> {
+        for &op_pos in ops {
+            self.positions
+                .insert((context, op_pos), self.emitted.len() as u32);
+            let entry = match &self.code[op_pos as usize].inst {
+                Instruction::Subroutine { jump } => jump.get_pos(),
+                _ => {
+                    self.emitted.push((context, op_pos));
+                    continue;
+                }
+            };
+            if self.calls.contains(&entry) {
+                return Err(SubroutineError::Recursive { op_pos });
+            }
+
+            // the op storing the return address is dropped, there is nothing left to store
+            let inner = self.contexts.len();
+            self.contexts.push(Context {
+                subroutine: Some(entry),
+                parent: context,
+                call: op_pos,
+            });
+            self.positions
+                .insert((inner, entry), self.emitted.len() as u32);
+            let body = &self.subroutines[&entry].body;
+            let body: Vec<u32> = body
+                .iter()
+                .filter(|op_pos| **op_pos > entry)
+                .chain(body.iter().filter(|op_pos| **op_pos < entry))
+                .copied()
+                .collect();
+            self.calls.push(entry);
+            self.emit(inner, &body)?;
+            self.calls.pop();
+        }
+        Ok(())
+    }
+
+    // Where a jump in the context lands, it can leave the subroutine to an outer context.
+    fn resolve(&self, mut context: usize, target: u32) -> Option<u32> {
+        loop {
+            let outer = &self.contexts[context];
+            match outer.subroutine {
+                Some(entry) if !self.subroutines[&entry].ops.contains(&target) => {
+                    context = outer.parent
+                }
+                _ => return self.positions.get(&(context, target)).copied(),
+            }
+        }
+    }
+
+    // Every position the old op ended up at, one for every copy.
+    fn positions_of(&self, op_pos: u32) -> Vec<u32> {
+        let mut positions: Vec<u32> = self
+            .positions
+            .iter()
+            .filter(|((_, old_pos), new_pos)| {
+                *old_pos == op_pos && (**new_pos as usize) < self.emitted.len()
+            })
+            .map(|(_, new_pos)| *new_pos)
+            .collect();
+        positions.sort();
+        positions.dedup();
+        positions
+    }
+
+    // The runs of consecutive new ops for which value gives the same value.
+    fn runs<T: Copy + PartialEq>(
+        &self,
+        value: impl Fn(usize, u32) -> Option<T>,
+    ) -> Vec<(u32, u32, T)> {
+        let mut runs: Vec<(u32, u32, T)> = Vec::new();
+        for (new_pos, &(context, origin)) in self.emitted.iter().enumerate() {
+            let new_pos = new_pos as u32;
+            if let Some(value) = value(context, origin) {
+                match runs.last_mut() {
+                    Some((_, end, last)) if *end == new_pos && *last == value => *end += 1,
+                    _ => runs.push((new_pos, new_pos + 1, value)),
+                }
+            }
+        }
+        runs
+    }
+
+    // Where ret goes back to, the op after the call in the context that made it.
+    fn continuation(&self, context: usize) -> Result<u32, SubroutineError> {
+        let call = &self.contexts[context];
+        self.resolve(call.parent, call.call + 1)
+            .ok_or(SubroutineError::NoContinuation { op_pos: call.call })
+    }
+
+    // A ret which would only jump to the op right after it is dropped. The ops after it move up,
+    // and the ret's own position becomes the op it fell through to.
+    fn drop_returns(&mut self) -> Result<(), SubroutineError> {
+        let mut dropped = Vec::new();
+        for (new_pos, &(context, origin)) in self.emitted.iter().enumerate() {
+            if let Instruction::SubroutineReturn { .. } = self.code[origin as usize].inst {
+                if self.continuation(context)? == new_pos as u32 + 1 {
+                    dropped.push(new_pos as u32);
+                }
+            }
+        }
+        if dropped.is_empty() {
+            return Ok(());
+        }
+
+        for position in self.positions.values_mut() {
+            *position -= dropped.partition_point(|dropped_pos| dropped_pos < position) as u32;
+        }
+        self.emitted = self
+            .emitted
+            .iter()
+            .enumerate()
+            .filter(|(new_pos, _)| dropped.binary_search(&(*new_pos as u32)).is_err())
+            .map(|(_, emitted)| *emitted)
+            .collect();
+        Ok(())
+    }
+
+    fn build(&self) -> Result<Vec<Op>, SubroutineError> {
+        let mut code = Vec::with_capacity(self.emitted.len());
+        for (new_pos, &(context, origin)) in self.emitted.iter().enumerate() {
+            let new_pos = new_pos as u32;
+            let mut op = self.code[origin as usize].clone();
+            if let Instruction::SubroutineReturn { .. } = op.inst {
+                // ret goes back to the op after the call, the ones falling through are dropped
+                code.push(Op {
+                    op: GOTO,
+                    raw_op: GOTO,
+                    inst: Instruction::Jump {
+                        jump: JumpValue::from_pos(self.continuation(context)?),
+                    },
+                    pc: 0,
+                    byte_length: 0,
+                });
+                continue;
+            }
+
+            // the op it falls through to has to stay right after it
+            let falls_through = successors(self.code, origin).contains(&(origin + 1));
+            if falls_through && self.resolve(context, origin + 1) != Some(new_pos + 1) {
+                return Err(SubroutineError::JumpIntoSubroutine { op_pos: origin });
+            }
+            for jump in op.inst.jumps_mut() {
+                let target = self
+                    .resolve(context, jump.get_pos())
+                    .ok_or(SubroutineError::JumpIntoSubroutine { op_pos: origin })?;
+                jump.set_pos(target);
+            }
+            code.push(op);
+        }
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java::AttributeInfo::CodeAttribute;
+    use crate::java_asm::assemble;
+
+    // The ops of the only method of the listing after inlining, with their jump targets.
+    fn inlined(method: &str) -> Vec<(u8, Vec<u32>)> {
+        let text = format!(".class public A\n.super java/lang/Object\n{}", method);
+        let mut class_info = assemble(&text).unwrap();
+        let code = class_info.methods[0]
+            .attribute_info
+            .iter_mut()
+            .find_map(|attribute| match &mut attribute.info {
+                CodeAttribute { code } => Some(code),
+                _ => None,
+            })
+            .unwrap();
+        inline_subroutines(code).unwrap();
+        code.code
+            .iter_mut()
+            .map(|op| (op.op, op.inst.jumps_mut().into_iter().map(|jump| jump.get_pos()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn return_falls_through() {
+        let ops = inlined(
+            ".method public static f()V
+                .limit stack 2
+                .limit locals 2
+                jsr finally
+                return
+            finally:
+                astore_1
+                getstatic java/lang/System/out Ljava/io/PrintStream;
+                invokevirtual java/io/PrintStream/println()V
+                ret 1
+            .end method",
+        );
+        assert_eq!(
+            ops,
+            [(GETSTATIC, vec![]), (INVOKEVIRTUAL, vec![]), (RETURN, vec![])]
+        );
+    }
+
+    #[test]
+    fn return_jumps() {
+        // the first ret has to jump over the rest of the subroutine, the last one falls through
+        let ops = inlined(
+            ".method public static f(I)V
+                .limit stack 2
+                .limit locals 2
+                jsr finally
+                return
+            finally:
+                astore_1
+                iload_0
+                ifeq print
+                ret 1
+            print:
+                getstatic java/lang/System/out Ljava/io/PrintStream;
+                invokevirtual java/io/PrintStream/println()V
+                ret 1
+            .end method",
+        );
+        assert_eq!(
+            ops,
+            [
+                (ILOAD, vec![]),
+                (IFEQ, vec![3]),
+                (GOTO, vec![5]),
+                (GETSTATIC, vec![]),
+                (INVOKEVIRTUAL, vec![]),
+                (RETURN, vec![]),
+            ]
+        );
+    }
+}
@@ -1,7 +1,6 @@
-use core::panicking::panic;
-use std::str::Chars;
-use std::thread::park;
+use std::fmt;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     Void,
     Boolean,
@@ -13,17 +12,23 @@ pub enum Type {
     Long,
     Double,
     Class { name: String },
-    Array { component: Type },
+    Array { component: Box<Type> },
 }
 
 impl Type {
-    pub fn parse_str(text: &mut String, start: usize, stop: usize) -> Type {
-        Self::parse(&mut text.chars(), start, stop)
+    // Parses a whole field descriptor like "[Ljava/lang/String;".
+    pub fn parse_str(text: &str) -> Option<Type> {
+        match Self::parse(text)? {
+            (typ, "") => Some(typ),
+            _ => None,
+        }
     }
 
-    pub fn parse(chars: &mut Chars, start: usize, stop: usize) -> Type {
-        let first_char = chars.nth(current_pos).unwrap();
-        return match first_char {
+    // Parses one type from the start of the text and returns the rest.
+    pub fn parse(text: &str) -> Option<(Type, &str)> {
+        let mut chars = text.chars();
+        let typ = match chars.next()? {
+            'V' => Type::Void,
             'B' => Type::Byte,
             'C' => Type::Char,
             'D' => Type::Double,
@@ -32,14 +37,74 @@ impl Type {
             'J' => Type::Long,
             'S' => Type::Short,
             'Z' => Type::Boolean,
-            '[' => Type::Array { component: Self::parse(chars, start + 1, stop) },
+            '[' => {
+                let (component, rest) = Self::parse(chars.as_str())?;
+                return Some((
+                    Type::Array {
+                        component: Box::new(component),
+                    },
+                    rest,
+                ));
+            }
             'L' => {
-                let stop = chars.position(|c| c == ';').unwrap() - 1;
-                Type::Class {
-                    name: chars.as_str()[(start + 1)..stop].to_string()
-                }
+                let rest = chars.as_str();
+                let stop = rest.find(';')?;
+                return Some((
+                    Type::Class {
+                        name: rest[..stop].to_string(),
+                    },
+                    &rest[stop + 1..],
+                ));
             }
-            _ => panic!("Invalid Type Parsing")
+            _ => return None,
         };
+        Some((typ, chars.as_str()))
+    }
+}
+
+// java/lang/String becomes java.lang.String, arrays get their brackets at the end.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Byte => write!(f, "byte"),
+            Type::Short => write!(f, "short"),
+            Type::Char => write!(f, "char"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Long => write!(f, "long"),
+            Type::Double => write!(f, "double"),
+            Type::Class { name } => write!(f, "{}", name.replace('/', ".")),
+            Type::Array { component } => write!(f, "{}[]", component),
+        }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodType {
+    pub parameters: Vec<Type>,
+    pub return_type: Type,
+}
+
+impl MethodType {
+    // Parses a method descriptor like "(ILjava/lang/String;)V".
+    pub fn parse_str(text: &str) -> Option<MethodType> {
+        let mut rest = text.strip_prefix('(')?;
+        let mut parameters = Vec::new();
+        loop {
+            if let Some(stripped) = rest.strip_prefix(')') {
+                rest = stripped;
+                break;
+            }
+            let (parameter, stripped) = Type::parse(rest)?;
+            parameters.push(parameter);
+            rest = stripped;
+        }
+        let return_type = Type::parse_str(rest)?;
+        Some(MethodType {
+            parameters,
+            return_type,
+        })
+    }
+}
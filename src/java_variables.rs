@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use crate::consts::*;
+use crate::java::AttributeInfo;
+use crate::java_code::{Code, Instruction};
+
+// The local vars of a method, found by which stores every load can read. javac gives a var an
+// entry in the local variable table for every range where it has a value, and a slot is used
+// again by vars which are not alive at the same time, so neither the table nor the slot is a var.
+// Ops in the range of the same entry are in the same var, even when the value is never read.
+pub struct Variables {
+    // The var of every load, store and iinc, by the pc of the op.
+    pub vars: HashMap<u32, usize>,
+    // The var of every parameter, with this first.
+    pub parameters: Vec<usize>,
+    // An entry of the local variable table for the vars which have one.
+    pub locals: HashMap<usize, usize>,
+}
+
+// A value put in a slot, by a parameter or by an op. The kind is the load op which reads it.
+struct Definition {
+    slot: u16,
+    kind: u8,
+}
+
+impl Code {
+    // The parameters are given as their slot and the load op for their type.
+    pub fn variables(&self, parameters: &[(u16, u8)]) -> Variables {
+        let mut definitions: Vec<Definition> = parameters
+            .iter()
+            .map(|(slot, kind)| Definition {
+                slot: *slot,
+                kind: *kind,
+            })
+            .collect();
+        let mut op_definition = HashMap::new();
+        for (op_pos, op) in self.code.iter().enumerate() {
+            let definition = match op.inst {
+                Instruction::Store { var } => Definition {
+                    slot: var,
+                    kind: op.op - ISTORE + ILOAD,
+                },
+                Instruction::Increment { var, .. } => Definition {
+                    slot: var,
+                    kind: ILOAD,
+                },
+                _ => continue,
+            };
+            op_definition.insert(op_pos as u32, definitions.len());
+            definitions.push(definition);
+        }
+
+        // the definitions which reach the start of every chunk
+        let count = self.code_chunks.len();
+        let mut reaching: Vec<Vec<usize>> = vec![Vec::new(); count];
+        if count > 0 {
+            reaching[0] = (0..parameters.len()).collect();
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for chunk_pos in 0..count {
+                let chunk = &self.code_chunks[chunk_pos];
+                let mut current = reaching[chunk_pos].clone();
+                // a handler can be reached from anywhere in the chunk
+                let mut anywhere = current.clone();
+                for op_pos in chunk.start..chunk.stop {
+                    if let Some(definition) = op_definition.get(&op_pos) {
+                        let slot = definitions[*definition].slot;
+                        current.retain(|other| definitions[*other].slot != slot);
+                        current.push(*definition);
+                        anywhere.push(*definition);
+                    }
+                }
+                for target in self.normal_successors(chunk_pos as u32) {
+                    changed |= merge(&mut reaching[target as usize], &current);
+                }
+                for handler in &chunk.handlers {
+                    changed |= merge(&mut reaching[handler.chunk_pos as usize], &anywhere);
+                }
+            }
+        }
+
+        // every load joins the definitions it can read into one var, and so does every entry
+        let mut parents: Vec<usize> = (0..definitions.len()).collect();
+        let mut entry_definition: HashMap<usize, usize> = HashMap::new();
+        let mut join_entry = |parents: &mut Vec<usize>, entry: Option<usize>, definition: usize| {
+            if let Some(entry) = entry {
+                let first = *entry_definition.entry(entry).or_insert(definition);
+                union(parents, first, definition);
+            }
+        };
+        for (parameter, (slot, _)) in parameters.iter().enumerate() {
+            join_entry(&mut parents, self.local(*slot, 0, None), parameter);
+        }
+        for (op_pos, definition) in &op_definition {
+            join_entry(&mut parents, self.op_local(*op_pos), *definition);
+        }
+        let mut loads: Vec<(u32, Option<usize>)> = Vec::new();
+        for (chunk_pos, chunk) in self.code_chunks.iter().enumerate() {
+            let mut current = reaching[chunk_pos].clone();
+            for op_pos in chunk.start..chunk.stop {
+                let op = &self.code[op_pos as usize];
+                let (slot, kind) = match op.inst {
+                    Instruction::Load { var } => (var, op.op),
+                    Instruction::Increment { var, .. } => (var, ILOAD),
+                    _ => (0, NOP),
+                };
+                if kind != NOP {
+                    let read: Vec<usize> = current
+                        .iter()
+                        .copied()
+                        .filter(|other| {
+                            definitions[*other].slot == slot && definitions[*other].kind == kind
+                        })
+                        .collect();
+                    for other in read.iter().skip(1) {
+                        union(&mut parents, read[0], *other);
+                    }
+                    match op_definition.get(&op_pos) {
+                        Some(definition) => {
+                            if let Some(first) = read.first() {
+                                union(&mut parents, *first, *definition);
+                            }
+                        }
+                        None => {
+                            if let Some(first) = read.first() {
+                                join_entry(&mut parents, self.op_local(op_pos), *first);
+                            }
+                            loads.push((op_pos, read.first().copied()));
+                        }
+                    }
+                }
+                if let Some(definition) = op_definition.get(&op_pos) {
+                    let slot = definitions[*definition].slot;
+                    current.retain(|other| definitions[*other].slot != slot);
+                    current.push(*definition);
+                }
+            }
+        }
+
+        // a load which reads nothing is in unreachable code and gets a var of its own
+        let mut vars = HashMap::new();
+        let mut locals = HashMap::new();
+        let mut ops: Vec<(u32, usize)> = Vec::new();
+        for (op_pos, definition) in &op_definition {
+            ops.push((*op_pos, find(&mut parents, *definition)));
+        }
+        for (op_pos, definition) in loads {
+            let var = match definition {
+                Some(definition) => find(&mut parents, definition),
+                None => parents.len() + op_pos as usize,
+            };
+            ops.push((op_pos, var));
+        }
+        ops.sort_unstable();
+        for (op_pos, var) in ops {
+            vars.insert(self.code[op_pos as usize].pc, var);
+            if let Some(entry) = self.op_local(op_pos) {
+                locals.entry(var).or_insert(entry);
+            }
+        }
+        let parameter_vars: Vec<usize> = (0..parameters.len())
+            .map(|parameter| find(&mut parents, parameter))
+            .collect();
+        for (var, (slot, _)) in parameter_vars.iter().zip(parameters) {
+            if let Some(entry) = self.local(*slot, 0, None) {
+                locals.insert(*var, entry);
+            }
+        }
+        Variables {
+            vars,
+            parameters: parameter_vars,
+            locals,
+        }
+    }
+
+    // The entry in the local variable table for a load, store or iinc. The range of a var starts
+    // right after the store which gives it a value, every other op is inside the range.
+    fn op_local(&self, op_pos: u32) -> Option<usize> {
+        let op = &self.code[op_pos as usize];
+        match op.inst {
+            Instruction::Store { var } => {
+                let next = self
+                    .code
+                    .get(op_pos as usize + 1)
+                    .map_or(u32::MAX, |next| next.pc);
+                self.local(var, op.pc, Some(next))
+            }
+            Instruction::Load { var } | Instruction::Increment { var, .. } => {
+                self.local(var, op.pc, None)
+            }
+            _ => None,
+        }
+    }
+
+    fn local(&self, slot: u16, pc: u32, next: Option<u32>) -> Option<usize> {
+        let locals = self
+            .attribute_info
+            .iter()
+            .find_map(|attribute| match &attribute.info {
+                AttributeInfo::LocalVariableTable {
+                    local_variable_table,
+                } => Some(local_variable_table),
+                _ => None,
+            })?;
+        let starts_after = |start: u32| next.is_some_and(|next| start > pc && start <= next);
+        locals
+            .iter()
+            .position(|local| local.index == slot && starts_after(local.start_pc as u32))
+            .or_else(|| {
+                locals.iter().position(|local| {
+                    let start = local.start_pc as u32;
+                    local.index == slot && start <= pc && pc < start + local.length as u32
+                })
+            })
+    }
+}
+
+// Adds the definitions which are not there yet, and tells if there were any.
+fn merge(into: &mut Vec<usize>, from: &[usize]) -> bool {
+    let before = into.len();
+    for definition in from {
+        if !into.contains(definition) {
+            into.push(*definition);
+        }
+    }
+    into.len() != before
+}
+
+fn find(parents: &mut [usize], node: usize) -> usize {
+    let mut root = node;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut node = node;
+    while parents[node] != root {
+        let next = parents[node];
+        parents[node] = root;
+        node = next;
+    }
+    root
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    if a != b {
+        parents[a.max(b)] = a.min(b);
+    }
+}
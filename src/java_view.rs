@@ -0,0 +1,487 @@
+#![allow(dead_code)]
+
+use std::borrow::Cow;
+
+use nom::bytes::complete::{tag, take};
+use nom::combinator::map_opt;
+use nom::error::{ErrorKind, make_error};
+use nom::multi::length_count;
+use nom::number::complete::{be_u16, be_u32, be_u8};
+
+use crate::consts::ClassAccessFlags;
+use crate::java::{decode_mutf8, ConstantInfo, IResult, PoolError, Utf8Pool};
+use crate::java_code::{Code, Instruction};
+
+// A borrowed view over a class file which only indexes where things are.
+// Nothing is decoded until it is asked for, so scanning a large jar for class names or the
+// hierarchy does not pay for parsing every method body.
+pub struct ClassView<'a> {
+    pub constant_pool: PoolView<'a>,
+    pub access_flags: ClassAccessFlags,
+
+    pub this_class: u16,
+    pub super_class: u16,
+
+    interfaces: &'a [u8],
+    pub fields: Vec<MemberView<'a>>,
+    pub methods: Vec<MemberView<'a>>,
+    pub attributes: Vec<AttributeView<'a>>,
+}
+
+impl<'a> ClassView<'a> {
+    pub fn parse(input: &'a [u8]) -> IResult<'a, Self> {
+        let class = input;
+        let (input, _) = tag(b"\xca\xfe\xba\xbe")(input)?;
+        // The version does not change how the pool or the members are laid out.
+        let (input, _) = take(4usize)(input)?;
+        let (input, constant_pool) = PoolView::parse(input)?;
+
+        let (input, access_flags) = map_opt(be_u16, ClassAccessFlags::from_bits)(input)?;
+        let (input, this_class) = be_u16(input)?;
+        let (input, super_class) = be_u16(input)?;
+        let (input, interface_count) = be_u16(input)?;
+        let (input, interfaces) = take(interface_count as usize * 2)(input)?;
+
+        let (input, fields) = length_count(be_u16, |input| MemberView::parse(class, input))(input)?;
+        let (input, methods) = length_count(be_u16, |input| MemberView::parse(class, input))(input)?;
+        let (input, attributes) = length_count(be_u16, |input| AttributeView::parse(class, input))(input)?;
+
+        Ok((
+            input,
+            ClassView {
+                constant_pool,
+                access_flags,
+                this_class,
+                super_class,
+                interfaces,
+                fields,
+                methods,
+                attributes,
+            },
+        ))
+    }
+
+    pub fn name(&self) -> Result<Cow<'a, str>, PoolError> {
+        self.constant_pool.class_name(self.this_class)
+    }
+
+    // java/lang/Object is the only class without a super class.
+    pub fn super_name(&self) -> Result<Option<Cow<'a, str>>, PoolError> {
+        if self.super_class == 0 {
+            return Ok(None);
+        }
+        self.constant_pool.class_name(self.super_class).map(Some)
+    }
+
+    pub fn interfaces(&self) -> impl Iterator<Item = u16> + 'a {
+        self.interfaces
+            .chunks_exact(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // The class the way a hierarchy listing shows it, like "class a/B extends a/C implements a/D".
+    pub fn hierarchy(&self) -> Result<String, PoolError> {
+        let mut text = if self.access_flags.contains(ClassAccessFlags::INTERFACE) {
+            "interface ".to_string()
+        } else {
+            "class ".to_string()
+        };
+        text += &self.name()?;
+        if let Some(super_name) = self.super_name()? {
+            text += " extends ";
+            text += &super_name;
+        }
+        let interfaces = self
+            .interfaces()
+            .map(|index| self.constant_pool.class_name(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !interfaces.is_empty() {
+            text += " implements ";
+            text += &interfaces.join(", ");
+        }
+        Ok(text)
+    }
+
+    pub fn field(&self, name: &str, descriptor: &str) -> Option<&MemberView<'a>> {
+        self.fields
+            .iter()
+            .find(|field| field.is(&self.constant_pool, name, descriptor))
+    }
+
+    pub fn method(&self, name: &str, descriptor: &str) -> Option<&MemberView<'a>> {
+        self.methods
+            .iter()
+            .find(|method| method.is(&self.constant_pool, name, descriptor))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&AttributeView<'a>> {
+        find_attribute(&self.attributes, &self.constant_pool, name)
+    }
+
+    // Decodes the Code attribute of a method, if it has one.
+    pub fn code(&self, method: &MemberView<'a>) -> Option<IResult<'a, Code>> {
+        method
+            .attribute(&self.constant_pool, "Code")
+            .map(|attribute| Code::parse(attribute.info, &self.constant_pool))
+    }
+}
+
+// A field or a method. The access flags are kept as read, FieldAccessFlags and
+// MethodAccessFlags give them a meaning depending on which list the member is in.
+pub struct MemberView<'a> {
+    // Offset of the member in the class bytes.
+    pub offset: u32,
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<AttributeView<'a>>,
+}
+
+impl<'a> MemberView<'a> {
+    pub fn parse(class: &'a [u8], input: &'a [u8]) -> IResult<'a, Self> {
+        let offset = (class.len() - input.len()) as u32;
+        let (input, access_flags) = be_u16(input)?;
+        let (input, name_index) = be_u16(input)?;
+        let (input, descriptor_index) = be_u16(input)?;
+        let (input, attributes) = length_count(be_u16, |input| AttributeView::parse(class, input))(input)?;
+
+        Ok((
+            input,
+            MemberView {
+                offset,
+                access_flags,
+                name_index,
+                descriptor_index,
+                attributes,
+            },
+        ))
+    }
+
+    fn is(&self, constant_pool: &PoolView<'a>, name: &str, descriptor: &str) -> bool {
+        constant_pool.utf8(self.name_index).is_ok_and(|text| text == name)
+            && constant_pool
+                .utf8(self.descriptor_index)
+                .is_ok_and(|text| text == descriptor)
+    }
+
+    pub fn attribute(&self, constant_pool: &PoolView<'a>, name: &str) -> Option<&AttributeView<'a>> {
+        find_attribute(&self.attributes, constant_pool, name)
+    }
+}
+
+pub struct AttributeView<'a> {
+    // Offset of the attribute header in the class bytes, info starts 6 bytes later.
+    pub offset: u32,
+    pub name_index: u16,
+    pub info: &'a [u8],
+}
+
+impl<'a> AttributeView<'a> {
+    pub fn parse(class: &'a [u8], input: &'a [u8]) -> IResult<'a, Self> {
+        let offset = (class.len() - input.len()) as u32;
+        let (input, name_index) = be_u16(input)?;
+        let (input, length) = be_u32(input)?;
+        let (input, info) = take(length)(input)?;
+        Ok((
+            input,
+            AttributeView {
+                offset,
+                name_index,
+                info,
+            },
+        ))
+    }
+}
+
+fn find_attribute<'a, 'b>(
+    attributes: &'b [AttributeView<'a>],
+    constant_pool: &PoolView<'a>,
+    name: &str,
+) -> Option<&'b AttributeView<'a>> {
+    attributes
+        .iter()
+        .find(|attribute| constant_pool.utf8(attribute.name_index).is_ok_and(|text| text == name))
+}
+
+// The constant pool as offsets into the class bytes. Entries are decoded on every lookup,
+// Utf8 entries are borrowed straight from the input unless they differ from UTF-8.
+pub struct PoolView<'a> {
+    data: &'a [u8],
+    // Offset of every entry's tag, None for the slot following a Long or Double.
+    offsets: Vec<Option<u32>>,
+}
+
+impl<'a> PoolView<'a> {
+    pub fn parse(input: &'a [u8]) -> IResult<'a, Self> {
+        let data = input;
+        let (mut input, count) = be_u16(input)?;
+
+        let mut offsets = Vec::with_capacity(count as usize);
+        while offsets.len() + 1 < count as usize {
+            offsets.push(Some((data.len() - input.len()) as u32));
+            let (input2, variant) = be_u8(input)?;
+            let (input2, _) = match variant {
+                1 => {
+                    let (input2, length) = be_u16(input2)?;
+                    take(length)(input2)?
+                }
+                7 | 8 | 16 => take(2u8)(input2)?,
+                15 => take(3u8)(input2)?,
+                3 | 4 | 9 | 10 | 11 | 12 | 18 => take(4u8)(input2)?,
+                5 | 6 => {
+                    offsets.push(None);
+                    take(8u8)(input2)?
+                }
+                _ => return Err(nom::Err::Error(make_error(input, ErrorKind::Alt))),
+            };
+            input = input2;
+        }
+
+        let length = data.len() - input.len();
+        Ok((
+            input,
+            PoolView {
+                data: &data[..length],
+                offsets,
+            },
+        ))
+    }
+
+    fn entry(&self, index: u16) -> Result<&'a [u8], PoolError> {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|index| self.offsets.get(index))
+            .map(|offset| match offset {
+                Some(offset) => &self.data[*offset as usize..],
+                None => &[],
+            })
+            .ok_or(PoolError::InvalidIndex { index })
+    }
+
+    // Decodes a single entry, only Utf8 entries allocate.
+    pub fn get(&self, index: u16) -> Result<ConstantInfo, PoolError> {
+        match self.entry(index)? {
+            [] => Ok(ConstantInfo::Unusable),
+            entry @ [1, ..] => ConstantInfo::parse(entry)
+                .map(|(_, info)| info)
+                .map_err(|_| PoolError::InvalidUtf8 { index }),
+            entry @ [tag, ..] => ConstantInfo::parse(entry)
+                .map(|(_, info)| info)
+                .map_err(|_| PoolError::InvalidTag { index, tag: *tag }),
+        }
+    }
+
+    fn mismatch(&self, index: u16, expected: &'static str) -> PoolError {
+        match self.get(index) {
+            Ok(info) => PoolError::KindMismatch {
+                index,
+                expected,
+                found: info.kind_name(),
+            },
+            Err(error) => error,
+        }
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<Cow<'a, str>, PoolError> {
+        let entry = self.entry(index)?;
+        match entry {
+            [1, high, low, text @ ..] => {
+                let length = u16::from_be_bytes([*high, *low]) as usize;
+                decode_mutf8(&text[..length]).ok_or(PoolError::InvalidUtf8 { index })
+            }
+            _ => Err(self.mismatch(index, "Utf8")),
+        }
+    }
+
+    pub fn class_name(&self, index: u16) -> Result<Cow<'a, str>, PoolError> {
+        match self.get(index)? {
+            ConstantInfo::Class { name_index } => self.utf8(name_index),
+            info => Err(PoolError::KindMismatch {
+                index,
+                expected: "Class",
+                found: info.kind_name(),
+            }),
+        }
+    }
+
+    pub fn name_and_type(&self, index: u16) -> Result<(Cow<'a, str>, Cow<'a, str>), PoolError> {
+        match self.get(index)? {
+            ConstantInfo::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Ok((self.utf8(name_index)?, self.utf8(descriptor_index)?)),
+            info => Err(PoolError::KindMismatch {
+                index,
+                expected: "NameAndType",
+                found: info.kind_name(),
+            }),
+        }
+    }
+
+    pub fn member_ref(&self, index: u16) -> Result<MemberRefView<'a>, PoolError> {
+        match self.get(index)? {
+            ConstantInfo::Field {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantInfo::Method {
+                class_index,
+                name_and_type_index,
+            }
+            | ConstantInfo::Interface {
+                class_index,
+                name_and_type_index,
+            } => {
+                let owner = self.class_name(class_index)?;
+                let (name, descriptor) = self.name_and_type(name_and_type_index)?;
+                Ok(MemberRefView {
+                    owner,
+                    name,
+                    descriptor,
+                })
+            }
+            info => Err(PoolError::KindMismatch {
+                index,
+                expected: "Fieldref, Methodref or InterfaceMethodref",
+                found: info.kind_name(),
+            }),
+        }
+    }
+
+    pub fn string(&self, index: u16) -> Result<Cow<'a, str>, PoolError> {
+        match self.get(index)? {
+            ConstantInfo::String { string_index } => self.utf8(string_index),
+            info => Err(PoolError::KindMismatch {
+                index,
+                expected: "String",
+                found: info.kind_name(),
+            }),
+        }
+    }
+
+    // What the operand of an instruction refers to in the pool, the way javap comments it.
+    pub fn comment(&self, inst: &Instruction) -> Option<String> {
+        let resolved = match inst {
+            Instruction::GetField { pool_pos }
+            | Instruction::GetStaticField { pool_pos }
+            | Instruction::PutField { pool_pos }
+            | Instruction::PutStaticField { pool_pos }
+            | Instruction::InvokeMethod { pool_pos, .. } => self
+                .member_ref(*pool_pos)
+                .map(|member| format!("{}.{}:{}", member.owner, member.name, member.descriptor)),
+            Instruction::New { pool_pos }
+            | Instruction::Cast { pool_pos }
+            | Instruction::Instanceof { pool_pos } => {
+                self.class_name(*pool_pos).map(Cow::into_owned)
+            }
+            Instruction::ConstantPool { pool } => self.string(*pool as u16).map(|text| format!("{:?}", text)),
+            Instruction::ConstantPoolWide { pool } => self.string(*pool).map(|text| format!("{:?}", text)),
+            _ => return None,
+        };
+        resolved.ok()
+    }
+}
+
+impl<'a> Utf8Pool for PoolView<'a> {
+    fn utf8(&self, index: u16) -> Result<Cow<'_, str>, PoolError> {
+        PoolView::utf8(self, index)
+    }
+}
+
+// A Fieldref, Methodref or InterfaceMethodref like MemberRef, its text is only copied when it
+// had to be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberRefView<'a> {
+    pub owner: Cow<'a, str>,
+    pub name: Cow<'a, str>,
+    pub descriptor: Cow<'a, str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java::ConstantPool;
+
+    #[test]
+    fn mutf8() {
+        // "a\0b", U+1F600 as two surrogates, a lone surrogate and plain text
+        let mut data = vec![0, 5];
+        data.extend_from_slice(b"\x01\x00\x04a\xc0\x80b");
+        data.extend_from_slice(b"\x01\x00\x06\xed\xa0\xbd\xed\xb8\x80");
+        data.extend_from_slice(b"\x01\x00\x03\xed\xa0\x80");
+        data.extend_from_slice(b"\x01\x00\x05plain");
+        let (_, view) = PoolView::parse(&data).unwrap();
+        let (_, pool) = ConstantPool::parse(&data).unwrap();
+        for index in 1..=4 {
+            assert_eq!(view.utf8(index).ok().as_deref(), pool.utf8(index).ok(), "{}", index);
+        }
+        assert_eq!(view.utf8(1).ok().as_deref(), Some("a\0b"));
+        assert_eq!(view.utf8(2).ok().as_deref(), Some("\u{1f600}"));
+        assert!(matches!(view.utf8(4), Ok(Cow::Borrowed("plain"))));
+    }
+
+    // class A { private final int x; public static void m() { return; } } from A.java
+    fn class() -> Vec<u8> {
+        let mut data = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x0c".to_vec();
+        for (tag, entry) in [
+            (1, &b"A"[..]),
+            (7, b"\x00\x01"),
+            (1, b"java/lang/Object"),
+            (7, b"\x00\x03"),
+            (1, b"x"),
+            (1, b"I"),
+            (1, b"m"),
+            (1, b"()V"),
+            (1, b"Code"),
+            (1, b"SourceFile"),
+            (1, b"A.java"),
+        ] {
+            data.push(tag);
+            if tag == 1 {
+                data.extend_from_slice(&(entry.len() as u16).to_be_bytes());
+            }
+            data.extend_from_slice(entry);
+        }
+        // flags, this, super and no interfaces
+        data.extend_from_slice(b"\x00\x21\x00\x02\x00\x04\x00\x00");
+        // the field x
+        data.extend_from_slice(b"\x00\x01\x00\x12\x00\x05\x00\x06\x00\x00");
+        // the method m with a Code attribute holding a single return
+        data.extend_from_slice(b"\x00\x01\x00\x09\x00\x07\x00\x08\x00\x01");
+        data.extend_from_slice(b"\x00\x09\x00\x00\x00\x0d\x00\x00\x00\x00\x00\x00\x00\x01\xb1\x00\x00\x00\x00");
+        // the SourceFile attribute
+        data.extend_from_slice(b"\x00\x01\x00\x0a\x00\x00\x00\x02\x00\x0b");
+        data
+    }
+
+    #[test]
+    fn members() {
+        let data = class();
+        let (rest, view) = ClassView::parse(&data).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(view.hierarchy().unwrap(), "class A extends java/lang/Object");
+
+        let field = view.field("x", "I").unwrap();
+        assert_eq!(field.access_flags, 0x0012);
+        assert!(field.attributes.is_empty());
+        assert_eq!(&data[field.offset as usize..][..2], b"\x00\x12");
+        assert!(view.field("x", "J").is_none());
+        assert!(view.method("x", "I").is_none());
+
+        let method = view.method("m", "()V").unwrap();
+        assert_eq!(method.access_flags, 0x0009);
+        // the field takes 8 bytes and the method count 2
+        assert_eq!(method.offset, field.offset + 10);
+        let code = method.attribute(&view.constant_pool, "Code").unwrap();
+        assert_eq!(code.offset, method.offset + 8);
+        assert_eq!(code.info.len(), 13);
+        let (_, code) = view.code(method).unwrap().unwrap();
+        assert_eq!(code.code.len(), 1);
+
+        let source_file = view.attribute("SourceFile").unwrap();
+        assert_eq!(source_file.offset as usize, data.len() - 8);
+        let index = u16::from_be_bytes([source_file.info[0], source_file.info[1]]);
+        assert_eq!(view.constant_pool.utf8(index).unwrap(), "A.java");
+        assert!(view.attribute("Code").is_none());
+    }
+}
@@ -1,82 +1,215 @@
-use std::fs::File;
-use std::io::Read;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::path::Path;
 
-use crate::consts::{MethodAccessFlags, print_op};
 use crate::java::{AttributeInfo, ClassInfo};
-use crate::java_code::{Code, CodeChunkTarget};
+use crate::java_code::Code;
+use crate::java_view::ClassView;
 
 mod consts;
 mod java;
+mod java_asm;
 mod java_code;
+mod java_disasm;
 mod java_decomp;
+mod java_dominators;
+mod java_dot;
+mod java_loops;
+mod java_source;
+mod java_structure;
+mod java_subroutine;
 mod java_type;
+mod java_variables;
+mod java_view;
 
-fn main() {
-    println!("Hello, world!");
-    let mut f = File::open("./run/Test.class").expect("hahafunny");
-    let mut buffer = Vec::new();
-    // read the whole file
-
-    f.read_to_end(&mut buffer).expect("fuckk");
-    let (_buffer, class_info) = ClassInfo::parse(&buffer).unwrap();
+const USAGE: &str = "usage: reflow <command> <arguments>
+    hierarchy <class file>...
+    asm <listing> <class file>
+    disasm <class file>
+    dot <class file> <directory>
+    decomp <class file>
+    check <class file>...
+    ops <class file> <name> <descriptor>
+    dominators <class file> <name> <descriptor>
+    loops <class file> <name> <descriptor>";
 
-    for x in class_info.methods {
-        let i = x.access_flags;
-        if i.contains(MethodAccessFlags::PUBLIC) {
-            println!("public");
-        } else if i.contains(MethodAccessFlags::PROTECTED) {
-            println!("protected");
-        } else if i.contains(MethodAccessFlags::PRIVATE) {
-            println!("private");
-        } else {
-            println!("package-private");
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let (command, arguments) = match args.as_slice() {
+        [_, command, arguments @ ..] => (command.as_str(), arguments),
+        _ => ("", &[][..]),
+    };
+    match (command, arguments) {
+        // reflow hierarchy <class file>... only reads the headers and the constant pools
+        ("hierarchy", inputs) if !inputs.is_empty() => {
+            for input in inputs {
+                let buffer = std::fs::read(input).expect("could not read the class file");
+                match ClassView::parse(&buffer).map(|(_, view)| view.hierarchy()) {
+                    Ok(Ok(hierarchy)) => println!("{}", hierarchy),
+                    Ok(Err(error)) => eprintln!("{}: {:?}", input, error),
+                    Err(error) => eprintln!("{}: could not parse the class: {:?}", input, error),
+                }
+            }
         }
-
-        for x in x.attribute_info {
-            match x {
-                java::AttributeInfo::CodeAttribute { code } => {
-                    println!();
-                    println!();
-                    let mut i = 0;
-                    for x in code.code_chunks {
-                        for x in x.start..x.stop {
-                            let op = &code.code[x as usize];
-                            println!("{}", op.print());
+        // reflow ops <class file> <name> <descriptor> decodes the code of that method only
+        ("ops", [input, name, descriptor]) => with_method_code(input, name, descriptor, |view, code| {
+            for (op_pos, op) in code.code.iter().enumerate() {
+                match view.constant_pool.comment(&op.inst) {
+                    Some(comment) => println!("{:5}: {} // {}", op_pos, op.print(), comment),
+                    None => println!("{:5}: {}", op_pos, op.print()),
+                }
+            }
+        }),
+        // reflow asm <listing> <class file>
+        ("asm", [input, output]) => {
+            let text = std::fs::read_to_string(input).expect("could not read the listing");
+            match java_asm::assemble(&text) {
+                Ok(class_info) => {
+                    let bytes = class_info.write().expect("could not write the class");
+                    std::fs::write(output, bytes).expect("could not write the class file");
+                }
+                Err(error) => eprintln!("{}:{}: {}", input, error.line, error.message),
+            }
+        }
+        // reflow disasm <class file>
+        ("disasm", [input]) => {
+            let buffer = std::fs::read(input).expect("could not read the class file");
+            match ClassInfo::parse(&buffer) {
+                Ok((_, class_info)) => print!("{}", java_disasm::disassemble(&class_info)),
+                Err(error) => eprintln!("{}: could not parse the class: {:?}", input, error),
+            }
+        }
+        // reflow decomp <class file> prints the class as Java source
+        ("decomp", [input]) => {
+            let buffer = std::fs::read(input).expect("could not read the class file");
+            match ClassInfo::parse(&buffer) {
+                Ok((_, mut class_info)) => {
+                    // an enum switch looks up its cases in a class which is next to this one
+                    let mut switch_maps = HashMap::new();
+                    for class in java_source::switch_map_classes(&class_info) {
+                        let name = class.rsplit('/').next().unwrap_or(&class);
+                        let path = Path::new(input).with_file_name(format!("{}.class", name));
+                        if let Ok(buffer) = std::fs::read(path) {
+                            if let Ok((_, mut map_class)) = ClassInfo::parse(&buffer) {
+                                switch_maps.extend(java_source::class_switch_maps(&mut map_class));
+                            }
                         }
-
-                    //   print!("{} [label=\"", i);
-                    //   for x in x.start..x.stop {
-                    //       let op = &code.code[x as usize];
-                    //       print!("{}\n", op.print());
-                    //   }
-                    //   println!("\"];");
-                    //   //print!("{}-{} ", x.start, x.stop);
-                    //  match x.target {
-                    //      CodeChunkTarget::Basic => {
-                    //          println!("{} -> {}", i, i + 1);
-                    //      }
-                    //      CodeChunkTarget::Return => {
-                    //          println!("{}", i);
-                    //      }
-                    //      CodeChunkTarget::Throw => {
-
-                    //      }
-                    //      CodeChunkTarget::Condition { true_chunk_pos } => {
-                    //          println!("{} -> {}", i, i + 1);
-                    //          println!("{} -> {}", i, true_chunk_pos + 1);
-                    //      }
-                    //      CodeChunkTarget::Goto { chunk_pos } => {
-                    //          println!("{} -> {}", i, chunk_pos + 1);
-                    //      }
-                    //      CodeChunkTarget::Switch { .. } => {}
-                    //  }
-                        i += 1;
                     }
+                    print!("{}", java_source::class_source(&mut class_info, &switch_maps))
+                }
+                Err(error) => eprintln!("{}: could not parse the class: {:?}", input, error),
+            }
+        }
+        // reflow check <class file>... checks the control flow graph of every method, and that
+        // the source has no stack values which were never found
+        ("check", inputs) if !inputs.is_empty() => {
+            for input in inputs {
+                let buffer = std::fs::read(input).expect("could not read the class file");
+                let mut class_info = match ClassInfo::parse(&buffer) {
+                    Ok((_, class_info)) => class_info,
+                    Err(error) => {
+                        eprintln!("{}: could not parse the class: {:?}", input, error);
+                        continue;
+                    }
+                };
+                for method in &class_info.methods {
+                    for attribute in &method.attribute_info {
+                        if let AttributeInfo::CodeAttribute { code } = &attribute.info {
+                            if let Err(error) = code.validate_cfg() {
+                                let name = class_info.constant_pool.utf8(method.name_index).unwrap_or("?");
+                                let descriptor = class_info.constant_pool.utf8(method.descriptor_index).unwrap_or("?");
+                                eprintln!("{}: {}{}: {:?}", input, name, descriptor, error);
+                            }
+                        }
+                    }
+                }
+                let source = java_source::class_source(&mut class_info, &HashMap::new());
+                if source.contains("$stack") {
+                    eprintln!("{}: the source uses stack values which were never found", input);
+                }
+            }
+        }
+        // reflow dot <class file> <directory> writes a graph for every method with code
+        ("dot", [input, output]) => {
+            let buffer = std::fs::read(input).expect("could not read the class file");
+            let class_info = match ClassInfo::parse(&buffer) {
+                Ok((_, class_info)) => class_info,
+                Err(error) => {
+                    eprintln!("{}: could not parse the class: {:?}", input, error);
+                    return;
+                }
+            };
+            let class_name = class_info.constant_pool.class_name(class_info.this_class).unwrap_or("class");
+            for (method_pos, method) in class_info.methods.iter().enumerate() {
+                if let Some(dot) = java_dot::method_dot(&class_info, method) {
+                    // overloads share a name, so the position of the method is added
+                    let name = class_info.constant_pool.utf8(method.name_index).unwrap_or("method");
+                    let file_name = format!("{}.{}.{}.dot", class_name, name, method_pos)
+                        .replace(['/', '$'], ".")
+                        .replace(['<', '>'], "");
+                    let path = std::path::Path::new(output).join(file_name);
+                    std::fs::write(path, dot).expect("could not write the dot file");
                 }
-                _ => {}
             }
         }
+        // reflow dominators <class file> <name> <descriptor> prints the dominator and post
+        // dominator trees of the chunks of that method
+        ("dominators", [input, name, descriptor]) => with_method_code(input, name, descriptor, |_, code| {
+            let dominators = code.dominators();
+            let post_dominators = code.post_dominators();
+            let exit = code.code_chunks.len() as u32;
+            let node = |node: Option<u32>| match node {
+                Some(node) if node == exit => "exit".to_string(),
+                Some(node) => node.to_string(),
+                None => "-".to_string(),
+            };
+            for (chunk_pos, chunk) in code.code_chunks.iter().enumerate() {
+                let chunk_pos = chunk_pos as u32;
+                println!(
+                    "{:5}: ops {}..{} idom {} children {:?} frontier {:?}, ipdom {} post frontier {:?}",
+                    chunk_pos,
+                    chunk.start,
+                    chunk.stop,
+                    node(dominators.immediate(chunk_pos)),
+                    dominators.children(chunk_pos),
+                    dominators.frontier[chunk_pos as usize],
+                    node(post_dominators.immediate(chunk_pos)),
+                    post_dominators.frontier[chunk_pos as usize],
+                );
+            }
+        }),
+        // reflow loops <class file> <name> <descriptor> prints the loop forest of that method
+        ("loops", [input, name, descriptor]) => with_method_code(input, name, descriptor, |_, code| {
+            let forest = code.loops();
+            for (loop_pos, inner) in forest.loops.iter().enumerate() {
+                println!(
+                    "loop {}: header {} latches {:?} body {:?} exits {:?} parent {:?} children {:?}",
+                    loop_pos, inner.header, inner.latches, inner.body, inner.exits, inner.parent, inner.children,
+                );
+            }
+            for region in &forest.irreducible {
+                println!("irreducible: entries {:?} body {:?}", region.entries, region.body);
+            }
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+// Finds a method through the view and decodes its code, only that method's bytes are parsed.
+fn with_method_code(input: &str, name: &str, descriptor: &str, f: impl FnOnce(&ClassView, Code)) {
+    let buffer = std::fs::read(input).expect("could not read the class file");
+    let view = match ClassView::parse(&buffer) {
+        Ok((_, view)) => view,
+        Err(error) => {
+            eprintln!("{}: could not parse the class: {:?}", input, error);
+            return;
+        }
+    };
+    match view.method(name, descriptor).and_then(|method| view.code(method)) {
+        Some(Ok((_, code))) => f(&view, code),
+        Some(Err(error)) => eprintln!("{}: could not parse the code: {:?}", input, error),
+        None => eprintln!("{}: no code for {}{}", input, name, descriptor),
     }
-    // and more! See the other methods for more details.
 }